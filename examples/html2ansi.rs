@@ -0,0 +1,29 @@
+extern crate boxrs;
+
+use std::env;
+
+use boxrs::layout::Viewport;
+use boxrs::loader::{file_url, FileLoader};
+use boxrs::painting::ansi;
+use boxrs::Engine;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let html_filename = args.next().expect("HTML file provided as first argument");
+
+    // Each pixel is half a character, so keep the page small enough to fit in a terminal.
+    let width = 80;
+    let height = 48;
+
+    let viewport = Viewport::new(width as f32, height as f32);
+
+    let mut engine = Engine::new(viewport);
+    engine
+        .load_document(&FileLoader, &file_url(&html_filename).unwrap())
+        .unwrap();
+
+    print!(
+        "{}",
+        ansi::render(engine.display_list(), width, height, None)
+    );
+}
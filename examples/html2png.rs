@@ -1,70 +1,55 @@
 extern crate boxrs;
 extern crate image;
 
-use std::default::Default;
 use std::env;
-use std::fs::File;
-use std::io::Read;
 
-use boxrs::css::Color;
+use boxrs::dom::Node;
+use boxrs::images::{Image, ImageCache};
+use boxrs::layout::Viewport;
+use boxrs::loader::{file_url, FileLoader, ResourceLoader};
+use boxrs::Engine;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use url::Url;
 
 fn main() {
+    // Print how long the phases of rendering take with RUST_LOG=boxrs=debug, and the boxes that
+    // are laid out with RUST_LOG=boxrs=trace.
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+
     let mut args = env::args().skip(1);
     let html_filename = args.next().expect("HTML file provided as first argument");
-    let css_filename = args.next().expect("CSS file provided as second argument");
-
-    let html = read_source(&html_filename);
-    let css = read_source(&css_filename);
 
     // Since we don't have an actual window, hard-code the "viewport" size.
     let width = 800;
     let height = 600;
 
-    let mut viewport: boxrs::layout::Dimensions = Default::default();
-    viewport.content.width = width as f32;
-    viewport.content.height = height as f32;
+    let viewport = Viewport::new(width as f32, height as f32);
+
+    let mut engine = Engine::new(viewport);
+    engine
+        .load_document(&FileLoader, &file_url(&html_filename).unwrap())
+        .unwrap();
+    engine.load_frames(&FileLoader);
 
-    // Parsing and rendering:
-    let root_node = boxrs::parse_html(&html);
-    let stylesheet = boxrs::parse_css(&css);
-    let style_root = boxrs::build_style_tree(&root_node, &stylesheet);
-    let layout_root = boxrs::build_layout_tree(&style_root, viewport);
-    let display_list = boxrs::build_display_list(&layout_root);
+    // Images are loaded relative to the HTML file.
+    let base = engine.base_url().unwrap().clone();
+    let mut images = ImageCache::new();
+    load_images(engine.document().unwrap(), &base, &mut images);
+    *engine.images_mut() = images;
 
     let filename = "output.png";
 
     // Rasterize:
-    let background = Color {
-        r: 255,
-        g: 255,
-        b: 255,
-        a: 255,
-    };
-    let mut canvas = vec![background; width * height];
+    let canvas = engine.render();
 
-    for item in display_list {
-        match item {
-            boxrs::painting::DisplayCommand::SolidColor(color, rect) => {
-                // Clip the rectangle to the canvas boundaries.
-                let x0 = rect.x.clamp(0.0, width as f32) as usize;
-                let y0 = rect.y.clamp(0.0, height as f32) as usize;
-                let x1 = (rect.x + rect.width).clamp(0.0, width as f32) as usize;
-                let y1 = (rect.y + rect.height).clamp(0.0, height as f32) as usize;
-
-                for y in y0..y1 {
-                    for x in x0..x1 {
-                        // TODO: alpha compositing with existing pixel
-                        canvas[y * width + x] = color.clone();
-                    }
-                }
-            }
-        }
-    }
-
-    let img = image::ImageBuffer::from_fn(width as u32, height as u32, move |x, y| {
-        let color = &canvas[(y * width as u32 + x) as usize];
-        image::Rgba([color.r, color.g, color.b, color.a])
-    });
+    let img =
+        image::RgbaImage::from_raw(canvas.width as u32, canvas.height as u32, canvas.to_rgba8())
+            .unwrap();
 
     let result = img.save(filename);
 
@@ -74,20 +59,28 @@ fn main() {
     }
 }
 
-fn read_source(filename: &str) -> String {
-    let mut s = String::new();
-    File::open(filename)
-        .unwrap()
-        .read_to_string(&mut s)
-        .unwrap();
-    s
-}
+/// Decode the images of the `img` elements in a document, and add them to the cache by their
+/// `src`. Images that can't be read are left out.
+fn load_images(node: &Node, base: &Url, images: &mut ImageCache) {
+    if let Node::Element { tag, children, .. } = node {
+        if let (true, Some(src)) = (tag == "img", node.get_attribute("src")) {
+            let data = base
+                .join(src)
+                .ok()
+                .and_then(|url| FileLoader.fetch(&url).ok());
+            if let Some(Ok(decoded)) = data.map(|data| image::load_from_memory(&data)) {
+                let decoded = decoded.into_rgba8();
+                let image = Image {
+                    width: decoded.width(),
+                    height: decoded.height(),
+                    pixels: decoded.into_raw(),
+                };
+                images.insert(src, image);
+            }
+        }
 
-trait Clamp {
-    fn clamp(self, lower: Self, upper: Self) -> Self;
-}
-impl Clamp for f32 {
-    fn clamp(self, lower: f32, upper: f32) -> f32 {
-        self.max(lower).min(upper)
+        for child in children {
+            load_images(child, base, images);
+        }
     }
 }
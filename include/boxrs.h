@@ -0,0 +1,102 @@
+/* The C interface of boxrs, with the `boxrs-capi` feature. See src/capi.rs. */
+
+#ifndef BOXRS_H
+#define BOXRS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BoxrsEngine BoxrsEngine;
+
+typedef struct {
+    float x;
+    float y;
+    float width;
+    float height;
+} BoxrsRect;
+
+typedef struct {
+    uint8_t r;
+    uint8_t g;
+    uint8_t b;
+    uint8_t a;
+} BoxrsColor;
+
+typedef enum {
+    BOXRS_SOLID_COLOR,
+    BOXRS_ROUNDED_RECT,
+    BOXRS_PUSH_CLIP,
+    BOXRS_PUSH_ROUNDED_CLIP,
+    BOXRS_POP_CLIP,
+    BOXRS_TEXT,
+    BOXRS_IMAGE,
+    BOXRS_PUSH_TRANSFORM,
+    BOXRS_POP_TRANSFORM,
+    BOXRS_PUSH_OPACITY_LAYER,
+    BOXRS_POP_LAYER,
+    BOXRS_PUSH_ROUNDED_CLIP_OUT,
+} BoxrsCommandKind;
+
+typedef struct {
+    uint32_t character;
+    float x;
+    float advance;
+    /* The index of the glyph in its face, or -1 if it has none. */
+    int32_t id;
+} BoxrsGlyph;
+
+/* A command of a display list. The fields that its kind doesn't use are zero. */
+typedef struct {
+    BoxrsCommandKind kind;
+    /* The rectangle that is filled, clipped to or drawn in. For text, x and y are the origin
+       on the baseline. */
+    BoxrsRect rect;
+    BoxrsColor color;
+    /* The radii of the top left, top right, bottom right and bottom left corners. */
+    float radii[4];
+    /* The matrix a, b, c, d, e, f of a transformation. */
+    float transform[6];
+    float opacity;
+    size_t image;
+    float font_size;
+    const BoxrsGlyph *glyphs;
+    size_t glyph_count;
+} BoxrsCommand;
+
+/* Every function accepts null pointers: it then does nothing, and returns false or NULL. */
+
+/* Create an engine with an empty document, in a viewport of CSS pixels. */
+BoxrsEngine *boxrs_engine_new(float width, float height);
+void boxrs_engine_free(BoxrsEngine *engine);
+
+/* Parse a document or a stylesheet. Return false if it isn't valid UTF-8 or can't be parsed. */
+bool boxrs_engine_load_html(BoxrsEngine *engine, const char *html);
+bool boxrs_engine_add_stylesheet(BoxrsEngine *engine, const char *css);
+/* Add a font from the data of a TrueType or OpenType file. Return false if it can't be parsed. */
+bool boxrs_engine_add_font(BoxrsEngine *engine, const uint8_t *data, size_t length);
+
+/* Lay the document out in a viewport of CSS pixels, with dpr device pixels per CSS pixel.
+   Return false if engine is NULL or the viewport couldn't be changed. */
+bool boxrs_engine_set_viewport(BoxrsEngine *engine, float width, float height, float dpr);
+/* Magnify the page, which is laid out again in fewer CSS pixels. Return false if engine is NULL
+   or the zoom couldn't be changed. */
+bool boxrs_engine_set_zoom(BoxrsEngine *engine, float zoom);
+
+/* The display list in device pixels, which stays valid until the next call with the engine.
+   Return NULL if engine or count is NULL, or if the document can't be laid out. */
+const BoxrsCommand *boxrs_engine_display_list(BoxrsEngine *engine, size_t *count);
+/* The document in 8-bit RGBA pixels, row by row from the top, which stay valid until the next
+   call with the engine. Return NULL if a pointer is NULL, or if the document can't be
+   rendered. */
+const uint8_t *boxrs_engine_render(BoxrsEngine *engine, size_t *width, size_t *height);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
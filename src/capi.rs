@@ -0,0 +1,460 @@
+//! Functions for C, so that applications in other languages can embed the engine. The header
+//! `include/boxrs.h` declares them.
+//!
+//! An engine is created with `boxrs_engine_new` and freed with `boxrs_engine_free`. The display
+//! list and the pixels that it returns stay valid until the next call with the same engine.
+//! Functions that are passed a null pointer do nothing, and return false or null.
+
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::css::Color;
+use crate::layout::{CornerRadii, Rect, Viewport};
+use crate::painting::DisplayCommand;
+use crate::Engine;
+
+/// An engine, with the display list and the pixels that it returned last.
+pub struct BoxrsEngine {
+    engine: Engine,
+    commands: Vec<BoxrsCommand>,
+    glyphs: Vec<Vec<BoxrsGlyph>>,
+    pixels: Vec<u8>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoxrsRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoxrsColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoxrsCommandKind {
+    SolidColor,
+    RoundedRect,
+    PushClip,
+    PushRoundedClip,
+    PopClip,
+    Text,
+    Image,
+    PushTransform,
+    PopTransform,
+    PushOpacityLayer,
+    PopLayer,
+    PushRoundedClipOut,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxrsGlyph {
+    pub character: u32,
+    pub x: f32,
+    pub advance: f32,
+    /// The index of the glyph in its face, or -1 if it has none.
+    pub id: i32,
+}
+
+/// A command of a display list. The fields that its kind doesn't use are zero.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct BoxrsCommand {
+    pub kind: BoxrsCommandKind,
+    /// The rectangle that is filled, clipped to or drawn in. For text, `x` and `y` are the
+    /// origin on the baseline.
+    pub rect: BoxrsRect,
+    pub color: BoxrsColor,
+    /// The radii of the top left, top right, bottom right and bottom left corners.
+    pub radii: [f32; 4],
+    /// The matrix `a, b, c, d, e, f` of a transformation.
+    pub transform: [f32; 6],
+    pub opacity: f32,
+    /// The id of the image in the image cache of the engine.
+    pub image: usize,
+    pub font_size: f32,
+    pub glyphs: *const BoxrsGlyph,
+    pub glyph_count: usize,
+}
+
+impl BoxrsCommand {
+    fn new(kind: BoxrsCommandKind) -> BoxrsCommand {
+        BoxrsCommand {
+            kind,
+            rect: BoxrsRect::default(),
+            color: BoxrsColor::default(),
+            radii: [0.0; 4],
+            transform: [0.0; 6],
+            opacity: 0.0,
+            image: 0,
+            font_size: 0.0,
+            glyphs: std::ptr::null(),
+            glyph_count: 0,
+        }
+    }
+}
+
+impl From<Rect> for BoxrsRect {
+    fn from(rect: Rect) -> BoxrsRect {
+        BoxrsRect {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+impl From<&Color> for BoxrsColor {
+    fn from(color: &Color) -> BoxrsColor {
+        BoxrsColor {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+            a: color.a,
+        }
+    }
+}
+
+fn radii(radii: &CornerRadii) -> [f32; 4] {
+    [
+        radii.top_left,
+        radii.top_right,
+        radii.bottom_right,
+        radii.bottom_left,
+    ]
+}
+
+/// Run a function, and return `default` if it panics, since a panic may not unwind into C.
+fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+/// Create an engine with an empty document, in a viewport of `width` by `height` CSS pixels.
+#[no_mangle]
+pub extern "C" fn boxrs_engine_new(width: f32, height: f32) -> *mut BoxrsEngine {
+    Box::into_raw(Box::new(BoxrsEngine {
+        engine: Engine::new(Viewport::new(width, height)),
+        commands: Vec::new(),
+        glyphs: Vec::new(),
+        pixels: Vec::new(),
+    }))
+}
+
+/// Free an engine.
+///
+/// # Safety
+///
+/// `engine` must come from `boxrs_engine_new`, and may not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_free(engine: *mut BoxrsEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Parse a document with the stylesheets of its `style` elements. Returns false if `html` isn't
+/// valid UTF-8 or can't be parsed.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`, and `html` must be null or a string
+/// that ends in a zero.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_load_html(
+    engine: *mut BoxrsEngine,
+    html: *const c_char,
+) -> bool {
+    let engine = match engine.as_mut() {
+        Some(engine) if !html.is_null() => &mut engine.engine,
+        _ => return false,
+    };
+    match CStr::from_ptr(html).to_str() {
+        Ok(html) => guard(false, || engine.load_html(html).is_ok()),
+        Err(_) => false,
+    }
+}
+
+/// Parse a stylesheet, and add it after the stylesheets before it. Returns false if `css` isn't
+/// valid UTF-8 or can't be parsed.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`, and `css` must be null or a string
+/// that ends in a zero.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_add_stylesheet(
+    engine: *mut BoxrsEngine,
+    css: *const c_char,
+) -> bool {
+    let engine = match engine.as_mut() {
+        Some(engine) if !css.is_null() => &mut engine.engine,
+        _ => return false,
+    };
+    match CStr::from_ptr(css).to_str() {
+        Ok(css) => guard(false, || engine.add_stylesheet(css).is_ok()),
+        Err(_) => false,
+    }
+}
+
+/// Add a font from the data of a TrueType or OpenType file. Returns false if it can't be parsed.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`, and `data` must be null or point to
+/// `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_add_font(
+    engine: *mut BoxrsEngine,
+    data: *const u8,
+    length: usize,
+) -> bool {
+    let engine = match engine.as_mut() {
+        Some(engine) if !data.is_null() => &mut engine.engine,
+        _ => return false,
+    };
+    let data = std::slice::from_raw_parts(data, length).to_vec();
+    guard(false, || engine.fonts_mut().add_font_data(data).is_ok())
+}
+
+/// Lay the document out in a viewport of `width` by `height` CSS pixels, on a screen with
+/// `dpr` device pixels per CSS pixel. The zoom stays the same. Returns false if `engine` is null
+/// or the viewport couldn't be changed.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_set_viewport(
+    engine: *mut BoxrsEngine,
+    width: f32,
+    height: f32,
+    dpr: f32,
+) -> bool {
+    let engine = match engine.as_mut() {
+        Some(engine) => &mut engine.engine,
+        None => return false,
+    };
+    guard(false, || {
+        let viewport = Viewport {
+            dpr,
+            zoom: engine.viewport().zoom,
+            ..Viewport::new(width, height)
+        };
+        engine.set_viewport(viewport);
+        true
+    })
+}
+
+/// Magnify the page by `zoom`, which lays it out again in fewer CSS pixels. Returns false if
+/// `engine` is null or the zoom couldn't be changed.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_set_zoom(engine: *mut BoxrsEngine, zoom: f32) -> bool {
+    let engine = match engine.as_mut() {
+        Some(engine) => &mut engine.engine,
+        None => return false,
+    };
+    guard(false, || {
+        engine.set_zoom(zoom);
+        true
+    })
+}
+
+/// The display list of the document in device pixels, for applications that draw it themselves.
+/// Its length is written to `count`. Returns null if a pointer is null or the document can't be
+/// laid out.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`, and `count` must be null or point to
+/// a `size_t`.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_display_list(
+    engine: *mut BoxrsEngine,
+    count: *mut usize,
+) -> *const BoxrsCommand {
+    let engine = match engine.as_mut() {
+        Some(engine) if !count.is_null() => engine,
+        _ => return std::ptr::null(),
+    };
+    let list = match guard(None, || Some(engine.engine.display_list().clone())) {
+        Some(list) => list,
+        None => return std::ptr::null(),
+    };
+
+    engine.glyphs.clear();
+    engine.commands = list
+        .iter()
+        .map(|item| match item {
+            DisplayCommand::SolidColor(color, rect) => BoxrsCommand {
+                rect: (*rect).into(),
+                color: color.into(),
+                ..BoxrsCommand::new(BoxrsCommandKind::SolidColor)
+            },
+            DisplayCommand::RoundedRect(color, rect, corners) => BoxrsCommand {
+                rect: (*rect).into(),
+                color: color.into(),
+                radii: radii(corners),
+                ..BoxrsCommand::new(BoxrsCommandKind::RoundedRect)
+            },
+            DisplayCommand::PushClip(rect) => BoxrsCommand {
+                rect: (*rect).into(),
+                ..BoxrsCommand::new(BoxrsCommandKind::PushClip)
+            },
+            DisplayCommand::PushRoundedClip(rect, corners) => BoxrsCommand {
+                rect: (*rect).into(),
+                radii: radii(corners),
+                ..BoxrsCommand::new(BoxrsCommandKind::PushRoundedClip)
+            },
+            DisplayCommand::PushRoundedClipOut(rect, corners) => BoxrsCommand {
+                rect: (*rect).into(),
+                radii: radii(corners),
+                ..BoxrsCommand::new(BoxrsCommandKind::PushRoundedClipOut)
+            },
+            DisplayCommand::PopClip => BoxrsCommand::new(BoxrsCommandKind::PopClip),
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                color,
+                font,
+            } => {
+                let glyphs: Vec<_> = glyphs
+                    .iter()
+                    .map(|glyph| BoxrsGlyph {
+                        character: glyph.character as u32,
+                        x: glyph.x,
+                        advance: glyph.advance,
+                        id: glyph.id.map_or(-1, i32::from),
+                    })
+                    .collect();
+                // The glyphs don't move when their vector is moved into the list.
+                let command = BoxrsCommand {
+                    rect: BoxrsRect {
+                        x: origin.0,
+                        y: origin.1,
+                        ..Default::default()
+                    },
+                    color: color.into(),
+                    font_size: font.size,
+                    glyphs: glyphs.as_ptr(),
+                    glyph_count: glyphs.len(),
+                    ..BoxrsCommand::new(BoxrsCommandKind::Text)
+                };
+                engine.glyphs.push(glyphs);
+                command
+            }
+            DisplayCommand::Image { image, rect } => BoxrsCommand {
+                rect: (*rect).into(),
+                image: image.id(),
+                ..BoxrsCommand::new(BoxrsCommandKind::Image)
+            },
+            DisplayCommand::PushTransform(t) => BoxrsCommand {
+                transform: [t.a, t.b, t.c, t.d, t.e, t.f],
+                ..BoxrsCommand::new(BoxrsCommandKind::PushTransform)
+            },
+            DisplayCommand::PopTransform => BoxrsCommand::new(BoxrsCommandKind::PopTransform),
+            DisplayCommand::PushOpacityLayer(opacity) => BoxrsCommand {
+                opacity: *opacity,
+                ..BoxrsCommand::new(BoxrsCommandKind::PushOpacityLayer)
+            },
+            DisplayCommand::PopLayer => BoxrsCommand::new(BoxrsCommandKind::PopLayer),
+        })
+        .collect();
+
+    *count = engine.commands.len();
+    engine.commands.as_ptr()
+}
+
+/// Rasterize the document into 8-bit RGBA pixels, row by row from the top, and write the size
+/// of the image in device pixels to `width` and `height`. Returns null if a pointer is null or
+/// the document can't be rendered.
+///
+/// # Safety
+///
+/// `engine` must be null or come from `boxrs_engine_new`, and `width` and `height` must be null
+/// or point to `size_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn boxrs_engine_render(
+    engine: *mut BoxrsEngine,
+    width: *mut usize,
+    height: *mut usize,
+) -> *const u8 {
+    let engine = match engine.as_mut() {
+        Some(engine) if !width.is_null() && !height.is_null() => engine,
+        _ => return std::ptr::null(),
+    };
+    let rendered = guard(None, || {
+        let canvas = engine.engine.render();
+        Some((canvas.width, canvas.height, canvas.to_rgba8()))
+    });
+    match rendered {
+        Some((w, h, pixels)) => {
+            engine.pixels = pixels;
+            *width = w;
+            *height = h;
+            engine.pixels.as_ptr()
+        }
+        None => std::ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capi::*;
+
+    #[test]
+    fn test_capi() {
+        unsafe {
+            let engine = boxrs_engine_new(20.0, 10.0);
+            assert!(boxrs_engine_load_html(engine, c"<div></div>".as_ptr()));
+            assert!(boxrs_engine_add_stylesheet(
+                engine,
+                c"div { width: 5px; height: 4px; background: #ff0000; }".as_ptr()
+            ));
+            // A stylesheet that can't be parsed is reported, and leaves the others alone.
+            assert!(!boxrs_engine_add_stylesheet(engine, c"div {".as_ptr()));
+            assert!(boxrs_engine_set_viewport(engine, 20.0, 10.0, 2.0));
+
+            let mut count = 0;
+            let list = boxrs_engine_display_list(engine, &mut count);
+            let list = std::slice::from_raw_parts(list, count);
+            assert_eq!(list[0].kind, BoxrsCommandKind::SolidColor);
+            assert_eq!(list[0].rect.width, 10.0);
+            assert_eq!(list[0].color.r, 255);
+
+            let (mut width, mut height) = (0, 0);
+            let pixels = boxrs_engine_render(engine, &mut width, &mut height);
+            let pixels = std::slice::from_raw_parts(pixels, width * height * 4);
+            assert_eq!((width, height), (40, 20));
+            assert_eq!(pixels[..4], [255, 0, 0, 255]);
+            assert_eq!(pixels[10 * 4..11 * 4], [255, 255, 255, 255]);
+
+            boxrs_engine_free(engine);
+
+            // Null pointers are refused instead of dereferenced.
+            use std::ptr::null_mut as null;
+            assert!(!boxrs_engine_load_html(null(), c"<div></div>".as_ptr()));
+            assert!(!boxrs_engine_add_font(null(), std::ptr::null(), 0));
+            assert!(!boxrs_engine_set_zoom(null(), 2.0));
+            assert!(boxrs_engine_display_list(null(), &mut count).is_null());
+            let engine = boxrs_engine_new(20.0, 10.0);
+            assert!(boxrs_engine_display_list(engine, null()).is_null());
+            assert!(boxrs_engine_render(engine, &mut width, null()).is_null());
+            boxrs_engine_free(engine);
+        }
+    }
+}
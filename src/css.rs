@@ -1,200 +1,682 @@
 use std::cmp::Reverse;
+use std::fmt;
 use std::str::FromStr;
 
-pub struct Sheet(pub Vec<Rule>);
+use url::Url;
+
+pub use crate::dom::Atom;
+
+mod color;
+#[cfg(feature = "cssparser")]
+mod servo;
+pub mod units;
+
+use self::units::UnitContext;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sheet {
+    /// The URLs of the stylesheets that the `@import` rules import, before the other rules.
+    ///
+    /// https://www.w3.org/TR/css-cascade-4/#at-import
+    pub imports: Vec<String>,
+    pub rules: Vec<Rule>,
+    pub font_faces: Vec<FontFace>,
+    pub pages: Vec<PageRule>,
+}
 
 impl Sheet {
+    /// Parse a stylesheet, or fail if it isn't valid.
+    pub fn parse(css: &str) -> Result<Sheet, ParseError> {
+        css_parser::rules(css)
+    }
+
     pub fn add_rule(mut self, rule: Rule) -> Self {
-        self.0.push(rule);
+        self.rules.push(rule);
         self
     }
+
+    fn add_statement(&mut self, statement: Statement) {
+        match statement {
+            Statement::Import(url) => self.imports.push(url),
+            Statement::Rule(rule) => self.rules.push(rule),
+            Statement::FontFace(font_face) => self.font_faces.push(font_face),
+            Statement::Page(page) => self.pages.push(page),
+            Statement::Media(rules) => self.rules.extend(rules),
+        }
+    }
+
+    /// The URLs of the `url()` values of the rules, such as background images.
+    pub fn urls(&self) -> Vec<&str> {
+        let values = self.rules.iter().flat_map(|rule| &rule.declarations);
+        values
+            .flat_map(|declaration| declaration.value.urls())
+            .collect()
+    }
+
+    /// Resolve every URL of the stylesheet against the URL it was loaded from, so that they
+    /// still refer to the same resources when it is combined with other stylesheets.
+    pub fn resolve_urls(&mut self, base: &Url) {
+        let resolve = |url: &mut String| {
+            if let Ok(resolved) = base.join(url) {
+                *url = resolved.into();
+            }
+        };
+        self.imports.iter_mut().for_each(resolve);
+        let rules = self
+            .rules
+            .iter_mut()
+            .flat_map(|rule| &mut rule.declarations);
+        let font_faces = self
+            .font_faces
+            .iter_mut()
+            .flat_map(|font_face| &mut font_face.descriptors);
+        for declaration in rules.chain(font_faces) {
+            declaration.value.urls_mut().into_iter().for_each(resolve);
+        }
+    }
 }
 
-impl From<&Sheet> for String {
-    fn from(sheet: &Sheet) -> String {
-        let Sheet(rules) = sheet;
-        rules.iter().fold("".to_owned(), |acc, rule| {
-            format!("{}{}", acc, String::from(rule))
-        })
+impl fmt::Display for Sheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for url in &self.imports {
+            write!(f, "@import \"{}\";", url)?;
+        }
+        for font_face in &self.font_faces {
+            write!(f, "{}", font_face)?;
+        }
+        for page in &self.pages {
+            write!(f, "{}", page)?;
+        }
+        for rule in &self.rules {
+            write!(f, "{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a stylesheet, like `Sheet::parse`.
+impl FromStr for Sheet {
+    type Err = ParseError;
+
+    fn from_str(css: &str) -> Result<Sheet, ParseError> {
+        Sheet::parse(css)
     }
 }
 
+/// A font that a style sheet makes available for its text, described by an `@font-face` rule.
+///
+/// https://www.w3.org/TR/css-fonts-3/#font-face-rule
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FontFace {
+    pub descriptors: Box<[Declaration]>,
+}
+
+impl FontFace {
+    /// The value of a descriptor, such as `font-family` or `src`.
+    pub fn descriptor(&self, name: &str) -> Option<&Value> {
+        self.descriptors
+            .iter()
+            .rev()
+            .find(|declaration| &*declaration.name == name)
+            .map(|declaration| &declaration.value)
+    }
+
+    /// The URLs in the `src` descriptor, in order of preference.
+    pub fn sources(&self) -> Vec<&str> {
+        let sources = match self.descriptor("src") {
+            Some(Value::CommaList(sources)) => sources.iter().collect(),
+            Some(source) => vec![source],
+            None => Vec::new(),
+        };
+        sources
+            .into_iter()
+            .filter_map(|source| match source {
+                // A source may be followed by a format hint, as in `url(a.woff) format("woff")`.
+                Value::List(values) => values.first(),
+                _ => Some(source),
+            })
+            .filter_map(|source| match source {
+                Value::Function(name, arguments) if name == "url" => match arguments.first() {
+                    Some(Value::Str(url)) => Some(url.as_str()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for FontFace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@font-face{{{}}}", Joined(&self.descriptors, ";"))
+    }
+}
+
+/// The margins and the size of the pages that a document is printed on, described by an `@page`
+/// rule. Rules for some pages only, like `@page :first`, have a selector.
+///
+/// https://www.w3.org/TR/css-page-3/#at-page-rule
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageRule {
+    pub selector: Option<String>,
+    pub declarations: Box<[Declaration]>,
+}
+
+impl fmt::Display for PageRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let selector = self.selector.as_deref().unwrap_or("");
+        let declarations = Joined(&self.declarations, ";");
+        write!(f, "@page {}{{{}}}", selector, declarations)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rule {
     pub selectors: Vec<Selector>,
-    pub declarations: Vec<Declaration>,
+    pub declarations: Box<[Declaration]>,
+    pub origin: Origin,
+    /// The media that the rule applies to, from the `@media` rule that it is in.
+    pub media: MediaList,
+}
+
+/// The kind of device that a document is rendered on, which `@media` rules select rules for.
+///
+/// https://www.w3.org/TR/mediaqueries-4/#media-types
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Medium {
+    #[default]
+    Screen,
+    Print,
+}
+
+/// The media queries of an `@media` rule, as they are written. An empty list matches every
+/// medium.
+///
+/// Only media types are supported, optionally with `only` or `not`. Queries with media features,
+/// like `(min-width: 600px)`, never match.
+///
+/// https://www.w3.org/TR/mediaqueries-4/#media
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MediaList(pub Vec<String>);
+
+impl MediaList {
+    pub fn matches(&self, medium: Medium) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query_matches(query, medium))
+    }
+}
+
+fn query_matches(query: &str, medium: Medium) -> bool {
+    let words: Vec<String> = query
+        .split_ascii_whitespace()
+        .map(str::to_ascii_lowercase)
+        .collect();
+    let (negated, media_type) = match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["not", media_type] => (true, media_type),
+        ["only", media_type] | [media_type] => (false, media_type),
+        _ => return false,
+    };
+    let matches = match media_type {
+        "all" => true,
+        "screen" => medium == Medium::Screen,
+        "print" => medium == Medium::Print,
+        _ => false,
+    };
+    matches != negated
+}
+
+/// Where a rule comes from. The rules of the document win over those of the user agent,
+/// whatever their specificity.
+///
+/// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Origin {
+    UserAgent,
+    #[default]
+    Author,
 }
 
 impl Rule {
-    pub fn add_selector(mut self, selector: Selector) -> Self {
+    /// Parse a single style rule, such as `p, li { margin: 0 }`, or fail if it isn't valid.
+    pub fn parse(css: &str) -> Result<Rule, ParseError> {
+        css_parser::single_rule(css)
+    }
+}
+
+/// Builds a rule from its selectors and declarations, like
+/// `rule().add_selector(selector().add_tag("p")).add_declaration("margin", value)`. The
+/// declarations are collected in a vector, which is turned into the boxed slice of the rule once,
+/// when it is built.
+#[derive(Clone, Debug, Default)]
+pub struct RuleBuilder {
+    selectors: Vec<SelectorBuilder>,
+    declarations: Vec<Declaration>,
+}
+
+impl RuleBuilder {
+    pub fn add_selector(mut self, selector: SelectorBuilder) -> Self {
         self.selectors.push(selector);
         self
     }
 
     pub fn add_declaration(mut self, name: &str, value: Value) -> Self {
         self.declarations.push(Declaration {
-            name: name.to_owned(),
+            name: Atom::from(name),
             value,
         });
         self
     }
-}
-
-impl From<&Rule> for String {
-    fn from(rule: &Rule) -> String {
-        let selectors_str = rule
-            .selectors
-            .iter()
-            .map(String::from)
-            .collect::<Vec<_>>()
-            .join(",");
 
-        let declarations_str = rule
-            .declarations
-            .iter()
-            .map(String::from)
-            .collect::<Vec<_>>()
-            .join(";");
+    /// The rule, or an error if it has no selectors or one of its selectors isn't valid, like
+    /// the parser would fail for it.
+    pub fn build(self) -> Result<Rule, BuildError> {
+        if self.selectors.is_empty() {
+            return Err(BuildError::NoSelectors);
+        }
+        let selectors = self.selectors.into_iter().map(SelectorBuilder::build);
+        Ok(Rule {
+            selectors: selectors.collect::<Result<_, _>>()?,
+            declarations: self.declarations.into_boxed_slice(),
+            origin: Origin::Author,
+            media: MediaList::default(),
+        })
+    }
+}
 
-        format!("{}{{{}}}", selectors_str, declarations_str)
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let style_rule = format!(
+            "{}{{{}}}",
+            Joined(&self.selectors, ","),
+            Joined(&self.declarations, ";")
+        );
+        match self.media.0.is_empty() {
+            true => write!(f, "{}", style_rule),
+            false => write!(f, "@media {}{{{}}}", self.media.0.join(","), style_rule),
+        }
     }
 }
 
 pub type Specificity = (usize, usize, usize);
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Selector {
     pub tag: Option<String>,
     pub class: Vec<String>,
     pub id: Option<String>,
     pub attr: Vec<(String, AttrOp, String)>,
+    /// The pseudo-element that the selector matches, as in `h2::before`.
+    pub pseudo_element: Option<String>,
 }
 
 impl Selector {
+    /// A selector from its components, or an error if it has more than one id, tag or
+    /// pseudo-element.
+    fn from_components(components: Vec<SelectorComponent>) -> Result<Selector, BuildError> {
+        let mut ids = vec![];
+        let mut classes = vec![];
+        let mut attributes = vec![];
+        let mut tags = vec![];
+        let mut pseudo_elements = vec![];
+
+        for c in components {
+            match c {
+                SelectorComponent::Id(s) => ids.push(s),
+                SelectorComponent::Class(s) => classes.push(s),
+                SelectorComponent::Attribute(n, o, v) => attributes.push((n, o, v)),
+                SelectorComponent::Tag(s) => tags.push(s),
+                SelectorComponent::Universal => (),
+                SelectorComponent::PseudoElement(s) => pseudo_elements.push(s),
+            }
+        }
+
+        if ids.len() > 1 {
+            return Err(BuildError::TooManyIds);
+        }
+
+        if tags.len() > 1 {
+            return Err(BuildError::TooManyTags);
+        }
+
+        if pseudo_elements.len() > 1 {
+            return Err(BuildError::TooManyPseudoElements);
+        }
+
+        Ok(Selector {
+            tag: tags.pop(),
+            class: classes,
+            id: ids.pop(),
+            attr: attributes,
+            pseudo_element: pseudo_elements.pop(),
+        })
+    }
+
+    /// The number of ids, of classes and attribute selectors, and of type selectors and
+    /// pseudo-elements in the selector. The universal selector isn't counted. Specificities are
+    /// compared in that order, and rules of equal specificity apply in the order they are in.
+    ///
+    /// https://www.w3.org/TR/selectors-3/#specificity
+    pub fn get_specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len() + self.attr.len();
+        let c = self.tag.iter().count() + self.pseudo_element.iter().count();
+        (a, b, c)
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref tag_name) = self.tag {
+            write!(f, "{}", tag_name)?;
+        }
+
+        for c in &self.class {
+            write!(f, ".{}", c)?;
+        }
+
+        if let Some(ref id_name) = self.id {
+            write!(f, "#{}", id_name)?;
+        }
+
+        for (name, op, value) in &self.attr {
+            match op {
+                AttrOp::Exists => write!(f, "[{}]", name)?,
+                AttrOp::Eq => write!(f, "[{}{}\"{}\"]", name, op, value)?,
+            }
+        }
+
+        if let Some(ref name) = self.pseudo_element {
+            write!(f, "::{}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a simple selector, such as `p.note`, or fails if it isn't valid.
+impl FromStr for Selector {
+    type Err = ParseError;
+
+    fn from_str(css: &str) -> Result<Selector, ParseError> {
+        css_parser::single_selector(css)
+    }
+}
+
+/// Builds a selector from its parts, like `selector().add_tag("p").add_class("note")`.
+#[derive(Clone, Debug, Default)]
+pub struct SelectorBuilder {
+    components: Vec<SelectorComponent>,
+}
+
+impl SelectorBuilder {
     pub fn add_tag(mut self, tag_name: &str) -> Self {
-        self.tag = Some(tag_name.to_owned());
+        let tag = SelectorComponent::Tag(tag_name.to_owned());
+        self.components.push(tag);
         self
     }
 
     pub fn add_class(mut self, class_name: &str) -> Self {
-        self.class.push(class_name.to_owned());
+        let class = SelectorComponent::Class(class_name.to_owned());
+        self.components.push(class);
         self
     }
 
     pub fn add_id(mut self, id_name: &str) -> Self {
-        self.id = Some(id_name.to_owned());
+        let id = SelectorComponent::Id(id_name.to_owned());
+        self.components.push(id);
         self
     }
 
     pub fn add_attr(mut self, attr_name: &str, attr_op: AttrOp, attr_value: &str) -> Self {
-        self.attr.push((attr_name.to_owned(), attr_op, attr_value.to_owned()));
+        let attr =
+            SelectorComponent::Attribute(attr_name.to_owned(), attr_op, attr_value.to_owned());
+        self.components.push(attr);
         self
     }
 
-    pub fn get_specificity(&self) -> Specificity {
-        let a = self.id.iter().count();
-        let b = self.class.iter().count() + self.attr.iter().count();
-        let c = self.tag.iter().count();
-        (a, b, c)
+    pub fn add_pseudo_element(mut self, name: &str) -> Self {
+        let pseudo_element = SelectorComponent::PseudoElement(name.to_owned());
+        self.components.push(pseudo_element);
+        self
     }
-}
-
-impl From<&Selector> for String {
-    fn from(selector: &Selector) -> String {
-        let mut selector_str = String::new();
-
-        if let Some(ref tag_name) = selector.tag {
-            selector_str.push_str(tag_name);
-        }
 
-        for c in &selector.class {
-            selector_str.push('.');
-            selector_str.push_str(&c);
-        }
+    /// The selector, or an error if it has more than one id, tag or pseudo-element, like the
+    /// parser would fail for it.
+    pub fn build(self) -> Result<Selector, BuildError> {
+        Selector::from_components(self.components)
+    }
+}
 
-        if let Some(ref id_name) = selector.id {
-            selector_str.push('#');
-            selector_str.push_str(id_name);
-        }
+/// Why a builder couldn't build a selector or a rule that the parser would accept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    TooManyIds,
+    TooManyTags,
+    TooManyPseudoElements,
+    NoSelectors,
+}
 
-        for a in &selector.attr {
-            selector_str.push('[');
-            selector_str.push_str(&a.0);
-            selector_str.push_str(&String::from(&a.1));
-            selector_str.push('"');
-            selector_str.push_str(&a.2);
-            selector_str.push('"');
-            selector_str.push(']');
+impl BuildError {
+    /// What the parser expects instead, for its error messages.
+    fn expected(self) -> &'static str {
+        match self {
+            BuildError::TooManyIds => "a maximum of one id",
+            BuildError::TooManyTags => "a maximum of one tag",
+            BuildError::TooManyPseudoElements => "a maximum of one pseudo-element",
+            BuildError::NoSelectors => "at least one selector",
         }
+    }
+}
 
-        selector_str
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}", self.expected())
     }
 }
 
+impl std::error::Error for BuildError {}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AttrOp {
     Eq,
+    /// The element has the attribute, with any value.
+    Exists,
 }
 
-impl From<&AttrOp> for String {
-    fn from(op: &AttrOp) -> String {
-        match op {
-            AttrOp::Eq => "=".to_owned(),
+impl fmt::Display for AttrOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttrOp::Eq => write!(f, "="),
+            AttrOp::Exists => Ok(()),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Declaration {
-    pub name: String,
+    pub name: Atom,
     pub value: Value,
 }
 
-impl From<&Declaration> for String {
-    fn from(declaration: &Declaration) -> String {
-        format!("{}:{}", declaration.name, String::from(&declaration.value))
+impl Declaration {
+    /// Parse a single declaration, such as `margin: 0 auto`, or fail if it isn't valid.
+    pub fn parse(css: &str) -> Result<Declaration, ParseError> {
+        css_parser::single_declaration(css)
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.value)
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    Number(f32),
     ColorValue(Color),
+    /// A space-separated list of values, as in `grid-template-columns: 100px 1fr`.
+    List(Vec<Value>),
+    /// A comma-separated list of values, as in `font-family: Georgia, serif`.
+    CommaList(Vec<Value>),
+    /// A function with comma-separated arguments, as in `transform: translate(10px, 20px)`.
+    Function(String, Vec<Value>),
+    /// A quoted string, as in `content: "Chapter "`.
+    Str(String),
 }
 
 impl Value {
+    /// The URLs of the `url()` functions in the value.
+    pub fn urls(&self) -> Vec<&str> {
+        match self {
+            Value::Function(name, arguments) if name == "url" => arguments
+                .iter()
+                .filter_map(|argument| match argument {
+                    Value::Str(url) => Some(url.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            Value::List(values) | Value::CommaList(values) | Value::Function(_, values) => {
+                values.iter().flat_map(Value::urls).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn urls_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Value::Function(name, arguments) => {
+                let is_url = name == "url";
+                arguments
+                    .iter_mut()
+                    .flat_map(|argument| match (is_url, argument) {
+                        (true, Value::Str(url)) => vec![url],
+                        (_, argument) => argument.urls_mut(),
+                    })
+                    .collect()
+            }
+            Value::List(values) | Value::CommaList(values) => {
+                values.iter_mut().flat_map(Value::urls_mut).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The length in px, with relative units resolved against the defaults of [`UnitContext`].
+    /// Styles resolve relative units against their elements and the viewport before layout, so
+    /// that this gives the lengths that they stand for.
     pub fn to_px(&self) -> f32 {
-        match *self {
-            Value::Length(f, Unit::Px) => f, // TODO: device-independent pixels
-            _ => 0.0,
+        self.resolve(&UnitContext::default())
+    }
+
+    /// Whether this is the `currentColor` keyword, which stands for the value of `color`.
+    ///
+    /// https://www.w3.org/TR/css-color-3/#currentcolor
+    pub fn is_current_color(&self) -> bool {
+        matches!(self, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor"))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Keyword(ref s) => write!(f, "{}", s),
+            Value::Length(v, ref u) => write!(f, "{}{}", v, u),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::ColorValue(c) => write!(f, "{}", c),
+            Value::List(values) => write!(f, "{}", Joined(values, " ")),
+            Value::Function(name, arguments) => write!(f, "{}({})", name, Joined(arguments, ",")),
+            Value::CommaList(values) => write!(f, "{}", Joined(values, ",")),
+            Value::Str(s) => write!(f, "\"{}\"", s),
         }
     }
 }
 
-impl From<&Value> for String {
-    fn from(value: &Value) -> String {
-        match value {
-            Value::Keyword(ref s) => String::from(s),
-            Value::Length(v, ref u) => format!("{}{}", v, String::from(u)),
-            Value::ColorValue(c) => format!("rgba({},{},{},{})", c.r, c.g, c.b, c.a),
+/// Parses a value, such as `0 auto` or `#ff0000`, or fails if it isn't valid.
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(css: &str) -> Result<Value, ParseError> {
+        css_parser::single_value(css)
+    }
+}
+
+/// Items that are displayed with a separator between them.
+struct Joined<'a, T>(&'a [T], &'a str);
+
+impl<T: fmt::Display> fmt::Display for Joined<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{}", self.1)?;
+            }
+            write!(f, "{}", item)?;
         }
+        Ok(())
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Unit {
     Px,
+    Percent,
+    /// A fraction of the free space in a grid container.
+    Fr,
+    /// A percentage of the width of the viewport.
+    Vw,
+    /// A percentage of the height of the viewport.
+    Vh,
+    Vmin,
+    Vmax,
+    /// The font size of the element, or of its parent in `font-size`.
+    Em,
+    /// The font size of the root element.
+    Rem,
+    In,
+    Cm,
+    Mm,
+    /// A point, 1/72 of an inch.
+    Pt,
+    /// A pica, 1/6 of an inch.
+    Pc,
 }
 
-impl From<&Unit> for String {
-    fn from(unit: &Unit) -> String {
-        match unit {
-            Unit::Px => "px".to_owned(),
-        }
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = match self {
+            Unit::Px => "px",
+            Unit::Percent => "%",
+            Unit::Fr => "fr",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::In => "in",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+        };
+        write!(f, "{}", unit)
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -202,54 +684,151 @@ pub struct Color {
     pub a: u8,
 }
 
-pub fn sheet() -> Sheet {
-    Sheet(vec![])
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rgba({},{},{},{})", self.r, self.g, self.b, self.a)
+    }
+}
+
+/// Parses a color in hexadecimal notation, or with `rgb()` or `rgba()`.
+impl FromStr for Color {
+    type Err = ParseError;
+
+    fn from_str(css: &str) -> Result<Color, ParseError> {
+        css_parser::single_color(css)
+    }
 }
 
-pub fn rule() -> Rule {
-    Rule {
-        selectors: vec![],
-        declarations: vec![],
+pub fn sheet() -> Sheet {
+    Sheet {
+        imports: vec![],
+        rules: vec![],
+        font_faces: vec![],
+        pages: vec![],
     }
 }
 
-pub fn selector() -> Selector {
-    Selector {
-        tag: None,
-        class: vec![],
-        id: None,
-        attr: vec![],
+pub fn rule() -> RuleBuilder {
+    RuleBuilder::default()
+}
+
+/// The default styles of the HTML elements, such as which ones are blocks and which ones are
+/// hidden.
+pub fn user_agent_sheet() -> Sheet {
+    let mut sheet = Sheet::from(include_str!("ua.css"));
+    for rule in &mut sheet.rules {
+        rule.origin = Origin::UserAgent;
     }
+    sheet
+}
+
+pub fn selector() -> SelectorBuilder {
+    SelectorBuilder::default()
 }
 
+/// Why a stylesheet couldn't be parsed, with the line and column where it went wrong.
+pub type ParseError = peg::error::ParseError<peg::str::LineCol>;
+
+/// Parses stylesheets that are known to be valid, such as those in the source code.
+///
+/// Panics if the stylesheet isn't valid. Use `Sheet::parse` for stylesheets that may not be.
 impl From<&str> for Sheet {
     fn from(s: &str) -> Sheet {
-        css_parser::rules(s).unwrap()
+        Sheet::parse(s).unwrap()
     }
 }
 
+enum Statement {
+    Import(String),
+    Rule(Rule),
+    FontFace(FontFace),
+    Page(PageRule),
+    Media(Vec<Rule>),
+}
 
+#[derive(Clone, Debug)]
 enum SelectorComponent {
     Id(String),
     Class(String),
     Attribute(String, AttrOp, String),
     Tag(String),
     Universal,
+    PseudoElement(String),
 }
 
 peg::parser! {
     grammar css_parser() for str {
         pub rule rules() -> Sheet
-            = __ r:(css_rule() ** __) __ { Sheet(r) }
+            = __ statements:(statement() ** __) __ {
+                let mut sheet = sheet();
+                for statement in statements {
+                    sheet.add_statement(statement);
+                }
+                sheet
+            }
+
+        rule statement() -> Statement
+            = i:import_rule() { Statement::Import(i) }
+            / f:font_face_rule() { Statement::FontFace(f) }
+            / p:page_rule() { Statement::Page(p) }
+            / m:media_rule() { Statement::Media(m) }
+            / r:css_rule() { Statement::Rule(r) }
+
+        pub rule import_rule() -> String
+            = "@import" __ url:(url_value() / function_value() / string_value()) __ ";" {?
+                match url {
+                    Value::Str(url) => Ok(url),
+                    Value::Function(name, arguments) if name == "url" => match arguments.as_slice() {
+                        [Value::Str(url)] => Ok(url.clone()),
+                        _ => Err("URL"),
+                    },
+                    _ => Err("URL"),
+                }
+            }
+
+        pub rule font_face_rule() -> FontFace
+            = "@font-face" __ d:declaration_block() { FontFace { descriptors: d } }
+
+        pub rule page_rule() -> PageRule
+            = "@page" __ s:(":" s:identifier() { format!(":{}", s) })? __ d:declaration_block() {
+                PageRule { selector: s, declarations: d }
+            }
+
+        pub rule media_rule() -> Vec<Rule>
+            = "@media" __ queries:(media_query() ** (__ "," __)) __ "{" __
+                rules:(css_rule() ** __) __ "}" {
+                let media = MediaList(queries);
+                rules.into_iter().map(|rule| Rule { media: media.clone(), ..rule }).collect()
+            }
+
+        rule media_query() -> String
+            = q:$((!['{' | ','] [_])+) { q.trim().to_owned() }
 
         pub rule css_rule() -> Rule
             = s:selectors() __ d:declaration_block() {
                 Rule {
                     selectors: s,
                     declarations: d,
+                    origin: Origin::Author,
+                    media: MediaList::default(),
                 }
             }
 
+        pub rule single_rule() -> Rule
+            = __ r:css_rule() { r }
+
+        pub rule single_declaration() -> Declaration
+            = __ d:declaration() __ ";"? __ { d }
+
+        pub rule single_selector() -> Selector
+            = __ s:simple_selector() __ { s }
+
+        pub rule single_value() -> Value
+            = __ v:value() __ { v }
+
+        pub rule single_color() -> Color
+            = __ c:color() __ { c }
+
         pub rule selectors() -> Vec<Selector>
             = selectors:(simple_selector() ++ selector_delimiter()) {
                 let mut ordered_selectors = selectors as Vec<Selector>;
@@ -266,37 +845,10 @@ peg::parser! {
                 class_selector() /
                 attribute_selector() /
                 tag_selector() /
-                universal_selector()
+                universal_selector() /
+                pseudo_element_selector()
             )+ {?
-                let mut ids = vec![];
-                let mut classes = vec![];
-                let mut attributes = vec![];
-                let mut tags = vec![];
-
-                for c in components {
-                    match c {
-                        SelectorComponent::Id(s) => ids.push(s),
-                        SelectorComponent::Class(s) => classes.push(s),
-                        SelectorComponent::Attribute(n, o, v) => attributes.push((n, o, v)),
-                        SelectorComponent::Tag(s) => tags.push(s),
-                        SelectorComponent::Universal => (),
-                    }
-                }
-
-                if ids.len() > 1 {
-                    return Err("a maximum of one id");
-                }
-
-                if tags.len() > 1 {
-                    return Err("a maximum of one tag");
-                }
-
-                Ok(Selector {
-                    tag: if tags.len() == 0 { None } else { Some(tags[0].clone()) },
-                    class: classes,
-                    id: if ids.len() == 0 { None } else { Some(ids[0].clone()) },
-                    attr: attributes,
-                })
+                Selector::from_components(components).map_err(BuildError::expected)
             }
 
         rule id_selector() -> SelectorComponent
@@ -307,6 +859,7 @@ peg::parser! {
 
         rule attribute_selector() -> SelectorComponent
             = "[" n:identifier() o:operator() v:identifier() "]" { SelectorComponent::Attribute(n, o, v) }
+            / "[" n:identifier() "]" { SelectorComponent::Attribute(n, AttrOp::Exists, String::new()) }
 
         pub rule operator() -> AttrOp
             = "=" { AttrOp::Eq }
@@ -317,36 +870,101 @@ peg::parser! {
         rule universal_selector() -> SelectorComponent
             = "*" { SelectorComponent::Universal }
 
-        pub rule declaration_block() -> Vec<Declaration>
-            = __ "{" __ d:(declaration() ** decl_delimiter()) decl_delimiter()? __ "}" __ { d }
+        rule pseudo_element_selector() -> SelectorComponent
+            = "::" s:identifier() { SelectorComponent::PseudoElement(s) }
+
+        pub rule declaration_block() -> Box<[Declaration]>
+            = __ "{" __ d:(declaration() ** decl_delimiter()) decl_delimiter()? __ "}" __ {
+                d.into_boxed_slice()
+            }
 
         pub rule decl_delimiter()
             = __ ";" __
 
         pub rule declaration() -> Declaration
             = n:identifier() __ ":" __ v:value() {
-                Declaration { name: n, value: v }
+                Declaration { name: Atom::from(n), value: v }
             }
 
         pub rule value() -> Value
+            = v:(space_separated_value() ++ (__ "," __)) {
+                if v.len() == 1 {
+                    v.into_iter().next().unwrap()
+                } else {
+                    Value::CommaList(v)
+                }
+            }
+
+        rule space_separated_value() -> Value
+            = v:(component_value() ++ (whitespace()+)) {
+                if v.len() == 1 {
+                    v.into_iter().next().unwrap()
+                } else {
+                    Value::List(v)
+                }
+            }
+
+        pub rule component_value() -> Value
             = color_value()
             / length_value()
+            / number_value()
+            / url_value()
+            / function_value()
             / keyword_value()
+            / string_value()
+            / "/" { Value::Keyword("/".to_owned()) }
+
+        pub rule function_value() -> Value
+            = name:identifier() "(" __ arguments:(component_value() ** (__ "," __)) __ ")" {
+                Value::Function(name, arguments)
+            }
+
+        // A URL may be unquoted. It is parsed as a `url` function with the URL as a string.
+        pub rule url_value() -> Value
+            = "url(" __ s:$([^'"' | '\'' | ')' | ' ']+) __ ")" {
+                Value::Function("url".to_owned(), vec![Value::Str(s.to_owned())])
+            }
+
+        pub rule string_value() -> Value
+            = "\"" s:$([^'"']*) "\"" { Value::Str(s.to_owned()) }
+            / "'" s:$([^'\'']*) "'" { Value::Str(s.to_owned()) }
 
         pub rule keyword_value() -> Value
             = s:identifier() { Value::Keyword(s.to_owned()) }
 
+        // Absolute lengths are a fixed number of pixels, so they are converted when they are
+        // parsed. https://www.w3.org/TR/css-values-3/#absolute-lengths
         pub rule length_value() -> Value
-            = "0" "px"? { Value::Length(0.0, Unit::Px) }
-            / n:f32_value() "px" { Value::Length(n, Unit::Px) }
+            = n:f32_value() u:unit() { Value::Length(n, u) }
+            / "0" !['0'..='9' | '.'] { Value::Length(0.0, Unit::Px) }
+
+        pub rule unit() -> Unit
+            = "px" { Unit::Px }
+            / "%" { Unit::Percent }
+            / "fr" { Unit::Fr }
+            / "vw" { Unit::Vw }
+            / "vh" { Unit::Vh }
+            / "vmin" { Unit::Vmin }
+            / "vmax" { Unit::Vmax }
+            / "em" { Unit::Em }
+            / "rem" { Unit::Rem }
+            / "in" { Unit::In }
+            / "cm" { Unit::Cm }
+            / "mm" { Unit::Mm }
+            / "pt" { Unit::Pt }
+            / "pc" { Unit::Pc }
+
+        pub rule number_value() -> Value
+            = n:f32_value() { Value::Number(n) }
 
         pub rule color_value() -> Value
-            = v:(
-                color_rgb_value() /
-                color_rgba_value() /
-                color_hex_value_six() /
-                color_hex_value_three()
-            ) { Value::ColorValue(v) }
+            = c:color() { Value::ColorValue(c) }
+
+        rule color() -> Color
+            = color_rgb_value()
+            / color_rgba_value()
+            / color_hex_value_six()
+            / color_hex_value_three()
 
         pub rule color_rgb_value() -> Color
             = "rgb(" r:dec_value() "," g:dec_value() "," b:dec_value() ")" {
@@ -387,7 +1005,7 @@ peg::parser! {
             ) { f32::from_str(n).unwrap() }
 
         pub rule dec_value() -> u8
-            = n:$(['0'..='9']+) { u8::from_str_radix(n, 10).unwrap() }
+            = n:$(['0'..='9']+) { n.parse::<u8>().unwrap() }
 
         pub rule hex_value_one() -> u8
             = n:$(['0'..='9' | 'a'..='f' | 'A'..='F']) { u8::from_str_radix(n, 16).unwrap() }
@@ -426,7 +1044,13 @@ mod tests {
     fn test_selectors() {
         let actual = css_parser::selectors("a");
         let expected = Ok(vec![
-            Selector { tag: Some("a".to_owned()), id: None, class: vec![], attr: vec![] },
+            Selector {
+                tag: Some("a".to_owned()),
+                id: None,
+                class: vec![],
+                attr: vec![],
+                pseudo_element: None,
+            },
             // Selector { tag: Some("b".to_owned()), id: None, class: vec![], attr: vec![] },
         ]);
         assert_eq!(actual, expected);
@@ -447,12 +1071,18 @@ mod tests {
                 foo: bar;
                 baz: 42px;
             }
-            "
+            ",
         );
-        let expected = Ok(vec![
-            Declaration { name: "foo".to_owned(), value: Value::Keyword("bar".to_owned()) },
-            Declaration { name: "baz".to_owned(), value: Value::Length(42.0, Unit::Px) },
-        ]);
+        let expected = Ok(Box::from([
+            Declaration {
+                name: Atom::from("foo"),
+                value: Value::Keyword("bar".to_owned()),
+            },
+            Declaration {
+                name: Atom::from("baz"),
+                value: Value::Length(42.0, Unit::Px),
+            },
+        ]));
         assert_eq!(actual, expected);
     }
 
@@ -460,23 +1090,196 @@ mod tests {
     fn test_declaration() {
         let actual = css_parser::declaration("foo: bar");
         let expected = Ok(Declaration {
-            name: "foo".to_owned(),
-            value: Value::Keyword("bar".to_owned())
+            name: Atom::from("foo"),
+            value: Value::Keyword("bar".to_owned()),
         });
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_number_value() {
+        assert_eq!(css_parser::value("-2"), Ok(Value::Number(-2.0)));
+        assert_eq!(css_parser::value("0.5"), Ok(Value::Number(0.5)));
+        assert_eq!(css_parser::value("0"), Ok(Value::Length(0.0, Unit::Px)));
+    }
+
+    #[test]
+    fn test_function_value() {
+        assert_eq!(
+            css_parser::value("translate(10px, -5px) scale(2)"),
+            Ok(Value::List(vec![
+                Value::Function(
+                    "translate".to_owned(),
+                    vec![Value::Length(10.0, Unit::Px), Value::Length(-5.0, Unit::Px)]
+                ),
+                Value::Function("scale".to_owned(), vec![Value::Number(2.0)]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_string_value() {
+        assert_eq!(
+            css_parser::value("counter(section) \". \""),
+            Ok(Value::List(vec![
+                Value::Function(
+                    "counter".to_owned(),
+                    vec![Value::Keyword("section".to_owned())]
+                ),
+                Value::Str(". ".to_owned()),
+            ]))
+        );
+        assert_eq!(css_parser::value("'\"'"), Ok(Value::Str("\"".to_owned())));
+    }
+
+    #[test]
+    fn test_url_value() {
+        let url = Value::Function("url".to_owned(), vec![Value::Str("a.png".to_owned())]);
+        assert_eq!(css_parser::value("url(a.png)"), Ok(url.clone()));
+        assert_eq!(css_parser::value("url( \"a.png\" )"), Ok(url));
+    }
+
+    #[test]
+    fn test_list_value() {
+        assert_eq!(
+            css_parser::value("100px 25% 1fr auto"),
+            Ok(Value::List(vec![
+                Value::Length(100.0, Unit::Px),
+                Value::Length(25.0, Unit::Percent),
+                Value::Length(1.0, Unit::Fr),
+                Value::Keyword("auto".to_owned()),
+            ]))
+        );
+
+        assert_eq!(
+            css_parser::value("16 / 9"),
+            Ok(Value::List(vec![
+                Value::Number(16.0),
+                Value::Keyword("/".to_owned()),
+                Value::Number(9.0),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_comma_list_value() {
+        assert_eq!(
+            css_parser::value("\"DejaVu Sans\", Times New Roman, serif"),
+            Ok(Value::CommaList(vec![
+                Value::Str("DejaVu Sans".to_owned()),
+                Value::List(vec![
+                    Value::Keyword("Times".to_owned()),
+                    Value::Keyword("New".to_owned()),
+                    Value::Keyword("Roman".to_owned()),
+                ]),
+                Value::Keyword("serif".to_owned()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_font_face() {
+        let sheet = Sheet::from(
+            "
+            @font-face {
+                font-family: \"Open Sans\";
+                src: url(a.woff2) format(\"woff2\"), url(a.ttf);
+                font-weight: bold;
+            }
+            p { font-family: \"Open Sans\", sans-serif; }
+        ",
+        );
+
+        assert_eq!(sheet.rules.len(), 1);
+        assert_eq!(sheet.font_faces.len(), 1);
+        let font_face = &sheet.font_faces[0];
+        assert_eq!(
+            font_face.descriptor("font-family"),
+            Some(&Value::Str("Open Sans".to_owned()))
+        );
+        assert_eq!(font_face.sources(), vec!["a.woff2", "a.ttf"]);
+    }
+
+    #[test]
+    fn test_media_and_page() {
+        let sheet = Sheet::from(
+            "
+            @page { size: A4 landscape; margin: 1in; }
+            @page :first { margin: 0; }
+            @media only print, (min-width: 600px) { a { width: 1cm; } b { width: 2px; } }
+            c { width: 3px; }
+        ",
+        );
+
+        assert_eq!(sheet.pages.len(), 2);
+        assert_eq!(sheet.pages[1].selector.as_deref(), Some(":first"));
+        assert_eq!(
+            sheet.pages[0].declarations[1].value,
+            Value::Length(1.0, Unit::In)
+        );
+
+        let media: Vec<_> = sheet.rules.iter().map(|rule| &rule.media).collect();
+        assert_eq!(media[0].0, vec!["only print", "(min-width: 600px)"]);
+        assert!(media[1].matches(Medium::Print));
+        assert!(!media[1].matches(Medium::Screen));
+        assert!(media[2].matches(Medium::Screen));
+        assert!(MediaList(vec!["not print".to_owned()]).matches(Medium::Screen));
+
+        assert_eq!(
+            sheet.rules[1].to_string(),
+            "@media only print,(min-width: 600px){b{width:2px}}"
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let mut sheet = Sheet::from(
+            "
+            @import url(base.css);
+            @import \"../theme.css\";
+            @font-face { font-family: Web; src: url(fonts/web.ttf); }
+            p { background-image: url('bg.png'); }
+        ",
+        );
+        assert_eq!(sheet.imports, vec!["base.css", "../theme.css"]);
+        assert_eq!(sheet.urls(), vec!["bg.png"]);
+
+        sheet.resolve_urls(&Url::parse("http://example.com/style/main.css").unwrap());
+        assert_eq!(
+            sheet.imports,
+            vec![
+                "http://example.com/style/base.css",
+                "http://example.com/theme.css"
+            ]
+        );
+        assert_eq!(sheet.urls(), vec!["http://example.com/style/bg.png"]);
+        assert_eq!(
+            sheet.font_faces[0].sources(),
+            vec!["http://example.com/style/fonts/web.ttf"]
+        );
+    }
+
     #[test]
     fn test_color_rgb_value() {
         let actual = css_parser::color_value("rgb(1,2,3)");
-        let expected = Ok(Value::ColorValue(Color { r: 1, g: 2, b: 3, a: 255 }));
+        let expected = Ok(Value::ColorValue(Color {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 255,
+        }));
         assert_eq!(actual, expected);
     }
 
     #[test]
     fn test_color_rgba_value() {
         let actual = css_parser::color_value("rgba(1,2,3,4)");
-        let expected = Ok(Value::ColorValue(Color { r: 1, g: 2, b: 3, a: 4 }));
+        let expected = Ok(Value::ColorValue(Color {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 4,
+        }));
         assert_eq!(actual, expected);
     }
 
@@ -497,14 +1300,24 @@ mod tests {
     #[test]
     fn test_color_hex_value_three() {
         let actual = css_parser::color_value("#abc");
-        let expected = Ok(Value::ColorValue(Color { r: 170, g: 187, b: 204, a: 255 }));
+        let expected = Ok(Value::ColorValue(Color {
+            r: 170,
+            g: 187,
+            b: 204,
+            a: 255,
+        }));
         assert_eq!(actual, expected);
     }
 
     #[test]
     fn test_color_hex_value_six() {
         let actual = css_parser::color_value("#abcdef");
-        let expected = Ok(Value::ColorValue(Color { r: 171, g: 205, b: 239, a: 255 }));
+        let expected = Ok(Value::ColorValue(Color {
+            r: 171,
+            g: 205,
+            b: 239,
+            a: 255,
+        }));
         assert_eq!(actual, expected);
     }
 
@@ -518,11 +1331,57 @@ mod tests {
                         .add_attr("class", AttrOp::Eq, "foo"),
                 )
                 .add_selector(selector().add_tag("p"))
+                .add_selector(selector().add_tag("p").add_pseudo_element("before"))
                 .add_declaration("margin", Value::Keyword("auto".to_owned()))
-                .add_declaration("width", Value::Length(24.0, Unit::Px)),
+                .add_declaration("width", Value::Length(24.0, Unit::Px))
+                .build()
+                .unwrap(),
         );
-        let expected = r#"body[class="foo"],p{margin:auto;width:24px}"#;
-        assert_eq!(String::from(&actual), expected);
+        let expected = r#"body[class="foo"],p,p::before{margin:auto;width:24px}"#;
+        assert_eq!(actual.to_string(), expected);
+    }
+
+    #[test]
+    fn test_builders() {
+        let actual = selector().add_id("a").add_class("b").add_id("c").build();
+        assert_eq!(actual, Err(BuildError::TooManyIds));
+        let actual = selector().add_tag("p").add_tag("li").build();
+        assert_eq!(actual, Err(BuildError::TooManyTags));
+        let actual = rule().add_declaration("margin", Value::Length(0.0, Unit::Px));
+        assert_eq!(actual.build(), Err(BuildError::NoSelectors));
+        let actual = rule().add_selector(selector().add_tag("p").add_tag("li"));
+        assert_eq!(actual.build(), Err(BuildError::TooManyTags));
+
+        let actual = rule()
+            .add_selector(selector().add_tag("p").add_class("note"))
+            .add_declaration("margin", Value::Keyword("auto".to_owned()))
+            .build()
+            .unwrap();
+        assert_eq!(actual, Rule::parse(" p.note { margin: auto; } ").unwrap());
+        assert!(Rule::parse("#a#c { margin: auto }").is_err());
+
+        let actual = Declaration::parse(" width: 24px; ").unwrap();
+        assert_eq!(actual.to_string(), "width:24px");
+        assert!(Declaration::parse("width: 24px; height: 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let selector: Selector = " p.note[lang=nl]::before ".parse().unwrap();
+        assert_eq!(selector.to_string(), "p.note[lang=\"nl\"]::before");
+        assert!("p li".parse::<Selector>().is_err());
+
+        let value: Value = "0 auto, url(a.png)".parse().unwrap();
+        assert_eq!(format!("{}", value), "0px auto,url(\"a.png\")");
+        assert_eq!(value.to_string().parse(), Ok(value));
+
+        let color: Color = "#ff8000".parse().unwrap();
+        assert_eq!(color.to_string(), "rgba(255,128,0,255)");
+        assert_eq!(color.to_string().parse(), Ok(color));
+        assert!("red".parse::<Color>().is_err());
+
+        let sheet: Sheet = "@import 'a.css'; p { margin: 0 }".parse().unwrap();
+        assert_eq!(sheet.to_string().parse(), Ok(sheet));
     }
 
     #[test]
@@ -546,10 +1405,10 @@ mod tests {
         ",
         );
 
-        assert_eq!(css.0[0].selectors[0].tag, Some("a".to_owned()));
-        assert_eq!(css.0[0].selectors[1].tag, Some("b".to_owned()));
-        assert_eq!(css.0[0].declarations[0].name, "display".to_owned());
+        assert_eq!(css.rules[0].selectors[0].tag, Some("a".to_owned()));
+        assert_eq!(css.rules[0].selectors[1].tag, Some("b".to_owned()));
+        assert_eq!(css.rules[0].declarations[0].name, "display".to_owned());
 
-        assert_eq!(css.0[1].selectors[0].tag, Some("c".to_owned()));
+        assert_eq!(css.rules[1].selectors[0].tag, Some("c".to_owned()));
     }
 }
@@ -0,0 +1,292 @@
+//! Conversions and arithmetic for colors, for backends and for embedders that configure colors
+//! with CSS strings.
+
+use crate::css::Color;
+
+impl Color {
+    /// A color as it is written in CSS: a named color such as `rebeccapurple` or `transparent`,
+    /// hexadecimal notation, or `rgb()` and `rgba()`. Named colors are case-insensitive.
+    ///
+    /// https://www.w3.org/TR/css-color-4/#named-colors
+    pub fn from_css_str(css: &str) -> Option<Color> {
+        let css = css.trim();
+        if css.eq_ignore_ascii_case("transparent") {
+            return Some(Color::default());
+        }
+        let named = NAMED_COLORS
+            .iter()
+            .find(|(name, _)| css.eq_ignore_ascii_case(name));
+        match named {
+            Some(&(_, rgb)) => Some(Color::from_u32(0xff000000 | rgb)),
+            None => css.parse().ok(),
+        }
+    }
+
+    /// The color in hexadecimal notation, as `#rrggbb`, or as `#rrggbbaa` if it is translucent.
+    pub fn to_hex_string(&self) -> String {
+        match self.a {
+            255 => format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b),
+            _ => format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a),
+        }
+    }
+
+    /// The color packed as `0xAARRGGBB`, optionally with the color channels multiplied by the
+    /// alpha, as many pixel buffers expect.
+    pub fn to_u32(&self, premultiplied: bool) -> u32 {
+        let channel = |c: u8| match premultiplied {
+            true => (c as u32 * self.a as u32 + 127) / 255,
+            false => c as u32,
+        };
+        (self.a as u32) << 24 | channel(self.r) << 16 | channel(self.g) << 8 | channel(self.b)
+    }
+
+    fn from_u32(argb: u32) -> Color {
+        let [a, r, g, b] = argb.to_be_bytes();
+        Color { r, g, b, a }
+    }
+
+    /// The color mixed with another: `0` gives this color, and `1` gives the other one.
+    pub fn mix(&self, other: &Color, amount: f32) -> Color {
+        let amount = amount.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+        Color {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: mix(self.a, other.a),
+        }
+    }
+
+    /// The color mixed with white, keeping its alpha.
+    pub fn lighten(&self, amount: f32) -> Color {
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: self.a,
+        };
+        self.mix(&white, amount)
+    }
+
+    /// The color mixed with black, keeping its alpha.
+    pub fn darken(&self, amount: f32) -> Color {
+        let black = Color {
+            a: self.a,
+            ..Color::default()
+        };
+        self.mix(&black, amount)
+    }
+
+    /// The color composited over another with source-over, as painting does.
+    ///
+    /// https://www.w3.org/TR/compositing-1/#porterduffcompositingoperators_srcover
+    pub fn over(&self, below: &Color) -> Color {
+        self.over_with_coverage(below, 1.0)
+    }
+
+    /// The color composited over another, with its alpha multiplied by how much of a pixel a
+    /// shape covers.
+    pub(crate) fn over_with_coverage(&self, below: &Color, coverage: f32) -> Color {
+        let alpha = coverage.clamp(0.0, 1.0) * self.a as f32 / 255.0;
+        let below_alpha = below.a as f32 / 255.0 * (1.0 - alpha);
+        let total = alpha + below_alpha;
+        if total <= 0.0 {
+            return below.clone();
+        }
+        let mix = |below_channel: u8, above_channel: u8| {
+            let channel =
+                (above_channel as f32 * alpha + below_channel as f32 * below_alpha) / total;
+            channel.round().clamp(0.0, 255.0) as u8
+        };
+
+        Color {
+            r: mix(below.r, self.r),
+            g: mix(below.g, self.g),
+            b: mix(below.b, self.b),
+            a: (total * 255.0).round().clamp(0.0, 255.0) as u8,
+        }
+    }
+}
+
+/// The named colors of CSS, as `0xRRGGBB`.
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+
+    #[test]
+    fn test_color() {
+        let purple = Color::from_css_str("RebeccaPurple").unwrap();
+        assert_eq!(purple.to_hex_string(), "#663399");
+        assert_eq!(Color::from_css_str(" #abc "), "#aabbcc".parse().ok());
+        assert_eq!(
+            Color::from_css_str("rgba(0,0,0,128)")
+                .unwrap()
+                .to_hex_string(),
+            "#00000080"
+        );
+        assert_eq!(Color::from_css_str("transparent"), Some(Color::default()));
+        assert_eq!(Color::from_css_str("reddish"), None);
+
+        let half_red = Color::from_css_str("rgba(255,0,0,128)").unwrap();
+        assert_eq!(half_red.to_u32(false), 0x80ff0000);
+        assert_eq!(half_red.to_u32(true), 0x80800000);
+
+        let white = Color::from_css_str("white").unwrap();
+        let black = Color::from_css_str("black").unwrap();
+        assert_eq!(black.lighten(0.5).to_hex_string(), "#808080");
+        assert_eq!(white.darken(0.25), white.mix(&black, 0.25));
+        assert_eq!(half_red.over(&white).to_hex_string(), "#ff7f7f");
+        assert_eq!(half_red.over(&Color::default()), half_red);
+    }
+}
@@ -0,0 +1,435 @@
+//! Parsing stylesheets with cssparser and selectors, the parsers of Servo, into the model of
+//! boxrs. They follow the CSS syntax to the letter: a rule or a declaration that isn't valid is
+//! left out, instead of failing the whole stylesheet.
+//!
+//! https://www.w3.org/TR/css-syntax-3/#error-handling
+
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::fmt;
+
+use cssparser::{
+    AtRuleParser, CowRcStr, DeclarationParser, ParseError, Parser, ParserInput, ParserState,
+    QualifiedRuleParser, RuleBodyItemParser, RuleBodyParser, StyleSheetParser, ToCss, Token,
+};
+use selectors::attr::AttrSelectorOperator;
+use selectors::parser::{
+    Combinator, Component, ParseRelative, SelectorList, SelectorParseErrorKind,
+};
+
+use super::{
+    css_parser, selector, sheet, AttrOp, Declaration, FontFace, MediaList, Origin, PageRule, Rule,
+    Selector, Sheet, Statement, Value,
+};
+
+type Error<'i> = ParseError<'i, SelectorParseErrorKind<'i>>;
+
+impl Sheet {
+    /// Parse a stylesheet with cssparser and selectors. Rules with selectors that boxrs doesn't
+    /// support, like those with combinators or pseudo-classes, are left out, and so are
+    /// declarations with values that it doesn't support.
+    pub fn parse_with_cssparser(css: &str) -> Sheet {
+        let mut input = ParserInput::new(css);
+        let mut input = Parser::new(&mut input);
+        let mut sheet = sheet();
+        for statement in StyleSheetParser::new(&mut input, &mut RuleParser).flatten() {
+            sheet.add_statement(statement);
+        }
+        sheet
+    }
+}
+
+/// Parses the rules at the top level of a stylesheet, and those in `@media` rules.
+struct RuleParser;
+
+enum AtRulePrelude {
+    Import(String),
+    FontFace,
+    Page(Option<String>),
+    Media(MediaList),
+}
+
+impl<'i> QualifiedRuleParser<'i> for RuleParser {
+    type Prelude = Vec<Selector>;
+    type QualifiedRule = Statement;
+    type Error = SelectorParseErrorKind<'i>;
+
+    fn parse_prelude<'t>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Vec<Selector>, Error<'i>> {
+        let list = SelectorList::parse(&SelectorParser, input, ParseRelative::No)?;
+        let mut selectors: Vec<_> = list.0.iter().filter_map(convert_selector).collect();
+        if selectors.is_empty() {
+            return Err(input.new_custom_error(SelectorParseErrorKind::EmptySelector));
+        }
+        // Like the grammar of boxrs, the most specific selector comes first.
+        selectors.sort_by_key(|selector| Reverse(selector.get_specificity()));
+        Ok(selectors)
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        selectors: Vec<Selector>,
+        _: &ParserState,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Statement, Error<'i>> {
+        Ok(Statement::Rule(Rule {
+            selectors,
+            declarations: parse_declarations(input),
+            origin: Origin::Author,
+            media: MediaList::default(),
+        }))
+    }
+}
+
+impl<'i> AtRuleParser<'i> for RuleParser {
+    type Prelude = AtRulePrelude;
+    type AtRule = Statement;
+    type Error = SelectorParseErrorKind<'i>;
+
+    fn parse_prelude<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<AtRulePrelude, Error<'i>> {
+        match &*name {
+            "import" => Ok(AtRulePrelude::Import(
+                input.expect_url_or_string()?.to_string(),
+            )),
+            "font-face" => Ok(AtRulePrelude::FontFace),
+            "page" => {
+                let selector = input.try_parse(|input| -> Result<_, Error<'i>> {
+                    input.expect_colon()?;
+                    Ok(format!(":{}", input.expect_ident()?))
+                });
+                Ok(AtRulePrelude::Page(selector.ok()))
+            }
+            "media" => {
+                let start = input.position();
+                while input.next().is_ok() {}
+                let queries = input.slice_from(start).split(',').map(str::trim);
+                let queries = queries.filter(|query| !query.is_empty());
+                Ok(AtRulePrelude::Media(MediaList(
+                    queries.map(str::to_owned).collect(),
+                )))
+            }
+            _ => Err(input.new_custom_error(SelectorParseErrorKind::UnexpectedIdent(name))),
+        }
+    }
+
+    fn rule_without_block(
+        &mut self,
+        prelude: AtRulePrelude,
+        _: &ParserState,
+    ) -> Result<Statement, ()> {
+        match prelude {
+            AtRulePrelude::Import(url) => Ok(Statement::Import(url)),
+            _ => Err(()),
+        }
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        prelude: AtRulePrelude,
+        _: &ParserState,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Statement, Error<'i>> {
+        match prelude {
+            AtRulePrelude::Import(_) => Err(input.new_error_for_next_token()),
+            AtRulePrelude::FontFace => Ok(Statement::FontFace(FontFace {
+                descriptors: parse_declarations(input),
+            })),
+            AtRulePrelude::Page(selector) => Ok(Statement::Page(PageRule {
+                selector,
+                declarations: parse_declarations(input),
+            })),
+            AtRulePrelude::Media(media) => {
+                let statements = StyleSheetParser::new(input, self).flatten();
+                let rules = statements.filter_map(|statement| match statement {
+                    Statement::Rule(rule) => Some(Rule {
+                        media: media.clone(),
+                        ..rule
+                    }),
+                    _ => None,
+                });
+                Ok(Statement::Media(rules.collect()))
+            }
+        }
+    }
+}
+
+/// The declarations of a block, without those that aren't valid.
+fn parse_declarations(input: &mut Parser) -> Box<[Declaration]> {
+    RuleBodyParser::new(input, &mut DeclarationListParser)
+        .flatten()
+        .collect()
+}
+
+/// Parses the declarations in the block of a rule.
+struct DeclarationListParser;
+
+impl<'i> DeclarationParser<'i> for DeclarationListParser {
+    type Declaration = Declaration;
+    type Error = SelectorParseErrorKind<'i>;
+
+    fn parse_value<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Declaration, Error<'i>> {
+        Ok(Declaration {
+            name: (&*name).into(),
+            value: parse_value(input)?,
+        })
+    }
+}
+
+impl<'i> AtRuleParser<'i> for DeclarationListParser {
+    type Prelude = ();
+    type AtRule = Declaration;
+    type Error = SelectorParseErrorKind<'i>;
+}
+
+impl<'i> QualifiedRuleParser<'i> for DeclarationListParser {
+    type Prelude = ();
+    type QualifiedRule = Declaration;
+    type Error = SelectorParseErrorKind<'i>;
+}
+
+impl<'i> RuleBodyItemParser<'i, Declaration, SelectorParseErrorKind<'i>> for DeclarationListParser {
+    fn parse_declarations(&self) -> bool {
+        true
+    }
+
+    fn parse_qualified(&self) -> bool {
+        false
+    }
+}
+
+/// A value of a declaration: a comma-separated list of space-separated lists, where each list
+/// with a single value is that value.
+fn parse_value<'i>(input: &mut Parser<'i, '_>) -> Result<Value, Error<'i>> {
+    let mut values = input.parse_comma_separated(|input| {
+        let mut values = vec![parse_single_value(input)?];
+        while !input.is_exhausted() {
+            values.push(parse_single_value(input)?);
+        }
+        Ok(match values.len() {
+            1 => values.pop().unwrap(),
+            _ => Value::List(values),
+        })
+    })?;
+    Ok(match values.len() {
+        1 => values.pop().unwrap(),
+        _ => Value::CommaList(values),
+    })
+}
+
+fn parse_single_value<'i>(input: &mut Parser<'i, '_>) -> Result<Value, Error<'i>> {
+    let location = input.current_source_location();
+    let token = input.next()?.clone();
+    // Colors and lengths are converted by the grammar of boxrs, so that both parsers give them
+    // the same values.
+    let value = match &token {
+        Token::Ident(keyword) => Some(Value::Keyword(keyword.to_string())),
+        Token::QuotedString(string) => Some(Value::Str(string.to_string())),
+        Token::UnquotedUrl(url) => Some(Value::Function(
+            "url".to_owned(),
+            vec![Value::Str(url.to_string())],
+        )),
+        Token::Number {
+            int_value: Some(0),
+            has_sign: false,
+            ..
+        } => css_parser::length_value("0").ok(),
+        Token::Number { value, .. } => Some(Value::Number(*value)),
+        Token::Percentage { .. } | Token::Dimension { .. } => {
+            css_parser::length_value(&token.to_css_string()).ok()
+        }
+        Token::Hash(hex) | Token::IDHash(hex) => css_parser::color_value(&format!("#{hex}")).ok(),
+        Token::Function(name) => {
+            let arguments = input.parse_nested_block(parse_arguments)?;
+            match &**name {
+                "rgb" | "rgba" => parse_color(name, &arguments),
+                _ => Some(Value::Function(name.to_string(), arguments)),
+            }
+        }
+        Token::Delim('/') => Some(Value::Keyword("/".to_owned())),
+        _ => None,
+    };
+    value.ok_or_else(|| location.new_unexpected_token_error(token))
+}
+
+/// The comma-separated arguments of a function.
+fn parse_arguments<'i>(input: &mut Parser<'i, '_>) -> Result<Vec<Value>, Error<'i>> {
+    match input.is_exhausted() {
+        true => Ok(Vec::new()),
+        false => input.parse_comma_separated(parse_single_value),
+    }
+}
+
+/// The color of an `rgb()` or `rgba()` function, whose arguments are integers.
+fn parse_color(name: &str, arguments: &[Value]) -> Option<Value> {
+    let numbers = arguments.iter().map(|argument| match argument {
+        Value::Number(n) => Some(n.to_string()),
+        Value::Length(n, _) if *n == 0.0 => Some("0".to_owned()),
+        _ => None,
+    });
+    let numbers = numbers.collect::<Option<Vec<_>>>()?;
+    css_parser::color_value(&format!("{}({})", name, numbers.join(","))).ok()
+}
+
+/// A selector of boxrs, or `None` if it has parts that boxrs doesn't support or that its parser
+/// would reject. The parts of a selector come from right to left, and a pseudo-element comes
+/// before its element.
+fn convert_selector(parsed: &selectors::parser::Selector<SelectorImpl>) -> Option<Selector> {
+    let mut selector = selector();
+    let mut components = parsed.iter();
+    loop {
+        for component in components.by_ref() {
+            selector = match component {
+                Component::LocalName(name) => selector.add_tag(&name.name.0),
+                Component::ID(id) => selector.add_id(&id.0),
+                Component::Class(class) => selector.add_class(&class.0),
+                Component::AttributeInNoNamespaceExists { local_name, .. } => {
+                    selector.add_attr(&local_name.0, AttrOp::Exists, "")
+                }
+                Component::AttributeInNoNamespace {
+                    local_name,
+                    operator: AttrSelectorOperator::Equal,
+                    value,
+                    ..
+                } => selector.add_attr(&local_name.0, AttrOp::Eq, &value.0),
+                Component::PseudoElement(name) => selector.add_pseudo_element(&name.0),
+                Component::ExplicitUniversalType | Component::ExplicitAnyNamespace => selector,
+                _ => return None,
+            }
+        }
+        match components.next_sequence() {
+            None => return selector.build().ok(),
+            Some(Combinator::PseudoElement) => {}
+            Some(_) => return None,
+        }
+    }
+}
+
+/// The types that selectors are parsed into. Names and values are kept as they are written, and
+/// pseudo-classes aren't supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SelectorImpl;
+
+impl selectors::SelectorImpl for SelectorImpl {
+    type ExtraMatchingData<'a> = ();
+    type AttrValue = CssString;
+    type Identifier = CssString;
+    type LocalName = CssString;
+    type NamespaceUrl = CssString;
+    type NamespacePrefix = CssString;
+    type BorrowedNamespaceUrl = str;
+    type BorrowedLocalName = str;
+    type NonTSPseudoClass = PseudoClass;
+    type PseudoElement = PseudoElement;
+}
+
+struct SelectorParser;
+
+impl<'i> selectors::Parser<'i> for SelectorParser {
+    type Impl = SelectorImpl;
+    type Error = SelectorParseErrorKind<'i>;
+
+    fn parse_pseudo_element(
+        &self,
+        _: cssparser::SourceLocation,
+        name: CowRcStr<'i>,
+    ) -> Result<PseudoElement, Error<'i>> {
+        Ok(PseudoElement(name.to_string()))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct CssString(String);
+
+impl From<&str> for CssString {
+    fn from(string: &str) -> CssString {
+        CssString(string.to_owned())
+    }
+}
+
+impl Borrow<str> for CssString {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToCss for CssString {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        cssparser::serialize_identifier(&self.0, dest)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PseudoElement(String);
+
+impl ToCss for PseudoElement {
+    fn to_css<W: fmt::Write>(&self, dest: &mut W) -> fmt::Result {
+        dest.write_str("::")?;
+        cssparser::serialize_identifier(&self.0, dest)
+    }
+}
+
+impl selectors::parser::PseudoElement for PseudoElement {
+    type Impl = SelectorImpl;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PseudoClass {}
+
+impl ToCss for PseudoClass {
+    fn to_css<W: fmt::Write>(&self, _: &mut W) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl selectors::parser::NonTSPseudoClass for PseudoClass {
+    type Impl = SelectorImpl;
+
+    fn is_active_or_hover(&self) -> bool {
+        match *self {}
+    }
+
+    fn is_user_action_state(&self) -> bool {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+
+    #[test]
+    fn test_parse_with_cssparser() {
+        let css = "
+            @import \"base.css\";
+            h1, p.intro::before { margin: 0 auto; color: #ff0000; content: \"- \"; }
+            div > p, a:hover { color: rgb(0, 0, 255); }
+            @media print { [lang=nl] { width: 50%; font-family: Georgia, serif; } }
+            @page :first { margin: 1in; }
+            p { width: 10px; height: 10ex; transform: translate(1px, 2px); }
+        ";
+        let sheet = Sheet::parse_with_cssparser(css);
+        let expected = Sheet::from(
+            "
+            @import \"base.css\";
+            h1, p.intro::before { margin: 0 auto; color: #ff0000; content: \"- \"; }
+            @media print { [lang=nl] { width: 50%; font-family: Georgia, serif; } }
+            @page :first { margin: 1in; }
+            p { width: 10px; transform: translate(1px, 2px); }
+        ",
+        );
+
+        // The rules with combinators or pseudo-classes, and the declarations with lengths in
+        // unsupported units, are left out.
+        assert_eq!(sheet, expected);
+    }
+}
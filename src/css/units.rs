@@ -0,0 +1,92 @@
+//! Lengths in the units of CSS turned into px, given what relative units are relative to.
+//!
+//! https://www.w3.org/TR/css-values-3/#lengths
+
+use crate::css::{Unit, Value};
+
+/// What lengths in relative and physical units are resolved against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitContext {
+    /// The number of px in an inch, for `in`, `cm`, `mm`, `pt` and `pc`. CSS fixes it at 96, but
+    /// a backend may calibrate it to the physical size of a screen or a printer.
+    pub dpi: f32,
+    /// The font size of the root element in px, for `rem`.
+    pub root_font_size: f32,
+    /// The font size of the element in px, for `em`.
+    pub font_size: f32,
+    /// The width and height of the viewport in px, for `vw`, `vh`, `vmin` and `vmax`.
+    pub viewport: (f32, f32),
+}
+
+impl Default for UnitContext {
+    fn default() -> Self {
+        UnitContext {
+            dpi: 96.0,
+            root_font_size: 16.0,
+            font_size: 16.0,
+            viewport: (0.0, 0.0),
+        }
+    }
+}
+
+impl Unit {
+    /// The number of px in one of this unit, or `None` for percentages and fractions, which are
+    /// relative to sizes that only layout knows.
+    pub fn in_px(&self, context: &UnitContext) -> Option<f32> {
+        let (width, height) = context.viewport;
+        match self {
+            Unit::Px => Some(1.0),
+            Unit::Em => Some(context.font_size),
+            Unit::Rem => Some(context.root_font_size),
+            Unit::Vw => Some(width / 100.0),
+            Unit::Vh => Some(height / 100.0),
+            Unit::Vmin => Some(width.min(height) / 100.0),
+            Unit::Vmax => Some(width.max(height) / 100.0),
+            Unit::In => Some(context.dpi),
+            Unit::Cm => Some(context.dpi / 2.54),
+            Unit::Mm => Some(context.dpi / 25.4),
+            Unit::Pt => Some(context.dpi / 72.0),
+            Unit::Pc => Some(context.dpi / 6.0),
+            Unit::Percent | Unit::Fr => None,
+        }
+    }
+}
+
+impl Value {
+    /// The length in px, or 0 for values that aren't lengths, and for percentages and fractions.
+    pub fn resolve(&self, context: &UnitContext) -> f32 {
+        match self {
+            Value::Length(length, unit) => unit.in_px(context).map_or(0.0, |px| length * px),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::units::*;
+
+    #[test]
+    fn test_resolve() {
+        let context = UnitContext {
+            font_size: 20.0,
+            viewport: (800.0, 600.0),
+            ..Default::default()
+        };
+        let resolve = |css: &str| css.parse::<Value>().unwrap().resolve(&context);
+        assert_eq!(resolve("12px"), 12.0);
+        assert_eq!(resolve("1.5em"), 30.0);
+        assert_eq!(resolve("2rem"), 32.0);
+        assert_eq!(resolve("10vmin"), 60.0);
+        assert_eq!(resolve("1in"), 96.0);
+        assert_eq!(resolve("72pt"), 96.0);
+        assert_eq!(resolve("50%"), 0.0);
+        assert_eq!(resolve("auto"), 0.0);
+
+        let print = UnitContext {
+            dpi: 300.0,
+            ..context
+        };
+        assert_eq!(Value::Length(2.54, Unit::Cm).resolve(&print), 300.0);
+    }
+}
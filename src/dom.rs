@@ -1,12 +1,24 @@
 use std::collections::HashSet;
+use std::fmt;
+
+use smallvec::SmallVec;
 
 use crate::html::Parser;
 
-#[derive(Debug)]
+/// An interned string. The names of elements, attributes and properties repeat throughout
+/// documents and stylesheets, so each is stored once and compared by pointer.
+pub type Atom = string_cache::DefaultAtom;
+
+/// The attributes of an element, in order. Most elements have at most one, which is stored
+/// inline.
+pub type Attributes = SmallVec<[(Atom, String); 1]>;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Node {
     Element {
-        tag: String,
-        attrs: Vec<(String, String)>,
+        tag: Atom,
+        attrs: Attributes,
         children: Vec<Node>,
     },
     Text(String),
@@ -15,8 +27,8 @@ pub enum Node {
 impl Node {
     pub fn elem(tag: &str) -> Self {
         Node::Element {
-            tag: tag.to_owned(),
-            attrs: vec![],
+            tag: Atom::from(tag),
+            attrs: Attributes::new(),
             children: vec![],
         }
     }
@@ -53,27 +65,30 @@ impl Node {
 
     pub fn add_attr(mut self, key: &str, value: &str) -> Self {
         if let Node::Element { ref mut attrs, .. } = self {
-            attrs.push((key.to_owned(), value.to_owned()));
+            attrs.push((Atom::from(key), value.to_owned()));
         }
         self
     }
 
     pub fn add_attrs(mut self, kvs: Vec<(String, String)>) -> Self {
         if let Node::Element { ref mut attrs, .. } = self {
-            for item in kvs {
-                attrs.push(item)
+            for (key, value) in kvs {
+                attrs.push((Atom::from(key), value))
             }
         }
         self
     }
 
+    /// Replace the children of an element with the nodes of an HTML fragment.
+    ///
+    /// Panics if the fragment isn't valid.
     pub fn inner_html(mut self, html: &str) -> Self {
         if let Node::Element {
             ref mut children, ..
         } = self
         {
             children.clear();
-            children.append(&mut Parser::parse_no_root(html.to_owned()));
+            children.append(&mut Parser::parse_no_root(html.to_owned()).unwrap());
         }
         self
     }
@@ -81,7 +96,7 @@ impl Node {
     pub fn get_id(&self) -> Option<&str> {
         if let Node::Element { ref attrs, .. } = self {
             for attr in attrs {
-                if attr.0 == "id" {
+                if &*attr.0 == "id" {
                     return Some(&attr.1);
                 }
             }
@@ -90,10 +105,20 @@ impl Node {
         None
     }
 
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        match self {
+            Node::Element { ref attrs, .. } => attrs
+                .iter()
+                .find(|attr| &*attr.0 == name)
+                .map(|attr| &*attr.1),
+            Node::Text(_) => None,
+        }
+    }
+
     pub fn get_classes(&self) -> HashSet<&str> {
         if let Node::Element { ref attrs, .. } = self {
             for attr in attrs {
-                if attr.0 == "class" {
+                if &*attr.0 == "class" {
                     return attr.1.split(' ').collect();
                 }
             }
@@ -104,9 +129,9 @@ impl Node {
 
     pub fn get_text_content(&self) -> String {
         match self {
-            Node::Element { ref children, .. } => {
+            Node::Element { .. } => {
                 let mut content = "".to_owned();
-                for c in children {
+                for c in self.document_children() {
                     content.push_str(&c.get_text_content());
                 }
                 content
@@ -117,18 +142,14 @@ impl Node {
 
     pub fn get_elements_by_tag_name(&self, tag_name: &str) -> Vec<&Self> {
         match self {
-            Node::Element {
-                ref tag,
-                ref children,
-                ..
-            } => {
+            Node::Element { ref tag, .. } => {
                 let mut result = vec![];
 
                 if tag == tag_name {
                     result.push(self);
                 }
 
-                for child in children {
+                for child in self.document_children() {
                     result.append(&mut child.get_elements_by_tag_name(tag_name));
                 }
 
@@ -137,6 +158,134 @@ impl Node {
             Node::Text(_) => vec![],
         }
     }
+
+    /// The first element with an `id`, in tree order, to change it.
+    pub fn get_element_by_id_mut(&mut self, id: &str) -> Option<&mut Self> {
+        if self.get_id() == Some(id) {
+            return Some(self);
+        }
+        match self {
+            Node::Element {
+                ref tag,
+                ref mut children,
+                ..
+            } if tag != "template" => children
+                .iter_mut()
+                .find_map(|child| child.get_element_by_id_mut(id)),
+            _ => None,
+        }
+    }
+
+    /// The content of a `template` element. It is kept out of the document: it isn't styled,
+    /// laid out or found by the methods that search the document, until it is copied into it.
+    ///
+    /// https://html.spec.whatwg.org/multipage/scripting.html#the-template-element
+    pub fn template_content(&self) -> Option<&[Node]> {
+        match self {
+            Node::Element { tag, children, .. } if tag == "template" => Some(children),
+            _ => None,
+        }
+    }
+
+    /// The content of a `template` element, to change it.
+    pub fn template_content_mut(&mut self) -> Option<&mut Vec<Node>> {
+        match self {
+            Node::Element { tag, children, .. } if tag == "template" => Some(children),
+            _ => None,
+        }
+    }
+
+    /// The children that are part of the document, which leaves out the content of templates.
+    pub(crate) fn document_children(&self) -> &[Node] {
+        match self {
+            Node::Element { tag, .. } if tag == "template" => &[],
+            Node::Element { children, .. } => children,
+            Node::Text(_) => &[],
+        }
+    }
+
+    /// The path to the first element with an `id`, in tree order: the index of each node among
+    /// the document children of its parent.
+    pub(crate) fn path_to_element_by_id(&self, id: &str) -> Option<Vec<usize>> {
+        if self.get_id() == Some(id) {
+            return Some(Vec::new());
+        }
+        let mut children = self.document_children().iter().enumerate();
+        children.find_map(|(index, child)| {
+            let mut path = child.path_to_element_by_id(id)?;
+            path.insert(0, index);
+            Some(path)
+        })
+    }
+
+    /// The node at the end of a path from this node, as given by `path_to_element_by_id`.
+    pub(crate) fn descendant(&self, path: &[usize]) -> Option<&Node> {
+        match path.split_first() {
+            None => Some(self),
+            Some((&index, rest)) => self.document_children().get(index)?.descendant(rest),
+        }
+    }
+
+    /// Set the value of an attribute of an element. Returns whether it changed.
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> bool {
+        let attrs = match self {
+            Node::Element { ref mut attrs, .. } => attrs,
+            Node::Text(_) => return false,
+        };
+        match attrs.iter_mut().find(|attr| &*attr.0 == name) {
+            Some(attr) if attr.1 == value => false,
+            Some(attr) => {
+                attr.1 = value.to_owned();
+                true
+            }
+            None => {
+                attrs.push((Atom::from(name), value.to_owned()));
+                true
+            }
+        }
+    }
+
+    /// Remove an attribute of an element. Returns whether it was there.
+    pub fn remove_attribute(&mut self, name: &str) -> bool {
+        match self {
+            Node::Element { ref mut attrs, .. } => {
+                let len = attrs.len();
+                attrs.retain(|attr| &*attr.0 != name);
+                attrs.len() != len
+            }
+            Node::Text(_) => false,
+        }
+    }
+
+    /// Add a class to an element if it doesn't have it, or remove it if it does.
+    pub fn toggle_class(&mut self, class: &str) -> bool {
+        let mut classes: Vec<&str> = match self.get_attribute("class") {
+            Some(value) => value.split(' ').filter(|name| !name.is_empty()).collect(),
+            None => Vec::new(),
+        };
+        match classes.iter().position(|&name| name == class) {
+            Some(index) => {
+                classes.remove(index);
+            }
+            None => classes.push(class),
+        }
+        let value = classes.join(" ");
+        self.set_attribute("class", &value)
+    }
+
+    /// Replace the children of an element with a text node. Returns whether its text changed.
+    pub fn set_text_content(&mut self, text: &str) -> bool {
+        if self.get_text_content() == text {
+            return false;
+        }
+        match self {
+            Node::Element {
+                ref mut children, ..
+            } => *children = vec![Node::text(text)],
+            Node::Text(t) => *t = text.to_owned(),
+        }
+        true
+    }
 }
 
 impl PartialEq for Node {
@@ -165,23 +314,25 @@ impl PartialEq for Node {
     }
 }
 
-impl From<&Node> for String {
-    fn from(n: &Node) -> String {
-        match n {
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
             Node::Element {
                 tag,
                 attrs,
                 children,
             } => {
-                let attrs_str = attrs.iter().fold("".to_owned(), |acc, x| {
-                    format!("{} {}=\"{}\"", acc, x.0, x.1)
-                });
-                let children_str = children.iter().fold("".to_owned(), |acc, x| {
-                    format!("{}{}", acc, String::from(x))
-                });
-                format!("<{}{}>{}</{}>", &tag, attrs_str, children_str, &tag)
+                write!(f, "<{}", tag)?;
+                for (name, value) in attrs.iter() {
+                    write!(f, " {}=\"{}\"", name, value)?;
+                }
+                write!(f, ">")?;
+                for child in children {
+                    write!(f, "{}", child)?;
+                }
+                write!(f, "</{}>", tag)
             }
-            Node::Text(t) => String::from(t),
+            Node::Text(t) => write!(f, "{}", t),
         }
     }
 }
@@ -196,7 +347,7 @@ pub fn text(t: &str) -> Node {
 
 #[cfg(test)]
 mod tests {
-    use crate::dom::{elem, Node};
+    use crate::dom::{elem, Atom, Attributes, Node};
 
     #[test]
     fn test_to_string() {
@@ -219,7 +370,33 @@ mod tests {
                 </body>\
             </html>\
         ";
-        assert_eq!(String::from(&actual), expected);
+        assert_eq!(actual.to_string(), expected);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let html = "<p class=\"note\">Hi <em>there</em></p>";
+        let node: Node = html.parse().unwrap();
+        assert_eq!(node.to_string(), html);
+        assert!("<p>".parse::<Node>().is_err());
+    }
+
+    #[test]
+    fn test_template_content() {
+        let mut doc = Node::from("<a><template><b id=\"x\">hi</b></template><b>!</b></a>");
+        assert_eq!(doc.get_elements_by_tag_name("b").len(), 1);
+        assert_eq!(doc.get_text_content(), "!");
+        assert!(doc.get_element_by_id_mut("x").is_none());
+
+        let template = doc.get_elements_by_tag_name("template")[0];
+        let content = template.template_content().unwrap().to_vec();
+        assert_eq!(content, [Node::from("<b id=\"x\">hi</b>")]);
+        assert_eq!(doc.template_content(), None);
+
+        if let Node::Element { children, .. } = &mut doc {
+            children.extend(content);
+        }
+        assert!(doc.get_element_by_id_mut("x").is_some());
     }
 
     #[test]
@@ -229,6 +406,26 @@ mod tests {
         assert_eq!(actual, Node::from(expected));
     }
 
+    #[test]
+    fn test_mutate() {
+        let mut doc = Node::from("<a><b id=\"x\" class=\"foo\">hi</b></a>");
+        let b = doc.get_element_by_id_mut("x").unwrap();
+        assert!(b.toggle_class("bar"));
+        assert_eq!(b.get_attribute("class"), Some("foo bar"));
+        assert!(b.toggle_class("foo"));
+        assert_eq!(b.get_attribute("class"), Some("bar"));
+
+        assert!(!b.set_text_content("hi"));
+        assert!(b.set_text_content("bye"));
+        assert!(b.remove_attribute("class"));
+        assert!(!b.remove_attribute("class"));
+        assert_eq!(doc.to_string(), "<a><b id=\"x\">bye</b></a>");
+
+        let path = doc.path_to_element_by_id("x").unwrap();
+        assert_eq!(doc.descendant(&path).and_then(Node::get_id), Some("x"));
+        assert_eq!(doc.path_to_element_by_id("y"), None);
+    }
+
     #[test]
     fn test_get_id() {
         let doc = elem("html").add_attr("id", "foo");
@@ -242,4 +439,91 @@ mod tests {
         assert!(classes.contains("foo"));
         assert!(classes.contains("bar"));
     }
+
+    #[test]
+    fn test_compact_storage() {
+        use std::mem::size_of;
+
+        // How nodes were stored before, with names as strings and attributes in a vector.
+        enum OldNode {
+            Element {
+                tag: String,
+                attrs: Vec<(String, String)>,
+                children: Vec<OldNode>,
+            },
+            Text(String),
+        }
+
+        fn old_node(node: &Node) -> OldNode {
+            match node {
+                Node::Element {
+                    tag,
+                    attrs,
+                    children,
+                } => OldNode::Element {
+                    tag: tag.to_string(),
+                    attrs: attrs
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect(),
+                    children: children.iter().map(old_node).collect(),
+                },
+                Node::Text(text) => OldNode::Text(text.clone()),
+            }
+        }
+
+        fn old_heap_size(node: &OldNode) -> usize {
+            match node {
+                OldNode::Element {
+                    tag,
+                    attrs,
+                    children,
+                } => {
+                    let names = attrs.iter().map(|(name, value)| name.len() + value.len());
+                    tag.len()
+                        + attrs.len() * size_of::<(String, String)>()
+                        + names.sum::<usize>()
+                        + children.len() * size_of::<OldNode>()
+                        + children.iter().map(old_heap_size).sum::<usize>()
+                }
+                OldNode::Text(text) => text.len(),
+            }
+        }
+
+        // Names are interned, so only attribute values and attributes that spill take room.
+        fn heap_size(node: &Node) -> usize {
+            match node {
+                Node::Element {
+                    attrs, children, ..
+                } => {
+                    let spilled = match attrs.spilled() {
+                        true => attrs.len() * size_of::<(Atom, String)>(),
+                        false => 0,
+                    };
+                    spilled
+                        + attrs.iter().map(|(_, value)| value.len()).sum::<usize>()
+                        + children.len() * size_of::<Node>()
+                        + children.iter().map(heap_size).sum::<usize>()
+                }
+                Node::Text(text) => text.len(),
+            }
+        }
+
+        let html = "<ul><li id=\"a\">one</li><li class=\"b\">two</li><li>three</li></ul>";
+        let doc = Node::from(html);
+        assert!(size_of::<Node>() <= size_of::<OldNode>());
+        assert!(heap_size(&doc) < old_heap_size(&old_node(&doc)));
+
+        let doc = Node::from("<a><b id=\"x\"></b><b id=\"y\" class=\"z\"></b></a>");
+        fn attrs(node: &Node) -> &Attributes {
+            match node {
+                Node::Element { attrs, .. } => attrs,
+                Node::Text(_) => panic!("not an element"),
+            }
+        }
+        let children = doc.get_elements_by_tag_name("b");
+        assert!(!attrs(children[0]).spilled());
+        assert!(attrs(children[1]).spilled());
+        assert_eq!(attrs(children[0])[0].0, attrs(children[1])[0].0);
+    }
 }
@@ -0,0 +1,1621 @@
+//! A document with its stylesheets, rendered in a viewport. The engine keeps what it rendered
+//! last, and only does the work again for what changed since.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+
+#[cfg(all(feature = "tokio", feature = "image"))]
+use futures_util::future::join;
+#[cfg(feature = "tokio")]
+use futures_util::future::join_all;
+use tracing::{debug, debug_span};
+use url::Url;
+
+use crate::css::units::UnitContext;
+use crate::css::{self, sheet, user_agent_sheet, Color, Medium, Sheet};
+use crate::dom::Node;
+use crate::fonts::FontContext;
+use crate::images::ImageCache;
+use crate::layout::{
+    is_frame, layout_tree_with_context, relayout_tree, LayoutBox, LayoutContext, PageBox, Rect,
+    RetainedLayout, Viewport,
+};
+#[cfg(feature = "tokio")]
+use crate::loader::AsyncResourceLoader;
+#[cfg(feature = "reqwest")]
+use crate::loader::HttpLoader;
+use crate::loader::{LoadError, ResourceLoader};
+use crate::painting::raster::Canvas;
+use crate::painting::{
+    build_page_display_lists, build_paint_layers, diff, flatten_paint_layers, scale_display_list,
+    snap_to_device_pixels, DisplayCommand, DisplayList, PaintLayer,
+};
+use crate::style::{
+    restyle_tree, style_tree_for_medium, style_tree_with_cache, Display, MatchCache, RetainedStyle,
+    StyledNode, Transform,
+};
+use crate::Error;
+
+pub use self::memory::{MemoryReport, MemoryUsage};
+#[cfg(feature = "serde")]
+pub use self::snapshot::Snapshot;
+
+mod memory;
+#[cfg(feature = "serde")]
+mod snapshot;
+
+/// How many frames deep documents are nested at most. Frames below are left empty.
+const MAX_FRAME_DEPTH: usize = 8;
+
+/// What an engine scrolls.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScrollTarget {
+    /// The viewport, which scrolls the whole document.
+    Root,
+    /// The scroll container with this `id`.
+    Element(String),
+}
+
+/// Renders a document, from its HTML and CSS to the pixels of a canvas.
+///
+/// A change to the document, the stylesheets or the viewport invalidates the display list, which
+/// is built again on the next render. The canvas is then only painted again where the new display
+/// list differs from the old one.
+#[derive(Debug)]
+pub struct Engine {
+    document: Option<Node>,
+    /// The URL of the document, which the URLs of its resources are relative to.
+    base: Option<Url>,
+    /// The rules of the user agent, and then those of every stylesheet of the document and every
+    /// stylesheet that was added, in order.
+    sheet: Sheet,
+    viewport: Viewport,
+    background: Color,
+    images: ImageCache,
+    fonts: FontContext,
+    /// The documents of the frames that were loaded, by the `src` of their `iframe` elements.
+    frames: HashMap<String, String>,
+    /// How far the viewport and the scroll containers are scrolled, in CSS pixels.
+    scroll_offsets: HashMap<ScrollTarget, (f32, f32)>,
+    /// The paint layers of the document before scrolling, or `None` if something changed since
+    /// they were built.
+    layers: Option<Vec<PaintLayer>>,
+    /// The display list that was rendered last, or `None` if something changed since.
+    display_list: Option<DisplayList>,
+    /// The display list that the canvas was painted from.
+    painted: Option<DisplayList>,
+    canvas: Canvas,
+    /// The rules that match the elements of the document, which are kept until the stylesheets
+    /// change.
+    match_cache: RefCell<MatchCache>,
+    /// The style and the layout of the document on the screen when it was last laid out, or
+    /// `None` if anything other than `mutate` changed it since.
+    retained: RefCell<Option<Retained>>,
+}
+
+/// The style and the layout of a document that are kept between renders, so that only the
+/// elements that changed since are styled and laid out again.
+#[derive(Debug)]
+struct Retained {
+    style: RetainedStyle,
+    layout: RetainedLayout,
+    /// The elements that `mutate` changed since, by their path in the document.
+    dirty: Vec<Vec<usize>>,
+}
+
+impl Engine {
+    /// An engine with an empty document in a viewport, on a white background.
+    pub fn new(viewport: Viewport) -> Engine {
+        let background = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        };
+        Engine {
+            document: None,
+            base: None,
+            sheet: user_agent_sheet(),
+            viewport,
+            background: background.clone(),
+            images: ImageCache::new(),
+            fonts: FontContext::new(),
+            frames: HashMap::new(),
+            scroll_offsets: HashMap::new(),
+            layers: None,
+            display_list: None,
+            painted: None,
+            canvas: canvas(viewport, background),
+            match_cache: RefCell::new(MatchCache::new()),
+            retained: RefCell::new(None),
+        }
+    }
+
+    /// Parse a document, which replaces the one before, with the stylesheets of its `style`
+    /// elements. The stylesheets of the document before, and those that were added, are removed.
+    /// Stylesheets that aren't valid CSS are left out.
+    pub fn load_html(&mut self, html: &str) -> Result<(), Error> {
+        for source in self.parse_document(html, None)? {
+            if let StylesheetSource::Style(style) = source {
+                self.add_rules(style);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch and parse a document, which replaces the one before, and fetch the stylesheets that
+    /// it links and imports. The resources that it refers to are loaded relative to its URL, and
+    /// the stylesheets that fail to load or to parse are left out.
+    pub fn load_document(&mut self, loader: &dyn ResourceLoader, url: &Url) -> Result<(), Error> {
+        let html = loader.fetch(url)?;
+        for source in self.parse_document(&String::from_utf8_lossy(&html), Some(url.clone()))? {
+            let loaded = match source {
+                StylesheetSource::Style(style) => import(loader, style, &mut Vec::new()),
+                StylesheetSource::Link(url) => {
+                    match fetch_stylesheet(loader, &url, &mut Vec::new()) {
+                        Ok(fetched) => fetched,
+                        Err(error) => {
+                            debug!(%url, %error, "left out a stylesheet");
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.add_rules(loaded);
+        }
+        Ok(())
+    }
+
+    /// Parse a stylesheet, and add its rules after those of the stylesheets before it. Its
+    /// `@import` rules are ignored.
+    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), Error> {
+        self.add_rules(Sheet::parse(css)?);
+        Ok(())
+    }
+
+    /// Parse a document like [`Engine::load_html`], and then stylesheets like
+    /// [`Engine::add_stylesheet`]. With the `rayon` feature, the document and the stylesheets are
+    /// parsed at the same time. Nothing changes if one of them isn't valid.
+    pub fn load_html_with_stylesheets(
+        &mut self,
+        html: &str,
+        stylesheets: &[&str],
+    ) -> Result<(), Error> {
+        let _span = debug_span!("parse").entered();
+        let (document, sheets) = join_in_parallel(
+            || -> Result<_, Error> {
+                let document = Node::parse(html)?;
+                let sources = stylesheet_sources(&document, None);
+                Ok((document, sources))
+            },
+            || map_in_parallel(stylesheets.to_vec(), Sheet::parse),
+        );
+        let (document, sources) = document?;
+        let sheets = sheets.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+        self.replace_document(document, None);
+        for source in sources {
+            if let StylesheetSource::Style(style) = source {
+                self.add_rules(style);
+            }
+        }
+        for sheet in sheets {
+            self.add_rules(sheet);
+        }
+        Ok(())
+    }
+
+    /// Fetch a stylesheet by its URL relative to the document, with the stylesheets it imports,
+    /// and add their rules after those of the stylesheets before it. The imports that fail to
+    /// load or to parse are left out.
+    pub fn load_stylesheet(
+        &mut self,
+        loader: &dyn ResourceLoader,
+        href: &str,
+    ) -> Result<(), Error> {
+        let url = self.resolve(href)?;
+        let fetched = fetch_stylesheet(loader, &url, &mut Vec::new())?;
+        self.add_rules(fetched);
+        Ok(())
+    }
+
+    /// Remove every stylesheet but that of the user agent.
+    pub fn clear_stylesheets(&mut self) {
+        self.sheet = user_agent_sheet();
+        self.invalidate_sheet();
+    }
+
+    /// Resize, zoom or move the viewport to a screen with another device pixel ratio.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        if viewport != self.viewport {
+            self.viewport = viewport;
+            self.invalidate();
+        }
+    }
+
+    /// Magnify the page. It is laid out again in a viewport of fewer CSS pixels, so that text
+    /// wraps within the width that is left, and painted at the scale of the zoom.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.set_viewport(Viewport {
+            zoom,
+            ..self.viewport
+        });
+    }
+
+    /// Set the color that the canvas is cleared with.
+    pub fn set_background(&mut self, background: Color) {
+        if background != self.background {
+            self.background = background;
+            self.painted = None;
+        }
+    }
+
+    pub fn document(&self) -> Option<&Node> {
+        self.document.as_ref()
+    }
+
+    /// The document, to change it. It is styled and laid out again on the next render.
+    pub fn document_mut(&mut self) -> Option<&mut Node> {
+        self.invalidate();
+        self.document.as_mut()
+    }
+
+    /// Change the document, like a script would, through the elements with an `id`. Only the
+    /// elements that changed and their descendants are styled again on the next render, and only
+    /// their boxes and the boxes around them are laid out again. The canvas is only painted
+    /// again where it looks different. Returns `None` without a document.
+    pub fn mutate<R>(&mut self, f: impl FnOnce(&mut Mutator) -> R) -> Option<R> {
+        let mut mutator = Mutator {
+            document: self.document.as_mut()?,
+            changed: Vec::new(),
+        };
+        let result = f(&mut mutator);
+        let mut changed = mutator.changed;
+        if !changed.is_empty() {
+            self.layers = None;
+            self.display_list = None;
+            if let Some(retained) = self.retained.get_mut() {
+                retained.dirty.append(&mut changed);
+            }
+        }
+        Some(result)
+    }
+
+    pub fn base_url(&self) -> Option<&Url> {
+        self.base.as_ref()
+    }
+
+    pub fn stylesheet(&self) -> &Sheet {
+        &self.sheet
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// The images of the document, to add the ones it loads. The document is laid out again on
+    /// the next render.
+    pub fn images_mut(&mut self) -> &mut ImageCache {
+        self.invalidate();
+        &mut self.images
+    }
+
+    pub fn images(&self) -> &ImageCache {
+        &self.images
+    }
+
+    /// The fonts that text is set in, to add faces to them. The document is laid out again on
+    /// the next render.
+    pub fn fonts_mut(&mut self) -> &mut FontContext {
+        self.invalidate();
+        &mut self.fonts
+    }
+
+    pub fn fonts(&self) -> &FontContext {
+        &self.fonts
+    }
+
+    /// Fetch the fonts of the `@font-face` rules of the stylesheets, and add them to the fonts.
+    pub fn load_font_faces(&mut self, loader: &dyn ResourceLoader) {
+        let base = self.base.as_ref();
+        self.fonts.add_font_faces(&self.sheet, |url| {
+            let url = Url::options().base_url(base).parse(url).ok()?;
+            loader.fetch(&url).ok()
+        });
+        self.invalidate();
+    }
+
+    /// Fetch the documents of the `iframe` elements of the document that have a `src` and no
+    /// `srcdoc`. Frames that were loaded before are kept, and those that fail to load are left
+    /// empty. The documents in frames are rendered with the stylesheets of their `style`
+    /// elements, and don't load resources of their own.
+    pub fn load_frames(&mut self, loader: &dyn ResourceLoader) {
+        for source in self.frame_sources() {
+            match self.resolve(&source).and_then(|url| loader.fetch(&url)) {
+                Ok(html) => {
+                    let html = String::from_utf8_lossy(&html).into_owned();
+                    self.frames.insert(source, html);
+                }
+                Err(error) => debug!(%source, %error, "left out a frame"),
+            }
+        }
+        self.invalidate();
+    }
+
+    /// Fetch and decode the images of the `img` elements of the document and of the `url()`
+    /// values of the stylesheets, such as background images. Images that were loaded before are
+    /// kept, and those that fail to load are left out.
+    #[cfg(feature = "image")]
+    pub fn load_images(&mut self, loader: &dyn ResourceLoader) {
+        for source in self.image_sources() {
+            if let Ok(data) = self.resolve(&source).and_then(|url| loader.fetch(&url)) {
+                let _ = self.images.decode(&source, &data);
+            }
+        }
+        self.invalidate();
+    }
+
+    /// Fetch a document over HTTP, or from the filesystem with a `file:` URL, and then the
+    /// stylesheets, fonts, frames and images that it refers to, relative to its URL, so that it
+    /// can be rendered. Resources that fail to load are left out.
+    #[cfg(feature = "reqwest")]
+    pub fn load_url(&mut self, url: &str) -> Result<(), Error> {
+        let url = Url::parse(url).map_err(LoadError::from)?;
+        self.load_document_with_resources(&HttpLoader::new(), &url)
+    }
+
+    /// Fetch and parse a document like `load_document`, and then fetch its fonts, frames and
+    /// images, one after the other. Resources that fail to load are left out.
+    pub fn load_document_with_resources(
+        &mut self,
+        loader: &dyn ResourceLoader,
+        url: &Url,
+    ) -> Result<(), Error> {
+        self.load_document(loader, url)?;
+        self.load_font_faces(loader);
+        self.load_frames(loader);
+        #[cfg(feature = "image")]
+        self.load_images(loader);
+        Ok(())
+    }
+
+    /// Fetch and parse a document like `load_document`, and then fetch the fonts and images of
+    /// the document and its stylesheets, so that it can be laid out. Resources of the same kind
+    /// are fetched at the same time, and those that fail to load are left out.
+    #[cfg(feature = "tokio")]
+    pub async fn load_html_async(
+        &mut self,
+        loader: &impl AsyncResourceLoader,
+        url: &Url,
+    ) -> Result<(), Error> {
+        let html = loader.fetch(url).await?;
+        let sources = self.parse_document(&String::from_utf8_lossy(&html), Some(url.clone()))?;
+        let stylesheets = sources.into_iter().map(|source| async move {
+            match source {
+                StylesheetSource::Style(style) => {
+                    Some(import_async(loader, style, Vec::new()).await)
+                }
+                StylesheetSource::Link(url) => {
+                    fetch_stylesheet_async(loader, url, Vec::new()).await.ok()
+                }
+            }
+        });
+        for loaded in join_all(stylesheets).await.into_iter().flatten() {
+            self.add_rules(loaded);
+        }
+
+        // The first source of each font face that loads, by its URL in the stylesheet.
+        let base = self.base.as_ref();
+        let font_faces = self.sheet.font_faces.iter().map(|font_face| async move {
+            for source in font_face.sources() {
+                let url = match resolve(base, source) {
+                    Ok(url) => url,
+                    Err(_) => continue,
+                };
+                match loader.fetch(&url).await {
+                    Ok(data) if ttf_parser::Face::parse(&data, 0).is_ok() => {
+                        return Some((source.to_owned(), data))
+                    }
+                    _ => continue,
+                }
+            }
+            None
+        });
+
+        #[cfg(feature = "image")]
+        let images = self.image_sources().into_iter().map(|source| async move {
+            let url = resolve(base, &source).ok()?;
+            let data = loader.fetch(&url).await.ok()?;
+            Some((source, data))
+        });
+        #[cfg(feature = "image")]
+        let (font_faces, images) = join(join_all(font_faces), join_all(images)).await;
+        #[cfg(not(feature = "image"))]
+        let font_faces = join_all(font_faces).await;
+
+        let font_faces: HashMap<_, _> = font_faces.into_iter().flatten().collect();
+        self.fonts
+            .add_font_faces(&self.sheet, |source| font_faces.get(source).cloned());
+        #[cfg(feature = "image")]
+        for (source, data) in images.into_iter().flatten() {
+            let _ = self.images.decode(&source, &data);
+        }
+
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Scroll the viewport or a scroll container to an offset in CSS pixels. The document isn't
+    /// laid out again: the next display list only moves the content that scrolled.
+    pub fn set_scroll_offset(&mut self, target: ScrollTarget, x: f32, y: f32) {
+        self.scroll_offsets.insert(target, (x, y));
+        if let Some(layers) = &mut self.layers {
+            scroll_layers(layers, &self.scroll_offsets, self.viewport.scale());
+        }
+        self.display_list = None;
+    }
+
+    /// How far the viewport or a scroll container is scrolled, in CSS pixels.
+    pub fn scroll_offset(&self, target: &ScrollTarget) -> (f32, f32) {
+        self.scroll_offsets
+            .get(target)
+            .copied()
+            .unwrap_or((0.0, 0.0))
+    }
+
+    /// The display list of the document in device pixels, for backends that paint it
+    /// themselves. It is built again if anything changed since it was last built.
+    pub fn display_list(&mut self) -> &DisplayList {
+        if self.layers.is_none() {
+            let mut layers = self.build_paint_layers();
+            scroll_layers(&mut layers, &self.scroll_offsets, self.viewport.scale());
+            self.layers = Some(layers);
+        }
+        if self.display_list.is_none() {
+            self.display_list = Some(self.build_display_list());
+        }
+        self.display_list.as_ref().unwrap()
+    }
+
+    /// Render the document on a canvas the size of the viewport in device pixels.
+    pub fn render(&mut self) -> &Canvas {
+        self.display_list();
+        let list = self.display_list.as_ref().unwrap();
+        let _span = debug_span!("raster").entered();
+
+        let (width, height) = self.viewport.device_size();
+        let resized = (self.canvas.width, self.canvas.height) != (width, height);
+        match &self.painted {
+            Some(painted) if !resized => {
+                for region in diff(painted, list) {
+                    repaint(
+                        &mut self.canvas,
+                        list,
+                        &self.images,
+                        self.background.clone(),
+                        region,
+                    );
+                }
+            }
+            _ => {
+                self.canvas = canvas(self.viewport, self.background.clone());
+                self.canvas.paint(list, Some(&self.images));
+            }
+        }
+
+        self.painted = Some(list.clone());
+        &self.canvas
+    }
+
+    /// The page that the document is printed on, from the `@page` rules of its stylesheets.
+    pub fn page_box(&self) -> PageBox {
+        PageBox::from_sheet(&self.sheet)
+    }
+
+    /// Print the document on pages of the page box: style it for print, lay it out in the page
+    /// area, and break it into pages. Returns the display list of each page, in CSS pixels from
+    /// the top left of the page, with the content clipped to the page area.
+    ///
+    /// https://www.w3.org/TR/css-page-3/
+    pub fn print(&self) -> Vec<DisplayList> {
+        let document = match &self.document {
+            Some(document) => document,
+            None => return Vec::new(),
+        };
+        let _span = debug_span!("print").entered();
+        let area = self.page_box().page_area();
+        let viewport = Rect {
+            x: 0.0,
+            y: 0.0,
+            ..area
+        };
+        let pages = self.lay_out(
+            document,
+            &self.sheet,
+            Medium::Print,
+            viewport,
+            0,
+            |_, root| debug_span!("paint").in_scope(|| build_page_display_lists(root, area.height)),
+        );
+
+        let margins = Transform::translate(area.x, area.y);
+        let pages = pages.unwrap_or_default().into_iter();
+        pages
+            .map(|page| {
+                let mut list = vec![DisplayCommand::PushTransform(margins)];
+                list.extend(page);
+                list.push(DisplayCommand::PopTransform);
+                list
+            })
+            .collect()
+    }
+
+    fn invalidate(&mut self) {
+        self.layers = None;
+        self.display_list = None;
+        *self.retained.get_mut() = None;
+    }
+
+    /// Add the rules of a stylesheet after those of the stylesheets before it. Every change to
+    /// the stylesheets goes through here or `invalidate_sheet`, so that the rules that were
+    /// matched before are forgotten.
+    fn add_rules(&mut self, sheet: Sheet) {
+        append(&mut self.sheet, sheet);
+        self.invalidate_sheet();
+    }
+
+    /// Mark the display list as stale, and forget which rules match the elements of the
+    /// document, after the stylesheets changed.
+    fn invalidate_sheet(&mut self) {
+        self.match_cache.get_mut().clear();
+        self.invalidate();
+    }
+
+    /// Replace the document, and the stylesheets with that of the user agent. Returns the
+    /// stylesheets of the document, in order. The document before is kept if the new one isn't
+    /// valid.
+    fn parse_document(
+        &mut self,
+        html: &str,
+        base: Option<Url>,
+    ) -> Result<Vec<StylesheetSource>, Error> {
+        let _span = debug_span!("parse").entered();
+        let document = Node::parse(html)?;
+        let sources = stylesheet_sources(&document, base.as_ref());
+        self.replace_document(document, base);
+        Ok(sources)
+    }
+
+    /// Replace the document, and the stylesheets with that of the user agent.
+    fn replace_document(&mut self, document: Node, base: Option<Url>) {
+        self.document = Some(document);
+        self.base = base;
+        self.sheet = user_agent_sheet();
+        self.invalidate_sheet();
+        // Like in a browser, a script that fails doesn't stop the document from rendering.
+        #[cfg(feature = "scripting")]
+        for error in self
+            .mutate(crate::scripting::run_scripts)
+            .unwrap_or_default()
+        {
+            debug!(%error, "a script failed");
+        }
+    }
+
+    /// The URL of a reference in the document, or of an absolute reference when there is no
+    /// document URL.
+    fn resolve(&self, href: &str) -> Result<Url, LoadError> {
+        resolve(self.base.as_ref(), href)
+    }
+
+    /// The URLs of the images of the `img` elements of the document and of the `url()` values of
+    /// the stylesheets, as they are written, without those that were loaded before.
+    #[cfg(feature = "image")]
+    fn image_sources(&self) -> Vec<String> {
+        let mut sources: Vec<String> = match &self.document {
+            Some(document) => document
+                .get_elements_by_tag_name("img")
+                .into_iter()
+                .filter_map(|img| img.get_attribute("src"))
+                .map(str::to_owned)
+                .collect(),
+            None => Vec::new(),
+        };
+        sources.extend(self.sheet.urls().into_iter().map(str::to_owned));
+        sources.retain(|source| self.images.handle(source).is_none());
+        sources.dedup();
+        sources
+    }
+
+    /// The `src` of the `iframe` elements of the document without a `srcdoc`, without those
+    /// that were loaded before.
+    fn frame_sources(&self) -> Vec<String> {
+        let mut sources: Vec<String> = match &self.document {
+            Some(document) => document
+                .get_elements_by_tag_name("iframe")
+                .into_iter()
+                .filter(|iframe| iframe.get_attribute("srcdoc").is_none())
+                .filter_map(|iframe| iframe.get_attribute("src"))
+                .filter(|source| !self.frames.contains_key(*source))
+                .map(str::to_owned)
+                .collect(),
+            None => Vec::new(),
+        };
+        sources.dedup();
+        sources
+    }
+
+    /// The paint layers of the document in CSS pixels, which are laid out for the viewport.
+    fn build_paint_layers(&self) -> Vec<PaintLayer> {
+        let document = match &self.document {
+            Some(document) => document,
+            None => return Vec::new(),
+        };
+        let medium = Medium::Screen;
+        let layers = self.lay_out(
+            document,
+            &self.sheet,
+            medium,
+            self.viewport.rect(),
+            0,
+            |_, root| debug_span!("paint").in_scope(|| build_paint_layers(root)),
+        );
+        layers.unwrap_or_default()
+    }
+
+    /// Style and lay out a document for a medium in a viewport, with the documents in its
+    /// frames, and pass the style tree and the layout tree to `f`. Returns `None` if the root
+    /// element isn't displayed. The `depth` is the number of frames that the document is nested
+    /// in.
+    fn lay_out<R>(
+        &self,
+        document: &Node,
+        sheet: &Sheet,
+        medium: Medium,
+        viewport: Rect,
+        depth: usize,
+        f: impl FnOnce(&StyledNode, &LayoutBox) -> R,
+    ) -> Option<R> {
+        // The engine keeps the style and the layout of its own document on the screen, and only
+        // does them again for the elements that changed since.
+        let retains = depth == 0 && medium == Medium::Screen;
+        let retained = match retains {
+            true => self.retained.take(),
+            false => None,
+        };
+        let (retained_style, retained_layout, dirty) = match retained {
+            Some(Retained {
+                style,
+                layout,
+                dirty,
+            }) => (Some(style), Some(layout), dirty),
+            None => (None, None, Vec::new()),
+        };
+
+        let (style_root, style) = debug_span!("style").in_scope(|| {
+            // The engine keeps the rules that match the elements of its own document. The
+            // documents in frames have stylesheets of their own.
+            let mut style_root = match (depth, retained_style) {
+                (0, Some(retained)) => {
+                    let cache = &mut self.match_cache.borrow_mut();
+                    restyle_tree(document, sheet, medium, cache, retained, &dirty)
+                }
+                (0, None) => {
+                    let cache = &mut self.match_cache.borrow_mut();
+                    style_tree_with_cache(document, sheet, medium, cache)
+                }
+                _ => style_tree_for_medium(document, sheet, medium),
+            };
+            let style = retains.then(|| style_root.retain());
+            style_root.resolve_units(&UnitContext {
+                viewport: (viewport.width, viewport.height),
+                ..Default::default()
+            });
+            (style_root, style)
+        });
+        if style_root.display() == Display::None {
+            return None;
+        }
+
+        let context = LayoutContext {
+            viewport,
+            images: Some(&self.images),
+            fonts: Some(&self.fonts),
+            ..LayoutContext::for_viewport(&self.viewport)
+        };
+        let mut layout_root = debug_span!("layout").in_scope(|| match retained_layout {
+            Some(retained) => {
+                let dirty: Vec<&Node> = dirty
+                    .iter()
+                    .filter_map(|path| document.descendant(path))
+                    .collect();
+                relayout_tree(&style_root, retained, &dirty, &context)
+            }
+            None => layout_tree_with_context(&style_root, &context),
+        });
+        self.paint_frames(&mut layout_root, medium, depth);
+        let result = f(&style_root, &layout_root);
+
+        if let Some(style) = style {
+            *self.retained.borrow_mut() = Some(Retained {
+                style,
+                layout: layout_root.into_retained(None),
+                dirty: Vec::new(),
+            });
+        }
+        Some(result)
+    }
+
+    /// Paint the documents of the frames in a layout tree: the `srcdoc` of an `iframe`, or else
+    /// the document that was loaded for its `src`. Frames are nested at most `MAX_FRAME_DEPTH`
+    /// deep, so that a document that contains itself ends.
+    fn paint_frames(&self, layout_box: &mut LayoutBox, medium: Medium, depth: usize) {
+        let node = layout_box.node().filter(|node| is_frame(node));
+        let html = node.and_then(|iframe| match iframe.get_attribute("srcdoc") {
+            Some(srcdoc) => Some(srcdoc),
+            None => self
+                .frames
+                .get(iframe.get_attribute("src")?)
+                .map(String::as_str),
+        });
+        if let (Some(html), true) = (html, depth < MAX_FRAME_DEPTH) {
+            let content = layout_box.dimensions.content;
+            let size = (content.width, content.height);
+            layout_box.set_frame(self.paint_frame(html, size, medium, depth + 1));
+        }
+
+        for child in &mut layout_box.children {
+            self.paint_frames(child, medium, depth);
+        }
+    }
+
+    /// The display list of the document in a frame, in CSS pixels relative to the top left of
+    /// the frame. A document that isn't valid leaves the frame empty.
+    fn paint_frame(
+        &self,
+        html: &str,
+        (width, height): (f32, f32),
+        medium: Medium,
+        depth: usize,
+    ) -> DisplayList {
+        let _span = debug_span!("frame", depth).entered();
+        let document = match Node::parse(html) {
+            Ok(document) => document,
+            Err(error) => {
+                debug!(%error, "left out a frame");
+                return Vec::new();
+            }
+        };
+        let mut sheet = user_agent_sheet();
+        for source in stylesheet_sources(&document, None) {
+            if let StylesheetSource::Style(style) = source {
+                append(&mut sheet, style);
+            }
+        }
+
+        let viewport = Rect {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+        };
+        let layers = self.lay_out(&document, &sheet, medium, viewport, depth, |_, root| {
+            build_paint_layers(root)
+        });
+        flatten_paint_layers(&layers.unwrap_or_default())
+    }
+
+    /// The display list of the paint layers, scrolled by their offsets.
+    fn build_display_list(&self) -> DisplayList {
+        let _span = debug_span!("display_list").entered();
+        let layers = self.layers.as_deref().unwrap_or_default();
+        let (x, y) = device_offset(
+            self.scroll_offset(&ScrollTarget::Root),
+            self.viewport.scale(),
+        );
+
+        let mut list = Vec::new();
+        if (x, y) != (0.0, 0.0) {
+            list.push(DisplayCommand::PushTransform(Transform::translate(-x, -y)));
+        }
+        list.extend(flatten_paint_layers(layers));
+        if (x, y) != (0.0, 0.0) {
+            list.push(DisplayCommand::PopTransform);
+        }
+
+        // Edges are snapped to device pixels after scaling, where a pixel is one unit. The scroll
+        // offsets are whole device pixels, so the edges stay snapped after scrolling.
+        scale_display_list(&mut list, self.viewport.scale());
+        snap_to_device_pixels(&mut list, 1.0);
+        list
+    }
+}
+
+/// A document that is being changed by `Engine::mutate`, which keeps track of the elements that
+/// changed.
+pub struct Mutator<'a> {
+    pub(crate) document: &'a mut Node,
+    /// The paths of the elements that changed. They are found by their `id`, but the `id` itself
+    /// may be what changed.
+    pub(crate) changed: Vec<Vec<usize>>,
+}
+
+impl Mutator<'_> {
+    pub fn document(&self) -> &Node {
+        self.document
+    }
+
+    /// The element with an `id`, to read it without counting as a change.
+    pub fn element(&self, id: &str) -> Option<&Node> {
+        let path = self.document.path_to_element_by_id(id)?;
+        self.document.descendant(&path)
+    }
+
+    /// The element with an `id`, to change it in any way. It counts as a change.
+    pub fn element_mut(&mut self, id: &str) -> Option<&mut Node> {
+        let path = self.document.path_to_element_by_id(id)?;
+        self.changed.push(path);
+        self.document.get_element_by_id_mut(id)
+    }
+
+    pub fn set_attribute(&mut self, id: &str, name: &str, value: &str) {
+        self.change(id, |element| element.set_attribute(name, value));
+    }
+
+    pub fn remove_attribute(&mut self, id: &str, name: &str) {
+        self.change(id, |element| element.remove_attribute(name));
+    }
+
+    pub fn toggle_class(&mut self, id: &str, class: &str) {
+        self.change(id, |element| element.toggle_class(class));
+    }
+
+    pub fn set_text_content(&mut self, id: &str, text: &str) {
+        self.change(id, |element| element.set_text_content(text));
+    }
+
+    /// Change the element with an `id`, if there is one, and remember it if it changed.
+    fn change(&mut self, id: &str, f: impl FnOnce(&mut Node) -> bool) {
+        let path = self.document.path_to_element_by_id(id);
+        let element = self.document.get_element_by_id_mut(id);
+        if let (Some(path), Some(element)) = (path, element) {
+            if f(element) {
+                self.changed.push(path);
+            }
+        }
+    }
+}
+
+/// Set the scroll offsets of the layers of scroll containers, by their `id`.
+fn scroll_layers(
+    layers: &mut [PaintLayer],
+    scroll_offsets: &HashMap<ScrollTarget, (f32, f32)>,
+    scale: f32,
+) {
+    for layer in layers {
+        if let Some(id) = &layer.id {
+            let target = ScrollTarget::Element(id.clone());
+            if let Some(&offset) = scroll_offsets.get(&target) {
+                layer.scroll_offset = device_offset(offset, scale);
+            }
+        }
+    }
+}
+
+/// A scroll offset in CSS pixels, rounded to whole device pixels.
+fn device_offset((x, y): (f32, f32), scale: f32) -> (f32, f32) {
+    ((x * scale).round() / scale, (y * scale).round() / scale)
+}
+
+/// Add the rules and font faces of a stylesheet after those of another.
+fn append(sheet: &mut Sheet, other: Sheet) {
+    sheet.rules.extend(other.rules);
+    sheet.font_faces.extend(other.font_faces);
+    sheet.pages.extend(other.pages);
+}
+
+/// A stylesheet of a document: one that a `style` element contains, or one that a `link` element
+/// links to. The content of a `style` element is parsed into a `Sheet`.
+enum StylesheetSource<S = Sheet> {
+    Style(S),
+    Link(Url),
+}
+
+/// The stylesheets of the `style` elements and the `link` elements of a document, in order. The
+/// URLs are resolved against the URL of the document. Links without a valid URL, and `style`
+/// elements without valid CSS, are left out.
+///
+/// https://html.spec.whatwg.org/multipage/semantics.html#the-style-element
+/// https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet
+fn stylesheet_sources(node: &Node, base: Option<&Url>) -> Vec<StylesheetSource> {
+    let mut sources = Vec::new();
+    find_stylesheet_sources(node, base, &mut sources);
+    let parsed = map_in_parallel(sources, |source| match source {
+        StylesheetSource::Style(css) => {
+            let mut style = match Sheet::parse(&css) {
+                Ok(style) => style,
+                Err(error) => {
+                    debug!(%error, "left out a style element");
+                    return None;
+                }
+            };
+            if let Some(base) = base {
+                style.resolve_urls(base);
+            }
+            Some(StylesheetSource::Style(style))
+        }
+        StylesheetSource::Link(url) => Some(StylesheetSource::Link(url)),
+    });
+    parsed.into_iter().flatten().collect()
+}
+
+/// Add the stylesheets of a node and its descendants to `sources`, with the CSS text of the
+/// `style` elements.
+fn find_stylesheet_sources(
+    node: &Node,
+    base: Option<&Url>,
+    sources: &mut Vec<StylesheetSource<String>>,
+) {
+    let tag = match node {
+        Node::Element { tag, .. } => tag,
+        Node::Text(_) => return,
+    };
+    match tag.as_str() {
+        "style" => sources.push(StylesheetSource::Style(node.get_text_content())),
+        "link" => {
+            let rel = node.get_attribute("rel").unwrap_or("");
+            let is_stylesheet = rel
+                .split_ascii_whitespace()
+                .any(|keyword| keyword.eq_ignore_ascii_case("stylesheet"));
+            let href = node.get_attribute("href").filter(|_| is_stylesheet);
+            if let Some(url) = href.and_then(|href| resolve(base, href).ok()) {
+                sources.push(StylesheetSource::Link(url));
+            }
+        }
+        _ => {
+            for child in node.document_children() {
+                find_stylesheet_sources(child, base, sources);
+            }
+        }
+    }
+}
+
+/// Apply `f` to each item, and keep the results in order. With the `rayon` feature, the items
+/// are spread over worker threads.
+#[cfg(feature = "rayon")]
+fn map_in_parallel<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Send + Sync) -> Vec<R> {
+    use rayon::prelude::*;
+    items.into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_in_parallel<T, R>(items: Vec<T>, f: impl Fn(T) -> R) -> Vec<R> {
+    items.into_iter().map(f).collect()
+}
+
+/// Run two functions, at the same time on worker threads with the `rayon` feature.
+#[cfg(feature = "rayon")]
+fn join_in_parallel<A: Send, B: Send>(
+    a: impl FnOnce() -> A + Send,
+    b: impl FnOnce() -> B + Send,
+) -> (A, B) {
+    rayon::join(a, b)
+}
+
+#[cfg(not(feature = "rayon"))]
+fn join_in_parallel<A, B>(a: impl FnOnce() -> A, b: impl FnOnce() -> B) -> (A, B) {
+    (a(), b())
+}
+
+/// Fetch a stylesheet and resolve its URLs, with the stylesheets that it imports. `importing`
+/// holds the stylesheets that import it, which it may not import again.
+fn fetch_stylesheet(
+    loader: &dyn ResourceLoader,
+    url: &Url,
+    importing: &mut Vec<Url>,
+) -> Result<Sheet, Error> {
+    let fetched = parse_stylesheet(&loader.fetch(url)?, url)?;
+    importing.push(url.clone());
+    let combined = import(loader, fetched, importing);
+    importing.pop();
+    Ok(combined)
+}
+
+/// Fetch the stylesheets that a stylesheet imports, and put their rules before its own. The
+/// imports that fail to load or to parse are left out.
+fn import(loader: &dyn ResourceLoader, mut importer: Sheet, importing: &mut Vec<Url>) -> Sheet {
+    let mut combined = sheet();
+    for import in std::mem::take(&mut importer.imports) {
+        let imported = Url::parse(&import)
+            .ok()
+            .filter(|import| !importing.contains(import))
+            .and_then(|import| fetch_stylesheet(loader, &import, importing).ok());
+        if let Some(imported) = imported {
+            append(&mut combined, imported);
+        }
+    }
+    append(&mut combined, importer);
+    combined
+}
+
+/// Fetch a stylesheet like `fetch_stylesheet`, and the stylesheets that it imports at the same
+/// time.
+#[cfg(feature = "tokio")]
+async fn fetch_stylesheet_async(
+    loader: &impl AsyncResourceLoader,
+    url: Url,
+    mut importing: Vec<Url>,
+) -> Result<Sheet, Error> {
+    let fetched = parse_stylesheet(&loader.fetch(&url).await?, &url)?;
+    importing.push(url);
+    Ok(import_async(loader, fetched, importing).await)
+}
+
+/// Fetch the stylesheets that a stylesheet imports like `import`, at the same time.
+#[cfg(feature = "tokio")]
+fn import_async<'a>(
+    loader: &'a impl AsyncResourceLoader,
+    mut importer: Sheet,
+    importing: Vec<Url>,
+) -> Pin<Box<dyn Future<Output = Sheet> + 'a>> {
+    Box::pin(async move {
+        let imports = std::mem::take(&mut importer.imports)
+            .into_iter()
+            .filter_map(|import| Url::parse(&import).ok())
+            .filter(|import| !importing.contains(import))
+            .map(|import| fetch_stylesheet_async(loader, import, importing.clone()));
+        let mut combined = sheet();
+        for imported in join_all(imports).await.into_iter().flatten() {
+            append(&mut combined, imported);
+        }
+        append(&mut combined, importer);
+        combined
+    })
+}
+
+/// Parse a stylesheet that was fetched, and resolve its URLs against the URL it was fetched from.
+fn parse_stylesheet(css: &[u8], url: &Url) -> Result<Sheet, css::ParseError> {
+    let mut parsed = Sheet::parse(&String::from_utf8_lossy(css))?;
+    parsed.resolve_urls(url);
+    Ok(parsed)
+}
+
+/// The URL of a reference relative to a base URL, or of an absolute reference if there is none.
+fn resolve(base: Option<&Url>, href: &str) -> Result<Url, LoadError> {
+    Ok(Url::options().base_url(base).parse(href)?)
+}
+
+fn canvas(viewport: Viewport, background: Color) -> Canvas {
+    let (width, height) = viewport.device_size();
+    Canvas::new(width, height, background)
+}
+
+/// Clear a region of the canvas, and paint the display list within it. The region is widened to
+/// whole pixels, so that no pixel at its edge is painted twice.
+fn repaint(
+    canvas: &mut Canvas,
+    list: &DisplayList,
+    images: &ImageCache,
+    background: Color,
+    region: Rect,
+) {
+    let x0 = region.x.floor().clamp(0.0, canvas.width as f32);
+    let y0 = region.y.floor().clamp(0.0, canvas.height as f32);
+    let x1 = (region.x + region.width)
+        .ceil()
+        .clamp(x0, canvas.width as f32);
+    let y1 = (region.y + region.height)
+        .ceil()
+        .clamp(y0, canvas.height as f32);
+
+    for y in y0 as usize..y1 as usize {
+        let row = y * canvas.width;
+        canvas.pixels[row + x0 as usize..row + x1 as usize].fill(background.clone());
+    }
+
+    let clip = Rect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    };
+    let mut clipped = Vec::with_capacity(list.len() + 2);
+    clipped.push(DisplayCommand::PushClip(clip));
+    clipped.extend(list.iter().cloned());
+    clipped.push(DisplayCommand::PopClip);
+    canvas.paint(&clipped, Some(images));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{self, Layer, SubscriberExt};
+
+    use crate::css::*;
+    use crate::engine::*;
+    use crate::loader::*;
+
+    #[test]
+    fn test_render() {
+        let mut viewport = Viewport::new(40.0, 30.0);
+
+        let html = "<a><b></b><c></c></a>";
+        let css = "a, b, c { display: block; height: 10px; } b { background: #ff0000; }";
+        let mut engine = Engine::new(viewport);
+        engine.load_html(html).unwrap();
+        engine.add_stylesheet(css).unwrap();
+
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        };
+        let canvas = engine.render();
+        assert_eq!(canvas.pixels[0], red);
+        assert_eq!(canvas.pixels[25 * 40], white);
+
+        // Only the changed box is painted again, which gives the same pixels as painting the
+        // whole canvas.
+        engine
+            .add_stylesheet("b { background: #0000ff; } c { background: #00ff00; }")
+            .unwrap();
+        let repainted = engine.render().pixels.clone();
+
+        let mut fresh = Engine::new(viewport);
+        fresh.load_html(html).unwrap();
+        fresh.add_stylesheet(css).unwrap();
+        fresh
+            .add_stylesheet("b { background: #0000ff; } c { background: #00ff00; }")
+            .unwrap();
+        assert_eq!(repainted, fresh.render().pixels);
+
+        viewport.width = 20.0;
+        engine.set_viewport(viewport);
+        assert_eq!(engine.render().width, 20);
+    }
+
+    /// Resources in memory, by their URL.
+    struct MemoryLoader(Vec<(&'static str, &'static str)>);
+
+    impl ResourceLoader for MemoryLoader {
+        fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError> {
+            match self.0.iter().find(|(source, _)| *source == url.as_str()) {
+                Some((_, data)) => Ok(data.as_bytes().to_vec()),
+                None => Err(std::io::Error::from(std::io::ErrorKind::NotFound).into()),
+            }
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl AsyncResourceLoader for MemoryLoader {
+        async fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError> {
+            ResourceLoader::fetch(self, url)
+        }
+    }
+
+    /// The height that each rule of the document sets, in order.
+    fn heights(engine: &Engine) -> Vec<f32> {
+        let rules = engine.stylesheet().rules.iter();
+        rules
+            .filter(|rule| rule.origin == Origin::Author)
+            .map(|rule| rule.declarations.last().unwrap().value.to_px())
+            .collect()
+    }
+
+    #[test]
+    fn test_load_document() {
+        let loader = MemoryLoader(vec![
+            (
+                "http://example.com/index.html",
+                "<html><head>
+                    <style>@import url(style/base.css); a { height: 5px; }</style>
+                    <link rel=\"stylesheet\" href=\"style/main.css\"></link>
+                </head></html>",
+            ),
+            (
+                "http://example.com/style/main.css",
+                "@import url(base.css); @import \"missing.css\"; a { height: 20px; }",
+            ),
+            (
+                "http://example.com/style/base.css",
+                "@import \"main.css\"; a { display: block; height: 10px; }",
+            ),
+        ]);
+
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        let url = Url::parse("http://example.com/index.html").unwrap();
+        engine.load_document(&loader, &url).unwrap();
+
+        // The stylesheets of the document come in order, after the rules that they import. The
+        // stylesheets that import each other are only loaded once.
+        assert_eq!(heights(&engine), vec![20.0, 10.0, 5.0, 10.0, 20.0]);
+
+        engine.load_stylesheet(&loader, "style/main.css").unwrap();
+        assert_eq!(
+            heights(&engine),
+            vec![20.0, 10.0, 5.0, 10.0, 20.0, 10.0, 20.0]
+        );
+
+        assert!(matches!(
+            engine.load_stylesheet(&loader, "other.css"),
+            Err(Error::Load(LoadError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn test_load_document_with_resources() {
+        let loader = MemoryLoader(vec![
+            (
+                "http://example.com/a/index.html",
+                "<html><head><link rel=\"stylesheet\" href=\"style.css\"></link></head>\
+                    <iframe src=\"../frame.html\"></iframe></html>",
+            ),
+            ("http://example.com/a/style.css", "a { height: 10px; }"),
+            ("http://example.com/frame.html", "<html></html>"),
+        ]);
+
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        let url = Url::parse("http://example.com/a/index.html").unwrap();
+        engine.load_document_with_resources(&loader, &url).unwrap();
+        assert_eq!(heights(&engine), vec![10.0]);
+        assert_eq!(engine.frames["../frame.html"], "<html></html>");
+
+        #[cfg(feature = "reqwest")]
+        assert!(matches!(
+            engine.load_url("example.com"),
+            Err(Error::Load(LoadError::InvalidUrl(_)))
+        ));
+    }
+
+    #[test]
+    fn test_load_html_with_stylesheets() {
+        let html = "<a><style>a { height: 5px; }</style><style>a { height: 10px; }</style></a>";
+        let stylesheets = ["a { height: 15px; }", "a { height: 20px; }"];
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        engine
+            .load_html_with_stylesheets(html, &stylesheets)
+            .unwrap();
+
+        // The stylesheets of the document come first, and every stylesheet keeps its place.
+        assert_eq!(heights(&engine), vec![5.0, 10.0, 15.0, 20.0]);
+
+        let error = engine.load_html_with_stylesheets("<b></b>", &["a {"]);
+        assert!(matches!(error, Err(Error::Css(_))));
+        assert_eq!(heights(&engine), vec![5.0, 10.0, 15.0, 20.0]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_load_html_async() {
+        let loader = MemoryLoader(vec![
+            (
+                "http://example.com/index.html",
+                "<html><head>
+                    <link rel=\"stylesheet\" href=\"a.css\"></link>
+                    <link rel=\"icon\" href=\"icon.css\"></link>
+                    <link rel=\"alternate stylesheet\" href=\"style/b.css\"></link>
+                </head></html>",
+            ),
+            ("http://example.com/a.css", "a { height: 10px; }"),
+            ("http://example.com/icon.css", "a { height: 30px; }"),
+            (
+                "http://example.com/style/b.css",
+                "@import url(c.css); a { height: 20px; }",
+            ),
+            ("http://example.com/style/c.css", "a { height: 15px; }"),
+        ]);
+
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        let url = Url::parse("http://example.com/index.html").unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime
+            .block_on(engine.load_html_async(&loader, &url))
+            .unwrap();
+
+        // The stylesheets are added in the order of their links, after their imports.
+        assert_eq!(engine.base_url(), Some(&url));
+        assert_eq!(heights(&engine), vec![10.0, 15.0, 20.0]);
+    }
+
+    #[test]
+    fn test_mutate() {
+        let mut engine = Engine::new(Viewport::new(10.0, 10.0));
+        engine.load_html("<a id=\"a\"></a>").unwrap();
+        engine
+            .add_stylesheet("a { display: block; height: 10px; } .red { background: #ff0000; }")
+            .unwrap();
+        assert_eq!(engine.render().pixels[0].g, 255);
+
+        engine.mutate(|document| document.toggle_class("a", "red"));
+        assert_eq!(engine.render().pixels[0].g, 0);
+
+        // Setting the class that the element already has leaves the document as it was laid out.
+        engine.mutate(|document| document.set_attribute("a", "class", "red"));
+        assert!(engine.display_list.is_some());
+        assert_eq!(
+            engine.mutate(|document| document.element_mut("b").is_none()),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_mutate_incrementally() {
+        // The number of boxes that are laid out, rather than moved into place, in a render.
+        fn boxes_laid_out(engine: &mut Engine) -> usize {
+            let names = Arc::new(Mutex::new(Vec::new()));
+            let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+            tracing::subscriber::with_default(subscriber, || engine.render());
+            let names = names.lock().unwrap();
+            names.iter().filter(|name| *name == "layout_box").count()
+        }
+
+        let mut engine = Engine::new(Viewport::new(100.0, 100.0));
+        let html = "<div><p>a</p><p>b</p><p>c</p></div><div id=\"d\"><p>d</p></div>";
+        engine.load_html(html).unwrap();
+        engine.add_stylesheet(".wide { width: 50px; }").unwrap();
+        let laid_out = boxes_laid_out(&mut engine);
+
+        engine.mutate(|document| document.toggle_class("d", "wide"));
+        engine.match_cache.get_mut().clear();
+        let relaid_out = boxes_laid_out(&mut engine);
+
+        // Only `div#d` and its `p` are matched again, with their `::before` and `::after`. The
+        // first `div` is moved into place, without laying out its paragraphs and the anonymous
+        // blocks of their text.
+        assert_eq!(engine.match_cache.borrow().len(), 6);
+        assert_eq!(relaid_out, laid_out - 6);
+
+        // The result is the same as styling and laying out the whole document again.
+        let list = engine.display_list().clone();
+        engine.document_mut();
+        assert_eq!(boxes_laid_out(&mut engine), laid_out);
+        assert_eq!(engine.display_list(), &list);
+    }
+
+    #[test]
+    fn test_add_stylesheet_after_render() {
+        let mut engine = Engine::new(Viewport::new(10.0, 10.0));
+        engine.load_html("<a class=\"x\"></a>").unwrap();
+        engine
+            .add_stylesheet("a { display: block; height: 10px; background: #ff0000; }")
+            .unwrap();
+        assert_eq!(engine.render().pixels[0].g, 0);
+
+        // The rules that matched `a` before are matched again with the new stylesheet.
+        engine
+            .add_stylesheet(".x { background: #00ff00; }")
+            .unwrap();
+        assert_eq!(engine.render().pixels[0].g, 255);
+        assert_eq!(engine.render().pixels[0].r, 0);
+    }
+
+    #[test]
+    fn test_zoom() {
+        let mut engine = Engine::new(Viewport::new(60.0, 40.0));
+        engine.load_html("<p>aaaa bbbb</p>").unwrap();
+        engine
+            .add_stylesheet("p { font-size: 10px; background: #ff0000; }")
+            .unwrap();
+        assert_eq!(engine.render().pixels[30 * 60].g, 255);
+
+        // At twice the size, the words don't fit next to each other any more.
+        engine.set_zoom(2.0);
+        assert_eq!(engine.viewport().zoom, 2.0);
+        assert_eq!(engine.render().pixels[30 * 60].g, 0);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let html = "<a><b id=\"list\"><c></c><d></d></b></a>";
+        let css = "a, b, c, d { display: block; height: 10px; } \
+                   b { overflow: scroll; } d { background: #ff0000; } a { height: 40px; }";
+        let mut engine = Engine::new(Viewport::new(10.0, 20.0));
+        engine.load_html(html).unwrap();
+        engine.add_stylesheet(css).unwrap();
+        assert_eq!(engine.render().pixels[5 * 10].g, 255);
+
+        // The content of the scroll container moves within it, without being laid out again.
+        engine.set_scroll_offset(ScrollTarget::Element("list".to_owned()), 0.0, 10.0);
+        assert!(engine.layers.is_some());
+        assert_eq!(engine.render().pixels[5 * 10].g, 0);
+        assert_eq!(engine.render().pixels[15 * 10].g, 255);
+
+        // Scrolling the viewport moves the scroll container as well.
+        engine.set_scroll_offset(ScrollTarget::Root, 0.0, 5.0);
+        let canvas = engine.render();
+        assert_eq!(canvas.pixels[4 * 10].g, 0);
+        assert_eq!(canvas.pixels[5 * 10].g, 255);
+        assert_eq!(engine.scroll_offset(&ScrollTarget::Root), (0.0, 5.0));
+    }
+
+    #[test]
+    fn test_viewport() {
+        let mut viewport = Viewport::new(40.0, 30.0);
+        viewport.dpr = 2.0;
+
+        let mut engine = Engine::new(viewport);
+        engine.load_html("<a><b></b></a>").unwrap();
+        engine
+            .add_stylesheet("a, b { display: block; height: 10px; }")
+            .unwrap();
+        engine
+            .add_stylesheet("b { width: 50vh; background: #ff0000; }")
+            .unwrap();
+
+        // Half of the viewport height is 15px, or 30 device pixels.
+        let canvas = engine.render();
+        assert_eq!((canvas.width, canvas.height), (80, 60));
+        assert_eq!(canvas.pixels[19 * 80 + 29].g, 0);
+        assert_eq!(canvas.pixels[19 * 80 + 30].g, 255);
+        assert_eq!(canvas.pixels[20 * 80].g, 255);
+
+        // Zooming in leaves a viewport of 20 by 15px, which is magnified.
+        viewport.dpr = 1.0;
+        viewport.zoom = 2.0;
+        engine.set_viewport(viewport);
+        let canvas = engine.render();
+        assert_eq!((canvas.width, canvas.height), (40, 30));
+        assert_eq!(canvas.pixels[19 * 40 + 14].g, 0);
+        assert_eq!(canvas.pixels[19 * 40 + 15].g, 255);
+    }
+
+    #[test]
+    fn test_frames() {
+        let loader = MemoryLoader(vec![
+            (
+                "http://example.com/index.html",
+                "<html>\
+                    <div id=\"a\"><iframe src=\"frame.html\"></iframe></div>\
+                    <iframe srcdoc=\"<html><div></div><style>div { background: #0000ff; }\
+                        div { height: 20px; }</style></html>\"></iframe>\
+                </html>",
+            ),
+            (
+                "http://example.com/frame.html",
+                "<html><div></div><style>div { height: 20px; background: #00ff00; }</style></html>",
+            ),
+        ]);
+        let mut engine = Engine::new(Viewport::new(40.0, 40.0));
+        let url = Url::parse("http://example.com/index.html").unwrap();
+        engine.load_document(&loader, &url).unwrap();
+        engine
+            .add_stylesheet(
+                "#a { padding: 5px; } \
+                iframe { display: block; width: 10px; height: 10px; border-width: 0; }",
+            )
+            .unwrap();
+
+        let pixel = |engine: &mut Engine, x: usize, y: usize| {
+            let pixel = engine.render().pixels[y * 40 + x].clone();
+            (pixel.r, pixel.g, pixel.b)
+        };
+
+        // The frame with a `src` is empty until its document is loaded.
+        assert_eq!(pixel(&mut engine, 6, 6), (255, 255, 255));
+        assert_eq!(pixel(&mut engine, 5, 25), (0, 0, 255));
+
+        // The documents in frames are offset to their content box, and clipped to it.
+        engine.load_frames(&loader);
+        assert_eq!(pixel(&mut engine, 4, 6), (255, 255, 255));
+        assert_eq!(pixel(&mut engine, 5, 5), (0, 255, 0));
+        assert_eq!(pixel(&mut engine, 14, 14), (0, 255, 0));
+        assert_eq!(pixel(&mut engine, 15, 14), (255, 255, 255));
+        assert_eq!(pixel(&mut engine, 14, 15), (255, 255, 255));
+        assert_eq!(pixel(&mut engine, 9, 29), (0, 0, 255));
+        assert_eq!(pixel(&mut engine, 10, 29), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_print() {
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        engine
+            .load_html("<html><a></a><b></b><c></c></html>")
+            .unwrap();
+        engine
+            .add_stylesheet(
+                "@page { size: 100px 60px; margin: 10px; } \
+                a, b, c { display: block; height: 30px; background: #ff0000; } \
+                @media print { a { height: 10px; } } \
+                @media screen { b { display: none; } }",
+            )
+            .unwrap();
+
+        // Only the rules for print apply, so a and b fit in the page area of 80 by 40px.
+        assert_eq!(engine.page_box().page_area().width, 80.0);
+        let pages = engine.print();
+        assert_eq!(pages.len(), 2);
+        let page = &pages[0];
+        assert_eq!(
+            page[0],
+            DisplayCommand::PushTransform(Transform::translate(10.0, 10.0))
+        );
+        assert_eq!(page.last(), Some(&DisplayCommand::PopTransform));
+        let heights: Vec<_> = page
+            .iter()
+            .filter_map(|command| match command {
+                DisplayCommand::SolidColor(_, rect) => Some(rect.height),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(heights, vec![10.0, 30.0]);
+
+        // Rendering for the screen is left as it was.
+        let canvas = engine.render();
+        assert_eq!(canvas.pixels[29 * 40].g, 0);
+    }
+
+    /// Collects the names of the spans that are entered.
+    struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for SpanNames {
+        fn on_new_span(&self, attrs: &Attributes, _: &Id, _: layer::Context<S>) {
+            let mut names = self.0.lock().unwrap();
+            names.push(attrs.metadata().name().to_owned());
+        }
+    }
+
+    #[test]
+    fn test_tracing() {
+        let mut engine = Engine::new(Viewport::new(20.0, 20.0));
+        engine.load_html("<a></a>").unwrap();
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+        tracing::subscriber::with_default(subscriber, || engine.render());
+
+        let names = names.lock().unwrap();
+        let phases = ["style", "layout", "paint", "display_list", "raster"];
+        assert_eq!(
+            names
+                .iter()
+                .filter(|name| phases.contains(&name.as_str()))
+                .count(),
+            5
+        );
+        assert!(names.contains(&"layout_box".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut engine = Engine::new(Viewport::new(20.0, 20.0));
+        engine.load_html("<a></a>").unwrap();
+        engine
+            .add_stylesheet("a { display: block; height: 10px; background: #ff0000; }")
+            .unwrap();
+
+        let error = engine.add_stylesheet("a { height: 10px; ").unwrap_err();
+        assert!(matches!(error, Error::Css(_)));
+        let error = engine.load_html("<a><b></a>").unwrap_err();
+        assert!(matches!(error, Error::Html(_)));
+        assert!(error.to_string().starts_with("invalid HTML: expected"));
+
+        // The document and the stylesheets before are kept.
+        let layers = engine.build_paint_layers();
+        assert_eq!(layers.len(), 1);
+        assert!(!layers[0].list.is_empty());
+
+        // A style element without valid CSS is left out, and a root that isn't displayed paints
+        // nothing.
+        engine
+            .load_html("<a><style>a { height: </style></a>")
+            .unwrap();
+        engine.add_stylesheet("a { display: none; }").unwrap();
+        assert!(engine.build_paint_layers().is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+//! Estimates of the memory that an engine uses, by the part of the pipeline that uses it, to find
+//! out which part to blame when a large document takes up too much.
+
+use std::fmt;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::css::{
+    AttrOp, Color, Declaration, FontFace, MediaList, Medium, PageRule, Rule, Selector, Sheet, Value,
+};
+use crate::dom::{Atom, Node};
+use crate::engine::Engine;
+use crate::layout::{Glyph, LayoutBox};
+use crate::painting::{DisplayCommand, PaintLayer};
+use crate::style::StyledNode;
+
+/// The memory that a part of an engine uses, and the number of items that it holds, such as
+/// nodes or boxes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryUsage {
+    pub bytes: usize,
+    pub count: usize,
+}
+
+impl MemoryUsage {
+    /// The bytes per item, or 0 if there are none.
+    pub fn average(&self) -> f32 {
+        match self.count {
+            0 => 0.0,
+            count => self.bytes as f32 / count as f32,
+        }
+    }
+
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        self.count += 1;
+    }
+}
+
+/// How much memory each part of an engine uses. Sizes are estimated from the values and the
+/// capacity of their buffers, without what the allocator adds to them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryReport {
+    /// The document, by node.
+    pub dom: MemoryUsage,
+    /// The stylesheets, by rule.
+    pub stylesheets: MemoryUsage,
+    /// The style tree, by styled node. It isn't kept, so it is built again to measure it.
+    pub style_tree: MemoryUsage,
+    /// The layout tree, by box. It isn't kept, so it is laid out again to measure it.
+    pub layout_tree: MemoryUsage,
+    /// The paint layers and the display lists that are cached, by drawing command.
+    pub display_list: MemoryUsage,
+    /// The decoded images, by image.
+    pub images: MemoryUsage,
+    /// The font data and the glyphs that were looked up in it, by face.
+    pub fonts: MemoryUsage,
+    /// The pixels of the canvas, by pixel.
+    pub canvas: MemoryUsage,
+}
+
+impl MemoryReport {
+    /// The bytes that all parts use together.
+    pub fn total(&self) -> usize {
+        self.parts().iter().map(|(_, usage, _)| usage.bytes).sum()
+    }
+
+    fn parts(&self) -> [(&str, MemoryUsage, &str); 8] {
+        [
+            ("dom", self.dom, "nodes"),
+            ("stylesheets", self.stylesheets, "rules"),
+            ("style tree", self.style_tree, "nodes"),
+            ("layout tree", self.layout_tree, "boxes"),
+            ("display list", self.display_list, "commands"),
+            ("images", self.images, "images"),
+            ("fonts", self.fonts, "faces"),
+            ("canvas", self.canvas, "pixels"),
+        ]
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, usage, items) in self.parts() {
+            let (bytes, count, average) = (usage.bytes, usage.count, usage.average().round());
+            writeln!(
+                f,
+                "{name}: {bytes} bytes for {count} {items}, {average} bytes each"
+            )?;
+        }
+        write!(f, "total: {} bytes", self.total())
+    }
+}
+
+impl Engine {
+    /// Estimate how much memory each part of the engine uses: the document, the stylesheets,
+    /// the style and layout trees, the display lists and the caches.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        if let Some(document) = &self.document {
+            count_nodes(document, &mut report.dom);
+            report.dom.bytes += size_of::<Node>();
+
+            let viewport = self.viewport.rect();
+            self.lay_out(
+                document,
+                &self.sheet,
+                Medium::Screen,
+                viewport,
+                0,
+                |style, root| {
+                    count_styled_nodes(style, &mut report.style_tree);
+                    report.style_tree.bytes += size_of::<StyledNode>();
+                    count_boxes(root, &mut report.layout_tree);
+                    report.layout_tree.bytes += size_of::<LayoutBox>();
+                },
+            );
+        }
+
+        let sheet = &self.sheet;
+        report.stylesheets = MemoryUsage {
+            bytes: size_of::<Sheet>() + sheet.heap_size(),
+            count: sheet.rules.len(),
+        };
+
+        let layers = self.layers.iter().flatten();
+        let lists = self.display_list.iter().chain(&self.painted);
+        report.display_list = MemoryUsage {
+            bytes: self.layers.heap_size()
+                + self.display_list.heap_size()
+                + self.painted.heap_size(),
+            count: layers.map(|layer| layer.list.len()).sum::<usize>()
+                + lists.map(Vec::len).sum::<usize>(),
+        };
+
+        report.images = self.images.memory_usage();
+        report.fonts = self.fonts.memory_usage();
+        report.canvas = MemoryUsage {
+            bytes: self.canvas.pixels.capacity() * size_of::<Color>(),
+            count: self.canvas.pixels.len(),
+        };
+        report
+    }
+}
+
+fn count_nodes(node: &Node, usage: &mut MemoryUsage) {
+    usage.add(node.heap_size());
+    if let Node::Element { children, .. } = node {
+        children.iter().for_each(|child| count_nodes(child, usage));
+    }
+}
+
+fn count_styled_nodes(style: &StyledNode, usage: &mut MemoryUsage) {
+    usage.add(style.heap_size());
+    let pseudo_elements = style.before.iter().chain(&style.after);
+    pseudo_elements.for_each(|pseudo_element| count_styled_nodes(pseudo_element, usage));
+    style
+        .children
+        .iter()
+        .for_each(|child| count_styled_nodes(child, usage));
+}
+
+fn count_boxes(layout_box: &LayoutBox, usage: &mut MemoryUsage) {
+    usage.add(layout_box.heap_size());
+    layout_box
+        .children
+        .iter()
+        .for_each(|child| count_boxes(child, usage));
+}
+
+/// The bytes that a value owns on the heap, besides its own size. The children of nodes and
+/// boxes are counted by their parents for the room they take in the list of children, and by
+/// themselves for the rest.
+trait HeapSize {
+    fn heap_size(&self) -> usize;
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<[T]> {
+    fn heap_size(&self) -> usize {
+        self.len() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl HeapSize for Value {
+    fn heap_size(&self) -> usize {
+        match self {
+            Value::Keyword(s) | Value::Str(s) => s.heap_size(),
+            Value::List(values) | Value::CommaList(values) => values.heap_size(),
+            Value::Function(name, arguments) => name.heap_size() + arguments.heap_size(),
+            Value::Length(..) | Value::Number(_) | Value::ColorValue(_) => 0,
+        }
+    }
+}
+
+// The names of declarations are interned, so they aren't counted.
+impl HeapSize for Declaration {
+    fn heap_size(&self) -> usize {
+        self.value.heap_size()
+    }
+}
+
+impl HeapSize for Selector {
+    fn heap_size(&self) -> usize {
+        let attr = self.attr.capacity() * size_of::<(String, AttrOp, String)>()
+            + self
+                .attr
+                .iter()
+                .map(|(name, _, value)| name.heap_size() + value.heap_size())
+                .sum::<usize>();
+        self.tag.heap_size()
+            + self.class.heap_size()
+            + self.id.heap_size()
+            + attr
+            + self.pseudo_element.heap_size()
+    }
+}
+
+impl HeapSize for MediaList {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
+impl HeapSize for Rule {
+    fn heap_size(&self) -> usize {
+        self.selectors.heap_size() + self.declarations.heap_size() + self.media.heap_size()
+    }
+}
+
+impl HeapSize for FontFace {
+    fn heap_size(&self) -> usize {
+        self.descriptors.heap_size()
+    }
+}
+
+impl HeapSize for PageRule {
+    fn heap_size(&self) -> usize {
+        self.selector.heap_size() + self.declarations.heap_size()
+    }
+}
+
+impl HeapSize for Sheet {
+    fn heap_size(&self) -> usize {
+        self.imports.heap_size()
+            + self.rules.heap_size()
+            + self.font_faces.heap_size()
+            + self.pages.heap_size()
+    }
+}
+
+// Tag names and attribute names are interned, so they aren't counted. Attributes are only on the
+// heap once there are too many to store inline.
+impl HeapSize for Node {
+    fn heap_size(&self) -> usize {
+        match self {
+            Node::Element {
+                attrs, children, ..
+            } => {
+                let attrs_size = match attrs.spilled() {
+                    true => attrs.capacity() * size_of::<(Atom, String)>(),
+                    false => 0,
+                };
+                let values = attrs.iter().map(|(_, value)| value.heap_size());
+                attrs_size + values.sum::<usize>() + children.capacity() * size_of::<Node>()
+            }
+            Node::Text(text) => text.heap_size(),
+        }
+    }
+}
+
+impl HeapSize for StyledNode<'_> {
+    fn heap_size(&self) -> usize {
+        // A hash map holds a byte of control data with each entry. Nodes that share their values
+        // are each charged their share.
+        let values = &self.specified_values;
+        let entries = values.capacity() * (size_of::<(String, Value)>() + 1);
+        let contents = values
+            .iter()
+            .map(|(name, value)| name.heap_size() + value.heap_size());
+        let shared = (entries + contents.sum::<usize>()) / Arc::strong_count(values);
+        let pseudo_elements = self.before.iter().chain(&self.after).count();
+        shared
+            + self.children.capacity() * size_of::<StyledNode>()
+            + pseudo_elements * size_of::<StyledNode>()
+    }
+}
+
+impl HeapSize for Glyph {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for LayoutBox<'_> {
+    fn heap_size(&self) -> usize {
+        let glyphs = self.glyph_run().map_or(0, |run| run.glyphs.heap_size());
+        let frame = self.frame().map_or(0, HeapSize::heap_size);
+        self.children.capacity() * size_of::<LayoutBox>() + glyphs + frame
+    }
+}
+
+impl HeapSize for DisplayCommand {
+    fn heap_size(&self) -> usize {
+        match self {
+            DisplayCommand::Text { glyphs, .. } => glyphs.heap_size(),
+            _ => 0,
+        }
+    }
+}
+
+impl HeapSize for PaintLayer {
+    fn heap_size(&self) -> usize {
+        self.list.heap_size() + self.id.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::*;
+
+    #[test]
+    fn test_memory_report() {
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        let empty = engine.memory_report();
+        assert_eq!(empty.dom, MemoryUsage::default());
+        assert_eq!(empty.display_list, MemoryUsage::default());
+
+        let html = "<a><b class=\"x\">text</b><c></c></a>";
+        engine.load_html(html).unwrap();
+        engine
+            .add_stylesheet("a, b, c { display: block; }")
+            .unwrap();
+        engine.render();
+
+        let report = engine.memory_report();
+        assert_eq!(report.dom.count, 4);
+        assert_eq!(report.style_tree.count, 4);
+        assert!(report.layout_tree.count >= 4);
+        assert!(report.display_list.count > 0);
+        assert_eq!(report.canvas.count, 40 * 30);
+        assert!(report.dom.average() > 0.0);
+        assert!(report.total() > report.canvas.bytes);
+        assert!(report.to_string().starts_with("dom: "));
+    }
+}
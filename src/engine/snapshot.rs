@@ -0,0 +1,150 @@
+//! Snapshots of the state of an engine, which can be stored as JSON and restored later, for
+//! instance to checkpoint a long-running render or to replay a document that failed.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::css::{Color, Sheet, Value};
+use crate::dom::Node;
+use crate::engine::{Engine, ScrollTarget};
+use crate::layout::{Dimensions, LayoutBox, Viewport};
+use crate::style::StyledNode;
+use crate::Error;
+
+/// The state of an engine: its document and stylesheets, and the styles and the layout that
+/// follow from them. Fonts and images aren't part of a snapshot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub document: Option<Node>,
+    pub base: Option<String>,
+    pub sheet: Sheet,
+    pub viewport: Viewport,
+    pub background: Color,
+    /// How far the viewport and the scroll containers are scrolled, in order of their targets.
+    pub scroll_offsets: Vec<(ScrollTarget, (f32, f32))>,
+    /// The documents of the frames, by the `src` of their `iframe` elements.
+    pub frames: BTreeMap<String, String>,
+    /// The specified values of the styled nodes, in tree order.
+    pub styles: Vec<BTreeMap<String, Value>>,
+    /// The dimensions of the layout boxes, in tree order.
+    pub boxes: Vec<Dimensions>,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("snapshots serialize to JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<Snapshot, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+impl Engine {
+    /// Take a snapshot of the state of the engine, with the styles and the layout of the document
+    /// on screen.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut styles = Vec::new();
+        let mut boxes = Vec::new();
+        if let Some(document) = &self.document {
+            let viewport = self.viewport.rect();
+            self.lay_out(
+                document,
+                &self.sheet,
+                Default::default(),
+                viewport,
+                0,
+                |style, root| {
+                    collect_styles(style, &mut styles);
+                    collect_boxes(root, &mut boxes);
+                },
+            );
+        }
+
+        let mut scroll_offsets: Vec<_> = self
+            .scroll_offsets
+            .iter()
+            .map(|(target, offset)| (target.clone(), *offset))
+            .collect();
+        scroll_offsets.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Snapshot {
+            document: self.document.clone(),
+            base: self.base.as_ref().map(Url::to_string),
+            sheet: self.sheet.clone(),
+            viewport: self.viewport,
+            background: self.background.clone(),
+            scroll_offsets,
+            frames: self.frames.clone().into_iter().collect(),
+            styles,
+            boxes,
+        }
+    }
+
+    /// Restore the state of the engine from a snapshot. The styles and the layout are computed
+    /// again from the document, and the fonts and images that were loaded are kept.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.document = snapshot.document;
+        self.base = snapshot.base.and_then(|base| Url::parse(&base).ok());
+        self.sheet = snapshot.sheet;
+        self.viewport = snapshot.viewport;
+        self.background = snapshot.background;
+        self.scroll_offsets = snapshot.scroll_offsets.into_iter().collect();
+        self.frames = snapshot.frames.into_iter().collect();
+        self.invalidate_sheet();
+    }
+}
+
+fn collect_styles(style: &StyledNode, styles: &mut Vec<BTreeMap<String, Value>>) {
+    let values = style.specified_values.iter();
+    styles.push(
+        values
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+    );
+    if let Some(before) = &style.before {
+        collect_styles(before, styles);
+    }
+    for child in &style.children {
+        collect_styles(child, styles);
+    }
+    if let Some(after) = &style.after {
+        collect_styles(after, styles);
+    }
+}
+
+fn collect_boxes(layout_box: &LayoutBox, boxes: &mut Vec<Dimensions>) {
+    boxes.push(layout_box.dimensions);
+    for child in &layout_box.children {
+        collect_boxes(child, boxes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::*;
+
+    #[test]
+    fn test_snapshot() {
+        let html = "<a><b></b><c>text</c></a>";
+        let css = "a, b, c { display: block; height: 10px; } b { background: #ff0000; }";
+        let mut engine = Engine::new(Viewport::new(40.0, 30.0));
+        engine.load_html(html).unwrap();
+        engine.add_stylesheet(css).unwrap();
+        engine.set_scroll_offset(ScrollTarget::Root, 0.0, 5.0);
+
+        let snapshot = engine.snapshot();
+        assert_eq!(snapshot.boxes.len(), 6);
+        assert_eq!(snapshot.boxes[1].content.height, 10.0);
+
+        let json = snapshot.to_json();
+        let mut restored = Engine::new(Viewport::new(10.0, 10.0));
+        restored.restore(Snapshot::from_json(&json).unwrap());
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.render().clone(), *engine.render());
+
+        assert!(Snapshot::from_json("{").is_err());
+    }
+}
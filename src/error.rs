@@ -0,0 +1,108 @@
+//! The errors of rendering a document: parsing it and its stylesheets, and loading the resources
+//! that it refers to.
+
+use std::fmt;
+
+use crate::loader::LoadError;
+use crate::{css, html};
+
+/// Why a document, or a part of it, couldn't be rendered.
+#[derive(Debug)]
+pub enum Error {
+    /// A document that isn't valid HTML.
+    Html(html::ParseError),
+    /// A stylesheet that isn't valid CSS.
+    Css(css::ParseError),
+    /// A resource that couldn't be fetched.
+    Load(LoadError),
+    /// Font data that isn't a TrueType or OpenType font.
+    Font(ttf_parser::FaceParsingError),
+    /// An image that couldn't be decoded or encoded.
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+    /// A snapshot that isn't valid JSON, or doesn't describe the state of an engine.
+    #[cfg(feature = "serde")]
+    Snapshot(serde_json::Error),
+    /// A window that couldn't be opened to preview a document.
+    #[cfg(feature = "preview")]
+    Window(glium::backend::glutin::DisplayCreationError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Html(error) => write!(f, "invalid HTML: {error}"),
+            Error::Css(error) => write!(f, "invalid CSS: {error}"),
+            Error::Load(error) => write!(f, "can't load resource: {error}"),
+            Error::Font(error) => write!(f, "invalid font: {error}"),
+            #[cfg(feature = "image")]
+            Error::Image(error) => write!(f, "image error: {error}"),
+            #[cfg(feature = "serde")]
+            Error::Snapshot(error) => write!(f, "invalid snapshot: {error}"),
+            #[cfg(feature = "preview")]
+            Error::Window(error) => write!(f, "can't open window: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Html(error) => Some(error),
+            Error::Css(error) => Some(error),
+            Error::Load(error) => Some(error),
+            Error::Font(error) => Some(error),
+            #[cfg(feature = "image")]
+            Error::Image(error) => Some(error),
+            #[cfg(feature = "serde")]
+            Error::Snapshot(error) => Some(error),
+            #[cfg(feature = "preview")]
+            Error::Window(error) => Some(error),
+        }
+    }
+}
+
+impl From<html::ParseError> for Error {
+    fn from(error: html::ParseError) -> Error {
+        Error::Html(error)
+    }
+}
+
+impl From<css::ParseError> for Error {
+    fn from(error: css::ParseError) -> Error {
+        Error::Css(error)
+    }
+}
+
+impl From<LoadError> for Error {
+    fn from(error: LoadError) -> Error {
+        Error::Load(error)
+    }
+}
+
+impl From<ttf_parser::FaceParsingError> for Error {
+    fn from(error: ttf_parser::FaceParsingError) -> Error {
+        Error::Font(error)
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Error {
+        Error::Image(error)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        Error::Snapshot(error)
+    }
+}
+
+#[cfg(feature = "preview")]
+impl From<glium::backend::glutin::DisplayCreationError> for Error {
+    fn from(error: glium::backend::glutin::DisplayCreationError) -> Error {
+        Error::Window(error)
+    }
+}
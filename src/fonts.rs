@@ -0,0 +1,707 @@
+//! Font faces, how they are chosen for the style of a text, and the metrics of their glyphs that
+//! text is laid out with.
+//!
+//! https://www.w3.org/TR/css-fonts-3/#font-matching-algorithm
+
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::size_of;
+#[cfg(feature = "system-fonts")]
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use ttf_parser::name_id;
+
+use crate::css::Sheet;
+use crate::engine::MemoryUsage;
+use crate::style::{font_family, font_style, font_weight, FontStyle, StyledNode};
+
+/// The generic font families, which stand for a family that the context picks.
+///
+/// https://www.w3.org/TR/css-fonts-3/#generic-font-families
+const GENERIC_FAMILIES: [(&str, &str); 5] = [
+    ("serif", "Times New Roman"),
+    ("sans-serif", "Arial"),
+    ("cursive", "Comic Sans MS"),
+    ("fantasy", "Impact"),
+    ("monospace", "Courier New"),
+];
+
+/// How far synthesized oblique glyphs lean to the right, for each unit of height: about 14
+/// degrees.
+const OBLIQUE_SKEW: f32 = 0.25;
+/// How much wider the strokes of synthesized bold glyphs are, as a fraction of the font size.
+const EMBOLDEN: f32 = 1.0 / 24.0;
+
+/// A reference to a font face in a `FontContext`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FaceId(usize);
+
+/// The vertical metrics of a font face, as fractions of the font size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    /// The height of the face above the baseline.
+    pub ascent: f32,
+    /// The depth of the face below the baseline.
+    pub descent: f32,
+}
+
+/// A glyph that shaping chose for a cluster of characters, with lengths as fractions of the font
+/// size.
+#[cfg(feature = "shaping")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapedGlyph {
+    /// The index of the glyph in its face.
+    pub id: u16,
+    /// The byte offset in the text of the first character that the glyph is for.
+    pub cluster: usize,
+    pub advance: f32,
+    /// How far the glyph is moved to the right of the pen position, as for combining marks.
+    pub offset: f32,
+}
+
+/// The styles that are faked when a family lacks a face for them, by making the glyphs of
+/// another face bolder or slanting them.
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-synthesis-intro
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Synthesis {
+    pub bold: bool,
+    pub oblique: bool,
+}
+
+/// The coverage of a glyph rasterized at a font size, from 0 to 255 for each pixel, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphBitmap {
+    pub width: u32,
+    pub height: u32,
+    /// How far the left edge of the bitmap is to the right of the pen position, in pixels.
+    pub left: i32,
+    /// How far the top edge of the bitmap is above the baseline, in pixels.
+    pub top: i32,
+    pub coverage: Vec<u8>,
+}
+
+/// The font properties of a text, which a face is chosen by.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontQuery {
+    /// The families to choose from, in order of preference.
+    pub families: Vec<String>,
+    pub weight: u16,
+    pub style: FontStyle,
+}
+
+impl FontQuery {
+    /// The font properties of a styled node.
+    pub fn new(style: &StyledNode) -> FontQuery {
+        FontQuery {
+            families: style.font_family(),
+            weight: style.font_weight(),
+            style: style.font_style(),
+        }
+    }
+}
+
+/// Where the data of a face is loaded from.
+enum FontSource {
+    Data(Rc<Vec<u8>>),
+    #[cfg(feature = "system-fonts")]
+    File(PathBuf),
+}
+
+/// A face that text can be set in, and how it can be chosen.
+struct FaceEntry {
+    families: Vec<String>,
+    weight: u16,
+    style: FontStyle,
+    source: FontSource,
+    /// The index of the face in a font collection.
+    index: u32,
+    /// Whether an `@font-face` rule added the face, which hides the other faces of its family.
+    from_font_face: bool,
+    /// The face once it was needed, or `None` if it couldn't be loaded.
+    face: OnceCell<Option<Face>>,
+}
+
+/// A loaded face, with the glyphs of characters as they are looked up.
+struct Face {
+    data: Rc<Vec<u8>>,
+    index: u32,
+    metrics: FontMetrics,
+    /// The glyph index, if there is one, and the advance of each character.
+    glyphs: RefCell<HashMap<char, (Option<u16>, f32)>>,
+}
+
+/// The font faces that a document can use, which are loaded when text is first set in them.
+#[derive(Default)]
+pub struct FontContext {
+    faces: Vec<FaceEntry>,
+    /// The families that the generic families stand for, by their keyword.
+    generic_families: HashMap<String, String>,
+    /// The faces that were chosen for each query before.
+    selected: RefCell<HashMap<FontQuery, Option<FaceId>>>,
+    /// The faces that were chosen before for characters that the face of their query lacks.
+    fallbacks: RefCell<HashMap<(FontQuery, char), Option<FaceId>>>,
+}
+
+// The data of the faces is left out, because it is large.
+impl fmt::Debug for FontContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FontContext")
+            .field("faces", &self.faces.len())
+            .field("generic_families", &self.generic_families)
+            .finish()
+    }
+}
+
+impl FontContext {
+    /// A context without any faces. Each generic family stands for a family that is common on
+    /// desktop systems.
+    pub fn new() -> FontContext {
+        let mut context = FontContext::default();
+        for (generic, family) in GENERIC_FAMILIES {
+            context.set_generic_family(generic, family);
+        }
+        context
+    }
+
+    /// Choose the family that a generic family, such as `serif`, stands for.
+    pub fn set_generic_family(&mut self, generic: &str, family: &str) {
+        self.generic_families
+            .insert(generic.to_owned(), family.to_owned());
+        self.selected.get_mut().clear();
+        self.fallbacks.get_mut().clear();
+    }
+
+    /// Add the faces in a font file, or in each font of a font collection.
+    pub fn add_font_data(&mut self, data: Vec<u8>) -> Result<(), ttf_parser::FaceParsingError> {
+        let data = Rc::new(data);
+        let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+        for index in 0..count {
+            let face = ttf_parser::Face::parse(&data, index)?;
+            let entry = FaceEntry {
+                families: family_names(&face),
+                weight: face.weight().to_number(),
+                style: match face.style() {
+                    ttf_parser::Style::Normal => FontStyle::Normal,
+                    ttf_parser::Style::Italic => FontStyle::Italic,
+                    ttf_parser::Style::Oblique => FontStyle::Oblique,
+                },
+                source: FontSource::Data(data.clone()),
+                index,
+                from_font_face: false,
+                face: OnceCell::new(),
+            };
+            self.faces.push(entry);
+        }
+        self.selected.get_mut().clear();
+        self.fallbacks.get_mut().clear();
+        Ok(())
+    }
+
+    /// Add the fonts of the `@font-face` rules of a style sheet, by the family, weight and style
+    /// that each rule describes. `fetch` loads the data of a URL. The first source of a rule
+    /// that loads is used, and the family keeps its other faces if none does.
+    pub fn add_font_faces(
+        &mut self,
+        sheet: &Sheet,
+        mut fetch: impl FnMut(&str) -> Option<Vec<u8>>,
+    ) {
+        for font_face in &sheet.font_faces {
+            let descriptor = |name| font_face.descriptor(name);
+            let family = match font_family(descriptor("font-family")).into_iter().next() {
+                Some(family) => family,
+                None => continue,
+            };
+            let data = font_face.sources().into_iter().find_map(|url| {
+                let data = fetch(url)?;
+                ttf_parser::Face::parse(&data, 0).ok()?;
+                Some(data)
+            });
+
+            if let Some(data) = data {
+                self.faces.push(FaceEntry {
+                    families: vec![family],
+                    weight: font_weight(descriptor("font-weight")),
+                    style: font_style(descriptor("font-style")),
+                    source: FontSource::Data(Rc::new(data)),
+                    index: 0,
+                    from_font_face: true,
+                    face: OnceCell::new(),
+                });
+            }
+        }
+        self.selected.get_mut().clear();
+        self.fallbacks.get_mut().clear();
+    }
+
+    /// Add the fonts that are installed on the system, and let the generic families stand for
+    /// the families that the system prefers. The files are only read when text is set in them.
+    #[cfg(feature = "system-fonts")]
+    pub fn load_system_fonts(&mut self) {
+        let mut database = fontdb::Database::new();
+        database.load_system_fonts();
+
+        for info in database.faces() {
+            let source = match &info.source {
+                fontdb::Source::File(path) => FontSource::File(path.clone()),
+                _ => match database.with_face_data(info.id, |data, _| data.to_vec()) {
+                    Some(data) => FontSource::Data(Rc::new(data)),
+                    None => continue,
+                },
+            };
+            self.faces.push(FaceEntry {
+                families: info.families.iter().map(|(name, _)| name.clone()).collect(),
+                weight: info.weight.0,
+                style: match info.style {
+                    fontdb::Style::Normal => FontStyle::Normal,
+                    fontdb::Style::Italic => FontStyle::Italic,
+                    fontdb::Style::Oblique => FontStyle::Oblique,
+                },
+                source,
+                index: info.index,
+                from_font_face: false,
+                face: OnceCell::new(),
+            });
+        }
+
+        let generic_families = [
+            ("serif", fontdb::Family::Serif),
+            ("sans-serif", fontdb::Family::SansSerif),
+            ("cursive", fontdb::Family::Cursive),
+            ("fantasy", fontdb::Family::Fantasy),
+            ("monospace", fontdb::Family::Monospace),
+        ];
+        for (generic, family) in generic_families {
+            let name = database.family_name(&family).to_owned();
+            self.set_generic_family(generic, &name);
+        }
+    }
+
+    /// Choose the face that best matches the font properties of a text, from the first family in
+    /// the query that has any faces. Without a match, the `serif` family is used, and then any
+    /// face at all.
+    ///
+    /// https://www.w3.org/TR/css-fonts-3/#font-style-matching
+    pub fn select(&self, query: &FontQuery) -> Option<FaceId> {
+        if let Some(&selected) = self.selected.borrow().get(query) {
+            return selected;
+        }
+
+        let selected = self.find_face(query, |_| true);
+        self.selected.borrow_mut().insert(query.clone(), selected);
+        selected
+    }
+
+    /// Choose the face for a character of a text. It is the face that `select` chooses, unless
+    /// that face has no glyph for the character. Then the families of the query are tried in
+    /// order, and then the other faces, for the first face that has one.
+    ///
+    /// https://www.w3.org/TR/css-fonts-3/#cluster-matching
+    pub fn select_for_character(&self, query: &FontQuery, character: char) -> Option<FaceId> {
+        let selected = self.select(query)?;
+        if character.is_whitespace() || self.has_glyph(selected, character) {
+            return Some(selected);
+        }
+
+        let key = (query.clone(), character);
+        if let Some(&fallback) = self.fallbacks.borrow().get(&key) {
+            return fallback;
+        }
+
+        let fallback = self
+            .find_face(query, |face| self.has_glyph(face, character))
+            .or(Some(selected));
+        self.fallbacks.borrow_mut().insert(key, fallback);
+        fallback
+    }
+
+    /// The best match for a query of the faces that can be loaded and are accepted, from the
+    /// first family that has one.
+    fn find_face(&self, query: &FontQuery, accept: impl Fn(FaceId) -> bool) -> Option<FaceId> {
+        let families = query.families.iter().map(String::as_str).chain(["serif"]);
+        families
+            .map(|family| match self.generic_families.get(family) {
+                Some(name) => Some(name.as_str()),
+                None => Some(family),
+            })
+            .chain([None])
+            .find_map(|family| {
+                let mut candidates: Vec<_> = (0..self.faces.len())
+                    .filter(|&i| {
+                        let names = &self.faces[i].families;
+                        family.is_none_or(|family| {
+                            names.iter().any(|name| name.eq_ignore_ascii_case(family))
+                        })
+                    })
+                    .collect();
+                if candidates.iter().any(|&i| self.faces[i].from_font_face) {
+                    candidates.retain(|&i| self.faces[i].from_font_face);
+                }
+                candidates.sort_by_key(|&i| {
+                    let face = &self.faces[i];
+                    (
+                        style_rank(query.style, face.style),
+                        weight_rank(query.weight, face.weight),
+                    )
+                });
+                candidates
+                    .into_iter()
+                    .map(FaceId)
+                    .find(|&face| self.face(face).is_some() && accept(face))
+            })
+    }
+
+    /// The styles that are synthesized when text with the font properties of a query is set in a
+    /// face: bold for bold text in a face that isn't, and oblique for italic or oblique text in
+    /// an upright face.
+    pub fn synthesis(&self, query: &FontQuery, face: FaceId) -> Synthesis {
+        let entry = &self.faces[face.0];
+        Synthesis {
+            bold: query.weight >= 600 && entry.weight < 600,
+            oblique: query.style != FontStyle::Normal && entry.style == FontStyle::Normal,
+        }
+    }
+
+    /// The vertical metrics of a face.
+    pub fn metrics(&self, face: FaceId) -> FontMetrics {
+        self.loaded(face).metrics
+    }
+
+    /// How far a character moves the pen along the baseline in a face, as a fraction of the font
+    /// size. Characters without a glyph in the face are as wide as its missing glyph.
+    pub fn advance(&self, face: FaceId, character: char) -> f32 {
+        self.glyph(face, character).1
+    }
+
+    /// The index of the glyph for a character in a face, if it has one.
+    pub fn glyph_index(&self, face: FaceId, character: char) -> Option<u16> {
+        self.glyph(face, character).0
+    }
+
+    pub fn has_glyph(&self, face: FaceId, character: char) -> bool {
+        self.glyph_index(face, character).is_some()
+    }
+
+    /// The glyph index and the advance of a character in a face.
+    fn glyph(&self, face: FaceId, character: char) -> (Option<u16>, f32) {
+        let face = self.loaded(face);
+        if let Some(&glyph) = face.glyphs.borrow().get(&character) {
+            return glyph;
+        }
+
+        let parsed = ttf_parser::Face::parse(&face.data, face.index).unwrap();
+        let id = parsed.glyph_index(character);
+        let units = parsed
+            .glyph_hor_advance(id.unwrap_or(ttf_parser::GlyphId(0)))
+            .unwrap_or(0);
+        let glyph = (
+            id.map(|id| id.0),
+            units as f32 / parsed.units_per_em() as f32,
+        );
+        face.glyphs.borrow_mut().insert(character, glyph);
+        glyph
+    }
+
+    /// Rasterize the outline of a glyph at a font size in pixels, with the styles that are
+    /// synthesized for it. Glyphs without an outline, such as spaces, have no bitmap.
+    pub fn rasterize(
+        &self,
+        face: FaceId,
+        id: u16,
+        size: f32,
+        synthesis: Synthesis,
+    ) -> Option<GlyphBitmap> {
+        let face = self.loaded(face);
+        let parsed = ttf_parser::Face::parse(&face.data, face.index).unwrap();
+        let id = ttf_parser::GlyphId(id);
+        let scale = size / parsed.units_per_em() as f32;
+        let skew = if synthesis.oblique { OBLIQUE_SKEW } else { 0.0 };
+        let embolden = if synthesis.bold { EMBOLDEN * size } else { 0.0 };
+
+        // Slanting moves the top of the glyph to the right, and emboldening widens it.
+        let bounds = parsed.glyph_bounding_box(id)?;
+        let (x_min, x_max) = (bounds.x_min as f32 * scale, bounds.x_max as f32 * scale);
+        let (y_min, y_max) = (bounds.y_min as f32 * scale, bounds.y_max as f32 * scale);
+        let left = (x_min + skew * y_min).floor() as i32;
+        let right = (x_max + skew * y_max + embolden).ceil() as i32;
+        let top = y_max.ceil() as i32;
+        let bottom = y_min.floor() as i32;
+        let (width, height) = ((right - left) as u32, (top - bottom) as u32);
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let mut outline = Outline {
+            rasterizer: Rasterizer::new(width as usize, height as usize),
+            scale,
+            skew,
+            origin: (left as f32, top as f32),
+            start: point(0.0, 0.0),
+            pen: point(0.0, 0.0),
+        };
+        parsed.outline_glyph(id, &mut outline)?;
+
+        // A bold glyph is drawn twice, side by side, which thickens its vertical strokes.
+        if synthesis.bold {
+            outline.origin.0 -= embolden;
+            parsed.outline_glyph(id, &mut outline)?;
+        }
+
+        let mut coverage = vec![0; (width * height) as usize];
+        outline.rasterizer.for_each_pixel(|i, alpha| {
+            coverage[i] = (alpha.min(1.0) * 255.0).round() as u8;
+        });
+        Some(GlyphBitmap {
+            width,
+            height,
+            left,
+            top,
+            coverage,
+        })
+    }
+
+    /// Shape a text in logical order with a face, which applies its kerning and ligatures and
+    /// joins the characters of scripts such as Arabic. The glyphs are in visual order, from left
+    /// to right.
+    #[cfg(feature = "shaping")]
+    pub fn shape(&self, face: FaceId, text: &str, rtl: bool) -> Vec<ShapedGlyph> {
+        let face = self.loaded(face);
+        let shaper = rustybuzz::Face::from_slice(&face.data, face.index).unwrap();
+
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.set_direction(match rtl {
+            true => rustybuzz::Direction::RightToLeft,
+            false => rustybuzz::Direction::LeftToRight,
+        });
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(&shaper, &[], buffer);
+
+        let units_per_em = shaper.units_per_em() as f32;
+        output
+            .glyph_infos()
+            .iter()
+            .zip(output.glyph_positions())
+            .map(|(info, position)| ShapedGlyph {
+                id: info.glyph_id as u16,
+                cluster: info.cluster as usize,
+                advance: position.x_advance as f32 / units_per_em,
+                offset: position.x_offset as f32 / units_per_em,
+            })
+            .collect()
+    }
+
+    /// The memory that the data of the faces takes up, with the glyphs that were looked up in
+    /// them. Faces of the same font collection share their data, which is counted once.
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        let mut data: Vec<&Rc<Vec<u8>>> = Vec::new();
+        let mut glyphs = 0;
+        for entry in &self.faces {
+            match &entry.source {
+                FontSource::Data(source) => data.push(source),
+                #[cfg(feature = "system-fonts")]
+                FontSource::File(_) => {}
+            }
+            if let Some(Some(face)) = entry.face.get() {
+                data.push(&face.data);
+                let entry_size = size_of::<(char, (Option<u16>, f32))>() + 1;
+                glyphs += face.glyphs.borrow().capacity() * entry_size;
+            }
+        }
+        data.sort_by_key(|data| Rc::as_ptr(data));
+        data.dedup_by_key(|data| Rc::as_ptr(data));
+        MemoryUsage {
+            bytes: data.iter().map(|data| data.capacity()).sum::<usize>() + glyphs,
+            count: self.faces.len(),
+        }
+    }
+
+    /// Load a face if it wasn't loaded before.
+    fn face(&self, face: FaceId) -> Option<&Face> {
+        let entry = &self.faces[face.0];
+        entry
+            .face
+            .get_or_init(|| {
+                let data = match &entry.source {
+                    FontSource::Data(data) => data.clone(),
+                    #[cfg(feature = "system-fonts")]
+                    FontSource::File(path) => Rc::new(std::fs::read(path).ok()?),
+                };
+                let parsed = ttf_parser::Face::parse(&data, entry.index).ok()?;
+                let units_per_em = parsed.units_per_em() as f32;
+                let metrics = FontMetrics {
+                    ascent: parsed.ascender() as f32 / units_per_em,
+                    descent: -parsed.descender() as f32 / units_per_em,
+                };
+                Some(Face {
+                    data,
+                    index: entry.index,
+                    metrics,
+                    glyphs: RefCell::new(HashMap::new()),
+                })
+            })
+            .as_ref()
+    }
+
+    /// A face that was selected, which means it could be loaded.
+    fn loaded(&self, face: FaceId) -> &Face {
+        self.face(face).expect("Selected faces are loaded")
+    }
+}
+
+/// Draws the outline of a glyph in font units into a bitmap, with the y axis pointing down.
+struct Outline {
+    rasterizer: Rasterizer,
+    scale: f32,
+    /// How far the outline moves to the right for each unit that it goes up.
+    skew: f32,
+    /// The top left corner of the bitmap, in pixels from the pen position.
+    origin: (f32, f32),
+    start: Point,
+    pen: Point,
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> Point {
+        let (x, y) = ((x + self.skew * y) * self.scale, y * self.scale);
+        point(x - self.origin.0, self.origin.1 - y)
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.pen = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.rasterizer.draw_line(self.pen, to);
+        self.pen = to;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (control, to) = (self.point(x1, y1), self.point(x, y));
+        self.rasterizer.draw_quad(self.pen, control, to);
+        self.pen = to;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (first, second) = (self.point(x1, y1), self.point(x2, y2));
+        let to = self.point(x, y);
+        self.rasterizer.draw_cubic(self.pen, first, second, to);
+        self.pen = to;
+    }
+
+    fn close(&mut self) {
+        if self.pen != self.start {
+            self.rasterizer.draw_line(self.pen, self.start);
+        }
+        self.pen = self.start;
+    }
+}
+
+/// The names of the family of a face, with the typographic family before the legacy family that
+/// only groups four styles together.
+fn family_names(face: &ttf_parser::Face) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for id in [name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY] {
+        let found = face.names().into_iter().filter(|name| name.name_id == id);
+        for name in found.filter_map(|name| name.to_string()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// How well the style of a face matches the desired style, where lower is better.
+fn style_rank(desired: FontStyle, style: FontStyle) -> u8 {
+    let order = match desired {
+        FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+        FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+        FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+    };
+    order.iter().position(|&s| s == style).unwrap() as u8
+}
+
+/// How well the weight of a face matches the desired weight, where lower is better. Around the
+/// normal weight, slightly bolder faces are preferred. Lighter weights prefer lighter faces and
+/// bolder weights prefer bolder faces.
+fn weight_rank(desired: u16, weight: u16) -> (u8, u16) {
+    let distance = desired.abs_diff(weight);
+    if (400..=500).contains(&desired) {
+        if (desired..=500).contains(&weight) {
+            (0, distance)
+        } else if weight < desired {
+            (1, distance)
+        } else {
+            (2, distance)
+        }
+    } else if desired < 400 {
+        (u8::from(weight > desired), distance)
+    } else {
+        (u8::from(weight < desired), distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fonts::*;
+
+    #[test]
+    fn test_weight_rank() {
+        let closest = |desired, weights: &[u16]| {
+            weights
+                .iter()
+                .copied()
+                .min_by_key(|&weight| weight_rank(desired, weight))
+                .unwrap()
+        };
+
+        assert_eq!(closest(400, &[300, 500, 700]), 500);
+        assert_eq!(closest(400, &[300, 600]), 300);
+        assert_eq!(closest(300, &[200, 400]), 200);
+        assert_eq!(closest(600, &[500, 800]), 800);
+        assert_eq!(closest(600, &[300, 500]), 500);
+    }
+
+    #[test]
+    fn test_select_without_faces() {
+        let context = FontContext::new();
+        let query = FontQuery {
+            families: vec!["serif".to_owned()],
+            weight: 400,
+            style: FontStyle::Normal,
+        };
+        assert_eq!(context.select(&query), None);
+    }
+
+    #[test]
+    fn test_font_faces_that_fail_to_load() {
+        let sheet =
+            Sheet::from("@font-face { font-family: Web; src: url(missing.ttf), url(broken.ttf); }");
+
+        let mut fetched = Vec::new();
+        let mut context = FontContext::new();
+        context.add_font_faces(&sheet, |url| {
+            fetched.push(url.to_owned());
+            (url == "broken.ttf").then(|| b"not a font".to_vec())
+        });
+
+        // Every source is tried, and the family is left to the other faces.
+        assert_eq!(fetched, vec!["missing.ttf", "broken.ttf"]);
+        let query = FontQuery {
+            families: vec!["Web".to_owned()],
+            weight: 400,
+            style: FontStyle::Normal,
+        };
+        assert_eq!(context.select(&query), None);
+    }
+}
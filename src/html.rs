@@ -1,5 +1,37 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "html5ever")]
+use html5ever::tendril::TendrilSink;
+#[cfg(feature = "html5ever")]
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+
 use crate::dom;
 
+/// Elements that can't have children, and so have no end tag.
+///
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Why a document couldn't be parsed: what the parser expected, and where.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// The byte offset in the document.
+    pub offset: usize,
+    pub expected: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {} at byte {}", self.expected, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 pub struct Parser {
     cursor: usize,
     data: String,
@@ -27,6 +59,22 @@ impl Parser {
         current_char
     }
 
+    /// Consume a character, or fail if it isn't the expected one.
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.eof() || self.next_char() != expected {
+            return Err(self.error(format!("'{expected}'")));
+        }
+        self.consume_char();
+        Ok(())
+    }
+
+    fn error(&self, expected: String) -> ParseError {
+        ParseError {
+            offset: self.cursor,
+            expected,
+        }
+    }
+
     fn consume_while<F>(&mut self, test: F) -> String
     where
         F: Fn(char) -> bool,
@@ -46,10 +94,10 @@ impl Parser {
         self.consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
     }
 
-    fn parse_node(&mut self) -> dom::Node {
+    fn parse_node(&mut self) -> Result<dom::Node, ParseError> {
         match self.next_char() {
             '<' => self.parse_element(),
-            _ => self.parse_text(),
+            _ => Ok(self.parse_text()),
         }
     }
 
@@ -57,96 +105,217 @@ impl Parser {
         dom::text(&self.consume_while(|c| c != '<'))
     }
 
-    fn parse_element(&mut self) -> dom::Node {
-        assert!(self.consume_char() == '<');
+    fn parse_element(&mut self) -> Result<dom::Node, ParseError> {
+        self.expect('<')?;
         let tag_name = self.parse_tag_name();
-        let attrs = self.parse_attributes();
-        assert!(self.consume_char() == '>');
+        let attrs = self.parse_attributes()?;
 
-        let children = self.parse_nodes();
+        // A tag that closes itself, like `<br/>`, is an element without children.
+        if self.starts_with("/") {
+            self.expect('/')?;
+            self.expect('>')?;
+            return Ok(dom::elem(&tag_name).add_attrs(attrs));
+        }
+        self.expect('>')?;
 
-        assert!(self.consume_char() == '<');
-        assert!(self.consume_char() == '/');
-        assert!(self.parse_tag_name() == tag_name);
-        assert!(self.consume_char() == '>');
+        // Void elements end at their start tag. An end tag right after it is skipped, as in
+        // `<br></br>`.
+        if VOID_ELEMENTS.contains(&tag_name.to_ascii_lowercase().as_str()) {
+            let start = self.cursor;
+            if self.starts_with("</") {
+                self.cursor += 2;
+                if self.parse_tag_name() == tag_name && self.starts_with(">") {
+                    self.consume_char();
+                } else {
+                    self.cursor = start;
+                }
+            }
+            return Ok(dom::elem(&tag_name).add_attrs(attrs));
+        }
+
+        let children = self.parse_nodes()?;
+
+        let end = self.cursor;
+        self.expect('<')?;
+        self.expect('/')?;
+        if self.parse_tag_name() != tag_name {
+            self.cursor = end;
+            return Err(self.error(format!("</{tag_name}>")));
+        }
+        self.expect('>')?;
 
-        dom::elem(&tag_name).add_attrs(attrs).add_children(children)
+        Ok(dom::elem(&tag_name).add_attrs(attrs).add_children(children))
     }
 
-    fn parse_attr(&mut self) -> (String, String) {
+    fn parse_attr(&mut self) -> Result<(String, String), ParseError> {
         let name = self.parse_tag_name();
-        assert!(self.consume_char() == '=');
-        let value = self.parse_attr_value();
-        (name, value)
+        self.expect('=')?;
+        let value = self.parse_attr_value()?;
+        Ok((name, value))
     }
 
-    fn parse_attr_value(&mut self) -> String {
-        let open_quote = self.consume_char();
-        assert!(open_quote == '"' || open_quote == '\'');
+    fn parse_attr_value(&mut self) -> Result<String, ParseError> {
+        let open_quote = match self.eof() {
+            false if matches!(self.next_char(), '"' | '\'') => self.consume_char(),
+            _ => return Err(self.error("a quoted attribute value".to_owned())),
+        };
         let value = self.consume_while(|c| c != open_quote);
-        assert!(self.consume_char() == open_quote);
-        value
+        self.expect(open_quote)?;
+        Ok(value)
     }
 
-    fn parse_attributes(&mut self) -> Vec<(String, String)> {
+    fn parse_attributes(&mut self) -> Result<Vec<(String, String)>, ParseError> {
         let mut attributes = vec![];
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
+            if self.eof() {
+                return Err(self.error("'>'".to_owned()));
+            }
+            if self.next_char() == '>' || self.starts_with("/>") {
                 break;
             }
-            let (name, value) = self.parse_attr();
+            let (name, value) = self.parse_attr()?;
             attributes.push((name, value));
         }
-        attributes
+        Ok(attributes)
     }
 
-    fn parse_nodes(&mut self) -> Vec<dom::Node> {
+    fn parse_nodes(&mut self) -> Result<Vec<dom::Node>, ParseError> {
         let mut nodes = Vec::new();
         loop {
+            let start = self.cursor;
             self.consume_whitespace();
 
+            // Doctypes and comments are skipped, up to and including their closing `>`.
             if self.starts_with("<!") {
                 self.consume_while(|c| c != '>');
-                continue
+                if !self.eof() {
+                    self.consume_char();
+                }
+                continue;
             }
 
             if self.eof() || self.starts_with("</") {
                 break;
             }
-            nodes.push(self.parse_node());
+
+            // White space between elements is dropped, but text keeps its leading white space,
+            // which is significant in preformatted text.
+            if !self.starts_with("<") {
+                self.cursor = start;
+            }
+            nodes.push(self.parse_node()?);
         }
-        nodes
+        Ok(nodes)
     }
 
-    pub fn parse_no_root(source: String) -> Vec<dom::Node> {
-        Parser {
+    pub fn parse_no_root(source: String) -> Result<Vec<dom::Node>, ParseError> {
+        let mut parser = Parser {
             cursor: 0,
             data: source,
+        };
+        let nodes = parser.parse_nodes()?;
+        // Only a closing tag without an element to close stops the parser early.
+        if !parser.eof() {
+            return Err(parser.error("the end of the document".to_owned()));
         }
-        .parse_nodes()
+        Ok(nodes)
     }
 
-    pub fn parse(source: String) -> dom::Node {
-        let mut nodes = Parser::parse_no_root(source);
+    pub fn parse(source: String) -> Result<dom::Node, ParseError> {
+        let mut nodes = Parser::parse_no_root(source)?;
 
         if nodes.len() == 1 {
-            nodes.pop().unwrap()
+            Ok(nodes.pop().unwrap())
         } else {
-            dom::elem("html").add_children(nodes)
+            Ok(dom::elem("html").add_children(nodes))
         }
     }
 }
 
+impl dom::Node {
+    /// Parse a document, or fail if it isn't valid.
+    pub fn parse(html: &str) -> Result<dom::Node, ParseError> {
+        Parser::parse(html.to_owned())
+    }
+
+    /// Parse a document with html5ever, which follows the HTML standard: it recovers from
+    /// errors like browsers do, and adds the elements that are left out, such as `body`.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html
+    #[cfg(feature = "html5ever")]
+    pub fn parse_with_html5ever(html: &str) -> dom::Node {
+        let parser = html5ever::parse_document(RcDom::default(), Default::default());
+        dom::Node::from(parser.one(html))
+    }
+}
+
+/// Parses a document, like `Node::parse`.
+impl FromStr for dom::Node {
+    type Err = ParseError;
+
+    fn from_str(html: &str) -> Result<dom::Node, ParseError> {
+        Parser::parse(html.to_owned())
+    }
+}
+
+/// Converts a document that html5ever parsed, so that it can be styled, laid out and painted. The
+/// root is its `html` element. Comments, doctypes and processing instructions are left out.
+#[cfg(feature = "html5ever")]
+impl From<RcDom> for dom::Node {
+    fn from(document: RcDom) -> dom::Node {
+        let children = document.document.children.borrow();
+        let root = children.iter().find_map(convert_html5ever_node);
+        root.unwrap_or_else(|| dom::elem("html"))
+    }
+}
+
+#[cfg(feature = "html5ever")]
+fn convert_html5ever_node(handle: &Handle) -> Option<dom::Node> {
+    match &handle.data {
+        NodeData::Element {
+            name,
+            attrs,
+            template_contents,
+            ..
+        } => {
+            let attrs = attrs.borrow();
+            // The content of a template is parsed into a fragment of its own, which is kept as
+            // the children of the template.
+            let template_contents = template_contents.borrow();
+            let children = match &*template_contents {
+                Some(fragment) => fragment.children.borrow(),
+                None => handle.children.borrow(),
+            };
+            Some(dom::Node::Element {
+                tag: dom::Atom::from(&*name.local),
+                attrs: attrs
+                    .iter()
+                    .map(|attr| (dom::Atom::from(&*attr.name.local), attr.value.to_string()))
+                    .collect(),
+                children: children.iter().filter_map(convert_html5ever_node).collect(),
+            })
+        }
+        NodeData::Text { contents } => Some(dom::text(&contents.borrow())),
+        _ => None,
+    }
+}
+
+/// Parses documents that are known to be valid, such as those in the source code.
+///
+/// Panics if the document isn't valid. Use `Node::parse` for documents that may not be.
 impl From<String> for dom::Node {
     fn from(s: String) -> dom::Node {
-        Parser::parse(s)
+        Parser::parse(s).unwrap()
     }
 }
 
+/// Parses documents that are known to be valid, such as those in the source code.
+///
+/// Panics if the document isn't valid. Use `Node::parse` for documents that may not be.
 impl From<&str> for dom::Node {
     fn from(s: &str) -> dom::Node {
-        Parser::parse(s.to_owned())
+        Parser::parse(s.to_owned()).unwrap()
     }
 }
 
@@ -165,6 +334,7 @@ mod tests {
                     .add_child(elem("p").add_text("Bye!")),
             );
         let actual = "
+            <!DOCTYPE html>
             <html lang=\"NL\">
                 <head>
                     <title>Hello, world!</title>
@@ -177,4 +347,58 @@ mod tests {
         ";
         assert_eq!(Node::from(actual), expected);
     }
+
+    #[test]
+    fn test_parse_error() {
+        let error = Node::parse("<a><b></a>").unwrap_err();
+        assert_eq!(error.offset, 6);
+        assert_eq!(error.expected, "</b>");
+        assert_eq!(error.to_string(), "expected </b> at byte 6");
+
+        assert!(Node::parse("<a href=x></a>").is_err());
+        assert!(Node::parse("<a").is_err());
+        assert!(Node::parse("<a></a></b>").is_err());
+    }
+
+    #[test]
+    fn test_void_elements() {
+        let expected = elem("p")
+            .add_text("a")
+            .add_child(elem("br"))
+            .add_text("b")
+            .add_child(elem("img").add_attr("src", "x.png"))
+            .add_child(elem("wbr"));
+        for html in [
+            "<p>a<br>b<img src=\"x.png\"><wbr></p>",
+            "<p>a<br/>b<img src=\"x.png\" /><wbr></wbr></p>",
+        ] {
+            assert_eq!(Node::parse(html).unwrap(), expected);
+        }
+
+        // Other elements can close themselves too.
+        assert_eq!(Node::parse("<div/>").unwrap(), elem("div"));
+    }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_parse_with_html5ever() {
+        let expected = elem("html").add_child(elem("head")).add_child(
+            elem("body").add_child(
+                elem("p")
+                    .add_attr("class", "x")
+                    .add_text("1")
+                    .add_child(elem("br"))
+                    .add_text("2"),
+            ),
+        );
+        let actual = Node::parse_with_html5ever("<!DOCTYPE html><p class=x>1<br>2<!-- 3 --></a>");
+        assert_eq!(actual, expected);
+
+        let actual = Node::parse_with_html5ever("<template><p>1</p></template>");
+        let template = actual.get_elements_by_tag_name("template")[0];
+        assert_eq!(
+            template.template_content(),
+            Some(&[Node::from("<p>1</p>")][..])
+        );
+    }
 }
@@ -0,0 +1,111 @@
+//! Decoded images, and the cache that layout and display lists refer to them by.
+
+use std::collections::HashMap;
+
+use crate::engine::MemoryUsage;
+
+/// An image, decoded to 8-bit RGBA pixels, row by row from the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A reference to an image in an `ImageCache`. It stays the same for as long as the image is in
+/// the cache, so a backend can upload each image once and look it up by its id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ImageHandle {
+    id: usize,
+    width: u32,
+    height: u32,
+}
+
+impl ImageHandle {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The natural size of the image, in CSS pixels.
+    pub fn size(&self) -> (f32, f32) {
+        (self.width as f32, self.height as f32)
+    }
+}
+
+/// The decoded images that a document uses, by the URL they were loaded from.
+#[derive(Debug, Default)]
+pub struct ImageCache {
+    images: Vec<Image>,
+    sources: HashMap<String, ImageHandle>,
+}
+
+impl ImageCache {
+    pub fn new() -> ImageCache {
+        Default::default()
+    }
+
+    /// Add a decoded image for a URL, and return its handle. An image that was added for the
+    /// same URL before is replaced.
+    pub fn insert(&mut self, source: &str, image: Image) -> ImageHandle {
+        let handle = ImageHandle {
+            id: self.images.len(),
+            width: image.width,
+            height: image.height,
+        };
+        self.images.push(image);
+        self.sources.insert(source.to_owned(), handle);
+        handle
+    }
+
+    /// Decode an image file in any format that the `image` crate supports, and add it for a URL.
+    #[cfg(feature = "image")]
+    pub fn decode(&mut self, source: &str, data: &[u8]) -> Result<ImageHandle, image::ImageError> {
+        let decoded = image::load_from_memory(data)?.into_rgba8();
+        let image = Image {
+            width: decoded.width(),
+            height: decoded.height(),
+            pixels: decoded.into_raw(),
+        };
+        Ok(self.insert(source, image))
+    }
+
+    /// The handle of the image that was loaded from a URL, if any.
+    pub fn handle(&self, source: &str) -> Option<ImageHandle> {
+        self.sources.get(source).copied()
+    }
+
+    pub fn get(&self, handle: ImageHandle) -> &Image {
+        &self.images[handle.id]
+    }
+
+    /// The memory that the pixels of the images take up.
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        let pixels = self.images.iter().map(|image| image.pixels.capacity());
+        MemoryUsage {
+            bytes: pixels.sum(),
+            count: self.images.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::images::*;
+
+    #[test]
+    fn test_image_cache() {
+        let mut cache = ImageCache::new();
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![255; 8],
+        };
+
+        let handle = cache.insert("a.png", image.clone());
+        assert_eq!(cache.handle("a.png"), Some(handle));
+        assert_eq!(cache.handle("b.png"), None);
+        assert_eq!(handle.size(), (2.0, 1.0));
+        assert_eq!(cache.get(handle), &image);
+    }
+}
@@ -1,12 +1,37 @@
 use std::default::Default;
-
-use crate::css::Unit::Px;
-use crate::css::Value::{Keyword, Length};
-use crate::style::{Display, StyledNode};
-
-pub use self::BoxType::{AnonymousBlock, BlockNode, InlineNode};
+use std::rc::Rc;
+use std::slice;
+
+use tracing::{trace, trace_span};
+
+use self::counters::{Counters, LIST_ITEM};
+use crate::css::Unit::{Percent, Px};
+use crate::css::Value::{self, Keyword, Length};
+use crate::dom::Node;
+use crate::fonts::FontContext;
+use crate::images::{ImageCache, ImageHandle};
+use crate::painting::DisplayList;
+use crate::style::{Direction, Display, ListStyleType, Overflow, Position, StyledNode, Transform};
+
+pub use self::compare::{assert_tree_eq, diff_trees};
+pub use self::inline::{Font, Glyph, GlyphRun};
+#[cfg(feature = "serde")]
+pub use self::json::to_json;
+pub use self::paged::PageBox;
+pub use self::BoxType::{AnonymousBlock, BlockNode, GridNode, InlineNode, MarkerBox, TextRun};
+
+mod compare;
+mod counters;
+mod flex;
+mod grid;
+mod inline;
+#[cfg(feature = "serde")]
+mod json;
+mod multicol;
+mod paged;
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -15,6 +40,7 @@ pub struct Rect {
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Dimensions {
     pub content: Rect,
     pub padding: EdgeSizes,
@@ -23,6 +49,7 @@ pub struct Dimensions {
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EdgeSizes {
     pub left: f32,
     pub right: f32,
@@ -30,115 +57,1082 @@ pub struct EdgeSizes {
     pub bottom: f32,
 }
 
+/// The radii of the rounded corners of a box.
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#border-radius
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CornerRadii {
+    pub top_left: f32,
+    pub top_right: f32,
+    pub bottom_right: f32,
+    pub bottom_left: f32,
+}
+
+impl CornerRadii {
+    /// Whether all corners are square.
+    pub fn is_zero(&self) -> bool {
+        self.top_left <= 0.0
+            && self.top_right <= 0.0
+            && self.bottom_right <= 0.0
+            && self.bottom_left <= 0.0
+    }
+}
+
+/// The window that a document is shown in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Viewport {
+    /// The width of the window in CSS pixels, without zoom.
+    pub width: f32,
+    pub height: f32,
+    /// The number of device pixels per CSS pixel of the screen.
+    pub dpr: f32,
+    /// How much the page is magnified. Zooming in leaves fewer CSS pixels for the layout.
+    pub zoom: f32,
+}
+
+impl Viewport {
+    /// A viewport of a size in CSS pixels, with one device pixel per CSS pixel and no zoom.
+    pub fn new(width: f32, height: f32) -> Viewport {
+        Viewport {
+            width,
+            height,
+            dpr: 1.0,
+            zoom: 1.0,
+        }
+    }
+
+    /// The initial containing block, in the CSS pixels of the page.
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.width / self.zoom,
+            height: self.height / self.zoom,
+        }
+    }
+
+    /// The number of device pixels per CSS pixel of the page.
+    pub fn scale(&self) -> f32 {
+        self.dpr * self.zoom
+    }
+
+    /// The size of the window in device pixels.
+    pub fn device_size(&self) -> (usize, usize) {
+        let width = (self.width * self.dpr).round().max(0.0) as usize;
+        let height = (self.height * self.dpr).round().max(0.0) as usize;
+        (width, height)
+    }
+}
+
+/// The environment that a layout runs in, which stays the same for every box in the tree.
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutContext<'a> {
+    /// The viewport, which is the initial containing block.
+    pub viewport: Rect,
+    /// The number of device pixels per CSS pixel.
+    pub device_pixel_ratio: f32,
+    /// The font size of the root element, and the initial value of `font-size`.
+    pub root_font_size: f32,
+    /// The images that have been loaded for the document. Images that are missing take up no
+    /// space.
+    pub images: Option<&'a ImageCache>,
+    /// The fonts that text is set in. Without a font, every glyph is half as wide as the font
+    /// size.
+    pub fonts: Option<&'a FontContext>,
+}
+
+impl LayoutContext<'_> {
+    /// A context for a viewport, with one device pixel per CSS pixel, the default font size, and
+    /// no images or fonts.
+    pub fn new(viewport: Rect) -> LayoutContext<'static> {
+        LayoutContext {
+            viewport,
+            device_pixel_ratio: 1.0,
+            root_font_size: 16.0,
+            images: None,
+            fonts: None,
+        }
+    }
+
+    /// A context for a viewport, with the default font size, and no images or fonts.
+    pub fn for_viewport(viewport: &Viewport) -> LayoutContext<'static> {
+        LayoutContext {
+            device_pixel_ratio: viewport.scale(),
+            ..LayoutContext::new(viewport.rect())
+        }
+    }
+}
+
+/// A node in the layout tree.
+///
+/// Each box owns its children by value, without shared ownership or interior mutability, so
+/// layout takes `&mut self` and borrows are checked at compile time. Parent and sibling links are
+/// provided by [`BoxRef`] instead of being stored in the tree.
 pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     pub box_type: BoxType<'a>,
     pub children: Vec<LayoutBox<'a>>,
+    /// Whether this is the root box of the layout tree, whose margins never collapse.
+    is_root: bool,
+    /// Whether this box is laid out by its parent flex container.
+    is_flex_item: bool,
+    /// Whether this box is placed in a cell of its parent grid container.
+    is_grid_item: bool,
+    /// The margins of this box as they collapse with the margins around it.
+    collapsed_margins: CollapsedMargins,
+    /// Where the normal flow would have put the margin box of an absolutely positioned box.
+    static_position: (f32, f32),
+    /// Whether the box itself must be laid out again, for instance because its style changed.
+    needs_layout: bool,
+    /// Whether any of the descendants of the box must be laid out again.
+    children_need_layout: bool,
+    /// The result of the last layout of the box, for reuse while it is clean.
+    layout_cache: Option<LayoutCache>,
+    /// The glyphs of a text run, placed by inline layout.
+    glyph_run: Option<GlyphRun>,
+    /// The loaded image that is the content of a replaced element.
+    image: Option<ImageHandle>,
+    /// The loaded image of `background-image`.
+    background_image: Option<ImageHandle>,
+    /// The display list of the document in a frame, relative to its content box.
+    frame: Option<DisplayList>,
+}
+
+/// The dimensions of a box right after it was laid out in a containing block.
+#[derive(Clone, Copy, Debug)]
+struct LayoutCache {
+    containing_block: Dimensions,
+    dimensions: Dimensions,
+}
+
+/// The layout of a box and its descendants without the style tree they were built from, which is
+/// kept while the document changes, so that only the boxes that changed are laid out again.
+#[derive(Debug)]
+pub struct RetainedLayout {
+    key: BoxKey,
+    dimensions: Dimensions,
+    collapsed_margins: CollapsedMargins,
+    static_position: (f32, f32),
+    layout_cache: Option<LayoutCache>,
+    glyph_run: Option<GlyphRun>,
+    image: Option<ImageHandle>,
+    background_image: Option<ImageHandle>,
+    children: Vec<RetainedLayout>,
+}
+
+/// What a box was generated for: its type, and the index of its node among the children of the
+/// node of its parent box, or `None` if it has the node of its parent or no node at all. A box
+/// that is built again only takes the layout of a retained box with the same key.
+#[derive(Clone, Debug, PartialEq)]
+struct BoxKey {
+    box_type: RetainedBoxType,
+    index: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum RetainedBoxType {
+    BlockNode,
+    InlineNode,
+    GridNode,
+    MarkerBox(ListMarker),
+    TextRun(String),
+    AnonymousBlock,
+}
+
+/// The widths that the content of a box prefers, regardless of its containing block.
+///
+/// https://www.w3.org/TR/css-sizing-3/#intrinsic-sizes
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct IntrinsicWidths {
+    /// The narrowest the content can be without overflowing, breaking lines wherever possible.
+    min: f32,
+    /// The width of the content without any line breaks other than forced ones.
+    max: f32,
+}
+
+/// A set of adjoining vertical margins, which combine into a single margin.
+///
+/// http://www.w3.org/TR/CSS2/box.html#collapsing-margins
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct CollapsedMargin {
+    /// The largest positive margin.
+    positive: f32,
+    /// The most negative margin.
+    negative: f32,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct CollapsedMargins {
+    /// The top margin, including margins of descendants that collapse through the top edge.
+    top: CollapsedMargin,
+    /// The bottom margin, including margins of descendants that collapse through the bottom edge.
+    bottom: CollapsedMargin,
+    /// Whether the top and bottom margins adjoin, as is the case for empty boxes.
+    through: bool,
 }
 
 #[derive(Debug)]
 pub enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
+    /// A block-level grid container, which places its children in the cells of a grid.
+    GridNode(&'a StyledNode<'a>),
+    /// The marker of a list item, which is placed outside of the list item it belongs to.
+    MarkerBox(&'a StyledNode<'a>, ListMarker),
+    /// The part of a text node that is laid out on a single line.
+    TextRun(&'a StyledNode<'a>, String),
     AnonymousBlock,
 }
 
+/// The content of a list item marker.
+///
+/// http://www.w3.org/TR/CSS2/generate.html#lists
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListMarker {
+    Disc,
+    /// The value of the `list-item` counter of the list item.
+    Decimal(i32),
+}
+
 impl<'a> LayoutBox<'a> {
     fn new(box_type: BoxType) -> LayoutBox {
         LayoutBox {
             box_type,
             dimensions: Default::default(),
             children: Vec::new(),
+            is_root: false,
+            is_flex_item: false,
+            is_grid_item: false,
+            collapsed_margins: Default::default(),
+            static_position: (0.0, 0.0),
+            needs_layout: true,
+            children_need_layout: true,
+            layout_cache: None,
+            glyph_run: None,
+            image: None,
+            background_image: None,
+            frame: None,
         }
     }
 
     fn get_style_node(&self) -> &'a StyledNode<'a> {
         match self.box_type {
-            BlockNode(node) | InlineNode(node) => node,
+            BlockNode(node) | InlineNode(node) | GridNode(node) | TextRun(node, _) => node,
+            MarkerBox(..) => panic!("Marker box has no style node"),
             AnonymousBlock => panic!("Anonymous block box has no style node"),
         }
     }
+
+    /// The specified value of a property, or None for anonymous boxes and markers.
+    fn style_value(&self, name: &str) -> Option<&'a Value> {
+        match self.box_type {
+            BlockNode(node) | InlineNode(node) | GridNode(node) | TextRun(node, _) => {
+                node.value(name)
+            }
+            MarkerBox(..) | AnonymousBlock => None,
+        }
+    }
+
+    /// Whether the content of this box may overflow it. Anonymous boxes never clip.
+    pub fn overflow(&self) -> Overflow {
+        match self.box_type {
+            BlockNode(node) | InlineNode(node) | GridNode(node) | TextRun(node, _) => {
+                node.overflow()
+            }
+            MarkerBox(..) | AnonymousBlock => Overflow::Visible,
+        }
+    }
+
+    /// The rectangle that descendants of this box are clipped to, if any.
+    ///
+    /// http://www.w3.org/TR/CSS2/visufx.html#overflow
+    pub fn clip_rect(&self) -> Option<Rect> {
+        match self.overflow() {
+            Overflow::Visible => None,
+            Overflow::Hidden | Overflow::Scroll | Overflow::Auto => {
+                Some(self.dimensions.padding_box())
+            }
+        }
+    }
+
+    /// The glyphs of a text run, once it has been laid out.
+    pub fn glyph_run(&self) -> Option<&GlyphRun> {
+        self.glyph_run.as_ref()
+    }
+
+    /// The image that is the content of this box, if it is a replaced element whose image has
+    /// been loaded.
+    pub fn image(&self) -> Option<ImageHandle> {
+        self.image
+    }
+
+    /// The image of the `background-image` of this box, if it has been loaded.
+    pub fn background_image(&self) -> Option<ImageHandle> {
+        self.background_image
+    }
+
+    /// The display list of the document in this box, if it is a frame whose document has been
+    /// painted.
+    pub fn frame(&self) -> Option<&DisplayList> {
+        self.frame.as_ref()
+    }
+
+    /// Set the display list of the document in a frame, in CSS pixels relative to the top left
+    /// of its content box.
+    pub(crate) fn set_frame(&mut self, frame: DisplayList) {
+        self.frame = Some(frame);
+    }
+
+    /// The used radii of the corners of the border box. Percentages refer to the width of the
+    /// border box, and radii that would overlap are scaled down until they fit.
+    ///
+    /// https://www.w3.org/TR/css-backgrounds-3/#corner-overlap
+    pub fn border_radii(&self) -> CornerRadii {
+        let style = match self.box_type {
+            BlockNode(style) | InlineNode(style) | GridNode(style) => style,
+            TextRun(..) | MarkerBox(..) | AnonymousBlock => return CornerRadii::default(),
+        };
+
+        let border_box = self.dimensions.border_box();
+        let shorthand = match style.value("border-radius") {
+            Some(Value::List(values)) => values.as_slice(),
+            Some(value) => slice::from_ref(value),
+            None => &[],
+        };
+
+        // The shorthand lists the corners clockwise from the top left, like `margin`.
+        let corner = |name: &str, index: usize| {
+            let fallback = match shorthand.len() {
+                0 => None,
+                1 => shorthand.first(),
+                2 => shorthand.get(index % 2),
+                3 => shorthand.get(if index == 3 { 1 } else { index }),
+                _ => shorthand.get(index),
+            };
+            let value = style.value(name).or(fallback);
+            match value {
+                Some(Length(radius, Px)) => radius.max(0.0),
+                Some(Length(percent, Percent)) => (percent / 100.0 * border_box.width).max(0.0),
+                _ => 0.0,
+            }
+        };
+
+        let radii = CornerRadii {
+            top_left: corner("border-top-left-radius", 0),
+            top_right: corner("border-top-right-radius", 1),
+            bottom_right: corner("border-bottom-right-radius", 2),
+            bottom_left: corner("border-bottom-left-radius", 3),
+        };
+
+        let fit = |length: f32, a: f32, b: f32| {
+            if a + b > length {
+                length / (a + b)
+            } else {
+                1.0
+            }
+        };
+        let scale = fit(border_box.width, radii.top_left, radii.top_right)
+            .min(fit(border_box.width, radii.bottom_left, radii.bottom_right))
+            .min(fit(border_box.height, radii.top_left, radii.bottom_left))
+            .min(fit(border_box.height, radii.top_right, radii.bottom_right));
+
+        CornerRadii {
+            top_left: radii.top_left * scale,
+            top_right: radii.top_right * scale,
+            bottom_right: radii.bottom_right * scale,
+            bottom_left: radii.bottom_left * scale,
+        }
+    }
+
+    /// The radii of the corners of the padding box, which is what overflow is clipped to. Each
+    /// radius shrinks by the width of the borders next to its corner.
+    ///
+    /// https://www.w3.org/TR/css-backgrounds-3/#corner-clipping
+    pub fn padding_radii(&self) -> CornerRadii {
+        let radii = self.border_radii();
+        let border = self.dimensions.border;
+        let inner = |radius: f32, a: f32, b: f32| (radius - a.max(b)).max(0.0);
+        CornerRadii {
+            top_left: inner(radii.top_left, border.top, border.left),
+            top_right: inner(radii.top_right, border.top, border.right),
+            bottom_right: inner(radii.bottom_right, border.bottom, border.right),
+            bottom_left: inner(radii.bottom_left, border.bottom, border.left),
+        }
+    }
+
+    /// The positioning scheme of this box. Anonymous boxes are never positioned.
+    pub fn position(&self) -> Position {
+        match self.box_type {
+            BlockNode(node) | InlineNode(node) | GridNode(node) | TextRun(node, _) => {
+                node.position()
+            }
+            MarkerBox(..) | AnonymousBlock => Position::Static,
+        }
+    }
+
+    /// The DOM node that generated this box, or None for anonymous boxes and markers. Boxes of
+    /// generated content return the element they belong to.
+    pub fn node(&self) -> Option<&'a Node> {
+        match self.box_type {
+            BlockNode(style) | InlineNode(style) | GridNode(style) | TextRun(style, _) => {
+                Some(style.node)
+            }
+            MarkerBox(..) | AnonymousBlock => None,
+        }
+    }
+
+    /// What generated this box, to tell boxes apart in traces.
+    fn name(&self) -> &str {
+        match (self.node(), &self.box_type) {
+            (Some(Node::Element { tag, .. }), _) => tag,
+            (Some(Node::Text(_)), _) => "#text",
+            (None, MarkerBox(..)) => "::marker",
+            (None, _) => "anonymous",
+        }
+    }
+
+    /// The first box in this subtree that was generated by `node`, in tree order. For elements,
+    /// this is their principal box, which contains any other boxes they generate. Its
+    /// dimensions are in the coordinates of the viewport.
+    pub fn box_for_node(&self, node: &Node) -> Option<&LayoutBox<'a>> {
+        if self.node().is_some_and(|n| std::ptr::eq(n, node)) {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .find_map(|child| child.box_for_node(node))
+    }
+
+    /// The border box of this box, in the coordinates of the viewport. Layout places every box
+    /// in these coordinates, so no offsets of ancestors have to be added.
+    pub fn border_box_absolute(&self) -> Rect {
+        self.dimensions.border_box()
+    }
+
+    /// A reference to this box as the root of a tree that can be walked in any direction. The
+    /// boxes it leads to keep their dimensions in the coordinates of the viewport.
+    pub fn as_tree(&self) -> BoxRef<'a, '_> {
+        BoxRef {
+            layout_box: self,
+            parent: None,
+            index: 0,
+        }
+    }
+}
+
+/// A box in a laid out tree, with links to its parent and siblings.
+///
+/// Boxes own their children, so the links to the rest of the tree are kept by the references
+/// that were followed to get to a box.
+#[derive(Clone)]
+pub struct BoxRef<'a, 'b> {
+    layout_box: &'b LayoutBox<'a>,
+    parent: Option<Rc<BoxRef<'a, 'b>>>,
+    /// The index of the box among the children of its parent.
+    index: usize,
+}
+
+impl<'a, 'b> BoxRef<'a, 'b> {
+    /// The box itself, with its dimensions in the coordinates of the viewport.
+    pub fn layout_box(&self) -> &'b LayoutBox<'a> {
+        self.layout_box
+    }
+
+    /// The box that contains this one, or None for the box that the tree was walked from.
+    pub fn parent(&self) -> Option<&BoxRef<'a, 'b>> {
+        self.parent.as_deref()
+    }
+
+    /// The child of the same parent just before this one, or None if this is the first.
+    pub fn previous_sibling(&self) -> Option<BoxRef<'a, 'b>> {
+        let parent = self.parent.as_ref()?;
+        let index = self.index.checked_sub(1)?;
+        Some(BoxRef {
+            layout_box: &parent.layout_box.children[index],
+            parent: Some(parent.clone()),
+            index,
+        })
+    }
+
+    /// The child of the same parent just after this one, or None if this is the last.
+    pub fn next_sibling(&self) -> Option<BoxRef<'a, 'b>> {
+        let parent = self.parent.as_ref()?;
+        let index = self.index + 1;
+        Some(BoxRef {
+            layout_box: parent.layout_box.children.get(index)?,
+            parent: Some(parent.clone()),
+            index,
+        })
+    }
+
+    /// The children of this box in tree order, each with a link back to this one.
+    pub fn children(&self) -> impl Iterator<Item = BoxRef<'a, 'b>> {
+        let parent = Rc::new(self.clone());
+        self.layout_box
+            .children
+            .iter()
+            .enumerate()
+            .map(move |(index, layout_box)| BoxRef {
+                layout_box,
+                parent: Some(parent.clone()),
+                index,
+            })
+    }
+}
+
+/// Lay out a style tree in a viewport, with the default context for it.
+pub fn layout_tree<'a>(node: &'a StyledNode<'a>, viewport: Dimensions) -> LayoutBox<'a> {
+    layout_tree_with_context(node, &LayoutContext::new(viewport.content))
+}
+
+pub fn layout_tree_with_context<'a>(
+    node: &'a StyledNode<'a>,
+    context: &LayoutContext,
+) -> LayoutBox<'a> {
+    let mut root_box = build_layout_tree(node, &mut Counters::default());
+    root_box.is_root = true;
+    root_box.relayout(context);
+    root_box
 }
 
-pub fn layout_tree<'a>(
+/// Lay out a style tree again, after the nodes in `dirty` changed. Every box takes the layout
+/// that was retained from the tree before, where the boxes are the same, and only the boxes of
+/// the nodes that changed, their descendants, and the boxes around them are laid out again.
+pub fn relayout_tree<'a>(
     node: &'a StyledNode<'a>,
-    mut containing_block: Dimensions,
+    retained: RetainedLayout,
+    dirty: &[&Node],
+    context: &LayoutContext,
 ) -> LayoutBox<'a> {
-    // The layout algorithm expects the container height to start at 0.
-    // TODO: Save the initial containing block height, for calculating percent heights.
-    containing_block.content.height = 0.0;
+    let mut root_box = build_layout_tree(node, &mut Counters::default());
+    root_box.is_root = true;
+    if root_box.key(None) == retained.key {
+        root_box.restore(retained, None);
+    }
 
-    let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    let mut paths = Vec::new();
+    root_box.dirty_paths(dirty, &mut Vec::new(), &mut paths);
+    for path in &paths {
+        root_box.mark_dirty(path);
+    }
+    root_box.relayout(context);
     root_box
 }
 
 /// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
-fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    // Create the root box.
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BlockNode(style_node),
-        Display::Inline => InlineNode(style_node),
-        Display::None => panic!("Root node has display: none."),
-    });
-
-    // Create the descendant boxes.
-    for child in &style_node.children {
-        match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
+fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>, counters: &mut Counters) -> LayoutBox<'a> {
+    // Create the root box. Absolutely positioned boxes are always block-level.
+    build_layout_box(
+        style_node,
+        match (style_node.display(), style_node.position()) {
+            (Display::None, _) => panic!("Root node has display: none."),
+            (Display::Grid, _) => GridNode(style_node),
+            (Display::Block | Display::InlineBlock | Display::Flex | Display::ListItem, _)
+            | (_, Position::Absolute) => BlockNode(style_node),
+            (Display::Inline, _) => InlineNode(style_node),
+        },
+        counters,
+    )
+}
+
+/// Whether a node is a replaced element, whose content is an image or a nested document rather
+/// than its children.
+///
+/// http://www.w3.org/TR/CSS2/conform.html#replaced-element
+fn is_replaced(style_node: &StyledNode) -> bool {
+    matches!(style_node.node, Node::Element { tag, .. } if tag == "img" || tag == "iframe")
+}
+
+/// Whether a node is a frame, whose content is a nested document.
+///
+/// https://html.spec.whatwg.org/multipage/iframe-embed-object.html#the-iframe-element
+pub(crate) fn is_frame(node: &Node) -> bool {
+    matches!(node, Node::Element { tag, .. } if tag == "iframe")
+}
+
+/// Build a box of the given type for a style node, and the boxes for its descendants.
+fn build_layout_box<'a>(
+    style_node: &'a StyledNode<'a>,
+    box_type: BoxType<'a>,
+    counters: &mut Counters,
+) -> LayoutBox<'a> {
+    let mut root = LayoutBox::new(box_type);
+    let is_flex_container = style_node.display() == Display::Flex;
+    let is_grid_container = style_node.display() == Display::Grid;
+
+    counters.enter(style_node);
+    let scope = counters.scope();
+
+    if style_node.display() == Display::ListItem {
+        let marker = match style_node.list_style_type() {
+            ListStyleType::Disc => Some(ListMarker::Disc),
+            ListStyleType::Decimal => Some(ListMarker::Decimal(counters.value(LIST_ITEM))),
+            ListStyleType::None => None,
+        };
+        if let Some(marker) = marker {
+            // The marker comes before the principal box of the list item.
+            root.children
+                .push(LayoutBox::new(MarkerBox(style_node, marker)));
+        }
+    }
+
+    if let Some(before) = &style_node.before {
+        root.build_generated_box(before, counters);
+    }
+
+    // Create the descendant boxes. The children of a replaced element are not rendered.
+    let children = match is_replaced(style_node) {
+        true => &[][..],
+        false => &style_node.children[..],
+    };
+    for child in children {
+        match (child.display(), child.position()) {
+            (Display::None, _) => {} // Don't lay out nodes with `display: none;`
+            (Display::Block | Display::Flex | Display::Grid | Display::ListItem, _)
+            | (_, Position::Absolute) => root.children.push(build_layout_tree(child, counters)),
+            // Elements in a flex or grid container are block-level items. Runs of text are wrapped
+            // in an anonymous item.
+            (Display::Inline | Display::InlineBlock, _)
+                if (is_flex_container || is_grid_container)
+                    && matches!(child.node, Node::Element { .. }) =>
+            {
+                root.children
+                    .push(build_layout_box(child, BlockNode(child), counters))
+            }
+            // Inline-blocks and replaced elements take part in the inline formatting context as
+            // a single unit.
+            (Display::InlineBlock, _) => root
+                .get_inline_container()
+                .children
+                .push(build_layout_box(child, BlockNode(child), counters)),
+            (Display::Inline, _) if is_replaced(child) => root
+                .get_inline_container()
+                .children
+                .push(build_layout_box(child, BlockNode(child), counters)),
+            (Display::Inline, _) => root
                 .get_inline_container()
                 .children
-                .push(build_layout_tree(child)),
-            Display::None => {} // Don't lay out nodes with `display: none;`
+                .push(build_layout_tree(child, counters)),
+        }
+    }
+
+    if let Some(after) = &style_node.after {
+        root.build_generated_box(after, counters);
+    }
+
+    counters.leave(scope);
+
+    if is_flex_container {
+        for child in &mut root.children {
+            child.is_flex_item = true;
+        }
+    }
+
+    if is_grid_container {
+        for child in &mut root.children {
+            child.is_grid_item = true;
         }
     }
+
     root
 }
 
 impl<'a> LayoutBox<'a> {
-    /// Lay out a box and its descendants.
-    fn layout(&mut self, containing_block: Dimensions) {
+    /// Add the box of a `::before` or `::after` pseudo-element to this box, with its generated
+    /// content as text.
+    ///
+    /// http://www.w3.org/TR/CSS2/generate.html#before-after-content
+    fn build_generated_box(&mut self, style: &'a StyledNode<'a>, counters: &mut Counters) {
+        let display = style.display();
+        if display == Display::None {
+            return;
+        }
+
+        counters.enter(style);
+        let text = counters.content(style);
+
+        let is_inline_level = matches!(display, Display::Inline | Display::InlineBlock)
+            && style.position() != Position::Absolute
+            && !matches!(self.box_type, BlockNode(node) if node.display() == Display::Flex)
+            && !matches!(self.box_type, GridNode(_));
+
+        let mut generated = LayoutBox::new(match display {
+            Display::Inline if is_inline_level => InlineNode(style),
+            _ => BlockNode(style),
+        });
+        generated
+            .get_inline_container()
+            .children
+            .push(LayoutBox::new(TextRun(style, text)));
+
+        if is_inline_level {
+            self.get_inline_container().children.push(generated);
+        } else {
+            self.children.push(generated);
+        }
+    }
+
+    /// Lay out the tree with this box as its root again, in the given context. Subtrees that
+    /// are not marked dirty, and whose containing block keeps its width, are moved into place
+    /// instead of being laid out again.
+    pub fn relayout(&mut self, context: &LayoutContext) {
+        // The layout algorithm expects the container height to start at 0.
+        // TODO: Use the initial containing block height for calculating percent heights.
+        let containing_block = Dimensions {
+            content: Rect {
+                height: 0.0,
+                ..context.viewport
+            },
+            ..Default::default()
+        };
+
+        // Absolutely positioned boxes without a positioned ancestor are placed relative to the
+        // whole initial containing block.
+        self.layout(containing_block, context);
+        if self.position() == Position::Static {
+            self.layout_absolute_descendants(context.viewport, context);
+        }
+    }
+
+    /// Mark a box as needing layout, after a change to its style or content. The box is found by
+    /// following `path` from this box: the index of each box among the children of its parent.
+    ///
+    /// Returns whether there is a box at `path`. Nothing is marked if there isn't.
+    pub fn mark_dirty(&mut self, path: &[usize]) -> bool {
+        match path.split_first() {
+            None => self.needs_layout = true,
+            Some((&index, rest)) => {
+                let marked = self
+                    .children
+                    .get_mut(index)
+                    .is_some_and(|child| child.mark_dirty(rest));
+                if !marked {
+                    return false;
+                }
+                self.children_need_layout = true;
+            }
+        }
+        true
+    }
+
+    /// The layout of this box and its descendants, for `relayout_tree`. `parent` is the node of
+    /// the nearest ancestor box that has one.
+    pub fn into_retained(self, parent: Option<&Node>) -> RetainedLayout {
+        let key = self.key(parent);
+        let node = self.node().or(parent);
+        RetainedLayout {
+            key,
+            dimensions: self.dimensions,
+            collapsed_margins: self.collapsed_margins,
+            static_position: self.static_position,
+            layout_cache: self.layout_cache,
+            glyph_run: self.glyph_run,
+            image: self.image,
+            background_image: self.background_image,
+            children: self
+                .children
+                .into_iter()
+                .map(|child| child.into_retained(node))
+                .collect(),
+        }
+    }
+
+    fn key(&self, parent: Option<&Node>) -> BoxKey {
+        let box_type = match &self.box_type {
+            BlockNode(_) => RetainedBoxType::BlockNode,
+            InlineNode(_) => RetainedBoxType::InlineNode,
+            GridNode(_) => RetainedBoxType::GridNode,
+            MarkerBox(_, marker) => RetainedBoxType::MarkerBox(*marker),
+            TextRun(_, text) => RetainedBoxType::TextRun(text.clone()),
+            AnonymousBlock => RetainedBoxType::AnonymousBlock,
+        };
+        let index = match (self.node(), parent) {
+            (Some(node), Some(parent)) => parent
+                .document_children()
+                .iter()
+                .position(|child| std::ptr::eq(child, node)),
+            _ => None,
+        };
+        BoxKey { box_type, index }
+    }
+
+    /// Take the layout of a retained box with the same key, and that of its children if they
+    /// all have the same keys. Otherwise the children are laid out again. The text runs that
+    /// inline layout broke the text of this box into are built again from the retained ones.
+    fn restore(&mut self, retained: RetainedLayout, parent: Option<&Node>) {
+        self.dimensions = retained.dimensions;
+        self.collapsed_margins = retained.collapsed_margins;
+        self.static_position = retained.static_position;
+        self.layout_cache = retained.layout_cache;
+        self.glyph_run = retained.glyph_run;
+        self.image = retained.image;
+        self.background_image = retained.background_image;
+        self.needs_layout = false;
+
+        if self.children.is_empty() {
+            if let InlineNode(style) | TextRun(style, _) = self.box_type {
+                self.children = retained
+                    .children
+                    .iter()
+                    .filter_map(|child| match &child.key.box_type {
+                        RetainedBoxType::TextRun(text) => {
+                            Some(LayoutBox::new(TextRun(style, text.clone())))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+            }
+        }
+
+        let node = self.node().or(parent);
+        let same_children = self.children.len() == retained.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&retained.children)
+                .all(|(child, retained)| child.key(node) == retained.key);
+        if !same_children {
+            self.children_need_layout = true;
+            return;
+        }
+
+        for (child, retained) in self.children.iter_mut().zip(retained.children) {
+            child.restore(retained, node);
+        }
+        self.children_need_layout = self
+            .children
+            .iter()
+            .any(|child| child.needs_layout || child.children_need_layout);
+    }
+
+    /// Add the paths to the boxes of `nodes`, and to all of their descendants, to `paths`.
+    fn dirty_paths(&self, nodes: &[&Node], path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        let is_dirty = self
+            .node()
+            .is_some_and(|node| nodes.iter().any(|dirty| std::ptr::eq(*dirty, node)));
+        if is_dirty {
+            self.subtree_paths(path, paths);
+            return;
+        }
+        for (index, child) in self.children.iter().enumerate() {
+            path.push(index);
+            child.dirty_paths(nodes, path, paths);
+            path.pop();
+        }
+    }
+
+    fn subtree_paths(&self, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        paths.push(path.clone());
+        for (index, child) in self.children.iter().enumerate() {
+            path.push(index);
+            child.subtree_paths(path, paths);
+            path.pop();
+        }
+    }
+
+    /// Lay out a box and its descendants, or reuse the last layout if nothing changed.
+    fn layout(&mut self, containing_block: Dimensions, context: &LayoutContext) {
+        let _span = trace_span!("layout_box", name = self.name()).entered();
+        if self.reuse_layout(containing_block) {
+            trace!("reused the last layout");
+            return;
+        }
+
+        self.layout_box(containing_block, context);
+        let content = self.dimensions.content;
+        trace!(
+            x = content.x,
+            y = content.y,
+            width = content.width,
+            height = content.height,
+            "laid out"
+        );
+
+        self.layout_cache = Some(LayoutCache {
+            containing_block,
+            dimensions: self.dimensions,
+        });
+    }
+
+    /// Move a clean box to where its last layout would put it in the containing block, and
+    /// return whether that was possible.
+    fn reuse_layout(&mut self, containing_block: Dimensions) -> bool {
+        let cache = match self.layout_cache {
+            Some(cache) if !self.needs_layout && !self.children_need_layout => cache,
+            _ => return false,
+        };
+
+        // Blocks are laid out below the content of their containing block so far, so only a
+        // change in that position can be made up for by moving the box.
+        let (previous, current) = (cache.containing_block.content, containing_block.content);
+        if previous.x != current.x || previous.width != current.width {
+            return false;
+        }
+
+        let dy = (current.y + current.height) - (previous.y + previous.height);
+        let target = cache.dimensions.content.y + dy;
+        self.translate(0.0, target - self.dimensions.content.y);
+        true
+    }
+
+    /// Lay out a box and its descendants according to its type.
+    fn layout_box(&mut self, containing_block: Dimensions, context: &LayoutContext) {
         match self.box_type {
-            BlockNode(_) => self.layout_block(containing_block),
-            InlineNode(_) | AnonymousBlock => {} // TODO
+            BlockNode(_) | GridNode(_) => self.layout_block(containing_block, context),
+            AnonymousBlock => self.layout_anonymous_block(containing_block, context),
+            InlineNode(_) => {} // TODO
+            // Placed by the block or line they are in.
+            MarkerBox(..) | TextRun(..) => {}
         }
     }
 
     /// Lay out a block-level element and its descendants.
-    fn layout_block(&mut self, containing_block: Dimensions) {
+    fn layout_block(&mut self, containing_block: Dimensions, context: &LayoutContext) {
         // Child width can depend on parent width, so we need to calculate this box's width before
         // laying out its children.
-        self.calculate_block_width(containing_block);
+        self.calculate_block_width(containing_block, context);
 
         // Determine where the box is located within its container.
         self.calculate_block_position(containing_block);
 
         // Recursively lay out the children of this box.
-        self.layout_block_children();
+        self.layout_children(context);
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(context);
+
+        self.finish_positioning(context);
     }
 
-    /// Calculate the width of a block-level non-replaced element in normal flow.
+    /// Lay out an anonymous block box, which holds the inline-level children of a block container,
+    /// below the content laid out so far in its containing block.
+    fn layout_anonymous_block(&mut self, containing_block: Dimensions, context: &LayoutContext) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+
+        self.layout_children(context);
+    }
+
+    /// Lay out the children of the box within its content area, according to the formatting
+    /// context the box establishes for them.
+    fn layout_children(&mut self, context: &LayoutContext) {
+        self.needs_layout = false;
+        self.children_need_layout = false;
+
+        self.image = self.replaced_image(context);
+        self.background_image = match self.style_value("background-image") {
+            Some(Value::Function(name, args)) if name == "url" => match args.as_slice() {
+                [Value::Str(url)] => context.images.and_then(|images| images.handle(url)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match self.box_type {
+            AnonymousBlock => self.layout_inline_children(context),
+            BlockNode(node) if node.display() == Display::Flex => {
+                self.layout_flex_children(context)
+            }
+            BlockNode(_) if self.is_multicol_container() => self.layout_multicol_children(context),
+            GridNode(_) => self.layout_grid_children(context),
+            _ => self.layout_block_children(context),
+        }
+    }
+
+    /// Lay out the absolutely positioned descendants, and apply relative positioning, once the
+    /// size of the box is final.
+    fn finish_positioning(&mut self, context: &LayoutContext) {
+        // A positioned box is the containing block of its absolutely positioned descendants, so
+        // those can only be laid out once its size is known.
+        if self.position() != Position::Static {
+            self.layout_absolute_descendants(self.dimensions.padding_box(), context);
+        }
+
+        if self.position() == Position::Relative {
+            self.apply_relative_offset();
+        }
+    }
+
+    /// Lay out an absolutely positioned element and its descendants.
     ///
-    /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    /// The normal flow that contains the box must be laid out first, to find its static position.
+    fn layout_absolute(&mut self, containing_block: Rect, context: &LayoutContext) {
+        let (static_x, static_y) = self.static_position;
+
+        self.calculate_absolute_width(containing_block, static_x, context);
+
+        // The vertical position can depend on the content height, so lay out the children at the
+        // static position first and move them into place afterwards.
+        self.dimensions.content.y = static_y;
+        self.layout_children(context);
+
+        self.calculate_absolute_height(containing_block, static_y);
+
+        let dy = self.dimensions.content.y - static_y;
+        for child in &mut self.children {
+            child.translate(0.0, dy);
+        }
+
+        self.layout_absolute_descendants(self.dimensions.padding_box(), context);
+    }
+
+    /// Lay out the absolutely positioned descendants that have `containing_block` as their
+    /// containing block, i.e. those without a closer positioned ancestor.
+    fn layout_absolute_descendants(&mut self, containing_block: Rect, context: &LayoutContext) {
+        for child in &mut self.children {
+            match child.position() {
+                Position::Absolute => child.layout_absolute(containing_block, context),
+                Position::Static => child.layout_absolute_descendants(containing_block, context),
+                Position::Relative => {} // Already laid out its own positioned descendants.
+            }
+        }
+    }
+
+    /// Calculate the width and horizontal position of an absolutely positioned, non-replaced
+    /// element.
     ///
-    /// Sets the horizontal margin/padding/border dimensions, and the `width`.
-    fn calculate_block_width(&mut self, containing_block: Dimensions) {
+    /// Sets the horizontal margin/padding/border dimensions, the `width`, and `x`.
+    fn calculate_absolute_width(
+        &mut self,
+        containing_block: Rect,
+        static_x: f32,
+        context: &LayoutContext,
+    ) {
+        let available_width = containing_block.width - self.horizontal_item_edges();
+        self.solve_constrained("width", |layout_box, width| {
+            let width = layout_box.resolve_intrinsic_width(width, available_width, context);
+            layout_box.solve_absolute_width(containing_block, static_x, width, context)
+        });
+    }
+
+    /// Solve the horizontal constraints of an absolutely positioned, non-replaced element for the
+    /// given `width`.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-width
+    fn solve_absolute_width(
+        &mut self,
+        containing_block: Rect,
+        static_x: f32,
+        mut width: Value,
+        context: &LayoutContext,
+    ) {
         let style = self.get_style_node();
 
-        // `width` has initial value `auto`.
+        // `left` and `right` have initial value `auto`.
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        let mut left = style.value("left").unwrap_or(&auto).clone();
+        let right = style.value("right").unwrap_or(&auto);
 
         // margin, border, and padding have initial value 0.
         let zero = Length(0.0, Px);
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        let mut margin_left = style.lookup("margin-left", "margin", &zero).clone();
+        let mut margin_right = style.lookup("margin-right", "margin", &zero).clone();
 
         let border_left = style.lookup("border-left-width", "border-width", &zero);
         let border_right = style.lookup("border-right-width", "border-width", &zero);
@@ -146,35 +1140,451 @@ impl<'a> LayoutBox<'a> {
         let padding_left = style.lookup("padding-left", "padding", &zero);
         let padding_right = style.lookup("padding-right", "padding", &zero);
 
+        // Auto values count as zero here; auto margins that are not solved for below also end up
+        // as zero, since that is what `to_px` makes of them.
         let total = sum([
+            &left,
             &margin_left,
-            &margin_right,
-            &border_left,
-            &border_right,
-            &padding_left,
-            &padding_right,
+            border_left,
+            padding_left,
             &width,
+            padding_right,
+            border_right,
+            &margin_right,
+            right,
         ]
         .iter()
         .map(|v| v.to_px()));
 
-        // If width is not auto and the total is wider than the container, treat auto margins as 0.
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = Length(0.0, Px);
+        let underflow = containing_block.width - total;
+        let static_left = Length(static_x - containing_block.x, Px);
+
+        // The direction of the containing block decides which side gives way. Direction is
+        // inherited, so the box usually has the same direction as its containing block.
+        let rtl = style.direction() == Direction::Rtl;
+
+        match (left == auto, width == auto, *right == auto) {
+            // If all three are auto, the box stays at its static position and shrinks to fit.
+            (true, true, true) => {
+                left = static_left;
+                width = Length(
+                    self.shrink_to_fit_width(underflow - left.to_px(), context),
+                    Px,
+                );
             }
-            if margin_right == auto {
-                margin_right = Length(0.0, Px);
+
+            // If none of the three is auto, solve for the auto margins, or ignore `right`.
+            (false, false, false) => match (margin_left == auto, margin_right == auto) {
+                (true, true) => {
+                    if underflow >= 0.0 {
+                        margin_left = Length(underflow / 2.0, Px);
+                        margin_right = Length(underflow / 2.0, Px);
+                    } else if rtl {
+                        margin_left = Length(underflow, Px);
+                        margin_right = Length(0.0, Px);
+                    } else {
+                        margin_left = Length(0.0, Px);
+                        margin_right = Length(underflow, Px);
+                    }
+                }
+                (true, false) => margin_left = Length(underflow, Px),
+                (false, true) => margin_right = Length(underflow, Px),
+                // In a right-to-left containing block, `left` is ignored instead.
+                (false, false) if rtl => left = Length(left.to_px() + underflow, Px),
+                (false, false) => {}
+            },
+
+            // Otherwise, the remaining cases are solved with auto margins set to 0.
+            (true, true, false) => {
+                width = Length(self.shrink_to_fit_width(underflow, context), Px);
+                left = Length(underflow - width.to_px(), Px);
             }
+            (true, false, true) => left = static_left,
+            (false, true, true) => width = Length(self.shrink_to_fit_width(underflow, context), Px),
+            (true, false, false) => left = Length(underflow, Px),
+            (false, true, false) => width = Length(underflow.max(0.0), Px),
+            (false, false, true) => {} // `right` follows from the others.
         }
 
-        // Adjust used values so that the above sum equals `containing_block.width`.
-        // Each arm of the `match` should increase the total width by exactly `underflow`,
-        // and afterward all values should be absolute lengths in px.
-        let underflow = containing_block.content.width - total;
+        let d = &mut self.dimensions;
+        d.content.width = width.to_px();
+
+        d.padding.left = padding_left.to_px();
+        d.padding.right = padding_right.to_px();
+
+        d.border.left = border_left.to_px();
+        d.border.right = border_right.to_px();
+
+        d.margin.left = margin_left.to_px();
+        d.margin.right = margin_right.to_px();
+
+        d.content.x =
+            containing_block.x + left.to_px() + d.margin.left + d.border.left + d.padding.left;
+    }
+
+    /// Calculate the height and vertical position of an absolutely positioned, non-replaced
+    /// element, after its children have been laid out.
+    ///
+    /// Sets the vertical margin/padding/border dimensions, the `height`, and `y`.
+    fn calculate_absolute_height(&mut self, containing_block: Rect, static_y: f32) {
+        let content_height = self.dimensions.content.height;
+        self.solve_constrained("height", |layout_box, height| {
+            layout_box.solve_absolute_height(containing_block, static_y, content_height, height)
+        });
+    }
+
+    /// Solve the vertical constraints of an absolutely positioned, non-replaced element for the
+    /// given `height`.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-height
+    fn solve_absolute_height(
+        &mut self,
+        containing_block: Rect,
+        static_y: f32,
+        content_height: f32,
+        mut height: Value,
+    ) {
+        let style = self.get_style_node();
+
+        // `top` and `bottom` have initial value `auto`.
+        let auto = Keyword("auto".to_string());
+        let mut top = style.value("top").unwrap_or(&auto).clone();
+        let bottom = style.value("bottom").unwrap_or(&auto);
+
+        // margin, border, and padding have initial value 0.
+        let zero = Length(0.0, Px);
+
+        let mut margin_top = style.lookup("margin-top", "margin", &zero).clone();
+        let mut margin_bottom = style.lookup("margin-bottom", "margin", &zero).clone();
+
+        let border_top = style.lookup("border-top-width", "border-width", &zero);
+        let border_bottom = style.lookup("border-bottom-width", "border-width", &zero);
+
+        let padding_top = style.lookup("padding-top", "padding", &zero);
+        let padding_bottom = style.lookup("padding-bottom", "padding", &zero);
+
+        let total = sum([
+            &top,
+            &margin_top,
+            border_top,
+            padding_top,
+            &height,
+            padding_bottom,
+            border_bottom,
+            &margin_bottom,
+            bottom,
+        ]
+        .iter()
+        .map(|v| v.to_px()));
+
+        let underflow = containing_block.height - total;
+        let static_top = Length(static_y - containing_block.y, Px);
+        let content_height = Length(content_height, Px);
+
+        match (top == auto, height == auto, *bottom == auto) {
+            // If all three are auto, the box stays at its static position and fits its content.
+            (true, true, true) => {
+                top = static_top;
+                height = content_height;
+            }
+
+            // If none of the three is auto, solve for the auto margins, or ignore `bottom`.
+            (false, false, false) => match (margin_top == auto, margin_bottom == auto) {
+                (true, true) => {
+                    margin_top = Length(underflow / 2.0, Px);
+                    margin_bottom = Length(underflow / 2.0, Px);
+                }
+                (true, false) => margin_top = Length(underflow, Px),
+                (false, true) => margin_bottom = Length(underflow, Px),
+                (false, false) => {}
+            },
+
+            // Otherwise, the remaining cases are solved with auto margins set to 0.
+            (true, true, false) => {
+                top = Length(underflow - content_height.to_px(), Px);
+                height = content_height;
+            }
+            (true, false, true) => top = static_top,
+            (false, true, true) => height = content_height,
+            (true, false, false) => top = Length(underflow, Px),
+            (false, true, false) => height = Length(underflow.max(0.0), Px),
+            (false, false, true) => {} // `bottom` follows from the others.
+        }
+
+        let d = &mut self.dimensions;
+        d.content.height = height.to_px();
+
+        d.padding.top = padding_top.to_px();
+        d.padding.bottom = padding_bottom.to_px();
+
+        d.border.top = border_top.to_px();
+        d.border.bottom = border_bottom.to_px();
+
+        d.margin.top = margin_top.to_px();
+        d.margin.bottom = margin_bottom.to_px();
+
+        d.content.y =
+            containing_block.y + top.to_px() + d.margin.top + d.border.top + d.padding.top;
+    }
+
+    /// The shrink-to-fit width of the box, given the width available in its containing block.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#shrink-to-fit-float
+    fn shrink_to_fit_width(&self, available_width: f32, context: &LayoutContext) -> f32 {
+        let widths = self.intrinsic_widths(context);
+        widths.min.max(available_width.min(widths.max))
+    }
+
+    /// Replace the `min-content`, `max-content` and `fit-content` keywords for the width of the
+    /// box by the lengths they stand for.
+    ///
+    /// https://www.w3.org/TR/css-sizing-3/#sizing-values
+    fn resolve_intrinsic_width(
+        &self,
+        width: Value,
+        available_width: f32,
+        context: &LayoutContext,
+    ) -> Value {
+        match width {
+            Keyword(ref keyword) => match keyword.as_str() {
+                "min-content" => Length(self.intrinsic_widths(context).min, Px),
+                "max-content" => Length(self.intrinsic_widths(context).max, Px),
+                "fit-content" => Length(self.shrink_to_fit_width(available_width, context), Px),
+                _ => width,
+            },
+            _ => width,
+        }
+    }
+
+    /// The intrinsic widths of the content box.
+    fn intrinsic_widths(&self, context: &LayoutContext) -> IntrinsicWidths {
+        if let Some(&Length(width, Px)) = self.style_value("width") {
+            return IntrinsicWidths {
+                min: width,
+                max: width,
+            };
+        }
+
+        if let Some((natural_width, _)) = self.natural_size(context) {
+            let width = match (self.definite_height(), self.preferred_ratio(context)) {
+                (Some(height), Some(ratio)) => height * ratio,
+                _ => natural_width,
+            };
+            return IntrinsicWidths {
+                min: width,
+                max: width,
+            };
+        }
+
+        let children = self
+            .children
+            .iter()
+            .filter(|child| {
+                !matches!(child.box_type, MarkerBox(..)) && child.position() != Position::Absolute
+            })
+            .map(|child| child.outer_intrinsic_widths(context));
+
+        match self.box_type {
+            AnonymousBlock => self.inline_intrinsic_widths(context),
+            MarkerBox(..) | TextRun(..) => IntrinsicWidths::default(),
+            // The items of a flex row sit next to each other, unless they wrap.
+            BlockNode(style)
+                if style.display() == Display::Flex
+                    && !matches!(
+                        self.style_keyword("flex-direction"),
+                        Some("column" | "column-reverse")
+                    ) =>
+            {
+                children.fold(IntrinsicWidths::default(), |a, b| IntrinsicWidths {
+                    min: a.min.max(b.min),
+                    max: a.max + b.max,
+                })
+            }
+            // TODO: Measure the tracks of grid containers.
+            _ => children.fold(IntrinsicWidths::default(), |a, b| IntrinsicWidths {
+                min: a.min.max(b.min),
+                max: a.max.max(b.max),
+            }),
+        }
+    }
+
+    /// The intrinsic widths of the margin box.
+    fn outer_intrinsic_widths(&self, context: &LayoutContext) -> IntrinsicWidths {
+        let widths = self.intrinsic_widths(context);
+        let edges = self.horizontal_item_edges();
+        IntrinsicWidths {
+            min: widths.min + edges,
+            max: widths.max + edges,
+        }
+    }
+
+    /// The horizontal margins, borders and padding of the box, with auto margins as 0.
+    fn horizontal_item_edges(&self) -> f32 {
+        let zero = Length(0.0, Px);
+        [
+            ("margin-left", "margin"),
+            ("margin-right", "margin"),
+            ("border-left-width", "border-width"),
+            ("border-right-width", "border-width"),
+            ("padding-left", "padding"),
+            ("padding-right", "padding"),
+        ]
+        .iter()
+        .map(|(name, fallback)| match self.box_type {
+            BlockNode(style) | InlineNode(style) | GridNode(style) => {
+                style.lookup(name, fallback, &zero).to_px()
+            }
+            MarkerBox(..) | TextRun(..) | AnonymousBlock => 0.0,
+        })
+        .sum()
+    }
+
+    /// Shift a relatively positioned box by its offsets, without affecting the normal flow.
+    ///
+    /// http://www.w3.org/TR/CSS2/visuren.html#relative-positioning
+    fn apply_relative_offset(&mut self) {
+        let style = self.get_style_node();
+
+        // If both offsets of an axis are specified, `left` and `top` win.
+        let offset = |name, opposite_name| match (style.value(name), style.value(opposite_name)) {
+            (Some(value @ Length(..)), _) => value.to_px(),
+            (_, Some(value @ Length(..))) => -value.to_px(),
+            _ => 0.0,
+        };
+
+        let dx = offset("left", "right");
+        let dy = offset("top", "bottom");
+        self.translate(dx, dy);
+    }
+
+    /// Move the box and all of its descendants.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        self.static_position.0 += dx;
+        self.static_position.1 += dy;
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    /// The image that is the content of this box, if it is a replaced element and the image has
+    /// been loaded.
+    fn replaced_image(&self, context: &LayoutContext) -> Option<ImageHandle> {
+        match self.box_type {
+            BlockNode(style) if is_replaced(style) => {
+                let source = style.node.get_attribute("src")?;
+                context.images?.handle(source)
+            }
+            _ => None,
+        }
+    }
+
+    /// The natural width and height of a replaced element. A frame has no natural size, so it
+    /// takes the default object size.
+    ///
+    /// https://www.w3.org/TR/css-images-3/#natural-dimensions
+    /// https://www.w3.org/TR/css-images-3/#default-object-size
+    fn natural_size(&self, context: &LayoutContext) -> Option<(f32, f32)> {
+        match self.box_type {
+            BlockNode(style) if is_frame(style.node) => Some((300.0, 150.0)),
+            _ => self.replaced_image(context).map(|image| image.size()),
+        }
+    }
+
+    /// The ratio of width to height that the box keeps: its `aspect-ratio`, or else the natural
+    /// ratio of its image.
+    fn preferred_ratio(&self, context: &LayoutContext) -> Option<f32> {
+        self.get_style_node().aspect_ratio().or_else(|| {
+            let (width, height) = self.replaced_image(context)?.size();
+            (width > 0.0 && height > 0.0).then_some(width / height)
+        })
+    }
+
+    /// Calculate the width of a block-level element in normal flow.
+    ///
+    /// Sets the horizontal margin/padding/border dimensions, and the `width`.
+    fn calculate_block_width(&mut self, containing_block: Dimensions, context: &LayoutContext) {
+        let available_width = containing_block.content.width - self.horizontal_item_edges();
+        let ratio = self.preferred_ratio(context);
+        let definite_height = self.definite_height();
+        let natural_width = self.natural_size(context).map(|(width, _)| width);
+        self.solve_constrained("width", |layout_box, width| {
+            // A box with an aspect ratio takes its width from its height, if that is definite.
+            let width = match (width, ratio, definite_height, natural_width) {
+                (Keyword(auto), Some(ratio), Some(height), _) if auto == "auto" => {
+                    Length(height * ratio, Px)
+                }
+                // Otherwise a replaced element is as wide as its image.
+                (Keyword(auto), _, _, Some(natural_width)) if auto == "auto" => {
+                    Length(natural_width, Px)
+                }
+                (width, _, _, _) => {
+                    layout_box.resolve_intrinsic_width(width, available_width, context)
+                }
+            };
+            layout_box.solve_block_width(containing_block, width)
+        });
+    }
+
+    /// Solve the horizontal constraints of a block-level non-replaced element in normal flow for
+    /// the given `width`.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    fn solve_block_width(&mut self, containing_block: Dimensions, mut width: Value) {
+        let style = self.get_style_node();
+
+        let auto = Keyword("auto".to_string());
+
+        // margin, border, and padding have initial value 0.
+        let zero = Length(0.0, Px);
+
+        let mut margin_left = style.lookup("margin-left", "margin", &zero).clone();
+        let mut margin_right = style.lookup("margin-right", "margin", &zero).clone();
+
+        let border_left = style.lookup("border-left-width", "border-width", &zero);
+        let border_right = style.lookup("border-right-width", "border-width", &zero);
+
+        let padding_left = style.lookup("padding-left", "padding", &zero);
+        let padding_right = style.lookup("padding-right", "padding", &zero);
+
+        let total = sum([
+            &margin_left,
+            &margin_right,
+            border_left,
+            border_right,
+            padding_left,
+            padding_right,
+            &width,
+        ]
+        .iter()
+        .map(|v| v.to_px()));
+
+        // If width is not auto and the total is wider than the container, treat auto margins as 0.
+        if width != auto && total > containing_block.content.width {
+            if margin_left == auto {
+                margin_left = Length(0.0, Px);
+            }
+            if margin_right == auto {
+                margin_right = Length(0.0, Px);
+            }
+        }
+
+        // Adjust used values so that the above sum equals `containing_block.width`.
+        // Each arm of the `match` should increase the total width by exactly `underflow`,
+        // and afterward all values should be absolute lengths in px.
+        let underflow = containing_block.content.width - total;
+
+        // The direction of the containing block decides which margin gives way. Direction is
+        // inherited, so the box usually has the same direction as its containing block.
+        let rtl = style.direction() == Direction::Rtl;
 
         match (width == auto, margin_left == auto, margin_right == auto) {
-            // If the values are overconstrained, calculate margin_right.
+            // If the values are overconstrained, calculate margin_right, or margin_left for
+            // right-to-left text.
+            (false, false, false) if rtl => {
+                margin_left = Length(margin_left.to_px() + underflow, Px);
+            }
             (false, false, false) => {
                 margin_right = Length(margin_right.to_px() + underflow, Px);
             }
@@ -199,6 +1609,10 @@ impl<'a> LayoutBox<'a> {
                 if underflow >= 0.0 {
                     // Expand width to fill the underflow.
                     width = Length(underflow, Px);
+                } else if rtl {
+                    // Width can't be negative. Adjust the left margin instead.
+                    width = Length(0.0, Px);
+                    margin_left = Length(margin_left.to_px() + underflow, Px);
                 } else {
                     // Width can't be negative. Adjust the right margin instead.
                     width = Length(0.0, Px);
@@ -262,32 +1676,278 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
-    /// Lay out the block's children within its content area.
+    /// Lay out the block's children within its content area, collapsing adjoining vertical
+    /// margins.
+    ///
+    /// http://www.w3.org/TR/CSS2/box.html#collapsing-margins
     ///
     /// Sets `self.dimensions.height` to the total content height.
-    fn layout_block_children(&mut self) {
+    fn layout_block_children(&mut self, context: &LayoutContext) {
+        // The margins of the first and last child collapse with the margins of this box, unless
+        // a border or padding separates them.
+        let contains_margins = self.establishes_formatting_context();
+        let auto_height = matches!(self.style_value("height"), None | Some(Keyword(_)))
+            && self
+                .style_value("min-height")
+                .is_none_or(|min| min.to_px() == 0.0);
         let d = &mut self.dimensions;
+        let collapse_top = !contains_margins && d.border.top == 0.0 && d.padding.top == 0.0;
+        let collapse_bottom =
+            !contains_margins && auto_height && d.border.bottom == 0.0 && d.padding.bottom == 0.0;
+
+        let mut margin_top = CollapsedMargin::new(d.margin.top);
+
+        // Margins adjoining the current position in the flow that haven't been applied yet.
+        let mut pending = CollapsedMargin::default();
+
+        // Whether the children so far collapsed into the top margin of this box.
+        let mut at_top = collapse_top;
+
+        d.content.height = 0.0;
+
         for child in &mut self.children {
-            child.layout(*d);
-            // Increment the height so each child is laid out below the previous one.
-            d.content.height += child.dimensions.margin_box().height;
+            if let MarkerBox(style, marker) = child.box_type {
+                child.layout_marker(style, marker, d.content, context);
+                continue;
+            }
+
+            if child.position() == Position::Absolute {
+                // Absolutely positioned boxes are taken out of the flow and laid out once their
+                // containing block is known. Until then, remember their static position.
+                let static_y = d.content.y + d.content.height + pending.resolve();
+                child.static_position = (d.content.x, static_y);
+                continue;
+            }
+
+            child.layout(*d, context);
+
+            if let BlockNode(_) | GridNode(_) = child.box_type {
+                let child_margins = child.collapsed_margins;
+
+                let offset = if at_top {
+                    margin_top = margin_top.adjoin(child_margins.top);
+                    0.0
+                } else {
+                    pending.adjoin(child_margins.top).resolve()
+                };
+
+                // The child placed itself below its own top margin: move it to where the collapsed
+                // margin puts it.
+                child.translate(0.0, offset - child.dimensions.margin.top);
+
+                if child_margins.through {
+                    if at_top {
+                        margin_top = margin_top.adjoin(child_margins.bottom);
+                    } else {
+                        pending = pending
+                            .adjoin(child_margins.top)
+                            .adjoin(child_margins.bottom);
+                    }
+                } else {
+                    // Increment the height so each child is laid out below the previous one.
+                    d.content.height += offset + child.dimensions.border_box().height;
+                    pending = child_margins.bottom;
+                    at_top = false;
+                }
+            } else {
+                // Anonymous boxes hold lines of inline content, which separate the margins around
+                // them.
+                child.translate(0.0, pending.resolve());
+                d.content.height += pending.resolve() + child.dimensions.margin_box().height;
+                pending = CollapsedMargin::default();
+                at_top = false;
+            }
         }
+
+        let margin_bottom = CollapsedMargin::new(d.margin.bottom);
+        self.collapsed_margins = if at_top && collapse_bottom {
+            CollapsedMargins {
+                top: margin_top,
+                bottom: margin_bottom,
+                through: true,
+            }
+        } else if collapse_bottom {
+            CollapsedMargins {
+                top: margin_top,
+                bottom: margin_bottom.adjoin(pending),
+                through: false,
+            }
+        } else {
+            d.content.height += pending.resolve();
+            CollapsedMargins {
+                top: margin_top,
+                bottom: margin_bottom,
+                through: false,
+            }
+        };
+    }
+
+    /// Whether the box establishes a new block formatting context, which keeps the margins of its
+    /// children from collapsing with its own.
+    fn establishes_formatting_context(&self) -> bool {
+        self.is_root
+            || self.is_flex_item
+            || self.is_grid_item
+            || self.position() == Position::Absolute
+            || self.overflow() != Overflow::Visible
+            || self.is_multicol_container()
     }
 
     /// Height of a block-level non-replaced element in normal flow with overflow visible.
-    fn calculate_block_height(&mut self) {
-        // If the height is set to an explicit length, use that exact length.
-        // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+    fn calculate_block_height(&mut self, context: &LayoutContext) {
+        let content_height = self.dimensions.content.height;
+
+        // A box with an aspect ratio takes its height from its width. Its content may still make
+        // it taller, unless the overflow is clipped.
+        let ratio_height = self.preferred_ratio(context).map(|ratio| {
+            let height = self.dimensions.content.width / ratio;
+            match self.overflow() {
+                Overflow::Visible => height.max(content_height),
+                Overflow::Hidden | Overflow::Scroll | Overflow::Auto => height,
+            }
+        });
+        // Otherwise a replaced element without a ratio, like a frame, is as tall as its natural
+        // height.
+        let ratio_height = ratio_height.or(self.natural_size(context).map(|(_, height)| height));
+
+        self.solve_constrained("height", |layout_box, height| {
+            // If the height is set to an explicit length, use that exact length.
+            // Otherwise, just keep the value set by `layout_block_children`.
+            layout_box.dimensions.content.height = match height {
+                Length(h, Px) => h,
+                _ => ratio_height.unwrap_or(content_height),
+            };
+        });
+    }
+
+    /// Solve for the used `width` or `height` of the box, and solve again with `max-width` or
+    /// `min-width` (or their height counterparts) in its place if the result violates them.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+    /// http://www.w3.org/TR/CSS2/visudet.html#min-max-heights
+    fn solve_constrained<F>(&mut self, name: &str, solve: F)
+    where
+        F: Fn(&mut Self, Value),
+    {
+        let style = self.get_style_node();
+        let used = |layout_box: &Self| match name {
+            "width" => layout_box.dimensions.content.width,
+            _ => layout_box.dimensions.content.height,
+        };
+
+        // `width` and `height` have initial value `auto`.
+        let auto = Keyword("auto".to_string());
+        solve(self, style.value(name).unwrap_or(&auto).clone());
+
+        // `max-width` and `max-height` have initial value `none`.
+        if let Some(max @ Length(..)) = style.value(&format!("max-{}", name)) {
+            if used(self) > max.to_px() {
+                solve(self, max.clone());
+            }
+        }
+
+        // `min-width` and `min-height` have initial value 0.
+        if let Some(min @ Length(..)) = style.value(&format!("min-{}", name)) {
+            if used(self) < min.to_px() {
+                solve(self, min.clone());
+            }
+        }
+    }
+
+    /// Lay out a flex or grid item with its margin box at (`x`, `y`), with the given content
+    /// width, and the given content height or the one that follows from its content.
+    fn layout_item(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: Option<f32>,
+        context: &LayoutContext,
+    ) {
+        let d = &mut self.dimensions;
+        d.content.x = x + d.margin.left + d.border.left + d.padding.left;
+        d.content.y = y + d.margin.top + d.border.top + d.padding.top;
+        d.content.width = width;
+
+        self.layout_children(context);
+
+        match (height, &self.box_type) {
+            (Some(height), _) => self.dimensions.content.height = height,
+            (None, MarkerBox(..) | TextRun(..) | AnonymousBlock) => {}
+            (None, _) => self.calculate_block_height(context),
+        }
+    }
+
+    /// Set the margins, borders and padding of a flex or grid item, or an inline box. Auto margins
+    /// are treated as 0.
+    fn calculate_item_edges(&mut self) {
+        let style = match self.box_type {
+            BlockNode(style) | InlineNode(style) | GridNode(style) => style,
+            MarkerBox(..) | TextRun(..) | AnonymousBlock => return,
+        };
+
+        let zero = Length(0.0, Px);
+        let d = &mut self.dimensions;
+
+        d.margin.left = style.lookup("margin-left", "margin", &zero).to_px();
+        d.margin.right = style.lookup("margin-right", "margin", &zero).to_px();
+        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
+        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+
+        d.border.left = style
+            .lookup("border-left-width", "border-width", &zero)
+            .to_px();
+        d.border.right = style
+            .lookup("border-right-width", "border-width", &zero)
+            .to_px();
+        d.border.top = style
+            .lookup("border-top-width", "border-width", &zero)
+            .to_px();
+        d.border.bottom = style
+            .lookup("border-bottom-width", "border-width", &zero)
+            .to_px();
+
+        d.padding.left = style.lookup("padding-left", "padding", &zero).to_px();
+        d.padding.right = style.lookup("padding-right", "padding", &zero).to_px();
+        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
+        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+    }
+
+    /// The used `height` of the box if it doesn't depend on its content.
+    fn definite_height(&self) -> Option<f32> {
+        match self.style_value("height") {
+            Some(height @ Length(..)) => Some(self.clamp_height(height.to_px())),
+            _ => None,
+        }
+    }
+
+    /// Clamp a height to `min-height` and `max-height`.
+    fn clamp_height(&self, height: f32) -> f32 {
+        let min = match self.style_value("min-height") {
+            Some(value @ Length(..)) => value.to_px(),
+            _ => 0.0,
+        };
+        let max = match self.style_value("max-height") {
+            Some(value @ Length(..)) => value.to_px(),
+            _ => f32::INFINITY,
+        };
+        height.min(max).max(min)
+    }
+
+    fn style_keyword(&self, name: &str) -> Option<&'a str> {
+        match self.box_type {
+            BlockNode(node) | InlineNode(node) | GridNode(node) | TextRun(node, _) => {
+                node.keyword(name)
+            }
+            MarkerBox(..) | AnonymousBlock => None,
         }
     }
 
     /// Where a new inline child should go.
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
-            InlineNode(_) | AnonymousBlock => self,
-            BlockNode(_) => {
+            InlineNode(_) | MarkerBox(..) | TextRun(..) | AnonymousBlock => self,
+            BlockNode(_) | GridNode(_) => {
                 // If we've just generated an anonymous block box, keep using it.
                 // Otherwise, create a new one.
                 match self.children.last() {
@@ -303,17 +1963,106 @@ impl<'a> LayoutBox<'a> {
     }
 }
 
+fn horizontal_edges(d: &Dimensions) -> f32 {
+    d.margin.horizontal() + d.border.horizontal() + d.padding.horizontal()
+}
+
+fn vertical_edges(d: &Dimensions) -> f32 {
+    d.margin.vertical() + d.border.vertical() + d.padding.vertical()
+}
+
 impl Rect {
+    /// The bounding box of the rectangle after a transformation.
+    pub fn transformed(self, transform: &Transform) -> Rect {
+        let corners = [
+            transform.apply(self.x, self.y),
+            transform.apply(self.x + self.width, self.y),
+            transform.apply(self.x, self.y + self.height),
+            transform.apply(self.x + self.width, self.y + self.height),
+        ];
+        let (x0, y0, x1, y1) = corners.iter().fold(
+            (
+                f32::INFINITY,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::NEG_INFINITY,
+            ),
+            |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        );
+        Rect {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+
     pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
         Rect {
             x: self.x - edge.left,
             y: self.y - edge.top,
-            width: self.width + edge.left + edge.right,
-            height: self.height + edge.top + edge.bottom,
+            width: self.width + edge.horizontal(),
+            height: self.height + edge.vertical(),
+        }
+    }
+
+    /// The rectangle moved to the right by `dx` and down by `dy`.
+    pub fn translate(self, dx: f32, dy: f32) -> Rect {
+        Rect {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..self
+        }
+    }
+
+    pub fn area(self) -> f32 {
+        self.width * self.height
+    }
+
+    /// Whether a point is in the rectangle. Points on its left and top edges are in it, and
+    /// points on its right and bottom edges aren't, so adjacent rectangles never both contain a
+    /// point.
+    pub fn contains_point(self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The smallest rectangle that contains both rectangles.
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    /// The area covered by both rectangles, which is empty if they don't overlap.
+    pub fn intersect(self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x).max(0.0),
+            height: ((self.y + self.height).min(other.y + other.height) - y).max(0.0),
         }
     }
 }
 
+impl EdgeSizes {
+    /// The sum of the left and right edges.
+    pub fn horizontal(self) -> f32 {
+        self.left + self.right
+    }
+
+    /// The sum of the top and bottom edges.
+    pub fn vertical(self) -> f32 {
+        self.top + self.bottom
+    }
+}
+
 impl Dimensions {
     /// The area covered by the content area plus its padding.
     pub fn padding_box(self) -> Rect {
@@ -329,6 +2078,27 @@ impl Dimensions {
     }
 }
 
+impl CollapsedMargin {
+    fn new(margin: f32) -> CollapsedMargin {
+        CollapsedMargin {
+            positive: margin.max(0.0),
+            negative: margin.min(0.0),
+        }
+    }
+
+    fn adjoin(self, other: CollapsedMargin) -> CollapsedMargin {
+        CollapsedMargin {
+            positive: self.positive.max(other.positive),
+            negative: self.negative.min(other.negative),
+        }
+    }
+
+    /// The width of the collapsed margin.
+    fn resolve(self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
 fn sum<I>(iter: I) -> f32
 where
     I: Iterator<Item = f32>,
@@ -342,6 +2112,7 @@ mod tests {
 
     use crate::css::*;
     use crate::dom::*;
+    use crate::images::*;
     use crate::layout::*;
     use crate::style::*;
 
@@ -393,6 +2164,31 @@ mod tests {
         assert_eq!(p.dimensions.content.width, 24.0);
     }
 
+    #[test]
+    fn test_rect_geometry() {
+        let a = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+        };
+        let b = a.translate(5.0, 5.0);
+        assert_eq!((b.x, b.y), (5.0, 5.0));
+        assert_eq!(a.intersect(b).area(), 25.0);
+        assert_eq!(a.union(b).area(), 225.0);
+        assert!(a.contains_point(0.0, 9.5));
+        assert!(!a.contains_point(10.0, 5.0));
+
+        let edges = EdgeSizes {
+            left: 1.0,
+            right: 2.0,
+            top: 3.0,
+            bottom: 4.0,
+        };
+        assert_eq!((edges.horizontal(), edges.vertical()), (3.0, 7.0));
+        assert_eq!(a.expanded_by(edges).area(), 13.0 * 17.0);
+    }
+
     #[test]
     fn test_layout_inline() {
         let document = Node::from(
@@ -503,4 +2299,541 @@ mod tests {
             panic!();
         }
     }
+
+    #[test]
+    fn test_layout_absolute() {
+        let document = Node::from("<a><b><c></c></b><e></e><d></d></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d, e {
+                display: block;
+            }
+
+            b {
+                position: relative;
+                margin: 10px;
+                height: 100px;
+            }
+
+            c {
+                position: absolute;
+                right: 20px;
+                bottom: 10px;
+                width: 50px;
+                height: 30px;
+            }
+
+            d {
+                height: 40px;
+            }
+
+            e {
+                position: absolute;
+                height: 20px;
+            }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let b = &actual.children[0];
+        let c = &b.children[0];
+        let e = &actual.children[1];
+        let d = &actual.children[2];
+
+        // c is placed against the padding box of its relatively positioned parent.
+        assert_eq!(
+            c.dimensions.content,
+            Rect {
+                x: 720.0,
+                y: 70.0,
+                width: 50.0,
+                height: 30.0
+            }
+        );
+
+        // e has no offsets, so it stays at its static position, without taking up space. It is
+        // empty, so its shrink-to-fit width is 0.
+        assert_eq!(
+            e.dimensions.content,
+            Rect {
+                x: 0.0,
+                y: 120.0,
+                width: 0.0,
+                height: 20.0
+            }
+        );
+        assert_eq!(d.dimensions.content.y, 120.0);
+    }
+
+    #[test]
+    fn test_layout_margin_collapsing() {
+        let document = Node::from("<a><b><c></c></b><d></d><e></e><f></f></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d, e, f {
+                display: block;
+            }
+
+            b { margin-bottom: 10px; }
+            c { margin-top: 20px; height: 10px; }
+            d { margin-top: 30px; margin-bottom: 5px; }
+            e { margin-top: -10px; height: 10px; }
+            f { margin-top: 15px; height: 10px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let b = &actual.children[0];
+        let c = &b.children[0];
+        let e = &actual.children[2];
+        let f = &actual.children[3];
+
+        // The top margin of c collapses through b, but not through the root.
+        assert_eq!(b.dimensions.content.y, 20.0);
+        assert_eq!(c.dimensions.content.y, 20.0);
+
+        // The margins of b, the empty d, and e collapse into max(10, 30, 5) + min(-10).
+        assert_eq!(e.dimensions.content.y, 50.0);
+        assert_eq!(f.dimensions.content.y, 75.0);
+        assert_eq!(actual.dimensions.content.height, 85.0);
+    }
+
+    #[test]
+    fn test_layout_min_max() {
+        let document = Node::from("<a><b></b><c><d></d></c></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d {
+                display: block;
+            }
+
+            b { max-width: 300px; min-height: 50px; }
+            c { width: 100px; min-width: 200px; max-height: 20px; }
+            d { height: 100px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let b = &actual.children[0];
+        let c = &actual.children[1];
+
+        assert_eq!(b.dimensions.content.width, 300.0);
+        assert_eq!(b.dimensions.content.height, 50.0);
+        assert_eq!(b.dimensions.margin.right, 500.0);
+        assert_eq!(c.dimensions.content.width, 200.0);
+        assert_eq!(c.dimensions.content.height, 20.0);
+    }
+
+    #[test]
+    fn test_layout_intrinsic_widths() {
+        let document =
+            Node::from("<a><b>aaa bbbbb</b><c>aaa bbbbb</c><d>aaa bbbbb</d><e><f></f></e></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d, e, f { display: block; font-size: 10px; }
+            b { width: min-content; }
+            c { width: max-content; }
+            d, e { width: fit-content; padding: 5px; }
+            f { width: 1000px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+        let widths: Vec<_> = actual
+            .children
+            .iter()
+            .map(|child| child.dimensions.content.width)
+            .collect();
+
+        // Glyphs are 5px wide, so the longest word is 25px wide and the whole text 45px.
+        assert_eq!(widths, vec![25.0, 45.0, 45.0, 1000.0]);
+    }
+
+    #[test]
+    fn test_layout_aspect_ratio() {
+        let document = Node::from("<a><b></b><c></c></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; }
+            b { width: 160px; aspect-ratio: 16 / 9; }
+            c { height: 50px; aspect-ratio: 2; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let b = &actual.children[0];
+        let c = &actual.children[1];
+
+        assert_eq!(b.dimensions.content.height, 90.0);
+        assert_eq!(c.dimensions.content.width, 100.0);
+    }
+
+    #[test]
+    fn test_layout_images() {
+        let document = Node::from(
+            "<a><b><img src=\"a.png\"></img><img src=\"b.png\"></img></b><c src=\"a.png\"></c></a>",
+        );
+
+        let style = Sheet::from(
+            "
+            a, b { display: block; }
+            c { display: block; width: 80px; background-image: url(a.png); }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut images = ImageCache::new();
+        let image = Image {
+            width: 40,
+            height: 20,
+            pixels: vec![0; 40 * 20 * 4],
+        };
+        let handle = images.insert("a.png", image);
+
+        let context = LayoutContext {
+            images: Some(&images),
+            ..LayoutContext::new(Rect {
+                x: 0.0,
+                y: 0.0,
+                width: 800.0,
+                height: 600.0,
+            })
+        };
+        let actual = layout_tree_with_context(&applied_styles, &context);
+
+        // The image that is loaded has its natural size, and the missing one takes up no space.
+        let line = &actual.children[0].children[0];
+        assert_eq!(line.children[0].image(), Some(handle));
+        assert_eq!(line.children[0].dimensions.content.width, 40.0);
+        assert_eq!(line.children[0].dimensions.content.height, 20.0);
+        assert_eq!(line.children[1].image(), None);
+        assert_eq!(line.children[1].dimensions.content.width, 0.0);
+
+        // Only `img` elements are replaced, but any element can have a background image.
+        let c = &actual.children[1];
+        assert_eq!(c.image(), None);
+        assert_eq!(c.background_image(), Some(handle));
+        assert_eq!(c.dimensions.content.height, 0.0);
+    }
+
+    #[test]
+    fn test_layout_list_items() {
+        let document = Node::from("<ol><li></li><li></li><li></li></ol>");
+
+        let style = Sheet::from(
+            "
+            ol { display: block; padding-left: 40px; }
+            li { display: list-item; list-style-type: decimal; height: 20px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let markers: Vec<_> = actual
+            .children
+            .iter()
+            .map(|li| match li.children[0].box_type {
+                MarkerBox(_, marker) => (marker, li.children[0].dimensions.content),
+                _ => panic!("list item without a marker"),
+            })
+            .collect();
+
+        assert_eq!(
+            markers[2],
+            (
+                ListMarker::Decimal(3),
+                Rect {
+                    x: 16.0,
+                    y: 40.0,
+                    width: 16.0,
+                    height: 19.2
+                }
+            )
+        );
+        assert_eq!(markers[0].0, ListMarker::Decimal(1));
+    }
+
+    #[test]
+    fn test_layout_counters() {
+        let document = Node::from(
+            "<a><h><ol><li></li><li><ol><li></li></ol></li></ol></h><h><ol><li></li></ol></h></a>",
+        );
+
+        let style = Sheet::from(
+            r#"
+            a, h, ol { display: block; }
+            h { counter-increment: section 2; }
+            h::before { content: "Section " counter(section); display: block; }
+            ol { counter-reset: list-item 4; }
+            li { display: list-item; list-style-type: decimal; }
+            li::before { content: counters(list-item, ".") ". "; }
+        "#,
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        fn collect(layout_box: &LayoutBox, content: &mut Vec<String>) {
+            match layout_box.box_type {
+                TextRun(_, ref text) => content.push(text.clone()),
+                MarkerBox(_, ListMarker::Decimal(n)) => content.push(format!("marker {}", n)),
+                _ => layout_box
+                    .children
+                    .iter()
+                    .for_each(|child| collect(child, content)),
+            }
+        }
+
+        let mut content = Vec::new();
+        collect(&actual, &mut content);
+        assert_eq!(
+            content,
+            vec![
+                "Section 2",
+                "marker 5",
+                "5. ",
+                "marker 6",
+                "6. ",
+                "marker 5",
+                "6.5. ",
+                "Section 4",
+                "marker 5",
+                "5. ",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layout_rtl() {
+        let document = Node::from(r#"<a dir="rtl"><b></b><c></c></a>"#);
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; }
+            b { width: 100px; margin-left: 10px; }
+            c { width: 1000px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        // Over-constrained boxes keep their right margin, and overflow to the left.
+        let b = &actual.children[0];
+        assert_eq!(b.dimensions.margin.left, 700.0);
+        assert_eq!(b.dimensions.content.x, 700.0);
+
+        let c = &actual.children[1];
+        assert_eq!(c.dimensions.content.x, -200.0);
+    }
+
+    #[test]
+    fn test_box_for_node() {
+        let document = Node::from("<a><b></b><c>text</c></a>");
+
+        let style = Sheet::from("a, b, c { display: block; } c { height: 20px; }");
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let c_node = applied_styles.children[1].node;
+        let c = actual.box_for_node(c_node).expect("c has a box");
+        assert!(std::ptr::eq(c.node().unwrap(), c_node));
+        assert_eq!(c.dimensions.content.height, 20.0);
+
+        let text_node = applied_styles.children[1].children[0].node;
+        let text = actual.box_for_node(text_node).expect("text has a box");
+        assert!(matches!(text.box_type, InlineNode(_)));
+
+        let other = Node::from("<c></c>");
+        assert!(actual.box_for_node(&other).is_none());
+    }
+
+    #[test]
+    fn test_box_ref() {
+        let document = Node::from("<a><b></b><c><d></d></c></a>");
+
+        let style = Sheet::from("a, b, c, d { display: block; height: 10px; padding: 5px; }");
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        let c = actual.as_tree().children().nth(1).unwrap();
+        let d = c.children().next().unwrap();
+        let b = c.previous_sibling().unwrap();
+
+        assert!(std::ptr::eq(
+            d.parent().unwrap().layout_box(),
+            c.layout_box()
+        ));
+        assert!(std::ptr::eq(b.layout_box(), &actual.children[0]));
+        assert!(b.previous_sibling().is_none());
+        assert!(c.next_sibling().is_none());
+        assert!(d.parent().unwrap().parent().unwrap().parent().is_none());
+
+        assert_eq!(
+            d.layout_box().border_box_absolute(),
+            Rect {
+                x: 10.0,
+                y: 30.0,
+                width: 780.0,
+                height: 20.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_layout_incremental() {
+        let document = Node::from("<a><b></b><c></c></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; }
+            b { height: 50px; }
+            c { height: 20px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let mut actual = layout_tree(&applied_styles, viewport);
+
+        // Clean boxes keep their dimensions, even when these are out of date.
+        actual.children[1].dimensions.content.height = 99.0;
+        actual.relayout(&LayoutContext::new(viewport.content));
+        assert_eq!(actual.children[1].dimensions.content.height, 99.0);
+
+        assert!(!actual.mark_dirty(&[1, 5]));
+        assert!(actual.mark_dirty(&[1]));
+        actual.relayout(&LayoutContext::new(viewport.content));
+        assert_eq!(actual.children[1].dimensions.content.height, 20.0);
+        assert_eq!(actual.dimensions.content.height, 70.0);
+
+        // A different width invalidates the layout of every box.
+        viewport.content.width = 400.0;
+        actual.relayout(&LayoutContext::new(viewport.content));
+        assert_eq!(actual.children[0].dimensions.content.width, 400.0);
+        assert_eq!(actual.children[1].dimensions.content.y, 50.0);
+    }
+
+    #[test]
+    fn test_layout_context() {
+        let document = Node::from("<p>aa</p>");
+        let style = Sheet::from("p { display: block; }");
+        let applied_styles = style_tree(&document, &style);
+
+        let mut context = LayoutContext::new(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 300.0,
+            height: 200.0,
+        });
+        context.root_font_size = 20.0;
+
+        let actual = layout_tree_with_context(&applied_styles, &context);
+
+        // The viewport is the containing block, and text without a font size uses the root one.
+        assert_eq!(actual.dimensions.content.width, 300.0);
+        assert_eq!(actual.dimensions.content.height, 24.0);
+    }
+
+    #[test]
+    fn test_layout_form_controls() {
+        let document = Node::from(
+            "<p><input></input><input type=\"checkbox\" checked=\"\"></input><button>a</button></p>",
+        );
+        let sheet = user_agent_sheet();
+        let applied_styles = style_tree(&document, &sheet);
+        let context = LayoutContext::new(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 800.0,
+            height: 600.0,
+        });
+        let actual = layout_tree_with_context(&applied_styles, &context);
+
+        // Empty controls keep their size, and the check of a checked checkbox is painted inside.
+        let line = &actual.children[0].children;
+        let sizes: Vec<_> = line
+            .iter()
+            .map(|control| {
+                let border_box = control.dimensions.border_box();
+                (border_box.width, border_box.height)
+            })
+            .collect();
+        assert_eq!(sizes[0], (156.0, 22.0));
+        assert_eq!(sizes[1], (15.0, 15.0));
+        assert!(sizes[2].1 > 6.0);
+        assert_eq!(line[1].children[0].dimensions.content.height, 7.0);
+    }
 }
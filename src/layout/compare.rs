@@ -0,0 +1,130 @@
+//! Comparisons of layout geometry that allow for rounding, for tests that compare layouts with
+//! the layouts they expect, where text measurement and percentages make exact equality brittle.
+
+use crate::layout::{Dimensions, LayoutBox};
+
+impl Dimensions {
+    /// Whether the content rectangles and the edges differ by no more than `epsilon` anywhere.
+    pub fn approx_eq(&self, other: &Dimensions, epsilon: f32) -> bool {
+        let mut fields = fields(self).into_iter().zip(fields(other));
+        fields.all(|((_, a), (_, b))| (a - b).abs() <= epsilon)
+    }
+}
+
+/// The differences between the geometry of two layout trees, one per line, such as
+/// `html > body[0] > p[1]: content.width is 24 instead of 25`. Children are named by their index.
+/// Numbers that differ by no more than `epsilon` are the same.
+pub fn diff_trees(actual: &LayoutBox, expected: &LayoutBox, epsilon: f32) -> Vec<String> {
+    let mut differences = Vec::new();
+    diff_boxes(actual, expected, epsilon, actual.name(), &mut differences);
+    differences
+}
+
+/// Panics with the differences between the geometry of two layout trees, if they have any.
+#[track_caller]
+pub fn assert_tree_eq(actual: &LayoutBox, expected: &LayoutBox, epsilon: f32) {
+    let differences = diff_trees(actual, expected, epsilon);
+    if !differences.is_empty() {
+        panic!("the layout trees differ:\n{}", differences.join("\n"));
+    }
+}
+
+fn diff_boxes(
+    actual: &LayoutBox,
+    expected: &LayoutBox,
+    epsilon: f32,
+    path: &str,
+    differences: &mut Vec<String>,
+) {
+    if actual.name() != expected.name() {
+        let names = (actual.name(), expected.name());
+        differences.push(format!("{}: is {} instead of {}", path, names.0, names.1));
+        return;
+    }
+
+    let fields = fields(&actual.dimensions)
+        .into_iter()
+        .zip(fields(&expected.dimensions));
+    for ((name, a), (_, b)) in fields {
+        if (a - b).abs() > epsilon {
+            differences.push(format!("{}: {} is {} instead of {}", path, name, a, b));
+        }
+    }
+
+    let counts = (actual.children.len(), expected.children.len());
+    if counts.0 != counts.1 {
+        let difference = format!(
+            "{}: has {} children instead of {}",
+            path, counts.0, counts.1
+        );
+        differences.push(difference);
+    }
+    let children = actual.children.iter().zip(&expected.children);
+    for (i, (a, b)) in children.enumerate() {
+        let path = format!("{} > {}[{}]", path, a.name(), i);
+        diff_boxes(a, b, epsilon, &path, differences);
+    }
+}
+
+/// The numbers of dimensions, by name.
+fn fields(d: &Dimensions) -> [(&'static str, f32); 16] {
+    [
+        ("content.x", d.content.x),
+        ("content.y", d.content.y),
+        ("content.width", d.content.width),
+        ("content.height", d.content.height),
+        ("padding.left", d.padding.left),
+        ("padding.right", d.padding.right),
+        ("padding.top", d.padding.top),
+        ("padding.bottom", d.padding.bottom),
+        ("border.left", d.border.left),
+        ("border.right", d.border.right),
+        ("border.top", d.border.top),
+        ("border.bottom", d.border.bottom),
+        ("margin.left", d.margin.left),
+        ("margin.right", d.margin.right),
+        ("margin.top", d.margin.top),
+        ("margin.bottom", d.margin.bottom),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_diff_trees() {
+        let document = Node::from("<a><b></b><c></c></a>");
+        let sheet = |width| {
+            Sheet::from(
+                format!("a, b, c {{ display: block; }} c {{ width: {}px; }}", width).as_str(),
+            )
+        };
+        let sheets = [sheet(24.0), sheet(24.001), sheet(25.0)];
+        let styles: Vec<_> = sheets
+            .iter()
+            .map(|sheet| style_tree(&document, sheet))
+            .collect();
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        let actual = layout_tree(&styles[0], viewport);
+        let expected = layout_tree(&styles[1], viewport);
+        let wider = layout_tree(&styles[2], viewport);
+
+        let c = |root: &LayoutBox| root.children[1].dimensions;
+        assert!(c(&actual).approx_eq(&c(&expected), 0.01));
+        assert!(!c(&actual).approx_eq(&c(&wider), 0.01));
+        assert_tree_eq(&actual, &expected, 0.01);
+        assert_eq!(
+            diff_trees(&actual, &wider, 0.01),
+            [
+                "a > c[1]: content.width is 24 instead of 25",
+                "a > c[1]: margin.right is 776 instead of 775",
+            ]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! CSS counters, which number list items and generated content.
+//!
+//! http://www.w3.org/TR/CSS2/generate.html#counters
+
+use std::slice;
+
+use crate::css::Value;
+use crate::style::{Display, StyledNode};
+
+/// The counter that list item markers show.
+///
+/// https://www.w3.org/TR/css-lists-3/#list-item-counter
+pub(super) const LIST_ITEM: &str = "list-item";
+
+/// The counters that are in scope while the layout tree is built in tree order. A counter is in
+/// scope for the element that created it, the following siblings of that element, and their
+/// descendants.
+#[derive(Default)]
+pub(super) struct Counters(Vec<(String, i32)>);
+
+impl Counters {
+    /// Reset and increment the counters of an element, before its descendants are visited.
+    pub(super) fn enter(&mut self, style: &StyledNode) {
+        let mut resets = style.counter_reset();
+        let mut increments = style.counter_increment();
+
+        // Lists number their items from 1, as if `ol, ul { counter-reset: list-item }`.
+        let is_list = style
+            .children
+            .iter()
+            .any(|child| child.display() == Display::ListItem);
+        if is_list && !resets.iter().any(|(name, _)| name == LIST_ITEM) {
+            resets.push((LIST_ITEM.to_owned(), 0));
+        }
+
+        // List items count themselves, unless they increment `list-item` explicitly.
+        if style.display() == Display::ListItem
+            && !increments.iter().any(|(name, _)| name == LIST_ITEM)
+        {
+            increments.push((LIST_ITEM.to_owned(), 1));
+        }
+
+        self.0.extend(resets);
+
+        // Incrementing a counter that isn't in scope creates it at 0.
+        for (name, amount) in increments {
+            match self
+                .0
+                .iter_mut()
+                .rev()
+                .find(|(counter, _)| *counter == name)
+            {
+                Some(counter) => counter.1 += amount,
+                None => self.0.push((name, amount)),
+            }
+        }
+    }
+
+    /// A mark for the counters that are in scope now, to pass to `leave` after the descendants
+    /// of an element have been visited.
+    pub(super) fn scope(&self) -> usize {
+        self.0.len()
+    }
+
+    /// End the scope of the counters that were created since `scope` was called.
+    pub(super) fn leave(&mut self, scope: usize) {
+        self.0.truncate(scope);
+    }
+
+    /// The value of the innermost counter with the given name, or 0 if there is none in scope.
+    pub(super) fn value(&self, name: &str) -> i32 {
+        self.0
+            .iter()
+            .rev()
+            .find(|(counter, _)| counter == name)
+            .map_or(0, |&(_, value)| value)
+    }
+
+    /// The text generated by the `content` property of a pseudo-element: strings, the values of
+    /// `counter()` and `counters()`, and the attributes of the element with `attr()`.
+    ///
+    /// http://www.w3.org/TR/CSS2/generate.html#propdef-content
+    pub(super) fn content(&self, style: &StyledNode) -> String {
+        let values = match style.value("content") {
+            Some(Value::List(values)) => values.as_slice(),
+            Some(value) => slice::from_ref(value),
+            None => &[],
+        };
+
+        let mut text = String::new();
+        for value in values {
+            match value {
+                Value::Str(s) => text.push_str(s),
+                Value::Function(function, arguments) => match (&**function, &**arguments) {
+                    ("counter", [Value::Keyword(name)]) => {
+                        text.push_str(&self.value(name).to_string())
+                    }
+                    ("counters", [Value::Keyword(name), Value::Str(separator)]) => {
+                        let values: Vec<_> = self
+                            .0
+                            .iter()
+                            .filter(|(counter, _)| counter == name)
+                            .map(|(_, value)| value.to_string())
+                            .collect();
+                        text.push_str(&values.join(separator));
+                    }
+                    ("attr", [Value::Keyword(name)]) => {
+                        text.push_str(style.node.get_attribute(name).unwrap_or(""))
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        text
+    }
+}
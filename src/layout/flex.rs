@@ -0,0 +1,593 @@
+use std::ops::Range;
+
+use crate::css::Unit::Px;
+use crate::css::Value::{self, Keyword, Length, Number};
+use crate::layout::{
+    horizontal_edges, vertical_edges, CollapsedMargin, CollapsedMargins, LayoutBox, LayoutContext,
+};
+use crate::style::Position;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlexDirection {
+    Row,
+    RowReverse,
+    Column,
+    ColumnReverse,
+}
+
+/// The values of `justify-content`, which distribute free space along the main axis.
+#[derive(Clone, Copy, PartialEq)]
+enum Justify {
+    Start,
+    End,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+/// The values of `align-items` and `align-self`, which place items along the cross axis.
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Start,
+    End,
+    Center,
+    Stretch,
+}
+
+/// A child of a flex container, while its main size is being resolved.
+struct FlexItem {
+    /// The index of the box of the item among the children of the container.
+    index: usize,
+    grow: f32,
+    shrink: f32,
+    /// The main size of the content box, before flexing.
+    base_size: f32,
+    /// The base size, clamped to the min and max main size.
+    hypothetical_size: f32,
+    /// The main size of the content box, after flexing.
+    target_size: f32,
+    min_size: f32,
+    max_size: f32,
+    /// The margins, borders and padding along the main axis.
+    main_edges: f32,
+    /// Whether `target_size` is final.
+    frozen: bool,
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the children of a flex container as flex items, in one or more lines.
+    ///
+    /// https://www.w3.org/TR/css-flexbox-1/#layout-algorithm
+    ///
+    /// Sets `self.dimensions.height` to the total content height.
+    pub(super) fn layout_flex_children(&mut self, context: &LayoutContext) {
+        let direction = match self.style_keyword("flex-direction") {
+            Some("row-reverse") => FlexDirection::RowReverse,
+            Some("column") => FlexDirection::Column,
+            Some("column-reverse") => FlexDirection::ColumnReverse,
+            _ => FlexDirection::Row,
+        };
+        let is_row = matches!(direction, FlexDirection::Row | FlexDirection::RowReverse);
+        let is_reverse = matches!(
+            direction,
+            FlexDirection::RowReverse | FlexDirection::ColumnReverse
+        );
+        let wrap = matches!(
+            self.style_keyword("flex-wrap"),
+            Some("wrap") | Some("wrap-reverse")
+        );
+
+        let justify = match self.style_keyword("justify-content") {
+            Some("flex-end") | Some("end") => Justify::End,
+            Some("center") => Justify::Center,
+            Some("space-between") => Justify::SpaceBetween,
+            Some("space-around") => Justify::SpaceAround,
+            Some("space-evenly") => Justify::SpaceEvenly,
+            _ => Justify::Start,
+        };
+        let align_items = self.align("align-items").unwrap_or(Align::Stretch);
+
+        // `gap` sets both `row-gap` (between lines of a row container) and `column-gap`.
+        let gap = |name| match self.style_value(name).or(self.style_value("gap")) {
+            Some(value @ Length(..)) => value.to_px(),
+            _ => 0.0,
+        };
+        let (main_gap, cross_gap) = if is_row {
+            (gap("column-gap"), gap("row-gap"))
+        } else {
+            (gap("row-gap"), gap("column-gap"))
+        };
+
+        let content = self.dimensions.content;
+        let definite_height = self.definite_height();
+        let (main_size, cross_size) = if is_row {
+            (Some(content.width), definite_height)
+        } else {
+            (definite_height, Some(content.width))
+        };
+
+        // Determine the flex base size and hypothetical main size of each item.
+        let mut items = Vec::new();
+        for (index, child) in self.children.iter_mut().enumerate() {
+            if child.position() == Position::Absolute {
+                // The static position of an absolutely positioned child is the content origin.
+                child.static_position = (content.x, content.y);
+                continue;
+            }
+
+            child.calculate_item_edges();
+            let d = child.dimensions;
+            let (main_edges, cross_edges) = if is_row {
+                (horizontal_edges(&d), vertical_edges(&d))
+            } else {
+                (vertical_edges(&d), horizontal_edges(&d))
+            };
+
+            let (grow, shrink, basis) = child.flex_factors();
+            let main_property = if is_row { "width" } else { "height" };
+            let base_size = match basis.as_ref().or(child.style_value(main_property)) {
+                Some(value @ Length(..)) => value.to_px(),
+                _ if is_row => {
+                    child.shrink_to_fit_width(main_size.unwrap_or(0.0) - main_edges, context)
+                }
+                _ => {
+                    // The content height follows from laying out the item at its cross size.
+                    let width =
+                        child.flex_item_width(content.width - cross_edges, align_items, context);
+                    child.layout_item(content.x, content.y, width, None, context);
+                    child.dimensions.content.height
+                }
+            };
+
+            let min_size = match child.style_value(&format!("min-{}", main_property)) {
+                Some(value @ Length(..)) => value.to_px(),
+                _ => 0.0,
+            };
+            let max_size = match child.style_value(&format!("max-{}", main_property)) {
+                Some(value @ Length(..)) => value.to_px(),
+                _ => f32::INFINITY,
+            };
+
+            items.push(FlexItem {
+                index,
+                grow,
+                shrink,
+                base_size,
+                hypothetical_size: base_size.min(max_size).max(min_size),
+                target_size: 0.0,
+                min_size,
+                max_size,
+                main_edges,
+                frozen: false,
+            });
+        }
+
+        let lines = collect_lines(&items, main_size, main_gap, wrap);
+        let single_line = lines.len() == 1;
+
+        let mut cross_position = 0.0;
+        let mut main_extent: f32 = 0.0;
+
+        for (line_index, line) in lines.into_iter().enumerate() {
+            let line_items = &mut items[line];
+            let gaps = main_gap * (line_items.len() - 1) as f32;
+
+            // A container without a definite main size is as large as its items want to be.
+            let available = main_size.unwrap_or_else(|| {
+                gaps + line_items
+                    .iter()
+                    .map(|item| item.hypothetical_size + item.main_edges)
+                    .sum::<f32>()
+            });
+
+            resolve_flexible_lengths(line_items, available - gaps);
+
+            let used = gaps
+                + line_items
+                    .iter()
+                    .map(|item| item.target_size + item.main_edges)
+                    .sum::<f32>();
+            let (mut main_position, spacing) =
+                justify.distribute(available - used, line_items.len());
+            main_extent = main_extent.max(used);
+
+            if line_index > 0 {
+                cross_position += cross_gap;
+            }
+
+            // Lay out the items at their main size, to find out their cross size.
+            let mut line_cross_size: f32 = 0.0;
+            for item in line_items.iter() {
+                let child = &mut self.children[item.index];
+                let outer_main = item.target_size + item.main_edges;
+                let main_offset = if is_reverse {
+                    available - main_position - outer_main
+                } else {
+                    main_position
+                };
+
+                if is_row {
+                    let x = content.x + main_offset;
+                    let y = content.y + cross_position;
+                    child.layout_item(x, y, item.target_size, None, context);
+                    line_cross_size = line_cross_size.max(child.dimensions.margin_box().height);
+                } else {
+                    let align = child.align("align-self").unwrap_or(align_items);
+                    let cross_edges = horizontal_edges(&child.dimensions);
+                    let width = child.flex_item_width(content.width - cross_edges, align, context);
+                    let x = content.x + cross_position;
+                    let y = content.y + main_offset;
+                    child.layout_item(x, y, width, Some(item.target_size), context);
+                    line_cross_size = line_cross_size.max(child.dimensions.margin_box().width);
+                }
+
+                main_position += outer_main + main_gap + spacing;
+            }
+
+            // A single line fills the cross size of its container.
+            if let (true, Some(cross_size)) = (single_line, cross_size) {
+                line_cross_size = cross_size;
+            }
+
+            // Align the items within the line along the cross axis.
+            for item in line_items.iter() {
+                let child = &mut self.children[item.index];
+                let align = child.align("align-self").unwrap_or(align_items);
+                let outer_cross = if is_row {
+                    child.dimensions.margin_box().height
+                } else {
+                    child.dimensions.margin_box().width
+                };
+                let free_space = line_cross_size - outer_cross;
+
+                match align {
+                    Align::Start => {}
+                    Align::End if is_row => child.translate(0.0, free_space),
+                    Align::End => child.translate(free_space, 0.0),
+                    Align::Center if is_row => child.translate(0.0, free_space / 2.0),
+                    Align::Center => child.translate(free_space / 2.0, 0.0),
+                    Align::Stretch if is_row => {
+                        // Only items with an auto cross size are stretched.
+                        if matches!(child.style_value("height"), None | Some(Keyword(_))) {
+                            let height = child.dimensions.content.height + free_space;
+                            child.dimensions.content.height = child.clamp_height(height);
+                        }
+                    }
+                    Align::Stretch => {} // Already stretched by `flex_item_width`.
+                }
+            }
+
+            cross_position += line_cross_size;
+        }
+
+        for item in &items {
+            self.children[item.index].finish_positioning(context);
+        }
+
+        self.dimensions.content.height = if is_row {
+            cross_position
+        } else {
+            main_size.unwrap_or(main_extent)
+        };
+
+        // The margins of a flex container never collapse with those of its items.
+        let d = &self.dimensions;
+        self.collapsed_margins = CollapsedMargins {
+            top: CollapsedMargin::new(d.margin.top),
+            bottom: CollapsedMargin::new(d.margin.bottom),
+            through: false,
+        };
+    }
+
+    /// The `flex-grow` and `flex-shrink` factors, and the `flex-basis` unless it is `auto`.
+    fn flex_factors(&self) -> (f32, f32, Option<Value>) {
+        // The `flex` shorthand supports a single number, `auto` and `none`.
+        let (grow, shrink, basis) = match self.style_value("flex") {
+            Some(&Number(grow)) => (grow, 1.0, Some(Length(0.0, Px))),
+            Some(Keyword(keyword)) if keyword == "auto" => (1.0, 1.0, None),
+            Some(Keyword(keyword)) if keyword == "none" => (0.0, 0.0, None),
+            _ => (0.0, 1.0, None),
+        };
+
+        let number = |name, default| match self.style_value(name) {
+            Some(&Number(n)) => n,
+            _ => default,
+        };
+        let basis = match self.style_value("flex-basis") {
+            Some(value @ Length(..)) => Some(value.clone()),
+            Some(Keyword(_)) => None,
+            _ => basis,
+        };
+
+        (
+            number("flex-grow", grow),
+            number("flex-shrink", shrink),
+            basis,
+        )
+    }
+
+    /// The width of an item in a column flex container, given the width available to it.
+    fn flex_item_width(&self, available_width: f32, align: Align, context: &LayoutContext) -> f32 {
+        match self.style_value("width") {
+            Some(width @ Length(..)) => {
+                let min = self.style_value("min-width").map_or(0.0, |v| v.to_px());
+                let max = match self.style_value("max-width") {
+                    Some(value @ Length(..)) => value.to_px(),
+                    _ => f32::INFINITY,
+                };
+                width.to_px().min(max).max(min)
+            }
+            _ if align == Align::Stretch => available_width.max(0.0),
+            _ => self.shrink_to_fit_width(available_width, context),
+        }
+    }
+
+    fn align(&self, name: &str) -> Option<Align> {
+        match self.style_keyword(name) {
+            Some("flex-start") | Some("start") | Some("baseline") => Some(Align::Start),
+            Some("flex-end") | Some("end") => Some(Align::End),
+            Some("center") => Some(Align::Center),
+            Some("stretch") => Some(Align::Stretch),
+            _ => None,
+        }
+    }
+}
+
+impl Justify {
+    /// The offset of the first item and the extra space between items, for the free space left
+    /// on a line with `count` items.
+    fn distribute(self, free_space: f32, count: usize) -> (f32, f32) {
+        let count = count as f32;
+        match self {
+            Justify::Start => (0.0, 0.0),
+            Justify::End => (free_space, 0.0),
+            Justify::Center => (free_space / 2.0, 0.0),
+
+            // Without free space to distribute, the spacing values fall back to start or center.
+            Justify::SpaceBetween if free_space < 0.0 || count < 2.0 => (0.0, 0.0),
+            Justify::SpaceAround | Justify::SpaceEvenly if free_space < 0.0 => {
+                (free_space / 2.0, 0.0)
+            }
+
+            Justify::SpaceBetween => (0.0, free_space / (count - 1.0)),
+            Justify::SpaceAround => (free_space / count / 2.0, free_space / count),
+            Justify::SpaceEvenly => (free_space / (count + 1.0), free_space / (count + 1.0)),
+        }
+    }
+}
+
+/// Split the items into flex lines, starting a new line when the next item doesn't fit.
+///
+/// https://www.w3.org/TR/css-flexbox-1/#algo-line-break
+fn collect_lines(
+    items: &[FlexItem],
+    main_size: Option<f32>,
+    gap: f32,
+    wrap: bool,
+) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut used = 0.0;
+
+    for (index, item) in items.iter().enumerate() {
+        let outer_size = item.hypothetical_size + item.main_edges;
+
+        if let (true, Some(main_size)) = (wrap && index > start, main_size) {
+            if used + gap + outer_size > main_size {
+                lines.push(start..index);
+                start = index;
+                used = 0.0;
+            }
+        }
+
+        if index > start {
+            used += gap;
+        }
+        used += outer_size;
+    }
+
+    if start < items.len() {
+        lines.push(start..items.len());
+    }
+
+    lines
+}
+
+/// Grow or shrink the items on a line to fill the available space, respecting their min and
+/// max main sizes.
+///
+/// https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+fn resolve_flexible_lengths(items: &mut [FlexItem], available: f32) {
+    let hypothetical_sum: f32 = items
+        .iter()
+        .map(|item| item.hypothetical_size + item.main_edges)
+        .sum();
+    let growing = hypothetical_sum < available;
+
+    // Items that can't flex in the needed direction keep their hypothetical size.
+    for item in items.iter_mut() {
+        let factor = if growing { item.grow } else { item.shrink };
+        item.target_size = item.hypothetical_size;
+        item.frozen = factor == 0.0
+            || (growing && item.base_size > item.hypothetical_size)
+            || (!growing && item.base_size < item.hypothetical_size);
+    }
+
+    let free_space = |items: &[FlexItem]| {
+        available
+            - items
+                .iter()
+                .map(|item| {
+                    item.main_edges
+                        + if item.frozen {
+                            item.target_size
+                        } else {
+                            item.base_size
+                        }
+                })
+                .sum::<f32>()
+    };
+    let initial_free_space = free_space(items);
+
+    while items.iter().any(|item| !item.frozen) {
+        let mut remaining = free_space(items);
+
+        let factor_sum: f32 = items
+            .iter()
+            .filter(|item| !item.frozen)
+            .map(|item| if growing { item.grow } else { item.shrink })
+            .sum();
+        if factor_sum < 1.0 && (initial_free_space * factor_sum).abs() < remaining.abs() {
+            remaining = initial_free_space * factor_sum;
+        }
+
+        // Shrinking is proportional to the base size, so large items shrink more.
+        let scaled_shrink_sum: f32 = items
+            .iter()
+            .filter(|item| !item.frozen)
+            .map(|item| item.shrink * item.base_size)
+            .sum();
+
+        let mut violations = Vec::with_capacity(items.len());
+        for item in items.iter_mut() {
+            if item.frozen {
+                violations.push(0.0);
+                continue;
+            }
+
+            let target = if growing {
+                item.base_size + remaining * item.grow / factor_sum
+            } else if scaled_shrink_sum > 0.0 {
+                item.base_size + remaining * item.shrink * item.base_size / scaled_shrink_sum
+            } else {
+                item.base_size
+            };
+
+            let clamped = target.min(item.max_size).max(item.min_size).max(0.0);
+            violations.push(clamped - target);
+            item.target_size = clamped;
+        }
+
+        // Freeze the items that violated their constraints in the same direction as the total,
+        // or all of them if the violations cancel out.
+        let total_violation: f32 = violations.iter().sum();
+        for (item, violation) in items.iter_mut().zip(violations) {
+            item.frozen = item.frozen
+                || total_violation == 0.0
+                || (total_violation > 0.0 && violation > 0.0)
+                || (total_violation < 0.0 && violation < 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::style::*;
+
+    fn layout_flex(html: &str, css: &str, check: impl Fn(&LayoutBox)) {
+        let document = Node::from(html);
+        let style = Sheet::from(css);
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        check(&layout_tree(&applied_styles, viewport));
+    }
+
+    #[test]
+    fn test_flex_row() {
+        layout_flex(
+            "<a><b></b><c></c><d></d></a>",
+            "
+            a { display: flex; height: 100px; gap: 20px; align-items: center; }
+            b { width: 100px; height: 10px; }
+            c { flex: 1; height: 20px; margin: 5px; }
+            d { flex: 3; align-self: stretch; }
+            ",
+            |root| {
+                let b = &root.children[0];
+                let c = &root.children[1];
+                let d = &root.children[2];
+
+                // 800 - 100 - 2 * 20 - 10 = 650 is shared between c and d in a 1:3 ratio.
+                assert_eq!(
+                    b.dimensions.content,
+                    Rect {
+                        x: 0.0,
+                        y: 45.0,
+                        width: 100.0,
+                        height: 10.0
+                    }
+                );
+                assert_eq!(
+                    c.dimensions.content,
+                    Rect {
+                        x: 125.0,
+                        y: 40.0,
+                        width: 162.5,
+                        height: 20.0
+                    }
+                );
+                assert_eq!(
+                    d.dimensions.content,
+                    Rect {
+                        x: 312.5,
+                        y: 0.0,
+                        width: 487.5,
+                        height: 100.0
+                    }
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_flex_wrap_and_justify() {
+        layout_flex(
+            "<a><b></b><b></b><b></b></a>",
+            "
+            a { display: flex; flex-wrap: wrap; justify-content: space-between; width: 500px; }
+            b { width: 200px; height: 50px; }
+            ",
+            |root| {
+                let xs: Vec<_> = root
+                    .children
+                    .iter()
+                    .map(|b| (b.dimensions.content.x, b.dimensions.content.y))
+                    .collect();
+
+                assert_eq!(xs, vec![(0.0, 0.0), (300.0, 0.0), (0.0, 50.0)]);
+                assert_eq!(root.dimensions.content.height, 100.0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_flex_column_shrink() {
+        layout_flex(
+            "<a><b></b><c></c></a>",
+            "
+            a { display: flex; flex-direction: column; height: 100px; }
+            b { height: 100px; }
+            c { height: 100px; flex-shrink: 3; }
+            ",
+            |root| {
+                let b = &root.children[0];
+                let c = &root.children[1];
+
+                assert_eq!(b.dimensions.content.height, 75.0);
+                assert_eq!(c.dimensions.content.y, 75.0);
+                assert_eq!(c.dimensions.content.height, 25.0);
+                assert_eq!(c.dimensions.content.width, 800.0);
+            },
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use std::ops::Range;
+use std::slice;
+
+use crate::css::Unit::{Fr, Percent, Px};
+use crate::css::Value::{self, Keyword, Length, List, Number};
+use crate::layout::{
+    horizontal_edges, vertical_edges, CollapsedMargin, CollapsedMargins, LayoutBox, LayoutContext,
+};
+use crate::style::Position;
+
+/// The size of a grid track, as specified by `grid-template-columns` or `grid-template-rows`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TrackSize {
+    /// A fixed length, with percentages already resolved.
+    Fixed(f32),
+    /// A share of the free space in the grid container.
+    Fraction(f32),
+    /// Sized to the items in the track.
+    Auto,
+}
+
+/// The cells of the grid covered by an item, as ranges of zero-based track indices.
+struct GridArea {
+    /// The index of the box of the item among the children of the container.
+    index: usize,
+    columns: Range<usize>,
+    rows: Range<usize>,
+}
+
+impl GridArea {
+    fn overlaps(&self, columns: &Range<usize>, rows: &Range<usize>) -> bool {
+        self.columns.start < columns.end
+            && columns.start < self.columns.end
+            && self.rows.start < rows.end
+            && rows.start < self.rows.end
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the children of a grid container in the cells of its grid.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#layout-algorithm
+    ///
+    /// Sets `self.dimensions.height` to the total content height.
+    pub(super) fn layout_grid_children(&mut self, context: &LayoutContext) {
+        let content = self.dimensions.content;
+        let definite_height = self.definite_height();
+
+        // `gap` sets both `row-gap` and `column-gap`.
+        let gap = |name| match self.style_value(name).or(self.style_value("gap")) {
+            Some(value @ Length(..)) => value.to_px(),
+            _ => 0.0,
+        };
+        let (column_gap, row_gap) = (gap("column-gap"), gap("row-gap"));
+
+        let mut columns = track_list(
+            self.style_value("grid-template-columns"),
+            Some(content.width),
+        );
+        let mut rows = track_list(self.style_value("grid-template-rows"), definite_height);
+
+        for child in &mut self.children {
+            if child.position() == Position::Absolute {
+                // The static position of an absolutely positioned child is the content origin.
+                child.static_position = (content.x, content.y);
+            }
+        }
+
+        let areas = self.place_grid_items(columns.len().max(1));
+
+        // Items placed outside of the explicit grid create implicit, auto-sized tracks.
+        let column_count = areas.iter().map(|area| area.columns.end).max();
+        let row_count = areas.iter().map(|area| area.rows.end).max();
+        columns.resize(
+            columns.len().max(column_count.unwrap_or(0)),
+            TrackSize::Auto,
+        );
+        rows.resize(rows.len().max(row_count.unwrap_or(0)), TrackSize::Auto);
+
+        let column_sizes = resolve_columns(&columns, content.width, column_gap);
+        let column_offsets = track_offsets(&column_sizes, column_gap);
+
+        // Lay out the items at the width of their area, to find out the height of auto rows.
+        let mut row_sizes: Vec<f32> = rows
+            .iter()
+            .map(|track| match *track {
+                TrackSize::Fixed(size) => size,
+                TrackSize::Fraction(_) | TrackSize::Auto => 0.0,
+            })
+            .collect();
+
+        for area in &areas {
+            let child = &mut self.children[area.index];
+            child.calculate_item_edges();
+
+            let area_width = span_size(&column_offsets, &column_sizes, &area.columns);
+            let width = match child.style_value("width") {
+                Some(width @ Length(_, Px)) => width.to_px(),
+                _ => (area_width - horizontal_edges(&child.dimensions)).max(0.0),
+            };
+            let x = content.x + column_offsets[area.columns.start];
+            child.layout_item(x, content.y, width, None, context);
+
+            // An item spanning several rows grows the last auto row it spans, if any.
+            let outer_height = child.dimensions.margin_box().height;
+            let sized = |track: &TrackSize| match track {
+                TrackSize::Auto => true,
+                TrackSize::Fraction(_) => definite_height.is_none(),
+                TrackSize::Fixed(_) => false,
+            };
+            if let Some(row) = area.rows.clone().rev().find(|&row| sized(&rows[row])) {
+                let others: f32 = area
+                    .rows
+                    .clone()
+                    .filter(|&other| other != row)
+                    .map(|other| row_sizes[other] + row_gap)
+                    .sum();
+                row_sizes[row] = row_sizes[row].max(outer_height - others);
+            }
+        }
+
+        // Fractional rows share the free space left by the other rows in a container of definite
+        // height. Otherwise they are sized like auto rows.
+        if let Some(height) = definite_height {
+            let fractions: Vec<f32> = rows
+                .iter()
+                .map(|track| match *track {
+                    TrackSize::Fraction(fraction) => fraction,
+                    _ => 0.0,
+                })
+                .collect();
+            let fixed: f32 = rows
+                .iter()
+                .zip(&row_sizes)
+                .filter(|(track, _)| !matches!(track, TrackSize::Fraction(_)))
+                .map(|(_, size)| size)
+                .sum();
+            let free_space = height - fixed - row_gap * (rows.len().max(1) - 1) as f32;
+            distribute_fractions(&mut row_sizes, &fractions, free_space);
+        }
+
+        let row_offsets = track_offsets(&row_sizes, row_gap);
+
+        // Move the items into their rows, and stretch those without a height to fill their area.
+        for area in &areas {
+            let child = &mut self.children[area.index];
+            child.translate(0.0, row_offsets[area.rows.start]);
+
+            if matches!(child.style_value("height"), None | Some(Keyword(_))) {
+                let area_height = span_size(&row_offsets, &row_sizes, &area.rows);
+                let height = area_height - vertical_edges(&child.dimensions);
+                child.dimensions.content.height = child.clamp_height(height);
+            }
+
+            child.finish_positioning(context);
+        }
+
+        self.dimensions.content.height = match (row_offsets.last(), row_sizes.last()) {
+            (Some(offset), Some(size)) => offset + size,
+            _ => 0.0,
+        };
+
+        // The margins of a grid container never collapse with those of its items.
+        let d = &self.dimensions;
+        self.collapsed_margins = CollapsedMargins {
+            top: CollapsedMargin::new(d.margin.top),
+            bottom: CollapsedMargin::new(d.margin.bottom),
+            through: false,
+        };
+    }
+
+    /// Assign a grid area to each in-flow child. Items with an explicit position are placed first,
+    /// and the others fill the remaining cells row by row.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+    fn place_grid_items(&self, column_count: usize) -> Vec<GridArea> {
+        let mut areas: Vec<GridArea> = Vec::new();
+        let mut pending = Vec::new();
+
+        for (index, child) in self.children.iter().enumerate() {
+            if child.position() == Position::Absolute {
+                continue;
+            }
+
+            match (child.grid_lines("column"), child.grid_lines("row")) {
+                (Ok(columns), Ok(rows)) => areas.push(GridArea {
+                    index,
+                    columns,
+                    rows,
+                }),
+                (columns, rows) => pending.push((index, columns, rows)),
+            }
+        }
+
+        let is_free = |areas: &[GridArea], columns: &Range<usize>, rows: &Range<usize>| {
+            !areas.iter().any(|area| area.overlaps(columns, rows))
+        };
+        let at = |start: usize, span: usize| start..start + span;
+
+        let mut cursor = (0, 0);
+        for (index, columns, rows) in pending {
+            let (columns, rows) = match (columns, rows) {
+                // An item with only an explicit row goes in the first column where it fits.
+                (Err(span), Ok(rows)) => {
+                    let column = (0..)
+                        .find(|&column| is_free(&areas, &at(column, span), &rows))
+                        .unwrap();
+                    (at(column, span), rows)
+                }
+                // An item with only an explicit column goes in the first row where it fits.
+                (Ok(columns), Err(span)) => {
+                    let row = (0..)
+                        .find(|&row| is_free(&areas, &columns, &at(row, span)))
+                        .unwrap();
+                    (columns, at(row, span))
+                }
+                // Other items go in the next free cell after the previous auto-placed item.
+                (Err(column_span), Err(row_span)) => loop {
+                    let (row, column) = cursor;
+                    let columns = at(column, column_span);
+                    let rows = at(row, row_span);
+
+                    let fits = column == 0 || columns.end <= column_count;
+                    if fits && is_free(&areas, &columns, &rows) {
+                        break (columns, rows);
+                    }
+
+                    cursor = if columns.end < column_count {
+                        (row, column + 1)
+                    } else {
+                        (row + 1, 0)
+                    };
+                },
+                (Ok(_), Ok(_)) => unreachable!(),
+            };
+
+            areas.push(GridArea {
+                index,
+                columns,
+                rows,
+            });
+        }
+
+        areas
+    }
+
+    /// The tracks spanned by the item along the given axis (`"column"` or `"row"`), or the number
+    /// of tracks to span if the item is placed automatically.
+    ///
+    /// Lines are given by `grid-column-start` and `grid-column-end`, or the `grid-column`
+    /// shorthand with a single start line, and likewise for rows.
+    fn grid_lines(&self, axis: &str) -> Result<Range<usize>, usize> {
+        let line = |value| match value {
+            Some(&Number(n)) if n >= 1.0 => Some(n as usize - 1),
+            _ => None,
+        };
+
+        let start = line(self.style_value(&format!("grid-{}-start", axis)))
+            .or_else(|| line(self.style_value(&format!("grid-{}", axis))));
+        let end = line(self.style_value(&format!("grid-{}-end", axis)));
+
+        match (start, end) {
+            (Some(start), Some(end)) if end > start => Ok(start..end),
+            // Lines given in reverse order are swapped.
+            (Some(start), Some(end)) if end < start => Ok(end..start),
+            (Some(start), _) => Ok(start..start + 1),
+            (None, Some(end)) => Ok(end.saturating_sub(1)..end.max(1)),
+            (None, None) => Err(1),
+        }
+    }
+}
+
+/// Parse a track list. Percentages resolve against `percentage_base`, and behave as `auto`
+/// without one.
+fn track_list(value: Option<&Value>, percentage_base: Option<f32>) -> Vec<TrackSize> {
+    let values = match value {
+        Some(List(values)) => values.as_slice(),
+        Some(Keyword(keyword)) if keyword == "none" => &[],
+        Some(value) => slice::from_ref(value),
+        None => &[],
+    };
+
+    values
+        .iter()
+        .map(|value| match *value {
+            Length(size, Px) => TrackSize::Fixed(size),
+            Length(percentage, Percent) => match percentage_base {
+                Some(base) => TrackSize::Fixed(base * percentage / 100.0),
+                None => TrackSize::Auto,
+            },
+            Length(fraction, Fr) => TrackSize::Fraction(fraction),
+            _ => TrackSize::Auto,
+        })
+        .collect()
+}
+
+/// Size the columns of a grid that is `width` wide.
+///
+/// Items can't be measured yet, so auto columns share the free space like `1fr` columns.
+fn resolve_columns(columns: &[TrackSize], width: f32, gap: f32) -> Vec<f32> {
+    let mut sizes: Vec<f32> = columns
+        .iter()
+        .map(|track| match *track {
+            TrackSize::Fixed(size) => size,
+            TrackSize::Fraction(_) | TrackSize::Auto => 0.0,
+        })
+        .collect();
+    let fractions: Vec<f32> = columns
+        .iter()
+        .map(|track| match *track {
+            TrackSize::Fixed(_) => 0.0,
+            TrackSize::Fraction(fraction) => fraction,
+            TrackSize::Auto => 1.0,
+        })
+        .collect();
+
+    let gaps = gap * (columns.len().max(1) - 1) as f32;
+    let free_space = width - gaps - sizes.iter().sum::<f32>();
+    distribute_fractions(&mut sizes, &fractions, free_space);
+    sizes
+}
+
+/// Share the free space between the tracks in proportion to their fractions. A sum of fractions
+/// below 1 leaves part of the free space unused.
+///
+/// https://www.w3.org/TR/css-grid-1/#algo-find-fr-size
+fn distribute_fractions(sizes: &mut [f32], fractions: &[f32], free_space: f32) {
+    let fraction_sum: f32 = fractions.iter().sum();
+    if fraction_sum == 0.0 || free_space <= 0.0 {
+        return;
+    }
+
+    let fraction_size = free_space / fraction_sum.max(1.0);
+    for (size, fraction) in sizes.iter_mut().zip(fractions) {
+        if *fraction > 0.0 {
+            *size = fraction * fraction_size;
+        }
+    }
+}
+
+/// The offset of the start of each track from the start of the grid.
+fn track_offsets(sizes: &[f32], gap: f32) -> Vec<f32> {
+    sizes
+        .iter()
+        .scan(0.0, |offset, size| {
+            let start = *offset;
+            *offset += size + gap;
+            Some(start)
+        })
+        .collect()
+}
+
+/// The size of a range of tracks, including the gaps between them.
+fn span_size(offsets: &[f32], sizes: &[f32], tracks: &Range<usize>) -> f32 {
+    offsets[tracks.end - 1] + sizes[tracks.end - 1] - offsets[tracks.start]
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::style::*;
+
+    fn layout_grid(html: &str, css: &str, check: impl Fn(&LayoutBox)) {
+        let document = Node::from(html);
+        let style = Sheet::from(css);
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        check(&layout_tree(&applied_styles, viewport));
+    }
+
+    #[test]
+    fn test_grid_tracks() {
+        layout_grid(
+            "<a><b></b><c></c><d></d><e></e></a>",
+            "
+            a { display: grid; grid-template-columns: 100px 25% 1fr 3fr; gap: 10px; }
+            b { height: 30px; }
+            c { height: 20px; }
+            d { grid-row-start: 2; grid-column-start: 2; grid-column-end: 4; }
+            e { height: 10px; padding: 5px; }
+            ",
+            |root| {
+                let content = |i: usize| root.children[i].dimensions.content;
+
+                // 800 - 100 - 200 - 3 * 10 = 470 is shared between the last columns in a 1:3
+                // ratio. The first row is as tall as its tallest item, and `e` is auto-placed
+                // next to `c`.
+                assert_eq!(
+                    content(0),
+                    Rect {
+                        x: 0.0,
+                        y: 0.0,
+                        width: 100.0,
+                        height: 30.0
+                    }
+                );
+                assert_eq!(
+                    content(1),
+                    Rect {
+                        x: 110.0,
+                        y: 0.0,
+                        width: 200.0,
+                        height: 20.0
+                    }
+                );
+                assert_eq!(
+                    content(2),
+                    Rect {
+                        x: 110.0,
+                        y: 40.0,
+                        width: 327.5,
+                        height: 0.0
+                    }
+                );
+                assert_eq!(
+                    content(3),
+                    Rect {
+                        x: 325.0,
+                        y: 5.0,
+                        width: 107.5,
+                        height: 10.0
+                    }
+                );
+                assert_eq!(root.dimensions.content.height, 40.0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_grid_rows() {
+        layout_grid(
+            "<a><b></b><c></c><d></d></a>",
+            "
+            a { display: grid; height: 200px; }
+            a { grid-template-columns: 1fr 1fr; grid-template-rows: 50% 1fr; }
+            d { grid-row: 1; grid-column: 2; }
+            ",
+            |root| {
+                let content = |i: usize| root.children[i].dimensions.content;
+
+                // The auto-placed `c` skips the cell taken by `d`.
+                assert_eq!(
+                    content(0),
+                    Rect {
+                        x: 0.0,
+                        y: 0.0,
+                        width: 400.0,
+                        height: 100.0
+                    }
+                );
+                assert_eq!(
+                    content(1),
+                    Rect {
+                        x: 0.0,
+                        y: 100.0,
+                        width: 400.0,
+                        height: 100.0
+                    }
+                );
+                assert_eq!(
+                    content(2),
+                    Rect {
+                        x: 400.0,
+                        y: 0.0,
+                        width: 400.0,
+                        height: 100.0
+                    }
+                );
+            },
+        );
+    }
+}
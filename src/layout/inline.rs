@@ -0,0 +1,1524 @@
+use crate::css::Unit::Px;
+use crate::css::Value::{Length, Number};
+use crate::dom::Node;
+use crate::fonts::{FaceId, FontContext, FontMetrics, FontQuery, Synthesis};
+use crate::layout::{
+    horizontal_edges, AnonymousBlock, BlockNode, InlineNode, IntrinsicWidths, LayoutBox,
+    LayoutContext, ListMarker, Rect, TextRun,
+};
+use crate::style::{
+    Direction, Overflow, OverflowWrap, Position, StyledNode, TextAlign, TextTransform, WhiteSpace,
+    WordBreak,
+};
+
+/// The advance of every glyph when no font is available, as a fraction of the font size.
+const GLYPH_ADVANCE: f32 = 0.5;
+/// The height of glyphs above the baseline when no font is available, as a fraction of the font
+/// size.
+const ASCENT: f32 = 0.8;
+/// The used value of `line-height: normal`, as a multiple of the font size.
+const NORMAL_LINE_HEIGHT: f32 = 1.2;
+/// The distance between tab stops, in spaces.
+const TAB_SIZE: f32 = 8.0;
+
+/// The font that a run of text is set in.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Font {
+    pub size: f32,
+    /// The styles that are faked, because the face of the text lacks them.
+    pub synthesis: Synthesis,
+}
+
+/// A glyph in a run of text.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Glyph {
+    pub character: char,
+    /// The distance from the left of the run to the left of the glyph.
+    pub x: f32,
+    pub advance: f32,
+    /// The face in the `FontContext` of the layout that the glyph is from, or `None` if the text
+    /// was laid out without one. Characters that the face of the text lacks fall back to other
+    /// faces.
+    pub face: Option<FaceId>,
+    /// The index of the glyph in its face, if it has one. Shaping may combine several characters
+    /// into one glyph, which then has the first of them as its character.
+    pub id: Option<u16>,
+}
+
+/// The glyphs of a text run, as they are placed on its line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphRun {
+    pub glyphs: Vec<Glyph>,
+    pub font: Font,
+    /// The distance from the top of the run to its baseline.
+    pub baseline: f32,
+}
+
+/// The properties of a text node that affect how it is broken into lines.
+struct TextStyle<'f> {
+    font: Font,
+    fonts: Option<&'f FontContext>,
+    query: FontQuery,
+    line_height: f32,
+    /// The distance from the top of a line of this text to its baseline, with the leading split
+    /// equally above and below the glyphs.
+    baseline: f32,
+    letter_spacing: f32,
+    word_spacing: f32,
+    text_transform: TextTransform,
+    white_space: WhiteSpace,
+    overflow_wrap: OverflowWrap,
+    word_break: WordBreak,
+}
+
+impl<'f> TextStyle<'f> {
+    fn new(style: &StyledNode, context: &LayoutContext<'f>) -> TextStyle<'f> {
+        let font_size = match style.value("font-size") {
+            Some(&Length(size, Px)) => size,
+            _ => context.root_font_size,
+        };
+        let line_height = match style.value("line-height") {
+            Some(&Length(height, Px)) => height,
+            Some(&Number(factor)) => factor * font_size,
+            _ => NORMAL_LINE_HEIGHT * font_size,
+        };
+
+        let fonts = context.fonts;
+        let query = FontQuery::new(style);
+        let face = fonts.and_then(|fonts| fonts.select(&query));
+        let metrics = match (fonts, face) {
+            (Some(fonts), Some(face)) => fonts.metrics(face),
+            _ => FontMetrics {
+                ascent: ASCENT,
+                descent: 1.0 - ASCENT,
+            },
+        };
+        let glyph_height = (metrics.ascent + metrics.descent) * font_size;
+        let synthesis = match (fonts, face) {
+            (Some(fonts), Some(face)) => fonts.synthesis(&query, face),
+            _ => Synthesis::default(),
+        };
+
+        TextStyle {
+            font: Font {
+                size: font_size,
+                synthesis,
+            },
+            fonts,
+            query,
+            line_height,
+            baseline: (line_height - glyph_height) / 2.0 + metrics.ascent * font_size,
+            letter_spacing: style.letter_spacing(),
+            word_spacing: style.word_spacing(),
+            text_transform: style.text_transform(),
+            white_space: style.white_space(),
+            overflow_wrap: style.overflow_wrap(),
+            word_break: style.word_break(),
+        }
+    }
+}
+
+impl TextStyle<'_> {
+    /// The face that a character is set in.
+    fn face(&self, character: char) -> Option<FaceId> {
+        self.fonts?.select_for_character(&self.query, character)
+    }
+
+    /// How far a character moves the pen along the baseline, including the spacing after it.
+    fn advance(&self, character: char) -> f32 {
+        let advance = match (self.fonts, self.face(character)) {
+            (Some(fonts), Some(face)) => fonts.advance(face, character) * self.font.size,
+            _ => GLYPH_ADVANCE * self.font.size,
+        };
+        advance + self.spacing(character)
+    }
+
+    /// The space that `letter-spacing` and `word-spacing` add after a character.
+    fn spacing(&self, character: char) -> f32 {
+        match character {
+            ' ' | '\u{a0}' => self.letter_spacing + self.word_spacing,
+            _ => self.letter_spacing,
+        }
+    }
+
+    /// Change the case of a text as `text-transform` says.
+    fn transform(&self, text: &str) -> String {
+        match self.text_transform {
+            TextTransform::None => text.to_owned(),
+            TextTransform::Uppercase => text.to_uppercase(),
+            TextTransform::Lowercase => text.to_lowercase(),
+            TextTransform::Capitalize => {
+                let mut capitalized = String::with_capacity(text.len());
+                let mut word_start = true;
+                for c in text.chars() {
+                    if word_start && c.is_alphanumeric() {
+                        capitalized.extend(c.to_uppercase());
+                        word_start = false;
+                    } else {
+                        capitalized.push(c);
+                        word_start = word_start || c.is_whitespace();
+                    }
+                }
+                capitalized
+            }
+        }
+    }
+
+    /// The width of a word, set on a single line.
+    fn width(&self, word: &str) -> f32 {
+        #[cfg(feature = "shaping")]
+        if let Some(fonts) = self.fonts {
+            return self
+                .runs(word)
+                .into_iter()
+                .map(|(rtl, face, text)| match face {
+                    Some(face) => {
+                        let glyphs = fonts.shape(face, &text, rtl);
+                        let spacing: f32 = text.chars().map(|c| self.spacing(c)).sum();
+                        glyphs.iter().map(|glyph| glyph.advance).sum::<f32>() * self.font.size
+                            + spacing
+                    }
+                    None => text.chars().map(|c| self.advance(c)).sum(),
+                })
+                .sum();
+        }
+
+        word.chars().map(|c| self.advance(c)).sum()
+    }
+
+    /// The distance between tab stops.
+    fn tab_size(&self) -> f32 {
+        TAB_SIZE * self.advance(' ')
+    }
+
+    /// Place the glyphs of a text run that is `width` wide, from left to right. Tabs share the
+    /// width that the other glyphs leave.
+    fn glyph_run(&self, text: &str, width: f32) -> GlyphRun {
+        let mut glyphs = self.glyphs(text);
+
+        let tabs = glyphs.iter().filter(|(g, _)| g.character == '\t').count();
+        let others: f32 = glyphs
+            .iter()
+            .filter(|(g, _)| g.character != '\t')
+            .map(|(g, _)| g.advance)
+            .sum();
+        let tab_advance = match tabs {
+            0 => 0.0,
+            _ => ((width - others) / tabs as f32).max(0.0),
+        };
+
+        let mut x = 0.0;
+        for (glyph, offset) in &mut glyphs {
+            if glyph.character == '\t' {
+                glyph.advance = tab_advance;
+            }
+            glyph.x = x + *offset;
+            x += glyph.advance;
+        }
+
+        GlyphRun {
+            glyphs: glyphs.into_iter().map(|(glyph, _)| glyph).collect(),
+            font: self.font.clone(),
+            baseline: self.baseline,
+        }
+    }
+
+    /// The glyphs of a text in visual order, each with how far it is moved from the pen
+    /// position, before they are placed.
+    fn glyphs(&self, text: &str) -> Vec<(Glyph, f32)> {
+        #[cfg(feature = "shaping")]
+        if let Some(fonts) = self.fonts {
+            return self.shaped_glyphs(fonts, text);
+        }
+
+        text.chars()
+            .map(|character| {
+                let face = self.face(character);
+                let glyph = Glyph {
+                    character,
+                    x: 0.0,
+                    advance: self.advance(character),
+                    face,
+                    id: self
+                        .fonts
+                        .zip(face)
+                        .and_then(|(fonts, face)| fonts.glyph_index(face, character)),
+                };
+                (glyph, 0.0)
+            })
+            .collect()
+    }
+
+    /// Split a text into runs of characters that have the same direction and face. Characters
+    /// without a direction of their own, such as spaces, join the run before them.
+    #[cfg(feature = "shaping")]
+    fn runs(&self, text: &str) -> Vec<(bool, Option<FaceId>, String)> {
+        let mut runs: Vec<(bool, Option<FaceId>, String)> = Vec::new();
+        for c in text.chars() {
+            let previous = runs.last().map(|(rtl, face, _)| (*rtl, *face));
+            let rtl = strong_direction(c)
+                .or(previous.map(|(rtl, _)| rtl))
+                .unwrap_or(false);
+            let face = match previous {
+                Some((_, face)) if c.is_whitespace() => face,
+                _ => self.face(c),
+            };
+            match runs.last_mut() {
+                Some((run_rtl, run_face, run)) if (*run_rtl, *run_face) == (rtl, face) => {
+                    run.push(c)
+                }
+                _ => runs.push((rtl, face, c.to_string())),
+            }
+        }
+        runs
+    }
+
+    /// Shape a text in visual order. Each run of right-to-left characters is put back in
+    /// logical order to be shaped, which gives its glyphs in visual order again.
+    #[cfg(feature = "shaping")]
+    fn shaped_glyphs(&self, fonts: &FontContext, text: &str) -> Vec<(Glyph, f32)> {
+        let mut glyphs = Vec::new();
+        for (rtl, face, run) in self.runs(text) {
+            let logical: String = match rtl {
+                true => run.chars().rev().collect(),
+                false => run,
+            };
+            let face = match face {
+                Some(face) => face,
+                None => continue,
+            };
+            // TODO: Leave out optional ligatures when letters are spaced.
+            let shaped = fonts.shape(face, &logical, rtl);
+
+            // The spacing of the characters of a cluster goes after its first glyph.
+            let mut clusters: Vec<usize> = shaped.iter().map(|glyph| glyph.cluster).collect();
+            clusters.sort();
+            clusters.dedup();
+            let mut previous = None;
+
+            for shaped in shaped {
+                let spacing = match previous == Some(shaped.cluster) {
+                    true => 0.0,
+                    false => {
+                        let next = clusters.iter().find(|&&c| c > shaped.cluster);
+                        let characters = &logical[shaped.cluster..*next.unwrap_or(&logical.len())];
+                        characters.chars().map(|c| self.spacing(c)).sum()
+                    }
+                };
+                previous = Some(shaped.cluster);
+
+                let glyph = Glyph {
+                    character: logical[shaped.cluster..].chars().next().unwrap(),
+                    x: 0.0,
+                    advance: shaped.advance * self.font.size + spacing,
+                    face: Some(face),
+                    id: Some(shaped.id),
+                };
+                glyphs.push((glyph, shaped.offset * self.font.size));
+            }
+        }
+        glyphs
+    }
+}
+
+/// The state of an inline formatting context while its content is broken into lines.
+///
+/// http://www.w3.org/TR/CSS2/visuren.html#inline-formatting
+struct LineBuilder {
+    /// The content box of the block container the lines are in.
+    bounds: Rect,
+    /// The direction of the block container. Lines are built from left to right, and mirrored
+    /// afterwards for right-to-left text.
+    direction: Direction,
+    /// The position of the pen on the current line, relative to the start of the line.
+    x: f32,
+    /// The top of the current line, relative to the top of the block container.
+    y: f32,
+    /// The height of the current line, which is 0 while it is empty.
+    height: f32,
+    /// Whether a collapsible space at the pen would be removed, because the line is empty or
+    /// already ends in a collapsible space.
+    skip_space: bool,
+    /// The width of the collapsible space at the end of the current line, which hangs.
+    hanging: f32,
+    /// Whether the line may wrap before the next word: after a space, an inline-block or a
+    /// `wbr` element, but not between words in adjacent boxes.
+    can_break: bool,
+    /// The lines that were ended.
+    lines: Vec<Line>,
+}
+
+impl LineBuilder {
+    fn new(bounds: Rect, direction: Direction) -> LineBuilder {
+        LineBuilder {
+            bounds,
+            direction,
+            x: 0.0,
+            y: 0.0,
+            height: 0.0,
+            skip_space: true,
+            hanging: 0.0,
+            can_break: false,
+            lines: Vec::new(),
+        }
+    }
+
+    /// End the current line where it may wrap, and start a new one below it.
+    fn break_line(&mut self) {
+        self.end_line(false);
+    }
+
+    /// End the current line at a preserved newline, and start a new one below it.
+    fn force_break(&mut self) {
+        self.end_line(true);
+    }
+
+    fn end_line(&mut self, forced: bool) {
+        self.lines.push(Line {
+            top: self.bounds.y + self.y,
+            width: self.x - self.hanging,
+            hanging: self.hanging > 0.0,
+            forced,
+        });
+        self.y += self.height;
+        self.x = 0.0;
+        self.height = 0.0;
+        self.skip_space = true;
+        self.hanging = 0.0;
+        self.can_break = false;
+    }
+
+    /// The total height of the lines, including the current one, and the lines.
+    fn finish(mut self) -> (f32, Vec<Line>) {
+        self.force_break();
+        (self.y, self.lines)
+    }
+}
+
+/// A line as it was built, before the boxes on it are aligned.
+struct Line {
+    top: f32,
+    /// The width of the content on the line, without the collapsible space that hangs at its end.
+    width: f32,
+    /// Whether a collapsible space hangs at the end of the line.
+    hanging: bool,
+    /// Whether the line ends at a preserved newline or at the end of the block, which keeps it
+    /// from being justified.
+    forced: bool,
+}
+
+/// The boxes on a line that are aligned by their baselines.
+struct LineBox {
+    /// The top of the line, before alignment.
+    top: f32,
+    /// The height of the tallest box on the line, before alignment.
+    height: f32,
+    /// The largest distance from the top of a box to its baseline.
+    above: f32,
+    /// The largest distance from the baseline of a box to its bottom.
+    below: f32,
+    /// How far the line moves down because the lines above it grew.
+    shift: f32,
+}
+
+/// The state of a measurement of the intrinsic widths of inline content.
+struct WidthMeasurer {
+    widths: IntrinsicWidths,
+    /// The width of the current line.
+    line: f32,
+    /// The width of the collapsible spaces at the end of the current line, which hang.
+    hanging: f32,
+    /// The width of the content since the last place where the line could break.
+    unbreakable: f32,
+    /// Whether a collapsible space would be removed.
+    skip_space: bool,
+}
+
+impl WidthMeasurer {
+    fn new() -> WidthMeasurer {
+        WidthMeasurer {
+            widths: IntrinsicWidths::default(),
+            line: 0.0,
+            hanging: 0.0,
+            unbreakable: 0.0,
+            skip_space: true,
+        }
+    }
+
+    /// Add content that the line can't break within.
+    fn add(&mut self, width: f32) {
+        self.line += width;
+        self.unbreakable += width;
+        self.hanging = 0.0;
+        self.skip_space = false;
+    }
+
+    /// Mark a place where the line could break.
+    fn break_opportunity(&mut self) {
+        self.widths.min = self.widths.min.max(self.unbreakable);
+        self.unbreakable = 0.0;
+    }
+
+    /// End the current line.
+    fn break_line(&mut self) {
+        self.break_opportunity();
+        self.widths.max = self.widths.max.max(self.line - self.hanging);
+        self.line = 0.0;
+        self.hanging = 0.0;
+        self.skip_space = true;
+    }
+
+    fn finish(mut self) -> IntrinsicWidths {
+        self.break_line();
+        self.widths
+    }
+}
+
+/// A piece of text between the places where white space is processed.
+enum Segment<'t> {
+    Word(&'t str),
+    Spaces(&'t str),
+    Newline,
+}
+
+/// Split text into words, runs of spaces and tabs, and newlines.
+fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            '\n' => {
+                segments.push(Segment::Newline);
+                rest = &rest[1..];
+                continue;
+            }
+            ' ' | '\t' | '\r' => rest.find(|c| !matches!(c, ' ' | '\t' | '\r')),
+            _ => rest.find(char::is_whitespace),
+        }
+        .unwrap_or(rest.len());
+
+        let (segment, remainder) = rest.split_at(end);
+        segments.push(match c {
+            ' ' | '\t' | '\r' => Segment::Spaces(segment),
+            _ => Segment::Word(segment),
+        });
+        rest = remainder;
+    }
+
+    segments
+}
+
+impl<'a> LayoutBox<'a> {
+    /// The intrinsic widths of the inline-level children of an anonymous block box.
+    pub(super) fn inline_intrinsic_widths(&self, context: &LayoutContext) -> IntrinsicWidths {
+        let mut measurer = WidthMeasurer::new();
+        for child in &self.children {
+            child.measure_inline(&mut measurer, context);
+        }
+        measurer.finish()
+    }
+
+    /// Measure an inline-level box, in the same way as it would be laid out on the lines.
+    fn measure_inline(&self, measurer: &mut WidthMeasurer, context: &LayoutContext) {
+        match self.box_type {
+            InlineNode(style) => match style.node {
+                Node::Text(ref text) => measure_text(style, text, measurer, context),
+                Node::Element { ref tag, .. } if tag == "br" => measurer.break_line(),
+                Node::Element { ref tag, .. } if tag == "wbr" => measurer.break_opportunity(),
+                Node::Element { .. } => {
+                    measurer.add(self.horizontal_item_edges());
+                    for child in &self.children {
+                        child.measure_inline(measurer, context);
+                    }
+                }
+            },
+            // The generated content of a pseudo-element.
+            TextRun(style, ref text) => measure_text(style, text, measurer, context),
+            BlockNode(_) => {
+                let widths = self.outer_intrinsic_widths(context);
+                measurer.break_opportunity();
+                measurer.widths.min = measurer.widths.min.max(widths.min);
+                measurer.add(widths.max);
+                measurer.break_opportunity();
+            }
+            _ => {}
+        }
+    }
+
+    /// Break the inline-level children of an anonymous block box into lines.
+    ///
+    /// Sets `self.dimensions.height` to the total height of the lines.
+    pub(super) fn layout_inline_children(&mut self, context: &LayoutContext) {
+        // An anonymous block has no style of its own, but its children inherit the direction and
+        // the alignment of the block container.
+        let style = self.children.iter().find_map(|child| match child.box_type {
+            InlineNode(style) => Some(style),
+            _ => None,
+        });
+        let direction = style.map_or(Direction::Ltr, |style| style.direction());
+        let text_align = style.map_or(TextAlign::Start, |style| style.text_align());
+
+        let bounds = self.dimensions.content;
+        let mut lines = LineBuilder::new(bounds, direction);
+
+        for child in &mut self.children {
+            child.layout_inline(&mut lines, context);
+        }
+
+        let (height, lines) = lines.finish();
+        self.align_lines(&lines, bounds.width, direction, text_align);
+        self.dimensions.content.height = height + self.align_baselines(context);
+
+        // Right-to-left lines start at the right edge of the block.
+        if direction == Direction::Rtl {
+            for child in &mut self.children {
+                child.mirror(2.0 * bounds.x + bounds.width);
+            }
+        }
+    }
+
+    /// Mirror the box and its descendants around the vertical line at `axis / 2`.
+    fn mirror(&mut self, axis: f32) {
+        let content = &mut self.dimensions.content;
+        content.x = axis - content.x - content.width;
+        for child in &mut self.children {
+            child.mirror(axis);
+        }
+    }
+
+    /// Move the text runs and inline-blocks on each line to the side of the line box that
+    /// `text-align` puts them on, or widen the spaces between words to fill the line box. Lines
+    /// are still laid out from left to right, before right-to-left lines are mirrored.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#text-align-property
+    fn align_lines(&mut self, lines: &[Line], width: f32, direction: Direction, align: TextAlign) {
+        // How much of the free space on a line goes to its left, as the line is mirrored after.
+        let start = match (align, direction) {
+            (TextAlign::Center, _) => 0.5,
+            (TextAlign::End | TextAlign::Right, Direction::Ltr)
+            | (TextAlign::Left, Direction::Rtl) => 1.0,
+            _ => 0.0,
+        };
+
+        // The number of spaces on each line that can be widened, which leaves out the space that
+        // hangs at its end.
+        let mut spaces = vec![0; lines.len()];
+        if align == TextAlign::Justify {
+            let mut index = 0;
+            for child in &mut self.children {
+                child.for_each_atom(&mut |atom| {
+                    index = line_index(lines, index, atom.atom_rect().y);
+                    if let Some(run) = &atom.glyph_run {
+                        spaces[index] += run.glyphs.iter().filter(|g| g.character == ' ').count();
+                    }
+                });
+            }
+            for (spaces, line) in spaces.iter_mut().zip(lines) {
+                if line.forced {
+                    *spaces = 0;
+                } else if line.hanging {
+                    *spaces = spaces.saturating_sub(1);
+                }
+            }
+        }
+
+        let mut index = 0;
+        let mut current = None;
+        let (mut shift, mut extra, mut remaining) = (0.0, 0.0, 0);
+        for child in &mut self.children {
+            child.for_each_atom(&mut |atom| {
+                index = line_index(lines, index, atom.atom_rect().y);
+                if current != Some(index) {
+                    current = Some(index);
+                    let free = (width - lines[index].width).max(0.0);
+                    remaining = spaces[index];
+                    (shift, extra) = match remaining {
+                        0 if align == TextAlign::Justify => (0.0, 0.0),
+                        0 => (start * free, 0.0),
+                        _ => (0.0, free / remaining as f32),
+                    };
+                }
+
+                atom.translate(shift, 0.0);
+                if let Some(run) = &mut atom.glyph_run {
+                    let widened = widen_spaces(run, extra, &mut remaining, direction);
+                    atom.dimensions.content.width += widened;
+                    shift += widened;
+                }
+            });
+            child.fit_inline_box();
+        }
+    }
+
+    /// Move the text runs and inline-blocks on each line so that their baselines line up, and
+    /// return how much taller the lines became.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#line-height
+    fn align_baselines(&mut self, context: &LayoutContext) -> f32 {
+        // Boxes are laid out in order, so the boxes on a line are visited one after another.
+        let mut lines: Vec<LineBox> = Vec::new();
+        for child in &mut self.children {
+            child.for_each_atom(&mut |atom| {
+                let rect = atom.atom_rect();
+                let above = atom.atom_baseline(context);
+                match lines.last_mut() {
+                    Some(line) if line.top == rect.y => {
+                        line.height = line.height.max(rect.height);
+                        line.above = line.above.max(above);
+                        line.below = line.below.max(rect.height - above);
+                    }
+                    _ => lines.push(LineBox {
+                        top: rect.y,
+                        height: rect.height,
+                        above,
+                        below: rect.height - above,
+                        shift: 0.0,
+                    }),
+                }
+            });
+        }
+
+        let mut shift = 0.0;
+        for line in &mut lines {
+            line.shift = shift;
+            shift += (line.above + line.below - line.height).max(0.0);
+        }
+
+        let mut index = 0;
+        for child in &mut self.children {
+            child.for_each_atom(&mut |atom| {
+                let top = atom.atom_rect().y;
+                while lines[index].top != top {
+                    index += 1;
+                }
+                let line = &lines[index];
+                atom.translate(0.0, line.shift + line.above - atom.atom_baseline(context));
+            });
+            child.fit_inline_box();
+        }
+
+        shift
+    }
+
+    /// Call `f` for each text run and inline-block in this inline-level box, in tree order.
+    fn for_each_atom(&mut self, f: &mut impl FnMut(&mut LayoutBox<'a>)) {
+        match self.box_type {
+            InlineNode(_) | TextRun(..) if !self.children.is_empty() => {
+                for child in &mut self.children {
+                    child.for_each_atom(f);
+                }
+            }
+            TextRun(..) | BlockNode(_) => f(self),
+            _ => {}
+        }
+    }
+
+    /// The last text run or inline-block in this inline-level box.
+    fn last_atom(&self) -> Option<&LayoutBox<'a>> {
+        match self.box_type {
+            InlineNode(_) | TextRun(..) if !self.children.is_empty() => self
+                .children
+                .iter()
+                .rev()
+                .find_map(|child| child.last_atom()),
+            TextRun(..) | BlockNode(_) => Some(self),
+            _ => None,
+        }
+    }
+
+    /// The rectangle that a text run or inline-block takes up on its line.
+    fn atom_rect(&self) -> Rect {
+        match self.box_type {
+            TextRun(..) => self.dimensions.content,
+            _ => self.dimensions.margin_box(),
+        }
+    }
+
+    /// The distance from the top of a text run or inline-block to its baseline.
+    ///
+    /// The baseline of an inline-block is the baseline of its last line box, or its bottom margin
+    /// edge if it has no line boxes or doesn't show its overflowing content.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#propdef-vertical-align
+    fn atom_baseline(&self, context: &LayoutContext) -> f32 {
+        let rect = self.atom_rect();
+        match self.box_type {
+            TextRun(style, _) => TextStyle::new(style, context).baseline,
+            _ if self.overflow() == Overflow::Visible => self
+                .last_line_baseline(context)
+                .map_or(rect.height, |baseline| baseline - rect.y),
+            _ => rect.height,
+        }
+    }
+
+    /// The vertical position of the baseline of the last line box in the normal flow of this
+    /// block container, if it has any.
+    fn last_line_baseline(&self, context: &LayoutContext) -> Option<f32> {
+        match self.box_type {
+            AnonymousBlock => {
+                let atom = self
+                    .children
+                    .iter()
+                    .rev()
+                    .find_map(|child| child.last_atom())?;
+                Some(atom.atom_rect().y + atom.atom_baseline(context))
+            }
+            BlockNode(_) => self
+                .children
+                .iter()
+                .rev()
+                .filter(|child| child.position() != Position::Absolute)
+                .find_map(|child| child.last_line_baseline(context)),
+            _ => None,
+        }
+    }
+
+    /// Fit the content of inline boxes around their descendants again, after these have moved.
+    fn fit_inline_box(&mut self) {
+        if let InlineNode(_) | TextRun(..) = self.box_type {
+            for child in &mut self.children {
+                child.fit_inline_box();
+            }
+            if let Some(content) = self
+                .children
+                .iter()
+                .map(|child| child.dimensions.content)
+                .reduce(Rect::union)
+            {
+                self.dimensions.content = content;
+            }
+        }
+    }
+
+    /// Place a list item marker outside of the content box of its list item, next to the start of
+    /// its first line, as text in the font of the list item, which has the style `style`.
+    ///
+    /// http://www.w3.org/TR/CSS2/generate.html#propdef-list-style-position
+    pub(super) fn layout_marker(
+        &mut self,
+        style: &StyledNode,
+        marker: ListMarker,
+        list_item: Rect,
+        context: &LayoutContext,
+    ) {
+        let text_style = TextStyle::new(style, context);
+        let text = match marker {
+            ListMarker::Disc => "\u{2022}".to_owned(),
+            ListMarker::Decimal(ordinal) => format!("{}.", ordinal),
+        };
+        let width = text_style.width(&text);
+
+        // The marker is followed by a space, which separates it from the list item.
+        let d = &mut self.dimensions;
+        d.margin.right = text_style.advance(' ');
+        d.content.width = width;
+        d.content.height = text_style.line_height;
+        d.content.x = list_item.x - d.margin.right - d.content.width;
+        d.content.y = list_item.y;
+        self.glyph_run = Some(text_style.glyph_run(&text, width));
+    }
+
+    /// Lay out an inline-level box on the lines, starting at the pen.
+    fn layout_inline(&mut self, lines: &mut LineBuilder, context: &LayoutContext) {
+        // TODO: Support block-level boxes inside inline boxes.
+        match self.box_type {
+            InlineNode(style) => match style.node {
+                Node::Text(ref text) => self.layout_text(style, text, lines, context),
+                Node::Element { ref tag, .. } if tag == "br" => {
+                    self.layout_line_break(style, lines, context)
+                }
+                Node::Element { ref tag, .. } if tag == "wbr" => lines.can_break = true,
+                Node::Element { .. } => self.layout_inline_box(lines, context),
+            },
+            // The generated content of a pseudo-element, which is broken into runs of its own.
+            TextRun(style, ref text) => {
+                let text = text.clone();
+                self.layout_text(style, &text, lines, context)
+            }
+            BlockNode(_) => self.layout_inline_block(lines, context),
+            _ => {}
+        }
+    }
+
+    /// Lay out an inline-block as a single unit on the line, which moves to the next line if it
+    /// doesn't fit.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#inlineblock-width
+    fn layout_inline_block(&mut self, lines: &mut LineBuilder, context: &LayoutContext) {
+        self.calculate_item_edges();
+
+        let edges = horizontal_edges(&self.dimensions);
+        let width = match self.style_value("width") {
+            Some(width @ Length(..)) => {
+                let min = self.style_value("min-width").map_or(0.0, |v| v.to_px());
+                let max = match self.style_value("max-width") {
+                    Some(value @ Length(..)) => value.to_px(),
+                    _ => f32::INFINITY,
+                };
+                width.to_px().min(max).max(min)
+            }
+            _ => self.shrink_to_fit_width(lines.bounds.width - edges, context),
+        };
+
+        if lines.x > 0.0 && lines.x + width + edges > lines.bounds.width {
+            lines.break_line();
+        }
+
+        let x = lines.bounds.x + lines.x;
+        let y = lines.bounds.y + lines.y;
+        self.layout_item(x, y, width, self.definite_height(), context);
+
+        lines.x += width + edges;
+        lines.height = lines.height.max(self.dimensions.margin_box().height);
+        lines.skip_space = false;
+        lines.hanging = 0.0;
+        lines.can_break = true;
+    }
+
+    /// End the line at a `br` element, which takes up the height of a line of its own text even
+    /// if the line is otherwise empty.
+    ///
+    /// https://html.spec.whatwg.org/multipage/rendering.html#phrasing-content-3
+    fn layout_line_break(
+        &mut self,
+        style: &StyledNode,
+        lines: &mut LineBuilder,
+        context: &LayoutContext,
+    ) {
+        let line_height = TextStyle::new(style, context).line_height;
+        self.dimensions.content = Rect {
+            x: lines.bounds.x + lines.x,
+            y: lines.bounds.y + lines.y,
+            width: 0.0,
+            height: line_height,
+        };
+        lines.height = lines.height.max(line_height);
+        lines.force_break();
+    }
+
+    /// Lay out an inline box and its descendants. Horizontal margins, borders and padding take
+    /// up room on the line, while vertical ones don't.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#inline-width
+    fn layout_inline_box(&mut self, lines: &mut LineBuilder, context: &LayoutContext) {
+        self.calculate_item_edges();
+
+        // The start edges come first on the line, which is on the right for right-to-left text.
+        let d = &self.dimensions;
+        let left = d.margin.left + d.border.left + d.padding.left;
+        let right = d.padding.right + d.border.right + d.margin.right;
+        let (start_edges, end_edges) = match lines.direction {
+            Direction::Ltr => (left, right),
+            Direction::Rtl => (right, left),
+        };
+
+        lines.x += start_edges;
+        let start = Rect {
+            x: lines.bounds.x + lines.x,
+            y: lines.bounds.y + lines.y,
+            width: 0.0,
+            height: 0.0,
+        };
+
+        for child in &mut self.children {
+            child.layout_inline(lines, context);
+        }
+
+        lines.x += end_edges;
+        self.dimensions.content = self
+            .children
+            .iter()
+            .map(|child| child.dimensions.content)
+            .reduce(Rect::union)
+            .unwrap_or(start);
+    }
+
+    /// Lay out the text of a text node, as one `TextRun` child for each line it is on.
+    ///
+    /// http://www.w3.org/TR/CSS2/text.html#white-space-model
+    fn layout_text(
+        &mut self,
+        style: &'a StyledNode<'a>,
+        text: &str,
+        lines: &mut LineBuilder,
+        context: &LayoutContext,
+    ) {
+        let text_style = TextStyle::new(style, context);
+        let white_space = text_style.white_space;
+        let text = text_style.transform(text);
+
+        // Text with a different direction than its block is embedded one level deeper.
+        let base_level = match (lines.direction, style.direction()) {
+            (Direction::Ltr, Direction::Ltr) => 0,
+            (Direction::Ltr, Direction::Rtl) | (Direction::Rtl, Direction::Rtl) => 1,
+            (Direction::Rtl, Direction::Ltr) => 2,
+        };
+
+        let mut runs = Vec::new();
+        let mut run = String::new();
+        let mut run_start = lines.x;
+
+        let mut end_run = |run: &mut String, run_start: &mut f32, lines: &LineBuilder| {
+            if !run.is_empty() {
+                runs.push((
+                    std::mem::take(run),
+                    Rect {
+                        x: lines.bounds.x + *run_start,
+                        y: lines.bounds.y + lines.y,
+                        width: lines.x - *run_start,
+                        height: text_style.line_height,
+                    },
+                ));
+            }
+            *run_start = lines.x;
+        };
+
+        for segment in segments(&text) {
+            match segment {
+                Segment::Word(word) => {
+                    let width = text_style.width(word);
+                    let break_all = text_style.word_break == WordBreak::BreakAll;
+                    let overflows = lines.x + width > lines.bounds.width;
+                    let can_break = lines.x > 0.0 && lines.can_break;
+                    if white_space.wraps() && can_break && overflows && !break_all {
+                        end_run(&mut run, &mut run_start, lines);
+                        lines.break_line();
+                        run_start = 0.0;
+                    }
+
+                    // Break within the word if that is always allowed, or if it doesn't fit on a
+                    // line by itself and would overflow the container otherwise.
+                    let break_word = text_style.overflow_wrap == OverflowWrap::BreakWord
+                        && lines.x + width > lines.bounds.width;
+                    if white_space.wraps() && (break_all || break_word) {
+                        for c in word.chars() {
+                            let advance = text_style.advance(c);
+                            if lines.x > 0.0 && lines.x + advance > lines.bounds.width {
+                                end_run(&mut run, &mut run_start, lines);
+                                lines.break_line();
+                                run_start = 0.0;
+                            }
+                            run.push(c);
+                            lines.x += advance;
+                            lines.height = lines.height.max(text_style.line_height);
+                        }
+                    } else {
+                        run.push_str(word);
+                        lines.x += width;
+                        lines.height = lines.height.max(text_style.line_height);
+                    }
+
+                    lines.skip_space = false;
+                    lines.hanging = 0.0;
+                    lines.can_break = false;
+                }
+                // Collapsible spaces never cause a line break, but hang past the end of the line.
+                Segment::Spaces(_) | Segment::Newline if white_space.collapses() => {
+                    if !lines.skip_space {
+                        run.push(' ');
+                        lines.hanging = text_style.advance(' ');
+                        lines.x += lines.hanging;
+                        lines.skip_space = true;
+                        lines.can_break = white_space.wraps();
+                    }
+                }
+                Segment::Spaces(spaces) => {
+                    for c in spaces.chars() {
+                        let width = match c {
+                            '\t' => {
+                                let tab_stop = text_style.tab_size();
+                                tab_stop - lines.x % tab_stop
+                            }
+                            '\r' => continue,
+                            _ => text_style.advance(c),
+                        };
+                        run.push(c);
+                        lines.x += width;
+                    }
+                    lines.height = lines.height.max(text_style.line_height);
+                    lines.hanging = 0.0;
+                    lines.can_break = white_space.wraps();
+                }
+                Segment::Newline => {
+                    // Preserved newlines force a line break, even on an empty line.
+                    lines.height = lines.height.max(text_style.line_height);
+                    end_run(&mut run, &mut run_start, lines);
+                    lines.force_break();
+                    lines.skip_space = false;
+                    run_start = 0.0;
+                }
+            }
+        }
+        end_run(&mut run, &mut run_start, lines);
+
+        self.children = runs
+            .into_iter()
+            .map(|(text, rect)| {
+                let text = reorder(&text, base_level);
+                let glyph_run = text_style.glyph_run(&text, rect.width);
+                let mut run = LayoutBox::new(TextRun(style, text));
+                run.dimensions.content = rect;
+                run.glyph_run = Some(glyph_run);
+                run
+            })
+            .collect();
+
+        self.dimensions.content = self
+            .children
+            .iter()
+            .map(|child| child.dimensions.content)
+            .reduce(Rect::union)
+            .unwrap_or_default();
+    }
+}
+
+/// Measure the text of a text node, following the same white space rules as `layout_text`.
+fn measure_text(
+    style: &StyledNode,
+    text: &str,
+    measurer: &mut WidthMeasurer,
+    context: &LayoutContext,
+) {
+    let text_style = TextStyle::new(style, context);
+    let white_space = text_style.white_space;
+    let text = text_style.transform(text);
+
+    for segment in segments(&text) {
+        match segment {
+            Segment::Word(word) => {
+                let width = text_style.width(word);
+                if white_space.wraps() && text_style.word_break == WordBreak::BreakAll {
+                    for c in word.chars() {
+                        measurer.add(text_style.advance(c));
+                        measurer.break_opportunity();
+                    }
+                } else {
+                    measurer.add(width);
+                }
+            }
+            Segment::Spaces(_) | Segment::Newline if white_space.collapses() => {
+                if !measurer.skip_space {
+                    let advance = text_style.advance(' ');
+                    measurer.line += advance;
+                    measurer.hanging += advance;
+                    measurer.skip_space = true;
+                    if white_space.wraps() {
+                        measurer.break_opportunity();
+                    } else {
+                        measurer.unbreakable += advance;
+                    }
+                }
+            }
+            Segment::Spaces(spaces) => {
+                for c in spaces.chars() {
+                    let width = match c {
+                        '\t' => {
+                            let tab_stop = text_style.tab_size();
+                            tab_stop - measurer.line % tab_stop
+                        }
+                        '\r' => continue,
+                        _ => text_style.advance(c),
+                    };
+                    measurer.add(width);
+                    if white_space.wraps() {
+                        measurer.break_opportunity();
+                    }
+                }
+            }
+            Segment::Newline => {
+                measurer.break_line();
+                measurer.skip_space = false;
+            }
+        }
+    }
+}
+
+/// Whether a character is strongly right-to-left (`Some(true)`), strongly left-to-right
+/// (`Some(false)`), or takes its direction from the surrounding text (`None`).
+///
+/// This simplifies the bidirectional character types, treating digits as left-to-right.
+fn strong_direction(c: char) -> Option<bool> {
+    match c {
+        // Hebrew, Arabic, Syriac, Thaana, NKo and their presentation forms.
+        '\u{0590}'..='\u{08FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}' => Some(true),
+        c if c.is_alphanumeric() => Some(false),
+        _ => None,
+    }
+}
+
+/// Reorder the characters of a line of text from logical to visual order, given the embedding
+/// level of the text: even for left-to-right, odd for right-to-left.
+///
+/// http://www.unicode.org/reports/tr9/#Reordering_Resolved_Levels
+fn reorder(text: &str, base_level: u8) -> String {
+    let mut chars: Vec<char> = text.chars().collect();
+    let strong: Vec<Option<bool>> = chars.iter().map(|&c| strong_direction(c)).collect();
+    let base_rtl = base_level % 2 == 1;
+
+    let level = |rtl: bool| match (rtl, base_rtl) {
+        (true, true) | (false, false) => base_level,
+        (true, false) | (false, true) => base_level + 1,
+    };
+
+    // Neutral characters between strong characters of the same direction take that direction,
+    // and otherwise the base direction.
+    let levels: Vec<u8> = (0..chars.len())
+        .map(|i| match strong[i] {
+            Some(rtl) => level(rtl),
+            None => {
+                let before = strong[..i].iter().rev().find_map(|&s| s);
+                let after = strong[i + 1..].iter().find_map(|&s| s);
+                match (before.unwrap_or(base_rtl), after.unwrap_or(base_rtl)) {
+                    (true, true) => level(true),
+                    (false, false) => level(false),
+                    _ => base_level,
+                }
+            }
+        })
+        .collect();
+
+    // From the highest level down to the lowest odd level, reverse every run of characters at
+    // that level or higher.
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd = levels.iter().copied().filter(|l| l % 2 == 1).min();
+    if let Some(lowest_odd) = lowest_odd {
+        for level in (lowest_odd..=highest).rev() {
+            let mut i = 0;
+            while i < chars.len() {
+                if levels[i] < level {
+                    i += 1;
+                    continue;
+                }
+                let end = (i..chars.len())
+                    .find(|&j| levels[j] < level)
+                    .unwrap_or(chars.len());
+                chars[i..end].reverse();
+                i = end;
+            }
+        }
+    }
+
+    chars.into_iter().collect()
+}
+
+/// The index of the line that starts at `top`, which is this line or one after it.
+fn line_index(lines: &[Line], mut index: usize, top: f32) -> usize {
+    while index + 1 < lines.len() && lines[index].top != top {
+        index += 1;
+    }
+    index
+}
+
+/// Widen up to `remaining` spaces of a glyph run by `extra` each, and move the glyphs after them.
+/// The spaces are taken in the order of the text, so that the space that hangs at the end of a
+/// line is widened last. Returns how much wider the run became.
+fn widen_spaces(
+    run: &mut GlyphRun,
+    extra: f32,
+    remaining: &mut usize,
+    direction: Direction,
+) -> f32 {
+    if extra == 0.0 {
+        return 0.0;
+    }
+
+    let mut widen = vec![false; run.glyphs.len()];
+    let mut order: Vec<usize> = (0..run.glyphs.len()).collect();
+    if direction == Direction::Rtl {
+        order.reverse();
+    }
+    for i in order {
+        if *remaining > 0 && run.glyphs[i].character == ' ' {
+            widen[i] = true;
+            *remaining -= 1;
+        }
+    }
+
+    let mut shift = 0.0;
+    for (glyph, widen) in run.glyphs.iter_mut().zip(widen) {
+        glyph.x += shift;
+        if widen {
+            glyph.advance += extra;
+            shift += extra;
+        }
+    }
+    shift
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::style::*;
+
+    /// Lay out a paragraph of text, and return its text runs and its height.
+    fn layout_text(html: &str, css: &str) -> (Vec<(String, Rect)>, f32) {
+        let document = Node::from(html);
+        let style = Sheet::from(css);
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let root = layout_tree(&applied_styles, viewport);
+
+        fn collect(layout_box: &LayoutBox, runs: &mut Vec<(String, Rect)>) {
+            // Generated content is broken into runs of its own, which are collected instead.
+            if let TextRun(_, ref text) = layout_box.box_type {
+                if layout_box.children.is_empty() {
+                    runs.push((text.clone(), layout_box.dimensions.content));
+                }
+            }
+            for child in &layout_box.children {
+                collect(child, runs);
+            }
+        }
+
+        let mut runs = Vec::new();
+        collect(&root, &mut runs);
+        (runs, root.dimensions.content.height)
+    }
+
+    fn run(text: &str, x: f32, y: f32, width: f32) -> (String, Rect) {
+        let rect = Rect {
+            x,
+            y,
+            width,
+            height: 12.0,
+        };
+        (text.to_owned(), rect)
+    }
+
+    #[test]
+    fn test_white_space_normal() {
+        let (runs, height) = layout_text(
+            "<p>aaa   bbb\n<b>ccc</b> ddd</p>",
+            "p { display: block; width: 40px; font-size: 10px; }",
+        );
+
+        // Each glyph is 5px wide. The space after "bbb" hangs past the end of the line.
+        assert_eq!(
+            runs,
+            vec![
+                run("aaa bbb ", 0.0, 0.0, 40.0),
+                run("ccc", 0.0, 12.0, 15.0),
+                run(" ddd", 15.0, 12.0, 20.0),
+            ]
+        );
+        assert_eq!(height, 24.0);
+    }
+
+    #[test]
+    fn test_white_space_pre() {
+        let css = "p { display: block; width: 40px; font-size: 10px; white-space: pre; }";
+        let (runs, height) = layout_text("<p>  aaa bbb ccc\n\n\tddd</p>", css);
+
+        // Lines don't wrap, and newlines and tabs are preserved.
+        assert_eq!(
+            runs,
+            vec![
+                run("  aaa bbb ccc", 0.0, 0.0, 65.0),
+                run("\tddd", 0.0, 24.0, 55.0),
+            ]
+        );
+        assert_eq!(height, 36.0);
+
+        let nowrap = css.replace("pre", "nowrap");
+        let (runs, _) = layout_text("<p>  aaa bbb\nccc</p>", &nowrap);
+        assert_eq!(runs, vec![run("aaa bbb ccc", 0.0, 0.0, 55.0)]);
+
+        let pre_wrap = css.replace("pre", "pre-wrap");
+        let (runs, _) = layout_text("<p>aaa  bbb ccc</p>", &pre_wrap);
+        assert_eq!(
+            runs,
+            vec![
+                run("aaa  bbb ", 0.0, 0.0, 45.0),
+                run("ccc", 0.0, 12.0, 15.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let (runs, height) = layout_text(
+            "<p>aaa<br><br/>bbb ccc<wbr>ddd eee<b>fff</b></p>",
+            "p { display: block; width: 40px; font-size: 10px; }",
+        );
+
+        // The second `br` ends an empty line. Lines wrap at `wbr`, but not between words that
+        // are only in different boxes.
+        assert_eq!(
+            runs,
+            vec![
+                run("aaa", 0.0, 0.0, 15.0),
+                run("bbb ccc", 0.0, 24.0, 35.0),
+                run("ddd eee", 0.0, 36.0, 35.0),
+                run("fff", 35.0, 36.0, 15.0),
+            ]
+        );
+        assert_eq!(height, 48.0);
+
+        // A `br` with or without a slash ends the first of two lines.
+        for html in ["<p>a<br>b</p>", "<p>a<br/>b</p>"] {
+            let (runs, height) = layout_text(html, "p { display: block; font-size: 10px; }");
+            assert_eq!(
+                runs,
+                vec![run("a", 0.0, 0.0, 5.0), run("b", 0.0, 12.0, 5.0)]
+            );
+            assert_eq!(height, 24.0);
+        }
+    }
+
+    #[test]
+    fn test_break_long_words() {
+        let css = "p { display: block; width: 40px; font-size: 10px; }";
+        let html = "<p>aa bbbbbbbbbbbb</p>";
+
+        // Without a break opportunity, the word overflows on a line of its own.
+        let (runs, _) = layout_text(html, css);
+        assert_eq!(
+            runs,
+            vec![
+                run("aa ", 0.0, 0.0, 15.0),
+                run("bbbbbbbbbbbb", 0.0, 12.0, 60.0)
+            ]
+        );
+
+        let break_word = css.replace('}', "overflow-wrap: break-word; }");
+        let (runs, _) = layout_text(html, &break_word);
+        assert_eq!(
+            runs,
+            vec![
+                run("aa ", 0.0, 0.0, 15.0),
+                run("bbbbbbbb", 0.0, 12.0, 40.0),
+                run("bbbb", 0.0, 24.0, 20.0),
+            ]
+        );
+
+        let break_all = css.replace('}', "word-break: break-all; }");
+        let (runs, _) = layout_text(html, &break_all);
+        assert_eq!(
+            runs,
+            vec![
+                run("aa bbbbb", 0.0, 0.0, 40.0),
+                run("bbbbbbb", 0.0, 12.0, 35.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_right_to_left() {
+        let css = "p { display: block; width: 100px; font-size: 10px; }";
+
+        // Right-to-left lines start at the right, and Latin text within them keeps its order. The
+        // paragraph itself is on the right side of the viewport, at x = 700.
+        let (runs, _) = layout_text("<p dir=\"rtl\">\u{5d0}\u{5d1} cd</p>", css);
+        assert_eq!(runs, vec![run("cd \u{5d1}\u{5d0}", 775.0, 0.0, 25.0)]);
+
+        let (runs, _) = layout_text("<p>ab \u{5d0}\u{5d1}! cd</p>", css);
+        assert_eq!(runs, vec![run("ab \u{5d1}\u{5d0}! cd", 0.0, 0.0, 45.0)]);
+    }
+
+    #[test]
+    fn test_text_align() {
+        let css = "p { display: block; width: 42px; font-size: 10px; text-align: right; }";
+        let html = "<p>aa bb cc dd</p>";
+
+        // The space at the end of the first line hangs, and isn't aligned with the text.
+        let (runs, _) = layout_text(html, css);
+        assert_eq!(
+            runs,
+            vec![
+                run("aa bb cc ", 2.0, 0.0, 45.0),
+                run("dd", 32.0, 12.0, 10.0)
+            ]
+        );
+
+        let (runs, _) = layout_text(html, &css.replace("right", "center"));
+        assert_eq!(
+            runs,
+            vec![
+                run("aa bb cc ", 1.0, 0.0, 45.0),
+                run("dd", 16.0, 12.0, 10.0)
+            ]
+        );
+
+        // The two spaces between the words of the first line share its free space, and the last
+        // line isn't justified.
+        let (runs, _) = layout_text(html, &css.replace("right", "justify"));
+        assert_eq!(
+            runs,
+            vec![run("aa bb cc ", 0.0, 0.0, 47.0), run("dd", 0.0, 12.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn test_text_spacing() {
+        let (runs, _) = layout_text(
+            "<p>aa bb</p>",
+            "p { display: block; font-size: 10px; letter-spacing: 1px; word-spacing: 2px;
+                 text-transform: uppercase; }",
+        );
+
+        // Each glyph is 6px wide with its letter spacing, and the space 8px.
+        assert_eq!(runs, vec![run("AA BB", 0.0, 0.0, 32.0)]);
+
+        let (runs, _) = layout_text(
+            "<p>hello (big) world</p>",
+            "p { display: block; font-size: 10px; text-transform: capitalize; }",
+        );
+        assert_eq!(runs, vec![run("Hello (Big) World", 0.0, 0.0, 85.0)]);
+    }
+
+    #[test]
+    fn test_inline_block_baseline() {
+        let (runs, height) = layout_text(
+            "<p>aa<i></i><b>xy</b></p>",
+            "
+            p { display: block; font-size: 10px; }
+            i { display: inline-block; width: 20px; height: 30px; }
+            b { display: inline-block; width: 20px; padding-top: 10px; }
+        ",
+        );
+
+        // The baseline of the text is 9px below the top of its line. The empty inline-block has
+        // its baseline at its bottom, and the other one at the baseline of its own text.
+        assert_eq!(
+            runs,
+            vec![run("aa", 0.0, 21.0, 10.0), run("xy", 30.0, 21.0, 10.0)]
+        );
+        assert_eq!(height, 33.0);
+    }
+
+    #[test]
+    fn test_generated_content() {
+        let (runs, height) = layout_text(
+            "<p>ccc</p>",
+            "
+            p { display: block; width: 30px; font-size: 10px; }
+            p::before { content: 'aa '; }
+            p::after { content: ' bb'; }
+        ",
+        );
+
+        // Generated content is laid out on the lines with the text of the element. The space at
+        // the start of the `::after` content hangs at the end of the first line.
+        assert_eq!(
+            runs,
+            vec![
+                run("aa ", 0.0, 0.0, 15.0),
+                run("ccc", 15.0, 0.0, 15.0),
+                run(" ", 30.0, 0.0, 5.0),
+                run("bb", 0.0, 12.0, 10.0),
+            ]
+        );
+        assert_eq!(height, 24.0);
+    }
+}
@@ -0,0 +1,84 @@
+//! Layout trees as JSON, for tools outside of boxrs, such as visualizers or tests that compare
+//! layouts between versions.
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::dom::Node;
+use crate::layout::{
+    AnonymousBlock, BlockNode, GridNode, InlineNode, LayoutBox, MarkerBox, TextRun,
+};
+
+/// A layout tree as JSON. Each box is an object with these fields:
+///
+/// - `box_type`: `"block"`, `"inline"`, `"grid"`, `"marker"`, `"text"` or `"anonymous"`.
+/// - `tag`: the tag name of the element that generated the box, or `null`.
+/// - `text`: the text of a text run, or `null`.
+/// - `dimensions`: the content rectangle and the padding, border and margin edges.
+/// - `children`: the boxes in the box.
+pub fn to_json(root: &LayoutBox) -> String {
+    serde_json::to_string(root).expect("layout trees serialize to JSON")
+}
+
+impl Serialize for LayoutBox<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (box_type, text) = match &self.box_type {
+            BlockNode(_) => ("block", None),
+            InlineNode(_) => ("inline", None),
+            GridNode(_) => ("grid", None),
+            MarkerBox(..) => ("marker", None),
+            TextRun(_, text) => ("text", Some(text.as_str())),
+            AnonymousBlock => ("anonymous", None),
+        };
+        let tag = match self.node() {
+            Some(Node::Element { tag, .. }) => Some(&**tag),
+            _ => None,
+        };
+
+        let mut state = serializer.serialize_struct("LayoutBox", 5)?;
+        state.serialize_field("box_type", box_type)?;
+        state.serialize_field("tag", &tag)?;
+        state.serialize_field("text", &text)?;
+        state.serialize_field("dimensions", &self.dimensions)?;
+        state.serialize_field("children", &self.children)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::painting;
+    use crate::style::*;
+
+    #[test]
+    fn test_to_json() {
+        let document = Node::from("<a><b>hi</b></a>");
+        let style = Sheet::from("a, b { display: block; height: 10px; background: #ff0000; }");
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&applied_styles, viewport);
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&layout_root)).unwrap();
+        assert_eq!(json["box_type"], "block");
+        assert_eq!(json["tag"], "a");
+        assert_eq!(json["dimensions"]["content"]["width"], 100.0);
+        let b = &json["children"][0];
+        assert_eq!(b["tag"], "b");
+        let text = &b["children"][0]["children"][0]["children"][0];
+        assert_eq!(text["box_type"], "text");
+        assert_eq!(text["text"], "hi");
+
+        let list = painting::build_display_list(&layout_root);
+        let json: serde_json::Value = serde_json::from_str(&painting::to_json(&list)).unwrap();
+        let solid_color = &json[0]["SolidColor"];
+        assert_eq!(
+            solid_color[0],
+            serde_json::json!({"r": 255, "g": 0, "b": 0, "a": 255})
+        );
+        assert_eq!(solid_color[1]["width"], 100.0);
+    }
+}
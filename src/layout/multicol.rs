@@ -0,0 +1,157 @@
+use crate::css::Unit::Px;
+use crate::css::Value::{Length, Number};
+use crate::layout::{LayoutBox, LayoutContext, MarkerBox};
+use crate::style::Position;
+
+/// The number of steps taken to find the shortest height that balances the columns.
+const BALANCING_STEPS: usize = 20;
+
+impl LayoutBox<'_> {
+    /// Whether this box lays out its content in columns.
+    pub(super) fn is_multicol_container(&self) -> bool {
+        matches!(self.style_value("column-count"), Some(&Number(n)) if n >= 1.0)
+            || matches!(self.style_value("column-width"), Some(&Length(w, Px)) if w > 0.0)
+    }
+
+    /// Lay out the children of a multi-column container: first as a single column, which is then
+    /// broken into columns of equal height, side by side.
+    ///
+    /// https://www.w3.org/TR/css-multicol-1/
+    ///
+    /// Sets `self.dimensions.height` to the height of the columns.
+    pub(super) fn layout_multicol_children(&mut self, context: &LayoutContext) {
+        let content = self.dimensions.content;
+        let gap = match self.style_value("column-gap") {
+            Some(value @ Length(..)) => value.to_px(),
+            // `column-gap: normal` is 1em.
+            // TODO: Use the font size of this box once it is inherited.
+            _ => context.root_font_size,
+        };
+        let (count, width) = self.column_count_and_width(content.width, gap);
+
+        self.dimensions.content.width = width;
+        self.layout_block_children(context);
+        self.dimensions.content.width = content.width;
+
+        // Balance the columns: find the shortest height for which the content fits in them.
+        let total = self.dimensions.content.height;
+        let (mut low, mut high) = (total / count as f32, total);
+        if self.column_breaks(low).len() > count {
+            for _ in 0..BALANCING_STEPS {
+                let middle = (low + high) / 2.0;
+                if self.column_breaks(middle).len() > count {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            low = high;
+        }
+
+        let breaks = self.column_breaks(low);
+        self.move_into_columns(&breaks, width + gap);
+        self.dimensions.content.height = (0..breaks.len())
+            .map(|i| breaks.get(i + 1).unwrap_or(&(content.y + total)) - breaks[i])
+            .fold(0.0, f32::max);
+    }
+
+    /// The used number and width of the columns, given the available width.
+    ///
+    /// https://www.w3.org/TR/css-multicol-1/#pseudo-algorithm
+    fn column_count_and_width(&self, available_width: f32, gap: f32) -> (usize, f32) {
+        let count = match self.style_value("column-count") {
+            Some(&Number(n)) if n >= 1.0 => Some(n as usize),
+            _ => None,
+        };
+        let width = match self.style_value("column-width") {
+            Some(&Length(w, Px)) if w > 0.0 => Some(w),
+            _ => None,
+        };
+
+        let count = match (count, width) {
+            (Some(count), None) => count,
+            (count, Some(width)) => {
+                let fitting = ((available_width + gap) / (width + gap)).floor().max(1.0) as usize;
+                count.map_or(fitting, |count| count.min(fitting))
+            }
+            (None, None) => 1,
+        };
+
+        let width = ((available_width + gap) / count as f32 - gap).max(0.0);
+        (count, width)
+    }
+
+    /// Move the descendants in the normal flow from the single column they were laid out in, to
+    /// the column that they start in. `breaks` holds the top of each column in the single column,
+    /// and `stride` is the distance between columns.
+    fn move_into_columns(&mut self, breaks: &[f32], stride: f32) {
+        for child in &mut self.children {
+            if matches!(child.box_type, MarkerBox(..)) || child.position() == Position::Absolute {
+                continue;
+            }
+
+            let border_box = child.dimensions.border_box();
+            let column = breaks
+                .iter()
+                .rposition(|&start| start <= border_box.y)
+                .unwrap_or(0);
+            let spans_break = breaks
+                .get(column + 1)
+                .is_some_and(|&next| border_box.y + border_box.height > next);
+
+            if spans_break && child.is_block_container() {
+                child.move_into_columns(breaks, stride);
+            } else {
+                child.translate(column as f32 * stride, breaks[0] - breaks[column]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_multicol() {
+        let document = Node::from("<a><b></b><b></b><b></b><b></b><b></b></a>");
+
+        let style = Sheet::from(
+            "
+            a, b { display: block; }
+            a { column-count: 2; column-gap: 20px; }
+            b { height: 10px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 820.0;
+        viewport.content.height = 600.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        // Three boxes fit in the first column and two in the second.
+        let positions: Vec<_> = actual
+            .children
+            .iter()
+            .map(|b| (b.dimensions.content.x, b.dimensions.content.y))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0.0, 0.0),
+                (0.0, 10.0),
+                (0.0, 20.0),
+                (420.0, 0.0),
+                (420.0, 10.0)
+            ]
+        );
+        assert_eq!(actual.children[0].dimensions.content.width, 400.0);
+        assert_eq!(actual.dimensions.content.height, 30.0);
+    }
+}
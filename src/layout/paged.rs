@@ -0,0 +1,288 @@
+//! Breaking block content across pages, for printing.
+//!
+//! https://www.w3.org/TR/css-break-3/
+
+use super::{BlockNode, EdgeSizes, LayoutBox, MarkerBox, Rect};
+use crate::css::units::UnitContext;
+use crate::css::Value::{self, Keyword, Length};
+use crate::css::{Declaration, Sheet};
+use crate::style::{Display, Position};
+
+/// The size of a page when the stylesheets don't set it: A4, in portrait.
+const DEFAULT_PAGE_SIZE: (f32, f32) = (210.0 * MM, 297.0 * MM);
+
+/// The number of CSS pixels in a millimeter.
+const MM: f32 = 96.0 / 25.4;
+
+/// The page that a document is printed on, from the `@page` rules of its stylesheets that apply
+/// to every page. Its size and its margins are in CSS pixels.
+///
+/// https://www.w3.org/TR/css-page-3/#page-model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageBox {
+    pub width: f32,
+    pub height: f32,
+    pub margin: EdgeSizes,
+}
+
+impl PageBox {
+    pub fn from_sheet(sheet: &Sheet) -> PageBox {
+        let declarations: Vec<&Declaration> = sheet
+            .pages
+            .iter()
+            .filter(|page| page.selector.is_none())
+            .flat_map(|page| &page.declarations)
+            .collect();
+        let value = |name: &str| {
+            let declaration = declarations.iter().rev().find(|d| &*d.name == name);
+            declaration.map(|declaration| &declaration.value)
+        };
+        let margin = |side: &str| {
+            let margin = value(&format!("margin-{side}")).or(value("margin"));
+            margin.and_then(length).unwrap_or(0.0)
+        };
+
+        let (width, height) = value("size")
+            .and_then(page_size)
+            .unwrap_or(DEFAULT_PAGE_SIZE);
+        PageBox {
+            width,
+            height,
+            margin: EdgeSizes {
+                left: margin("left"),
+                right: margin("right"),
+                top: margin("top"),
+                bottom: margin("bottom"),
+            },
+        }
+    }
+
+    /// The area within the margins, where the content of the page goes.
+    ///
+    /// https://www.w3.org/TR/css-page-3/#page-area
+    pub fn page_area(&self) -> Rect {
+        Rect {
+            x: self.margin.left,
+            y: self.margin.top,
+            width: (self.width - self.margin.horizontal()).max(0.0),
+            height: (self.height - self.margin.vertical()).max(0.0),
+        }
+    }
+}
+
+/// A length in px, for lengths in absolute units. Page boxes have no font size or viewport of
+/// their own, so relative units are resolved against the defaults.
+fn length(value: &Value) -> Option<f32> {
+    match value {
+        Length(length, unit) => unit.in_px(&UnitContext::default()).map(|px| length * px),
+        _ => None,
+    }
+}
+
+/// The width and height of a `size` value: one or two lengths, or a named size and an
+/// orientation. `auto` is the default size.
+///
+/// https://www.w3.org/TR/css-page-3/#page-size-prop
+fn page_size(value: &Value) -> Option<(f32, f32)> {
+    let values = match value {
+        Value::List(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    let lengths: Option<Vec<f32>> = values.iter().map(|value| length(value)).collect();
+    match lengths.as_deref() {
+        Some(&[side]) => return Some((side, side)),
+        Some(&[width, height]) => return Some((width, height)),
+        _ => {}
+    }
+
+    let (mut width, mut height) = DEFAULT_PAGE_SIZE;
+    let mut orientation = None;
+    for value in values {
+        let keyword = match value {
+            Keyword(keyword) => keyword.to_ascii_lowercase(),
+            _ => return None,
+        };
+        (width, height) = match keyword.as_str() {
+            "auto" => DEFAULT_PAGE_SIZE,
+            "a5" => (148.0 * MM, 210.0 * MM),
+            "a4" => (210.0 * MM, 297.0 * MM),
+            "a3" => (297.0 * MM, 420.0 * MM),
+            "b5" => (176.0 * MM, 250.0 * MM),
+            "b4" => (250.0 * MM, 353.0 * MM),
+            "letter" => (8.5 * 96.0, 11.0 * 96.0),
+            "legal" => (8.5 * 96.0, 14.0 * 96.0),
+            "ledger" => (11.0 * 96.0, 17.0 * 96.0),
+            "portrait" | "landscape" => {
+                orientation = Some(keyword);
+                continue;
+            }
+            _ => return None,
+        };
+    }
+
+    // Named sizes are in portrait, and `landscape` turns them.
+    match orientation.as_deref() {
+        Some("landscape") => Some((width.max(height), width.min(height))),
+        _ => Some((width, height)),
+    }
+}
+
+impl LayoutBox<'_> {
+    /// The vertical offsets where pages start when the content of this box is broken across pages
+    /// of the given height. The first page starts at the top of the margin box.
+    ///
+    /// Pages break between block boxes, or where `page-break-before` or `page-break-after` is
+    /// `always`. Boxes that are taller than a page are sliced where each page ends.
+    pub fn page_breaks(&self, page_height: f32) -> Vec<f32> {
+        let margin_box = self.dimensions.margin_box();
+        let mut pages = vec![margin_box.y];
+        self.break_pages(&mut pages, page_height);
+
+        trim_breaks(&mut pages, margin_box.y + margin_box.height);
+        pages
+    }
+
+    /// The vertical offsets where columns of the given height start, when the children of this
+    /// box are broken across columns. The first column starts at the top of the content box.
+    pub(super) fn column_breaks(&self, column_height: f32) -> Vec<f32> {
+        let content = self.dimensions.content;
+        let mut columns = vec![content.y];
+        for child in self.in_flow_children() {
+            child.break_pages(&mut columns, column_height);
+        }
+        trim_breaks(&mut columns, content.y + content.height);
+        columns
+    }
+
+    /// The children of this box that are laid out in its block formatting context.
+    fn in_flow_children(&self) -> impl Iterator<Item = &Self> {
+        self.children.iter().filter(|child| {
+            !matches!(child.box_type, MarkerBox(..)) && child.position() != Position::Absolute
+        })
+    }
+
+    /// Add the pages needed for this box and its descendants in the normal flow.
+    fn break_pages(&self, pages: &mut Vec<f32>, page_height: f32) {
+        let border_box = self.dimensions.border_box();
+        let bottom = border_box.y + border_box.height;
+
+        // Margins adjoining a break are truncated, so pages start at the border edge.
+        if self.style_keyword("page-break-before") == Some("always") {
+            start_page(pages, border_box.y);
+        }
+
+        if bottom > page_end(pages, page_height) {
+            if self.is_block_container() {
+                for child in self.in_flow_children() {
+                    child.break_pages(pages, page_height);
+                }
+            } else {
+                // Move the box to the next page if it fits there, and otherwise slice it.
+                if border_box.height <= page_height {
+                    start_page(pages, border_box.y);
+                }
+                while bottom > page_end(pages, page_height) {
+                    start_page(pages, page_end(pages, page_height));
+                }
+            }
+        }
+
+        if self.style_keyword("page-break-after") == Some("always") {
+            start_page(pages, bottom);
+        }
+    }
+
+    /// Whether this box has children that are laid out as blocks, so pages may break between them.
+    pub(super) fn is_block_container(&self) -> bool {
+        if self.children.is_empty() || self.is_multicol_container() {
+            return false;
+        }
+
+        match self.box_type {
+            BlockNode(style) => style.display() != Display::Flex,
+            _ => false,
+        }
+    }
+}
+
+/// Remove breaks at or after the end of the content: a forced break after the last box doesn't
+/// start an empty page.
+fn trim_breaks(breaks: &mut Vec<f32>, end: f32) {
+    while breaks.len() > 1 && breaks[breaks.len() - 1] >= end {
+        breaks.pop();
+    }
+}
+
+/// Start a new page at `y`, unless the current page starts there already.
+fn start_page(pages: &mut Vec<f32>, y: f32) {
+    if pages.last().is_some_and(|&start| y > start) {
+        pages.push(y);
+    }
+}
+
+/// Where the current page ends.
+fn page_end(pages: &[f32], page_height: f32) -> f32 {
+    pages.last().map_or(0.0, |&start| start + page_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_page_breaks() {
+        let document = Node::from("<a><b></b><c></c><d></d><e></e></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d, e { display: block; }
+            b { height: 60px; }
+            c { height: 60px; page-break-after: always; }
+            d { height: 250px; margin-top: 10px; }
+            e { height: 20px; page-break-after: always; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 100.0;
+
+        let actual = layout_tree(&applied_styles, viewport);
+
+        // c moves to the second page, and d starts a new page after it. It is sliced because it
+        // is taller than a page. The break after e is dropped because nothing follows it.
+        assert_eq!(
+            actual.page_breaks(100.0),
+            vec![0.0, 60.0, 120.0, 220.0, 320.0]
+        );
+    }
+
+    #[test]
+    fn test_page_box() {
+        let page_box = |css: &str| PageBox::from_sheet(&Sheet::from(css));
+
+        let letter = page_box("@page { size: letter landscape; margin: 1in; margin-top: 0; }");
+        assert_eq!((letter.width, letter.height), (1056.0, 816.0));
+        assert_eq!(
+            letter.page_area(),
+            Rect {
+                x: 96.0,
+                y: 0.0,
+                width: 864.0,
+                height: 720.0
+            }
+        );
+
+        // The last size wins, and rules for some pages only don't apply to every page.
+        let page = page_box(
+            "@page { size: 4in; } @page { size: 300px 200px; } @page :first { size: A5; }",
+        );
+        assert_eq!((page.width, page.height), (300.0, 200.0));
+        assert_eq!(page.margin, EdgeSizes::default());
+    }
+}
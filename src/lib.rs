@@ -1,18 +1,40 @@
 extern crate peg;
 
+#[cfg(feature = "boxrs-capi")]
+pub mod capi;
 pub mod css;
 pub mod dom;
+pub mod engine;
+mod error;
+pub mod fonts;
 pub mod html;
+pub mod images;
 pub mod layout;
+pub mod loader;
+#[cfg(feature = "markdown")]
+pub mod markdown;
 pub mod painting;
+#[cfg(feature = "preview")]
+pub mod preview;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 pub mod style;
+#[cfg(feature = "image")]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub fn parse_html(h: &str) -> dom::Node {
-    dom::Node::from(h)
+pub use engine::Engine;
+pub use error::Error;
+
+pub fn parse_html(h: &str) -> Result<dom::Node, Error> {
+    Ok(dom::Node::parse(h)?)
 }
 
-pub fn parse_css(c: &str) -> css::Sheet {
-    css::Sheet::from(c)
+pub fn parse_css(c: &str) -> Result<css::Sheet, Error> {
+    Ok(css::Sheet::parse(c)?)
 }
 
 pub fn build_style_tree<'a>(h: &'a dom::Node, c: &'a css::Sheet) -> style::StyledNode<'a> {
@@ -21,11 +43,104 @@ pub fn build_style_tree<'a>(h: &'a dom::Node, c: &'a css::Sheet) -> style::Style
 
 pub fn build_layout_tree<'a>(
     s: &'a style::StyledNode,
-    d: layout::Dimensions,
+    v: &layout::Viewport,
 ) -> layout::LayoutBox<'a> {
-    layout::layout_tree(s, d)
+    layout::layout_tree_with_context(s, &layout::LayoutContext::for_viewport(v))
 }
 
 pub fn build_display_list(l: &layout::LayoutBox) -> painting::DisplayList {
     painting::build_display_list(l)
 }
+
+pub fn build_visible_display_list(
+    l: &layout::LayoutBox,
+    v: Option<layout::Rect>,
+) -> painting::DisplayList {
+    painting::build_visible_display_list(l, v)
+}
+
+pub fn build_page_display_lists(l: &layout::LayoutBox, h: f32) -> Vec<painting::DisplayList> {
+    painting::build_page_display_lists(l, h)
+}
+
+/// Render a document with a stylesheet in a viewport, and save it as an image file. The format
+/// follows from the extension of the path, such as `.png`, `.jpg`, `.webp` or `.bmp`.
+#[cfg(feature = "image")]
+pub fn render_to_png(
+    html: &str,
+    css: &str,
+    viewport: layout::Viewport,
+    path: impl AsRef<std::path::Path>,
+) -> Result<(), Error> {
+    Ok(render_to_image(html, css, viewport)?.save(path)?)
+}
+
+/// Render a document like [`render_to_png`], and save it as an image file in a format, whatever
+/// the extension of the path. Formats without transparency, like JPEG, leave out the alpha
+/// channel, and WebP is encoded losslessly.
+#[cfg(feature = "image")]
+pub fn render_to_file(
+    html: &str,
+    css: &str,
+    viewport: layout::Viewport,
+    path: impl AsRef<std::path::Path>,
+    format: image::ImageFormat,
+) -> Result<(), Error> {
+    Ok(render_to_image(html, css, viewport)?.save_with_format(path, format)?)
+}
+
+#[cfg(feature = "image")]
+fn render_to_image(
+    html: &str,
+    css: &str,
+    viewport: layout::Viewport,
+) -> Result<image::RgbaImage, Error> {
+    let mut engine = Engine::new(viewport);
+    engine.load_html(html)?;
+    engine.add_stylesheet(css)?;
+
+    let canvas = engine.render();
+    let (width, height) = (canvas.width as u32, canvas.height as u32);
+    Ok(image::RgbaImage::from_raw(width, height, canvas.to_rgba8()).unwrap())
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use image::ImageFormat;
+
+    use crate::layout::Viewport;
+    use crate::*;
+
+    #[test]
+    fn test_render_to_png() {
+        let path = std::env::temp_dir().join("boxrs-test-render-to-png.png");
+        let css = "div { height: 10px; background: #ff0000; }";
+        render_to_png("<div></div>", css, Viewport::new(20.0, 20.0), &path).unwrap();
+
+        let image = image::open(&path).unwrap().into_rgba8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (20, 20));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 10).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_render_to_file() {
+        let css = "div { height: 10px; background: #ff0000; }";
+        let formats = [ImageFormat::Jpeg, ImageFormat::WebP, ImageFormat::Bmp];
+        for (i, format) in formats.into_iter().enumerate() {
+            let path = std::env::temp_dir().join(format!("boxrs-test-render-to-file-{i}"));
+            render_to_file("<div></div>", css, Viewport::new(20.0, 20.0), &path, format).unwrap();
+
+            let reader = image::io::Reader::open(&path).unwrap();
+            let reader = reader.with_guessed_format().unwrap();
+            assert_eq!(reader.format(), Some(format));
+            let image = reader.decode().unwrap().into_rgba8();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(image.dimensions(), (20, 20));
+            if format != ImageFormat::Jpeg {
+                assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Fetching the resources of a document by their URL: its stylesheets, images and fonts.
+
+use std::fmt;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+/// Fetches the data of a resource by its URL.
+///
+/// The engine loads every resource through a loader, so that an application decides where
+/// resources may come from, and can cache them or fetch them from its own storage.
+pub trait ResourceLoader {
+    fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError>;
+}
+
+/// Fetches the data of a resource by its URL without blocking, so that the engine can fetch
+/// several resources at the same time.
+#[cfg(feature = "tokio")]
+pub trait AsyncResourceLoader {
+    fn fetch(&self, url: &Url) -> impl Future<Output = Result<Vec<u8>, LoadError>>;
+}
+
+/// Why a resource could not be fetched.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The loader doesn't fetch URLs with this scheme.
+    UnsupportedScheme(String),
+    /// A URL that doesn't point to a file.
+    InvalidPath(Url),
+    /// A reference to a resource that isn't a valid URL.
+    InvalidUrl(url::ParseError),
+    Io(std::io::Error),
+    #[cfg(feature = "reqwest")]
+    Http(reqwest::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::UnsupportedScheme(scheme) => write!(f, "unsupported URL scheme: {scheme}"),
+            LoadError::InvalidPath(url) => write!(f, "not a file path: {url}"),
+            LoadError::InvalidUrl(error) => error.fmt(f),
+            LoadError::Io(error) => error.fmt(f),
+            #[cfg(feature = "reqwest")]
+            LoadError::Http(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::InvalidUrl(error) => Some(error),
+            LoadError::Io(error) => Some(error),
+            #[cfg(feature = "reqwest")]
+            LoadError::Http(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+impl From<url::ParseError> for LoadError {
+    fn from(error: url::ParseError) -> LoadError {
+        LoadError::InvalidUrl(error)
+    }
+}
+
+/// The `file:` URL of a path, relative to the current directory if it isn't absolute.
+#[cfg(feature = "fs")]
+pub fn file_url(path: impl AsRef<Path>) -> Result<Url, LoadError> {
+    let path = std::path::absolute(path)?;
+    Url::from_file_path(&path)
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput).into())
+}
+
+/// Reads `file:` URLs from the filesystem.
+#[cfg(feature = "fs")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileLoader;
+
+#[cfg(feature = "fs")]
+impl ResourceLoader for FileLoader {
+    fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError> {
+        Ok(std::fs::read(file_path(url)?)?)
+    }
+}
+
+/// Reads `file:` URLs from the filesystem on the blocking threads of tokio.
+#[cfg(all(feature = "tokio", feature = "fs"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncFileLoader;
+
+#[cfg(all(feature = "tokio", feature = "fs"))]
+impl AsyncResourceLoader for AsyncFileLoader {
+    async fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError> {
+        Ok(tokio::fs::read(file_path(url)?).await?)
+    }
+}
+
+/// The path of a `file:` URL.
+#[cfg(feature = "fs")]
+fn file_path(url: &Url) -> Result<PathBuf, LoadError> {
+    if url.scheme() != "file" {
+        return Err(LoadError::UnsupportedScheme(url.scheme().to_owned()));
+    }
+    url.to_file_path()
+        .map_err(|_| LoadError::InvalidPath(url.clone()))
+}
+
+/// Fetches `http:` and `https:` URLs with a blocking reqwest client, and reads `file:` URLs
+/// like a `FileLoader` with the `fs` feature.
+#[cfg(feature = "reqwest")]
+#[derive(Clone, Debug, Default)]
+pub struct HttpLoader {
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpLoader {
+    pub fn new() -> HttpLoader {
+        Default::default()
+    }
+
+    /// A loader that sends its requests with a client that was configured by the application.
+    pub fn with_client(client: reqwest::blocking::Client) -> HttpLoader {
+        HttpLoader { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl ResourceLoader for HttpLoader {
+    fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError> {
+        match url.scheme() {
+            "http" | "https" => {
+                let response = self
+                    .client
+                    .get(url.clone())
+                    .send()
+                    .and_then(|response| response.error_for_status())
+                    .map_err(LoadError::Http)?;
+                Ok(response.bytes().map_err(LoadError::Http)?.to_vec())
+            }
+            #[cfg(feature = "fs")]
+            "file" => FileLoader.fetch(url),
+            scheme => Err(LoadError::UnsupportedScheme(scheme.to_owned())),
+        }
+    }
+}
+
+/// Fetches `http:` and `https:` URLs with an async reqwest client, and reads `file:` URLs like
+/// an `AsyncFileLoader` with the `fs` feature.
+#[cfg(all(feature = "tokio", feature = "reqwest"))]
+#[derive(Clone, Debug, Default)]
+pub struct AsyncHttpLoader {
+    client: reqwest::Client,
+}
+
+#[cfg(all(feature = "tokio", feature = "reqwest"))]
+impl AsyncHttpLoader {
+    pub fn new() -> AsyncHttpLoader {
+        Default::default()
+    }
+
+    /// A loader that sends its requests with a client that was configured by the application.
+    pub fn with_client(client: reqwest::Client) -> AsyncHttpLoader {
+        AsyncHttpLoader { client }
+    }
+}
+
+#[cfg(all(feature = "tokio", feature = "reqwest"))]
+impl AsyncResourceLoader for AsyncHttpLoader {
+    async fn fetch(&self, url: &Url) -> Result<Vec<u8>, LoadError> {
+        match url.scheme() {
+            "http" | "https" => {
+                let response = self
+                    .client
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(LoadError::Http)?;
+                Ok(response.bytes().await.map_err(LoadError::Http)?.to_vec())
+            }
+            #[cfg(feature = "fs")]
+            "file" => AsyncFileLoader.fetch(url).await,
+            scheme => Err(LoadError::UnsupportedScheme(scheme.to_owned())),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use crate::loader::*;
+
+    #[test]
+    fn test_file_loader() {
+        let url = file_url("testpages/style/rainbow.css").unwrap();
+        assert_eq!(url.scheme(), "file");
+        assert!(url.path().ends_with("/testpages/style/rainbow.css"));
+
+        let data = FileLoader.fetch(&url).unwrap();
+        assert_eq!(data, std::fs::read("testpages/style/rainbow.css").unwrap());
+
+        assert!(matches!(
+            FileLoader.fetch(&url.join("missing.css").unwrap()),
+            Err(LoadError::Io(_))
+        ));
+        let http = Url::parse("http://example.com/a.css").unwrap();
+        assert!(matches!(
+            FileLoader.fetch(&http),
+            Err(LoadError::UnsupportedScheme(scheme)) if scheme == "http"
+        ));
+    }
+}
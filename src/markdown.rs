@@ -0,0 +1,566 @@
+//! Markdown documents, converted into the same nodes as the HTML that they stand for, so that
+//! README-style documents can be rendered without writing HTML. The common parts of CommonMark
+//! are supported: headings, paragraphs, block quotes, lists, code blocks, thematic breaks,
+//! emphasis, code spans, links, images and hard line breaks. HTML in the text is left as text.
+//!
+//! https://spec.commonmark.org/0.31.2/
+
+use std::collections::HashMap;
+
+use crate::css::Sheet;
+use crate::dom::{elem, text, Node};
+
+/// A stylesheet for documents from Markdown, with the margins and the fonts of a README.
+pub fn stylesheet() -> Sheet {
+    Sheet::from(include_str!("markdown.css"))
+}
+
+impl Node {
+    /// Convert a Markdown document into an `html` element with the blocks in its `body`.
+    pub fn from_markdown(markdown: &str) -> Node {
+        let lines: Vec<String> = markdown.lines().map(expand_tabs).collect();
+        let body = elem("body").add_children(parse_blocks(&lines));
+        elem("html").add_child(body)
+    }
+}
+
+/// A line with the tabs in its indentation replaced by spaces, up to the next multiple of four.
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = line.chars();
+    for c in chars.by_ref() {
+        match c {
+            '\t' => expanded.push_str(&" ".repeat(4 - expanded.len() % 4)),
+            ' ' => expanded.push(' '),
+            c => {
+                expanded.push(c);
+                break;
+            }
+        }
+    }
+    expanded.extend(chars);
+    expanded
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// The level and the text of an ATX heading, like `## Usage`.
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let rest = rest.trim();
+    // A closing sequence of `#` is left out when there is a space before it.
+    let closed = rest.trim_end_matches('#');
+    let text = match closed.is_empty() || closed.ends_with(' ') {
+        true => closed.trim_end(),
+        false => rest,
+    };
+    Some((level, text))
+}
+
+/// Whether a line is a thematic break: three or more of the same `*`, `-` or `_`, with only
+/// spaces between them.
+fn is_thematic_break(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| *c != ' ').collect();
+    marks.len() >= 3
+        && ["*", "-", "_"]
+            .iter()
+            .any(|mark| marks.chars().all(|c| c.to_string() == *mark))
+}
+
+/// The fence that opens a fenced code block, like ```` ``` ```` or `~~~`, and its info string.
+fn code_fence(line: &str) -> Option<(&str, &str)> {
+    let mark = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(mark).len();
+    let info = line[length..].trim();
+    if length < 3 || (mark == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((&line[..length], info))
+}
+
+/// The marker of a list item: the number of an ordered item, the character that ends the marker,
+/// and the width of the marker with the spaces after it, which the lines of the item are
+/// indented by.
+fn list_marker(line: &str) -> Option<(Option<u32>, char, usize)> {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (number, mark) = match line[digits..].chars().next()? {
+        mark @ ('-' | '*' | '+') if digits == 0 => (None, mark),
+        mark @ ('.' | ')') if (1..=9).contains(&digits) => {
+            (Some(line[..digits].parse().ok()?), mark)
+        }
+        _ => return None,
+    };
+    let rest = &line[digits + 1..];
+    let spaces = indent(rest);
+    match (rest.is_empty(), spaces) {
+        (true, _) => Some((number, mark, digits + 2)),
+        (false, 1..=4) => Some((number, mark, digits + 1 + spaces)),
+        (false, _) if spaces > 4 => Some((number, mark, digits + 2)),
+        _ => None,
+    }
+}
+
+/// Whether a line starts a block that interrupts a paragraph.
+fn interrupts_paragraph(line: &str) -> bool {
+    let line = line.trim_start();
+    atx_heading(line).is_some()
+        || is_thematic_break(line)
+        || code_fence(line).is_some()
+        || line.starts_with('>')
+        || matches!(list_marker(line), Some((number, _, width))
+            if matches!(number, None | Some(1)) && !is_blank(line.get(width..).unwrap_or("")))
+}
+
+fn parse_blocks(lines: &[String]) -> Vec<Node> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].as_str();
+        let trimmed = line.trim_start();
+        if is_blank(line) {
+            i += 1;
+        } else if indent(line) >= 4 {
+            let start = i;
+            while i < lines.len() && (is_blank(&lines[i]) || indent(&lines[i]) >= 4) {
+                i += 1;
+            }
+            while i > start && is_blank(&lines[i - 1]) {
+                i -= 1;
+            }
+            let code = lines[start..i]
+                .iter()
+                .map(|line| line.get(4..).unwrap_or(""));
+            blocks.push(code_block(code.collect::<Vec<_>>().join("\n"), ""));
+        } else if let Some((level, heading)) = atx_heading(trimmed) {
+            blocks.push(elem(&format!("h{level}")).add_children(parse_inline(heading)));
+            i += 1;
+        } else if is_thematic_break(trimmed) {
+            blocks.push(elem("hr"));
+            i += 1;
+        } else if let Some((fence, info)) = code_fence(trimmed) {
+            let fence_indent = indent(line);
+            let mut code = Vec::new();
+            i += 1;
+            while i < lines.len() {
+                let line = lines[i].trim_start();
+                if line.starts_with(fence) && line.trim_start_matches(&fence[..1]).trim().is_empty()
+                {
+                    i += 1;
+                    break;
+                }
+                let strip = indent(&lines[i]).min(fence_indent);
+                code.push(&lines[i][strip..]);
+                i += 1;
+            }
+            let language = info.split(' ').next().unwrap_or("");
+            blocks.push(code_block(code.join("\n"), language));
+        } else if trimmed.starts_with('>') {
+            let mut quoted = Vec::new();
+            while i < lines.len() && !is_blank(&lines[i]) {
+                let line = lines[i].trim_start();
+                match line.strip_prefix('>') {
+                    Some(line) => quoted.push(expand_tabs(line.strip_prefix(' ').unwrap_or(line))),
+                    // Lines of a paragraph in the quote may leave out the `>`.
+                    None if !interrupts_paragraph(line) => quoted.push(line.to_owned()),
+                    None => break,
+                }
+                i += 1;
+            }
+            blocks.push(elem("blockquote").add_children(parse_blocks(&quoted)));
+        } else if let Some(marker) = list_marker(trimmed) {
+            i = parse_list(lines, i, marker, &mut blocks);
+        } else {
+            let mut paragraph = vec![trimmed];
+            i += 1;
+            let mut level = None;
+            while i < lines.len() && !is_blank(&lines[i]) {
+                let line = lines[i].trim();
+                // A line of `=` or `-` under a paragraph makes it a heading.
+                if indent(&lines[i]) < 4 && !line.is_empty() {
+                    if line.chars().all(|c| c == '=') {
+                        level = Some(1);
+                    } else if line.chars().all(|c| c == '-') {
+                        level = Some(2);
+                    }
+                }
+                if level.is_some() {
+                    i += 1;
+                    break;
+                }
+                if interrupts_paragraph(&lines[i]) {
+                    break;
+                }
+                paragraph.push(lines[i].trim_start());
+                i += 1;
+            }
+            let tag = match level {
+                Some(level) => format!("h{level}"),
+                None => "p".to_owned(),
+            };
+            let inline = parse_inline(paragraph.join("\n").trim_end());
+            blocks.push(elem(&tag).add_children(inline));
+        }
+    }
+    blocks
+}
+
+/// Parse the items of a list that starts at a line, and return the line after it.
+fn parse_list(
+    lines: &[String],
+    mut i: usize,
+    (start, mark, _): (Option<u32>, char, usize),
+    blocks: &mut Vec<Node>,
+) -> usize {
+    let mut items = Vec::new();
+    let mut loose = false;
+    let mut blank_before = false;
+    while i < lines.len() {
+        let line = &lines[i];
+        let offset = indent(line);
+        let width = match list_marker(line.trim_start()) {
+            Some((number, item_mark, width))
+                if item_mark == mark && number.is_some() == start.is_some() && offset < 4 =>
+            {
+                offset + width
+            }
+            _ => break,
+        };
+        // A blank line between items makes a list loose.
+        loose |= blank_before;
+
+        let mut item = vec![line.get(width..).unwrap_or("").to_owned()];
+        i += 1;
+        while i < lines.len() {
+            let line = &lines[i];
+            if is_blank(line) {
+                item.push(String::new());
+            } else if indent(line) >= width {
+                item.push(line[width..].to_owned());
+            } else if !is_blank(item.last().unwrap())
+                && !interrupts_paragraph(line)
+                && list_marker(line.trim_start()).is_none()
+            {
+                // Lines of a paragraph in the item may leave out the indentation.
+                item.push(line.trim_start().to_owned());
+            } else {
+                break;
+            }
+            i += 1;
+        }
+
+        // So does a blank line between the blocks of an item.
+        let trailing = item.iter().rev().take_while(|line| is_blank(line)).count();
+        item.truncate(item.len() - trailing);
+        blank_before = trailing > 0;
+        loose |= item
+            .windows(2)
+            .any(|pair| is_blank(&pair[0]) && !is_blank(&pair[1]));
+        items.push(parse_blocks(&item));
+    }
+
+    let mut list = match start {
+        Some(1) => elem("ol"),
+        Some(start) => elem("ol").add_attr("start", &start.to_string()),
+        None => elem("ul"),
+    };
+    for item in items {
+        // The paragraphs of a tight list are shown without their margins, as just their text.
+        let children = item.into_iter().flat_map(|block| match block {
+            Node::Element { tag, children, .. } if !loose && &*tag == "p" => children,
+            block => vec![block],
+        });
+        list = list.add_child(elem("li").add_children(children.collect()));
+    }
+    blocks.push(list);
+    i
+}
+
+fn code_block(code: String, language: &str) -> Node {
+    let code_element = match language {
+        "" => elem("code"),
+        language => elem("code").add_attr("class", &format!("language-{language}")),
+    };
+    elem("pre").add_child(code_element.add_child(text(&code)))
+}
+
+/// Parse the inline content of a block: text with emphasis, code spans, links and images.
+fn parse_inline(source: &str) -> Vec<Node> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut closers = HashMap::new();
+    let mut nodes = Vec::new();
+    let mut buffer = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (node, next) = match chars[i] {
+            '\\' if chars.get(i + 1) == Some(&'\n') => (Some(elem("br")), i + 2),
+            '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                buffer.push(chars[i + 1]);
+                (None, i + 2)
+            }
+            ' ' if chars[i..].starts_with(&[' ', ' ', '\n']) => {
+                let spaces = chars[i..].iter().take_while(|c| **c == ' ').count();
+                match chars.get(i + spaces) {
+                    Some('\n') => (Some(elem("br")), i + spaces + 1),
+                    _ => {
+                        buffer.push(' ');
+                        (None, i + 1)
+                    }
+                }
+            }
+            '`' => code_span(&chars, i),
+            '*' | '_' => emphasis(&chars, i, &mut closers),
+            '!' if chars.get(i + 1) == Some(&'[') => match link(&chars, i + 1) {
+                Some((label, destination, next)) => {
+                    let alt: String = label.iter().collect();
+                    let image = elem("img").add_attr("src", &destination);
+                    (Some(image.add_attr("alt", &alt)), next)
+                }
+                None => (None, i),
+            },
+            '[' => match link(&chars, i) {
+                Some((label, destination, next)) => {
+                    let label: String = label.iter().collect();
+                    let anchor = elem("a").add_attr("href", &destination);
+                    (Some(anchor.add_children(parse_inline(&label))), next)
+                }
+                None => (None, i),
+            },
+            '<' => autolink(&chars, i),
+            _ => (None, i),
+        };
+        match node {
+            Some(node) => {
+                if !buffer.is_empty() {
+                    nodes.push(text(&buffer));
+                    buffer.clear();
+                }
+                nodes.push(node);
+            }
+            None if next == i => buffer.push(chars[i]),
+            None => {}
+        }
+        i = next.max(i + 1);
+    }
+    if !buffer.is_empty() {
+        nodes.push(text(&buffer));
+    }
+    nodes
+}
+
+/// A code span that starts at a run of backticks, and ends at a run of the same length.
+fn code_span(chars: &[char], start: usize) -> (Option<Node>, usize) {
+    let length = chars[start..].iter().take_while(|c| **c == '`').count();
+    let mut i = start + length;
+    while i < chars.len() {
+        let run = chars[i..].iter().take_while(|c| **c == '`').count();
+        if run == length {
+            let code: String = chars[start + length..i].iter().collect();
+            let code = code.replace('\n', " ");
+            // One space on both sides is left out, so that code can start with a backtick.
+            let code = match code.len() > 2 && code.starts_with(' ') && code.ends_with(' ') {
+                true => &code[1..code.len() - 1],
+                false => &code,
+            };
+            return (Some(elem("code").add_text(code)), i + length);
+        }
+        i += run.max(1);
+    }
+    // Without a closing run, the backticks are text.
+    let mut buffer = String::new();
+    buffer.extend(&chars[start..start + length]);
+    (Some(text(&buffer)), start + length)
+}
+
+/// Emphasis, strong emphasis with a double delimiter, or both with a triple one, that starts at
+/// a `*` or a `_` and ends at a run of at least as many of the same delimiter.
+fn emphasis(
+    chars: &[char],
+    start: usize,
+    closers: &mut HashMap<usize, Option<(usize, usize)>>,
+) -> (Option<Node>, usize) {
+    let (length, end) = match emphasis_closer(chars, start, closers) {
+        Some(closer) => closer,
+        None => return (None, start),
+    };
+    let inner: String = chars[start + length..end].iter().collect();
+    let inner = parse_inline(&inner);
+    let node = match length {
+        1 => elem("em").add_children(inner),
+        2 => elem("strong").add_children(inner),
+        _ => elem("em").add_child(elem("strong").add_children(inner)),
+    };
+    (Some(node), end + length)
+}
+
+/// The length of the delimiter of the emphasis that starts at `start`, and where its closing run
+/// is, or `None` if it doesn't close. Emphasis that opens in between is skipped, so that it can
+/// nest.
+///
+/// The result for every start is remembered in `closers`, because each opener before an
+/// unclosed one would scan past it again, which takes exponential time in the number of them.
+fn emphasis_closer(
+    chars: &[char],
+    start: usize,
+    closers: &mut HashMap<usize, Option<(usize, usize)>>,
+) -> Option<(usize, usize)> {
+    if let Some(closer) = closers.get(&start) {
+        return *closer;
+    }
+
+    let mark = chars[start];
+    let run = chars[start..].iter().take_while(|c| **c == mark).count();
+    let length = run.min(3);
+    let opens = chars.get(start + run).is_some_and(|c| !c.is_whitespace());
+    // Underscores within words are text, like in snake_case.
+    let intraword = mark == '_' && start > 0 && chars[start - 1].is_alphanumeric();
+
+    let mut closer = None;
+    let mut i = start + length + 1;
+    while opens && !intraword && i < chars.len() {
+        match chars[i] {
+            c if c == mark => {
+                let run = chars[i..].iter().take_while(|c| **c == mark).count();
+                let closes = run >= length
+                    && !chars[i - 1].is_whitespace()
+                    && !(mark == '_' && chars.get(i + run).is_some_and(|c| c.is_alphanumeric()));
+                if closes {
+                    closer = Some((length, i));
+                    break;
+                }
+                i = match emphasis_closer(chars, i, closers) {
+                    Some((length, end)) => end + length,
+                    None => i + run,
+                };
+            }
+            '`' => i = code_span(chars, i).1,
+            _ => i += 1,
+        }
+    }
+    closers.insert(start, closer);
+    closer
+}
+
+/// A link like `[label](destination "title")` that starts at a `[`, with its label, its
+/// destination and the position after it. Titles are left out.
+fn link(chars: &[char], start: usize) -> Option<(&[char], String, usize)> {
+    let mut depth = 0;
+    let mut end = None;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let end = end?;
+    if chars.get(end + 1) != Some(&'(') {
+        return None;
+    }
+    let close = end + 2 + chars[end + 2..].iter().position(|c| *c == ')')?;
+    let inside: String = chars[end + 2..close].iter().collect();
+    let destination = inside.split_whitespace().next().unwrap_or("");
+    let destination = destination.trim_start_matches('<').trim_end_matches('>');
+    Some((&chars[start + 1..end], destination.to_owned(), close + 1))
+}
+
+/// An autolink like `<https://example.com>` or `<name@example.com>`.
+fn autolink(chars: &[char], start: usize) -> (Option<Node>, usize) {
+    let end = match chars[start..].iter().position(|c| *c == '>') {
+        Some(end) => start + end,
+        None => return (None, start),
+    };
+    let address: String = chars[start + 1..end].iter().collect();
+    if address.contains(char::is_whitespace) {
+        return (None, start);
+    }
+    let href = match (address.contains("://"), address.contains('@')) {
+        (true, _) => address.clone(),
+        (false, true) => format!("mailto:{address}"),
+        (false, false) => return (None, start),
+    };
+    let anchor = elem("a").add_attr("href", &href).add_text(&address);
+    (Some(anchor), end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::*;
+
+    #[test]
+    fn test_from_markdown() {
+        let markdown = "\
+# boxrs *a **toy** engine*
+
+A **browser** engine, with `code`
+and [a link](https://example.com \"title\").
+
+Setext
+------
+
+- one
+- two with snake_case
+
+3. three
+4. four
+
+> quoted
+lazily
+
+```rust
+fn main() {}
+```
+
+***
+    indented  \n";
+
+        let expected = Node::from(
+            "<html><body>\
+            <h1>boxrs <em>a <strong>toy</strong> engine</em></h1>\
+            <p>A <strong>browser</strong> engine, with <code>code</code>\n\
+            and <a href=\"https://example.com\">a link</a>.</p>\
+            <h2>Setext</h2>\
+            <ul><li>one</li><li>two with snake_case</li></ul>\
+            <ol start=\"3\"><li>three</li><li>four</li></ol>\
+            <blockquote><p>quoted\nlazily</p></blockquote>\
+            <pre><code class=\"language-rust\">fn main() {}</code></pre>\
+            <hr></hr>\
+            <pre><code>indented  </code></pre>\
+            </body></html>",
+        );
+        assert_eq!(
+            Node::from_markdown(markdown).to_string(),
+            expected.to_string()
+        );
+        assert!(!crate::markdown::stylesheet().rules.is_empty());
+    }
+
+    #[test]
+    fn test_unclosed_emphasis() {
+        // Every delimiter is scanned past once, instead of once for every opener before it.
+        let markdown = "*a _b ".repeat(500);
+        let expected = Node::from(format!(
+            "<html><body><p>{}</p></body></html>",
+            markdown.trim()
+        ));
+        assert_eq!(
+            Node::from_markdown(&markdown).to_string(),
+            expected.to_string()
+        );
+    }
+}
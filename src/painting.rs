@@ -1,97 +1,1965 @@
-use crate::css::{Color, Value};
-use crate::layout::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect};
+use std::slice;
 
-#[derive(Debug)]
+use crate::css::{Color, Unit, Value};
+use crate::images::ImageHandle;
+use crate::layout::{
+    AnonymousBlock, BlockNode, CornerRadii, Font, Glyph, GridNode, InlineNode, LayoutBox,
+    MarkerBox, Rect, TextRun,
+};
+use crate::style::{BorderStyle, Display, Overflow, Position, StyledNode, Transform};
+
+pub use self::diff::{diff, DirtyRegions};
+pub use self::visitor::{visit, DisplayListVisitor};
+
+pub mod ansi;
+pub mod atlas;
+pub mod batch;
+mod diff;
+#[cfg(feature = "gl")]
+pub mod gpu;
+mod overlay;
+pub mod raster;
+#[cfg(feature = "tiny-skia")]
+pub mod skia;
+mod visitor;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+
+/// A drawing command, or a change to the state that the commands after it are drawn in.
+///
+/// More kinds of commands may be added, so backends outside of boxrs match them with a wildcard
+/// arm, or implement [`DisplayListVisitor`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
+    /// Fill a rectangle with rounded corners.
+    RoundedRect(Color, Rect, CornerRadii),
+    /// Clip the following commands to a rectangle, within the current clip, until the matching
+    /// `PopClip`.
+    PushClip(Rect),
+    /// Clip the following commands to a rectangle with rounded corners, within the current clip,
+    /// until the matching `PopClip`.
+    PushRoundedClip(Rect, CornerRadii),
+    /// Clip the following commands to outside of a rectangle with rounded corners, within the
+    /// current clip, until the matching `PopClip`.
+    PushRoundedClipOut(Rect, CornerRadii),
+    PopClip,
+    /// Draw a run of glyphs, placed to the right of `origin` on its baseline.
+    Text {
+        glyphs: Vec<Glyph>,
+        origin: (f32, f32),
+        color: Color,
+        font: Font,
+    },
+    /// Draw an image, scaled to fill a rectangle.
+    Image {
+        image: ImageHandle,
+        rect: Rect,
+    },
+    /// Transform the following commands, on top of the current transformation, until the matching
+    /// `PopTransform`.
+    PushTransform(Transform),
+    PopTransform,
+    /// Paint the following commands into a separate transparent layer, until the matching
+    /// `PopLayer`. The layer is then composited onto what is below it with the given opacity, so
+    /// overlapping content within the group doesn't show through itself.
+    PushOpacityLayer(f32),
+    PopLayer,
+}
+
+impl DisplayCommand {
+    /// The area that a drawing command paints, before any transformation, or `None` for the
+    /// commands that change the state of the following commands.
+    pub fn bounds(&self) -> Option<Rect> {
+        match self {
+            DisplayCommand::SolidColor(_, rect)
+            | DisplayCommand::RoundedRect(_, rect, _)
+            | DisplayCommand::Image { rect, .. } => Some(*rect),
+            // Glyphs may reach up to the font size above the baseline, and below it.
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                font,
+                ..
+            } => {
+                let start = glyphs.first().map_or(0.0, |glyph| glyph.x);
+                let end = glyphs.last().map_or(0.0, |glyph| glyph.x + glyph.advance);
+                Some(Rect {
+                    x: origin.0 + start,
+                    y: origin.1 - font.size,
+                    width: end - start,
+                    height: 2.0 * font.size,
+                })
+            }
+            DisplayCommand::PushClip(_)
+            | DisplayCommand::PushRoundedClip(..)
+            | DisplayCommand::PushRoundedClipOut(..)
+            | DisplayCommand::PopClip
+            | DisplayCommand::PushTransform(_)
+            | DisplayCommand::PopTransform
+            | DisplayCommand::PushOpacityLayer(_)
+            | DisplayCommand::PopLayer => None,
+        }
+    }
 }
 
 pub type DisplayList = Vec<DisplayCommand>;
 
+/// A display list as JSON, for tools outside of boxrs. Each command is an object with the name of
+/// its variant as its only key, and fields are named like those of the types they come from.
+#[cfg(feature = "serde")]
+pub fn to_json(list: &[DisplayCommand]) -> String {
+    serde_json::to_string(list).expect("display lists serialize to JSON")
+}
+
 pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+    build_display_list_with_options(layout_root, &PaintOptions::default())
+}
+
+/// How a display list is built, beyond painting the boxes as they are styled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PaintOptions {
+    /// Paint translucent outlines around the margin, border, padding and content areas of every
+    /// box on top of the page, with labels with the tag names of the elements, to inspect the
+    /// layout in any backend.
+    pub debug_overlay: bool,
+}
+
+pub fn build_display_list_with_options(
+    layout_root: &LayoutBox,
+    options: &PaintOptions,
+) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    render_stacking_context(&mut list, layout_root, &mut None);
+    if options.debug_overlay {
+        overlay::render_debug_overlay(&mut list, layout_root);
+    }
     list
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
+/// Paint a layout tree like [`build_display_list`], but pass each drawing command to `f` as soon
+/// as it is painted, for backends that draw commands right away. Only the inline content of a
+/// box is held back, until the block-level boxes that it is painted on top of are done.
+pub fn for_each_command(layout_root: &LayoutBox, f: impl FnMut(DisplayCommand)) {
+    render_stacking_context(&mut ForEach(f), layout_root, &mut None);
+}
+
+/// Where drawing commands go as they are painted: a display list, or a function that takes them
+/// one by one.
+trait Sink {
+    fn push(&mut self, command: DisplayCommand);
+
+    fn push_all(&mut self, commands: impl IntoIterator<Item = DisplayCommand>) {
+        for command in commands {
+            self.push(command);
+        }
+    }
+}
+
+impl Sink for DisplayList {
+    fn push(&mut self, command: DisplayCommand) {
+        Vec::push(self, command);
+    }
+
+    fn push_all(&mut self, commands: impl IntoIterator<Item = DisplayCommand>) {
+        Vec::extend(self, commands);
+    }
+}
+
+struct ForEach<F>(F);
+
+impl<F: FnMut(DisplayCommand)> Sink for ForEach<F> {
+    fn push(&mut self, command: DisplayCommand) {
+        (self.0)(command);
+    }
+}
+
+/// A part of the page that is rasterized on its own, so that it can be moved around by
+/// compositing it again, without painting it again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaintLayer {
+    /// The content of the layer, in page coordinates before scrolling.
+    pub list: DisplayList,
+    /// The rectangle that the layer is visible in, if it is clipped.
+    pub clip: Option<Rect>,
+    /// The `id` of the scroll container that the layer holds the content of, if it has one.
+    pub id: Option<String>,
+    /// How far the content of the layer is scrolled to the right and down.
+    pub scroll_offset: (f32, f32),
+}
+
+/// The paint layers that are being split off while building a display list, or `None` to paint
+/// everything in the same list.
+type Layers = Option<Vec<PaintLayer>>;
+
+/// Build the display lists of the paint layers of a page, to be composited in order. The first
+/// layer holds the root, and each scroll container puts its content in a layer of its own.
+///
+/// TODO: Composite content that comes after a scroll container on top of its layer.
+pub fn build_paint_layers(layout_root: &LayoutBox) -> Vec<PaintLayer> {
+    let mut layers = Some(Vec::new());
+    let mut list = Vec::new();
+    render_stacking_context(&mut list, layout_root, &mut layers);
+
+    let root = PaintLayer {
+        list,
+        clip: None,
+        id: None,
+        scroll_offset: (0.0, 0.0),
+    };
+    std::iter::once(root).chain(layers.unwrap()).collect()
+}
+
+/// Combine paint layers into one display list, in order. The content of each layer is moved by
+/// its scroll offset, within its clip rectangle.
+pub fn flatten_paint_layers(layers: &[PaintLayer]) -> DisplayList {
+    let mut list = Vec::new();
+    for layer in layers.iter().filter(|layer| !layer.list.is_empty()) {
+        if let Some(clip) = layer.clip {
+            list.push(DisplayCommand::PushClip(clip));
+        }
+        let (x, y) = layer.scroll_offset;
+        let scrolled = (x, y) != (0.0, 0.0);
+        if scrolled {
+            list.push(DisplayCommand::PushTransform(Transform::translate(-x, -y)));
+        }
+
+        list.extend(layer.list.iter().cloned());
+
+        if scrolled {
+            list.push(DisplayCommand::PopTransform);
+        }
+        if layer.clip.is_some() {
+            list.push(DisplayCommand::PopClip);
+        }
+    }
+    list
+}
+
+/// Build a display list with only the drawing commands that may paint inside the visible
+/// rectangle, after their transformations and clips are applied. Without a visible rectangle,
+/// nothing is left out.
+pub fn build_visible_display_list(layout_root: &LayoutBox, visible: Option<Rect>) -> DisplayList {
+    let list = build_display_list(layout_root);
+    match visible {
+        Some(visible) => cull(list, visible),
+        None => list,
+    }
+}
+
+/// Remove the drawing commands that paint entirely outside the visible rectangle. Commands that
+/// change state are kept, so that every push still has its matching pop.
+fn cull(list: DisplayList, visible: Rect) -> DisplayList {
+    // The part of the visible rectangle that the current clip leaves, in page coordinates.
+    let mut clips = vec![visible];
+    let mut transforms = vec![Transform::identity()];
+
+    let mut culled = Vec::with_capacity(list.len());
+    for command in list {
+        let clip = *clips.last().unwrap();
+        let transform = *transforms.last().unwrap();
+
+        match &command {
+            DisplayCommand::PushClip(rect) | DisplayCommand::PushRoundedClip(rect, _) => {
+                clips.push(clip.intersect(rect.transformed(&transform)));
+            }
+            // Clipping out can leave anything visible around the rectangle.
+            DisplayCommand::PushRoundedClipOut(..) => clips.push(clip),
+            DisplayCommand::PopClip => {
+                clips.pop();
+            }
+            DisplayCommand::PushTransform(t) => transforms.push(transform.multiply(*t)),
+            DisplayCommand::PopTransform => {
+                transforms.pop();
+            }
+            _ => {}
+        }
+
+        let is_visible = command.bounds().is_none_or(|bounds| {
+            let area = clip.intersect(bounds.transformed(&transform));
+            area.width > 0.0 && area.height > 0.0
+        });
+        if is_visible {
+            culled.push(command);
+        }
+    }
+    culled
+}
+
+/// Build one display list for each page that the content breaks into, with pages of the given
+/// height. Each list is clipped to its page, with the top of the page at y = 0.
+///
+/// https://www.w3.org/TR/css-break-3/
+pub fn build_page_display_lists(layout_root: &LayoutBox, page_height: f32) -> Vec<DisplayList> {
+    let list = build_display_list(layout_root);
+    let root = layout_root.dimensions.margin_box();
+    let breaks = layout_root.page_breaks(page_height);
+
+    breaks
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = breaks.get(i + 1).map_or(start + page_height, |&next| next);
+            let page = Rect {
+                x: root.x,
+                y: 0.0,
+                width: root.width,
+                height: end - start,
+            };
+
+            let mut page_list = vec![DisplayCommand::PushClip(page)];
+            for command in &list {
+                match command {
+                    DisplayCommand::SolidColor(_, rect) if rect.y >= end => {}
+                    DisplayCommand::SolidColor(_, rect) if rect.y + rect.height <= start => {}
+                    command => page_list.push(translate_command(command, -start)),
+                }
+            }
+            page_list.push(DisplayCommand::PopClip);
+            page_list
+        })
+        .collect()
+}
+
+/// Snap the edges of every rectangle in a display list to device pixels, given the number of
+/// device pixels per CSS pixel. Edges that are shared by adjacent rectangles snap to the same
+/// device pixel, so no gaps appear between them, and rectangles that are not empty stay at least
+/// one device pixel wide and high.
+///
+/// Layout itself is not snapped, so it can keep computing with fractional lengths.
+pub fn snap_to_device_pixels(list: &mut DisplayList, device_pixel_ratio: f32) {
+    for command in list {
+        match command {
+            DisplayCommand::SolidColor(_, rect)
+            | DisplayCommand::RoundedRect(_, rect, _)
+            | DisplayCommand::PushClip(rect)
+            | DisplayCommand::PushRoundedClip(rect, _)
+            | DisplayCommand::PushRoundedClipOut(rect, _)
+            | DisplayCommand::Image { rect, .. } => {
+                *rect = snap_rect(*rect, device_pixel_ratio);
+            }
+            DisplayCommand::Text { origin, .. } => {
+                let snap = |x: f32| (x * device_pixel_ratio).round() / device_pixel_ratio;
+                *origin = (snap(origin.0), snap(origin.1));
+            }
+            DisplayCommand::PopClip
+            | DisplayCommand::PushTransform(_)
+            | DisplayCommand::PopTransform
+            | DisplayCommand::PushOpacityLayer(_)
+            | DisplayCommand::PopLayer => {}
+        }
+    }
+}
+
+/// Scale a display list from CSS pixels to device pixels, given the number of device pixels per
+/// CSS pixel. Text is set at the scaled font size, rather than magnified, so that it stays sharp.
+pub fn scale_display_list(list: &mut DisplayList, scale: f32) {
+    let scale_rect = |rect: &mut Rect| {
+        *rect = Rect {
+            x: rect.x * scale,
+            y: rect.y * scale,
+            width: rect.width * scale,
+            height: rect.height * scale,
+        }
+    };
+    let scale_radii = |radii: &mut CornerRadii| {
+        radii.top_left *= scale;
+        radii.top_right *= scale;
+        radii.bottom_right *= scale;
+        radii.bottom_left *= scale;
+    };
+
+    for command in list {
+        match command {
+            DisplayCommand::SolidColor(_, rect)
+            | DisplayCommand::PushClip(rect)
+            | DisplayCommand::Image { rect, .. } => scale_rect(rect),
+            DisplayCommand::RoundedRect(_, rect, radii)
+            | DisplayCommand::PushRoundedClip(rect, radii)
+            | DisplayCommand::PushRoundedClipOut(rect, radii) => {
+                scale_rect(rect);
+                scale_radii(radii);
+            }
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                font,
+                ..
+            } => {
+                *origin = (origin.0 * scale, origin.1 * scale);
+                font.size *= scale;
+                for glyph in glyphs {
+                    glyph.x *= scale;
+                    glyph.advance *= scale;
+                }
+            }
+            // Transformations are around points on the page, which move with the scale.
+            DisplayCommand::PushTransform(transform) => {
+                transform.e *= scale;
+                transform.f *= scale;
+            }
+            DisplayCommand::PopClip
+            | DisplayCommand::PopTransform
+            | DisplayCommand::PushOpacityLayer(_)
+            | DisplayCommand::PopLayer => {}
+        }
+    }
+}
+
+fn snap_rect(rect: Rect, device_pixel_ratio: f32) -> Rect {
+    let snap = |edge: f32| (edge * device_pixel_ratio).round() / device_pixel_ratio;
+    let pixel = 1.0 / device_pixel_ratio;
+
+    let x = snap(rect.x);
+    let y = snap(rect.y);
+    let mut width = snap(rect.x + rect.width) - x;
+    let mut height = snap(rect.y + rect.height) - y;
+
+    // Keep thin borders visible.
+    if rect.width > 0.0 {
+        width = width.max(pixel);
+    }
+    if rect.height > 0.0 {
+        height = height.max(pixel);
+    }
+
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// Move a display command down by `dy`.
+fn translate_command(command: &DisplayCommand, dy: f32) -> DisplayCommand {
+    let translate = |rect: Rect| rect.translate(0.0, dy);
+
+    match command {
+        DisplayCommand::SolidColor(color, rect) => {
+            DisplayCommand::SolidColor(color.clone(), translate(*rect))
+        }
+        DisplayCommand::RoundedRect(color, rect, radii) => {
+            DisplayCommand::RoundedRect(color.clone(), translate(*rect), *radii)
+        }
+        DisplayCommand::PushClip(rect) => DisplayCommand::PushClip(translate(*rect)),
+        DisplayCommand::PushRoundedClip(rect, radii) => {
+            DisplayCommand::PushRoundedClip(translate(*rect), *radii)
+        }
+        DisplayCommand::PushRoundedClipOut(rect, radii) => {
+            DisplayCommand::PushRoundedClipOut(translate(*rect), *radii)
+        }
+        DisplayCommand::Text {
+            glyphs,
+            origin,
+            color,
+            font,
+        } => DisplayCommand::Text {
+            glyphs: glyphs.clone(),
+            origin: (origin.0, origin.1 + dy),
+            color: color.clone(),
+            font: font.clone(),
+        },
+        DisplayCommand::Image { image, rect } => DisplayCommand::Image {
+            image: *image,
+            rect: translate(*rect),
+        },
+        DisplayCommand::PopClip => DisplayCommand::PopClip,
+        // The transformed commands move as well, so the transformation moves along with them.
+        DisplayCommand::PushTransform(transform) => DisplayCommand::PushTransform(
+            Transform::translate(0.0, dy)
+                .multiply(*transform)
+                .multiply(Transform::translate(0.0, -dy)),
+        ),
+        DisplayCommand::PopTransform => DisplayCommand::PopTransform,
+        DisplayCommand::PushOpacityLayer(opacity) => DisplayCommand::PushOpacityLayer(*opacity),
+        DisplayCommand::PopLayer => DisplayCommand::PopLayer,
+    }
+}
+
+/// The boxes painted as part of a stacking context, apart from its in-flow content.
+///
+/// http://www.w3.org/TR/CSS2/zindex.html
+struct StackingContext<'a, 'b> {
+    /// Child stacking contexts with a negative stack level.
+    negative: Vec<(i32, Layer<'a, 'b>)>,
+    /// Positioned descendants with `z-index: auto` or `0`, in tree order.
+    zero: Vec<Layer<'a, 'b>>,
+    /// Child stacking contexts with a positive stack level.
+    positive: Vec<(i32, Layer<'a, 'b>)>,
+}
+
+/// A box that is painted separately from the flow it is in, and the clip rectangle that its
+/// ancestors within the stacking context apply to it.
+struct Layer<'a, 'b> {
+    layout_box: &'b LayoutBox<'a>,
+    clip: Option<Rect>,
+}
+
+impl<'a, 'b> StackingContext<'a, 'b> {
+    fn new(root: &'b LayoutBox<'a>) -> Self {
+        let mut context = StackingContext {
+            negative: Vec::new(),
+            zero: Vec::new(),
+            positive: Vec::new(),
+        };
+        context.collect_descendants(root, root.clip_rect(), root.clip_rect());
+
+        // Sorting is stable, so boxes with the same stack level stay in tree order.
+        context.negative.sort_by_key(|&(z_index, _)| z_index);
+        context.positive.sort_by_key(|&(z_index, _)| z_index);
+        context
+    }
+
+    /// Collect the positioned descendants of a box. `clip` applies to the descendants in the
+    /// normal flow, and `containing_clip` to the absolutely positioned descendants, which are only
+    /// clipped by the ancestors of their containing block.
+    fn collect_descendants(
+        &mut self,
+        layout_box: &'b LayoutBox<'a>,
+        clip: Option<Rect>,
+        containing_clip: Option<Rect>,
+    ) {
+        for child in &layout_box.children {
+            let position = child.position();
+            let layer = Layer {
+                layout_box: child,
+                clip: match position {
+                    Position::Absolute => containing_clip,
+                    Position::Static | Position::Relative => clip,
+                },
+            };
+
+            match z_index(child) {
+                Some(z_index) if z_index < 0 => self.negative.push((z_index, layer)),
+                Some(z_index) if z_index > 0 => self.positive.push((z_index, layer)),
+                Some(_) => self.zero.push(layer),
+                None => {
+                    // Positioned boxes with `z-index: auto` don't create a stacking context: their
+                    // positioned descendants belong to this one.
+                    let child_clip = intersect_clips(layer.clip, child.clip_rect());
+                    let child_containing_clip = match position {
+                        Position::Static => containing_clip,
+                        Position::Relative | Position::Absolute => child_clip,
+                    };
+
+                    if position != Position::Static {
+                        self.zero.push(layer);
+                    }
+
+                    self.collect_descendants(child, child_clip, child_containing_clip);
+                }
+            }
+        }
+    }
+}
+
+/// The in-flow content of a box, grouped by the pass of the painting order that paints it. Each
+/// group is painted after the one before it, so that inline content is on top of the backgrounds
+/// of all block-level boxes around it.
+///
+/// http://www.w3.org/TR/CSS2/zindex.html#painting-order
+struct FlowGroups<'l, S> {
+    /// The backgrounds and borders of the in-flow, non-positioned block-level boxes, which are
+    /// painted right away.
+    blocks: &'l mut S,
+    /// Text, list markers and inline-level boxes, which are painted as a whole after the blocks.
+    inline: DisplayList,
+}
+
+impl<'l, S: Sink> FlowGroups<'l, S> {
+    fn new(blocks: &'l mut S) -> Self {
+        FlowGroups {
+            blocks,
+            inline: Vec::new(),
+        }
+    }
+
+    /// Clip the following commands of both groups.
+    fn push_clip(&mut self, command: DisplayCommand) {
+        self.blocks.push(command.clone());
+        self.inline.push(command);
+    }
+
+    /// Close the clip of both groups. It is left out of the inline content if nothing was
+    /// painted in it, which is common for boxes without text.
+    fn pop_clip(&mut self) {
+        self.blocks.push(DisplayCommand::PopClip);
+        match self.inline.last() {
+            Some(DisplayCommand::PushClip(_) | DisplayCommand::PushRoundedClip(..)) => {
+                self.inline.pop();
+            }
+            _ => self.inline.push(DisplayCommand::PopClip),
+        }
+    }
+
+    /// Paint the inline content, after the block-level boxes.
+    fn finish(self) {
+        // TODO: Paint floats between the block-level boxes and the inline content, once they
+        // can be laid out.
+        self.blocks.push_all(self.inline);
+    }
+}
+
+/// Paint a stacking context and everything in it, in back-to-front order.
+fn render_stacking_context(list: &mut impl Sink, layout_box: &LayoutBox, layers: &mut Layers) {
+    let context = StackingContext::new(layout_box);
+
+    let transform = get_transform(layout_box);
+    if let Some(transform) = transform {
+        list.push(DisplayCommand::PushTransform(transform));
+    }
+
+    let opacity = get_opacity(layout_box);
+    if opacity < 1.0 {
+        list.push(DisplayCommand::PushOpacityLayer(opacity));
+    }
+
+    render_box(list, layout_box);
+
+    for (_, layer) in context.negative {
+        render_layer(list, layer, layers);
+    }
+
+    let mut groups = FlowGroups::new(list);
+    render_in_flow_children(&mut groups, layout_box, layers);
+    groups.finish();
+
+    for layer in context.zero {
+        render_layer(list, layer, layers);
+    }
+
+    for (_, layer) in context.positive {
+        render_layer(list, layer, layers);
+    }
+
+    if opacity < 1.0 {
+        list.push(DisplayCommand::PopLayer);
+    }
+
+    if transform.is_some() {
+        list.push(DisplayCommand::PopTransform);
+    }
+}
+
+/// Paint a positioned box within the clip rectangle of its layer.
+fn render_layer(list: &mut impl Sink, layer: Layer, layers: &mut Layers) {
+    if let Some(clip) = layer.clip {
+        list.push(DisplayCommand::PushClip(clip));
+    }
+
+    if z_index(layer.layout_box).is_some() {
+        render_stacking_context(list, layer.layout_box, layers);
+    } else {
+        // Its positioned descendants belong to the enclosing stacking context, but the rest is
+        // painted as if it was a stacking context of its own.
+        render_atomic(list, layer.layout_box, layers);
+    }
+
+    if layer.clip.is_some() {
+        list.push(DisplayCommand::PopClip);
+    }
+}
+
+/// Paint a box and its in-flow content as a whole, on top of what was painted before.
+fn render_atomic(list: &mut impl Sink, layout_box: &LayoutBox, layers: &mut Layers) {
+    render_box(list, layout_box);
+
+    let mut groups = FlowGroups::new(list);
+    render_in_flow_children(&mut groups, layout_box, layers);
+    groups.finish();
+}
+
+/// Paint the background, the borders and the replaced content of a box.
+fn render_box(list: &mut impl Sink, layout_box: &LayoutBox) {
     render_background(list, layout_box);
     render_borders(list, layout_box);
+    render_image(list, layout_box);
+    render_frame(list, layout_box);
+}
+
+/// Paint the descendants of a box that are in the normal flow, skipping positioned boxes which
+/// are painted in their own layer.
+fn render_in_flow_children(
+    groups: &mut FlowGroups<impl Sink>,
+    layout_box: &LayoutBox,
+    layers: &mut Layers,
+) {
+    // The content of a scroll container goes in a paint layer of its own, which is clipped when
+    // it is composited.
+    if let (Some(clip), Some(paint_layers)) = (layout_box.clip_rect(), layers.as_mut()) {
+        if matches!(layout_box.overflow(), Overflow::Scroll | Overflow::Auto) {
+            // The layer goes before the layers of nested scroll containers, which are on top.
+            let index = paint_layers.len();
+            paint_layers.push(PaintLayer {
+                list: Vec::new(),
+                clip: Some(clip),
+                id: element_id(layout_box),
+                scroll_offset: (0.0, 0.0),
+            });
+
+            let mut list = Vec::new();
+            let mut content = FlowGroups::new(&mut list);
+            render_children(&mut content, layout_box, layers);
+            content.finish();
+            if let Some(paint_layers) = layers.as_mut() {
+                paint_layers[index].list = list;
+            }
+            return;
+        }
+    }
+
+    // Boxes with rounded corners clip their content to the rounded padding box.
+    let clip = layout_box.clip_rect();
+    if let Some(clip) = clip {
+        let radii = layout_box.padding_radii();
+        if radii.is_zero() {
+            groups.push_clip(DisplayCommand::PushClip(clip));
+        } else {
+            groups.push_clip(DisplayCommand::PushRoundedClip(clip, radii));
+        }
+    }
+
+    render_children(groups, layout_box, layers);
+
+    if clip.is_some() {
+        groups.pop_clip();
+    }
+}
+
+fn render_children(
+    groups: &mut FlowGroups<impl Sink>,
+    layout_box: &LayoutBox,
+    layers: &mut Layers,
+) {
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        if let MarkerBox(style, _) = child.box_type {
+            render_text(&mut groups.inline, child, style);
+        } else if let TextRun(style, _) = child.box_type {
+            render_text(&mut groups.inline, child, style);
+            render_in_flow_children(groups, child, layers);
+        } else if child.position() != Position::Static || z_index(child).is_some() {
+            continue;
+        } else if is_inline_level(child) {
+            render_atomic(&mut groups.inline, child, layers);
+        } else {
+            render_box(groups.blocks, child);
+            render_in_flow_children(groups, child, layers);
+        }
     }
 }
 
-fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    if let Some(color) = get_color(layout_box, "background") {
-        list.push(DisplayCommand::SolidColor(
-            color,
-            layout_box.dimensions.border_box(),
-        ));
+/// Whether a box is painted with the inline content of its line, rather than with the block-level
+/// boxes.
+fn is_inline_level(layout_box: &LayoutBox) -> bool {
+    match layout_box.box_type {
+        InlineNode(_) => true,
+        BlockNode(style) => matches!(style.display(), Display::Inline | Display::InlineBlock),
+        _ => false,
+    }
+}
+
+fn render_background(list: &mut impl Sink, layout_box: &LayoutBox) {
+    if let Some(color) = get_color(layout_box, "background-color") {
+        let border_box = layout_box.dimensions.border_box();
+        let radii = layout_box.border_radii();
+        if radii.is_zero() {
+            list.push(DisplayCommand::SolidColor(color, border_box));
+        } else {
+            list.push(DisplayCommand::RoundedRect(color, border_box, radii));
+        }
     }
+
+    render_background_image(list, layout_box);
 }
 
-fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
+/// Paint the `background-image` of a box in its padding box, sized by `background-size`, placed
+/// by `background-position` and tiled by `background-repeat`.
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#backgrounds
+fn render_background_image(list: &mut impl Sink, layout_box: &LayoutBox) {
+    let (image, style) = match (layout_box.background_image(), &layout_box.box_type) {
+        (Some(image), BlockNode(style) | InlineNode(style) | GridNode(style)) => (image, *style),
         _ => return,
     };
 
-    let d = &layout_box.dimensions;
-    let border_box = d.border_box();
+    let area = layout_box.dimensions.padding_box();
+    let (width, height) = background_size(style, area, image.size());
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    let (x, y) = background_position(style, area, (width, height));
 
-    // Left border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
-
-    // Right border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
+    let (repeat_x, repeat_y) = match style.value("background-repeat") {
+        Some(Value::Keyword(keyword)) => match keyword.as_str() {
+            "repeat-x" => (true, false),
+            "repeat-y" => (false, true),
+            "no-repeat" => (false, false),
+            _ => (true, true),
         },
-    ));
-
-    // Top border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
-
-    // Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
+        _ => (true, true),
+    };
+
+    list.push(DisplayCommand::PushClip(area));
+    for tile_y in tiles(area.y, area.height, y, height, repeat_y) {
+        for tile_x in tiles(area.x, area.width, x, width, repeat_x) {
+            let rect = Rect {
+                x: tile_x,
+                y: tile_y,
+                width,
+                height,
+            };
+            list.push(DisplayCommand::Image { image, rect });
+        }
+    }
+    list.push(DisplayCommand::PopClip);
+}
+
+/// The size of a background image, from `background-size` and the natural size of the image.
+/// `auto` keeps the ratio of the image.
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#the-background-size
+fn background_size(style: &StyledNode, area: Rect, natural: (f32, f32)) -> (f32, f32) {
+    let (natural_width, natural_height) = natural;
+    let ratio = natural_width / natural_height;
+
+    let values = match style.value("background-size") {
+        Some(Value::Keyword(keyword)) if keyword == "cover" || keyword == "contain" => {
+            let scale_x = area.width / natural_width;
+            let scale_y = area.height / natural_height;
+            let scale = match keyword.as_str() {
+                "cover" => scale_x.max(scale_y),
+                _ => scale_x.min(scale_y),
+            };
+            return (natural_width * scale, natural_height * scale);
+        }
+        Some(Value::List(values)) => values.as_slice(),
+        Some(value) => slice::from_ref(value),
+        None => &[],
+    };
+
+    let resolve = |value: Option<&Value>, length: f32| match value {
+        Some(Value::Length(size, Unit::Px)) => Some(*size),
+        Some(Value::Length(percentage, Unit::Percent)) => Some(percentage / 100.0 * length),
+        _ => None,
+    };
+    match (
+        resolve(values.first(), area.width),
+        resolve(values.get(1), area.height),
+    ) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, width / ratio),
+        (None, Some(height)) => (height * ratio, height),
+        (None, None) => natural,
+    }
+}
+
+/// The offset of a background image from the top left of the area it is placed in, from
+/// `background-position`. Percentages and keywords align the same point of the image and the
+/// area, so `right` puts the image against the right edge.
+///
+/// https://www.w3.org/TR/css-backgrounds-3/#the-background-position
+fn background_position(style: &StyledNode, area: Rect, size: (f32, f32)) -> (f32, f32) {
+    let values = match style.value("background-position") {
+        Some(Value::List(values)) => values.as_slice(),
+        Some(value) => slice::from_ref(value),
+        None => &[],
+    };
+
+    let is_vertical =
+        |value: &Value| matches!(value, Value::Keyword(k) if k == "top" || k == "bottom");
+    let is_horizontal =
+        |value: &Value| matches!(value, Value::Keyword(k) if k == "left" || k == "right");
+    let center = Value::Keyword("center".to_owned());
+
+    // A single value leaves the other axis centered, and keywords may come in either order.
+    let (x, y) = match values {
+        [] => (None, None),
+        [value] if is_vertical(value) => (Some(&center), Some(value)),
+        [value] => (Some(value), Some(&center)),
+        [first, second, ..] if is_vertical(first) || is_horizontal(second) => {
+            (Some(second), Some(first))
+        }
+        [first, second, ..] => (Some(first), Some(second)),
+    };
+
+    let resolve = |value: Option<&Value>, free_space: f32| match value {
+        Some(Value::Length(offset, Unit::Px)) => *offset,
+        Some(Value::Length(percentage, Unit::Percent)) => percentage / 100.0 * free_space,
+        Some(Value::Keyword(keyword)) => match keyword.as_str() {
+            "center" => free_space / 2.0,
+            "right" | "bottom" => free_space,
+            _ => 0.0,
         },
-    ));
+        _ => 0.0,
+    };
+    (
+        resolve(x, area.width - size.0),
+        resolve(y, area.height - size.1),
+    )
+}
+
+/// Where the tiles of a background image start along one axis, given the start and the length of
+/// the area, the offset of the image in the area, and the size of a tile.
+fn tiles(start: f32, length: f32, offset: f32, size: f32, repeat: bool) -> Vec<f32> {
+    if !repeat {
+        return vec![start + offset];
+    }
+
+    // The first tile starts at or before the start of the area.
+    let first = start + offset - (offset / size).ceil() * size;
+    std::iter::successors(Some(first), |&tile| Some(tile + size))
+        .take_while(|&tile| tile < start + length)
+        .collect()
+}
+
+/// Paint the image of a replaced element, scaled to its content box.
+fn render_image(list: &mut impl Sink, layout_box: &LayoutBox) {
+    if let Some(image) = layout_box.image() {
+        list.push(DisplayCommand::Image {
+            image,
+            rect: layout_box.dimensions.content,
+        });
+    }
+}
+
+/// Paint the document in a frame at the top left of its content box, clipped to it.
+fn render_frame(list: &mut impl Sink, layout_box: &LayoutBox) {
+    if let Some(frame) = layout_box.frame() {
+        let content = layout_box.dimensions.content;
+        list.push(DisplayCommand::PushClip(content));
+        list.push(DisplayCommand::PushTransform(Transform::translate(
+            content.x, content.y,
+        )));
+        list.push_all(frame.iter().cloned());
+        list.push(DisplayCommand::PopTransform);
+        list.push(DisplayCommand::PopClip);
+    }
+}
+
+/// Paint the four sides of the border of a box, each in its `border-*-color`, or in
+/// `border-color` if that isn't set, or else in its `color`. The top and bottom borders cover the
+/// corners.
+///
+/// http://www.w3.org/TR/CSS2/box.html#border-color-properties
+fn render_borders(list: &mut impl Sink, layout_box: &LayoutBox) {
+    let style = match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) | GridNode(style) => style,
+        TextRun(..) | MarkerBox(..) | AnonymousBlock => return,
+    };
+
+    let d = &layout_box.dimensions;
+    let border_box = d.border_box();
+
+    let sides = [
+        (
+            "left",
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: d.border.left,
+                height: border_box.height,
+            },
+        ),
+        (
+            "right",
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y,
+                width: d.border.right,
+                height: border_box.height,
+            },
+        ),
+        (
+            "top",
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ),
+        (
+            "bottom",
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height - d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ),
+    ];
+
+    let mut commands = Vec::new();
+    let mut painted = Vec::new();
+    for (side, rect) in sides {
+        if rect.width <= 0.0 || rect.height <= 0.0 {
+            continue;
+        }
+        let color = style
+            .color(&format!("border-{}-color", side))
+            .unwrap_or_default();
+        let segments = border_segments(rect, style.border_style(side));
+        if !segments.is_empty() {
+            painted.push((side, color.clone()));
+        }
+        for segment in segments {
+            commands.push(DisplayCommand::SolidColor(color.clone(), segment));
+        }
+    }
+
+    // Rounded corners cut off the outer edge of the border, and round its inner edge with radii
+    // that are smaller by the border widths. Between the inner edge and the corners of the
+    // padding box, the corners are filled in by the top and bottom borders.
+    //
+    // https://www.w3.org/TR/css-backgrounds-3/#corner-shaping
+    let radii = layout_box.border_radii();
+    if radii.is_zero() || commands.is_empty() {
+        list.push_all(commands);
+        return;
+    }
+    let inner = layout_box.padding_radii();
+    list.push(DisplayCommand::PushRoundedClip(border_box, radii));
+    if inner.is_zero() {
+        list.push_all(commands);
+    } else {
+        let padding_box = d.padding_box();
+        let (left, top) = (padding_box.x, padding_box.y);
+        let right = padding_box.x + padding_box.width;
+        let bottom = padding_box.y + padding_box.height;
+        let corners = [
+            ("top", inner.top_left, left, top),
+            ("top", inner.top_right, right - inner.top_right, top),
+            (
+                "bottom",
+                inner.bottom_right,
+                right - inner.bottom_right,
+                bottom - inner.bottom_right,
+            ),
+            (
+                "bottom",
+                inner.bottom_left,
+                left,
+                bottom - inner.bottom_left,
+            ),
+        ];
+        for (side, radius, x, y) in corners {
+            let color = painted.iter().find(|(s, _)| *s == side);
+            if let (Some((_, color)), true) = (color, radius > 0.0) {
+                let corner = Rect {
+                    x,
+                    y,
+                    width: radius,
+                    height: radius,
+                };
+                commands.push(DisplayCommand::SolidColor(color.clone(), corner));
+            }
+        }
+        list.push(DisplayCommand::PushRoundedClipOut(padding_box, inner));
+        list.push_all(commands);
+        list.push(DisplayCommand::PopClip);
+    }
+    list.push(DisplayCommand::PopClip);
+}
+
+/// Split one side of a border into the rectangles that are painted for the given style.
+///
+/// Dashes are twice as long as the border is wide, and dots are squares. Both are separated by
+/// gaps as wide as the border. A double border is two lines of a third of the border width.
+fn border_segments(side: Rect, style: BorderStyle) -> Vec<Rect> {
+    let horizontal = side.width >= side.height;
+    let (length, thickness) = if horizontal {
+        (side.width, side.height)
+    } else {
+        (side.height, side.width)
+    };
+
+    // A piece of the side, from `start` along its length, and from `offset` across it.
+    let piece = |start: f32, length: f32, offset: f32, thickness: f32| {
+        if horizontal {
+            Rect {
+                x: side.x + start,
+                y: side.y + offset,
+                width: length,
+                height: thickness,
+            }
+        } else {
+            Rect {
+                x: side.x + offset,
+                y: side.y + start,
+                width: thickness,
+                height: length,
+            }
+        }
+    };
+
+    let dashes = |dash: f32| {
+        let mut pieces = Vec::new();
+        let mut start = 0.0;
+        while start < length {
+            pieces.push(piece(start, dash.min(length - start), 0.0, thickness));
+            start += dash + thickness;
+        }
+        pieces
+    };
+
+    match style {
+        BorderStyle::None => vec![],
+        BorderStyle::Solid => vec![side],
+        BorderStyle::Dashed => dashes(2.0 * thickness),
+        BorderStyle::Dotted => dashes(thickness),
+        BorderStyle::Double => {
+            let line = thickness / 3.0;
+            vec![
+                piece(0.0, length, 0.0, line),
+                piece(0.0, length, thickness - line, line),
+            ]
+        }
+    }
+}
+
+/// Paint the glyphs of a text run or a list item marker in its `color`.
+fn render_text(list: &mut impl Sink, layout_box: &LayoutBox, style: &StyledNode) {
+    if let Some(run) = layout_box.glyph_run() {
+        let content = layout_box.dimensions.content;
+        list.push(DisplayCommand::Text {
+            glyphs: run.glyphs.clone(),
+            origin: (content.x, content.y + run.baseline),
+            color: get_text_color(style),
+            font: run.font.clone(),
+        });
+    }
+}
+
+fn intersect_clips(a: Option<Rect>, b: Option<Rect>) -> Option<Rect> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersect(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// The `id` of the element that a box was generated for, if it has one.
+fn element_id(layout_box: &LayoutBox) -> Option<String> {
+    match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) | GridNode(style) => {
+            style.node.get_id().map(str::to_owned)
+        }
+        MarkerBox(..) | TextRun(..) | AnonymousBlock => None,
+    }
+}
+
+/// Return the stack level of a box that creates a stacking context, or None if it doesn't.
+/// Transformed and translucent boxes create a stacking context, with stack level 0 unless they
+/// are positioned.
+fn z_index(layout_box: &LayoutBox) -> Option<i32> {
+    match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) | GridNode(style) => {
+            match (style.position(), style.z_index()) {
+                (Position::Relative | Position::Absolute, Some(z_index)) => Some(z_index),
+                _ if style.transform().is_some() || style.opacity() < 1.0 => Some(0),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The opacity that a box and its content are composited with.
+fn get_opacity(layout_box: &LayoutBox) -> f32 {
+    match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) | GridNode(style) => style.opacity(),
+        TextRun(..) | MarkerBox(..) | AnonymousBlock => 1.0,
+    }
+}
+
+/// Return the transformation of a box in the coordinates of the page, which is its own
+/// transformation applied around the center of its border box.
+///
+/// https://www.w3.org/TR/css-transforms-1/#transform-origin-property
+fn get_transform(layout_box: &LayoutBox) -> Option<Transform> {
+    let transform = match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) | GridNode(style) => style.transform()?,
+        TextRun(..) | MarkerBox(..) | AnonymousBlock => return None,
+    };
+
+    let border_box = layout_box.dimensions.border_box();
+    let x = border_box.x + border_box.width / 2.0;
+    let y = border_box.y + border_box.height / 2.0;
+    Some(
+        Transform::translate(x, y)
+            .multiply(transform)
+            .multiply(Transform::translate(-x, -y)),
+    )
+}
+
+/// The `color` of a node, which is black if it isn't set.
+fn get_text_color(style: &StyledNode) -> Color {
+    style.color("color").unwrap_or_default()
 }
 
 /// Return the specified color for CSS property `name`, or None if no color was specified.
+/// `currentColor` is resolved to the `color` of the box.
 fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
     match layout_box.box_type {
-        BlockNode(style) | InlineNode(style) => match style.value(name) {
-            Some(Value::ColorValue(color)) => Some(color),
-            _ => None,
-        },
-        AnonymousBlock => None,
+        BlockNode(style) | InlineNode(style) | GridNode(style) => style.color(name),
+        TextRun(..) | MarkerBox(..) | AnonymousBlock => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::images::*;
+    use crate::layout::*;
+    use crate::painting::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_stacking_order() {
+        let document = Node::from("<a><b></b><c></c><d></d></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d {
+                display: block;
+                height: 10px;
+            }
+
+            a { background: #000000; }
+            b { position: absolute; z-index: 2; background: #ff0000; }
+            c { position: absolute; z-index: -1; background: #00ff00; }
+            d { position: relative; background: #0000ff; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+
+        let actual: Vec<_> = build_display_list(&layout_root)
+            .into_iter()
+            .filter_map(|command| match command {
+                DisplayCommand::SolidColor(color, _) => Some((color.r, color.g, color.b)),
+                _ => None,
+            })
+            .collect();
+
+        let expected = vec![(0, 0, 0), (0, 255, 0), (0, 0, 255), (255, 0, 0)];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_inline_content_order() {
+        let document = Node::from("<a><b>x</b><c></c></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; height: 10px; }
+            c { margin-top: -10px; background: #0000ff; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The text of b is painted on top of the background of c, which comes later in the tree.
+        assert_eq!(actual.len(), 2);
+        assert!(matches!(actual[0], DisplayCommand::SolidColor(..)));
+        assert!(matches!(actual[1], DisplayCommand::Text { .. }));
+    }
+
+    #[test]
+    fn test_for_each_command() {
+        let document = Node::from("<a><b>x</b><c></c><d>y</d></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d { display: block; height: 10px; background: #00ff00; }
+            a { overflow: hidden; opacity: 0.5; }
+            c { position: relative; z-index: -1; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let mut actual = Vec::new();
+        for_each_command(&layout_root, |command| actual.push(command));
+
+        assert_eq!(actual, build_display_list(&layout_root));
+        assert!(matches!(actual[0], DisplayCommand::PushOpacityLayer(..)));
+        assert!(matches!(actual.last(), Some(DisplayCommand::PopLayer)));
+    }
+
+    #[test]
+    fn test_border_colors() {
+        let document = Node::from("<a></a>");
+
+        let style = Sheet::from(
+            "
+            a {
+                display: block;
+                width: 10px;
+                height: 10px;
+                border-width: 2px;
+                border-right-width: 0;
+                border-color: #0000ff;
+                border-left-color: #ff0000;
+            }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The left side has its own color, and the right side has no width.
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            actual,
+            vec![
+                DisplayCommand::SolidColor(
+                    Color {
+                        r: 255,
+                        g: 0,
+                        b: 0,
+                        a: 255
+                    },
+                    rect(0.0, 0.0, 2.0, 14.0)
+                ),
+                DisplayCommand::SolidColor(blue.clone(), rect(0.0, 0.0, 12.0, 2.0)),
+                DisplayCommand::SolidColor(blue, rect(0.0, 12.0, 12.0, 2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_border_radius() {
+        let document = Node::from("<a></a>");
+
+        let style = Sheet::from(
+            "
+            a {
+                display: block;
+                width: 78px;
+                height: 23px;
+                background: #ff0000;
+                border-width: 1px;
+                border-color: #0000ff;
+                border-radius: 50% 10px;
+                border-bottom-left-radius: 0;
+            }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The radii on the right add up to 50px, which is too large for the height of 25px, so
+        // all radii are scaled down by half.
+        let radii = CornerRadii {
+            top_left: 20.0,
+            top_right: 5.0,
+            bottom_right: 20.0,
+            bottom_left: 0.0,
+        };
+        let border_box = layout_root.dimensions.border_box();
+        assert_eq!(
+            actual[0],
+            DisplayCommand::RoundedRect(
+                Color {
+                    r: 255,
+                    g: 0,
+                    b: 0,
+                    a: 255
+                },
+                border_box,
+                radii
+            )
+        );
+        assert_eq!(
+            actual[1],
+            DisplayCommand::PushRoundedClip(border_box, radii)
+        );
+        assert_eq!(actual.last(), Some(&DisplayCommand::PopClip));
+
+        // The inner edge of the border is rounded too, with radii that are 1px smaller.
+        let padding_box = layout_root.dimensions.padding_box();
+        assert_eq!(
+            actual[2],
+            DisplayCommand::PushRoundedClipOut(padding_box, layout_root.padding_radii())
+        );
+        assert_eq!(layout_root.padding_radii().top_left, 19.0);
+    }
+
+    #[test]
+    fn test_current_color() {
+        let document = Node::from("<a><b></b></a>");
+
+        let style = Sheet::from(
+            "
+            a, b { display: block; }
+            a { color: #ff0000; }
+            b { height: 10px; border-top-width: 1px; background: currentColor; color: currentColor; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // b inherits the color of a, which its background and border use.
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let colors: Vec<_> = actual
+            .iter()
+            .map(|command| match command {
+                DisplayCommand::SolidColor(color, _) => color.clone(),
+                _ => panic!("unexpected command {:?}", command),
+            })
+            .collect();
+        assert_eq!(colors, vec![red.clone(), red]);
+    }
+
+    #[test]
+    fn test_border_styles() {
+        let side = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 10.0,
+            height: 2.0,
+        };
+        let pieces = |style| {
+            border_segments(side, style)
+                .iter()
+                .map(|rect| (rect.x, rect.y, rect.width, rect.height))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            pieces(BorderStyle::Dashed),
+            vec![(0.0, 0.0, 4.0, 2.0), (6.0, 0.0, 4.0, 2.0)]
+        );
+        assert_eq!(pieces(BorderStyle::Dotted).len(), 3);
+        assert_eq!(pieces(BorderStyle::Dotted)[2], (8.0, 0.0, 2.0, 2.0));
+
+        let side = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 3.0,
+            height: 10.0,
+        };
+        assert_eq!(
+            border_segments(side, BorderStyle::Double)
+                .iter()
+                .map(|rect| (rect.x, rect.width))
+                .collect::<Vec<_>>(),
+            vec![(0.0, 1.0), (2.0, 1.0)]
+        );
+        assert!(border_segments(side, BorderStyle::None).is_empty());
+    }
+
+    #[test]
+    fn test_image() {
+        let document = Node::from("<a><img src=\"a.png\"></img></a>");
+        let style = Sheet::from("a { display: block; } img { display: block; width: 80px; }");
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut images = ImageCache::new();
+        let image = Image {
+            width: 40,
+            height: 20,
+            pixels: vec![0; 40 * 20 * 4],
+        };
+        let handle = images.insert("a.png", image);
+
+        let context = LayoutContext {
+            images: Some(&images),
+            ..LayoutContext::new(Rect {
+                x: 0.0,
+                y: 0.0,
+                width: 800.0,
+                height: 600.0,
+            })
+        };
+        let layout_root = layout_tree_with_context(&applied_styles, &context);
+        let actual = build_display_list(&layout_root);
+
+        // The image keeps its ratio when it is scaled to the width of the box.
+        assert_eq!(
+            actual,
+            vec![DisplayCommand::Image {
+                image: handle,
+                rect: Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 80.0,
+                    height: 40.0,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_background_image() {
+        let document = Node::from("<a><b></b><c></c></a>");
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; width: 100px; height: 50px; background-image: url(a.png); }
+            a { height: auto; background-image: none; }
+            b { background-repeat: repeat-x; background-position: center; }
+            c { background-repeat: no-repeat; background-size: cover; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut images = ImageCache::new();
+        let image = Image {
+            width: 40,
+            height: 20,
+            pixels: vec![0; 40 * 20 * 4],
+        };
+        let handle = images.insert("a.png", image);
+
+        let context = LayoutContext {
+            images: Some(&images),
+            ..LayoutContext::new(Rect {
+                x: 0.0,
+                y: 0.0,
+                width: 800.0,
+                height: 600.0,
+            })
+        };
+        let layout_root = layout_tree_with_context(&applied_styles, &context);
+        let actual = build_display_list(&layout_root);
+
+        // The centered tiles of b repeat in both directions from the center. The image of c is
+        // scaled to cover the box.
+        let tiles: Vec<_> = actual
+            .iter()
+            .filter_map(|command| match command {
+                DisplayCommand::Image { image, rect } if *image == handle => {
+                    Some((rect.x, rect.y, rect.width, rect.height))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            tiles,
+            vec![
+                (-10.0, 15.0, 40.0, 20.0),
+                (30.0, 15.0, 40.0, 20.0),
+                (70.0, 15.0, 40.0, 20.0),
+                (0.0, 50.0, 100.0, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let document = Node::from("<p>a\tb</p>");
+        let style =
+            Sheet::from("p { display: block; white-space: pre; font-size: 10px; color: #ff0000; }");
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The tab advances to the next tab stop, 8 spaces from the start of the line.
+        let glyph = |character, x, advance| Glyph {
+            character,
+            x,
+            advance,
+            face: None,
+            id: None,
+        };
+        assert_eq!(
+            actual,
+            vec![DisplayCommand::Text {
+                glyphs: vec![
+                    glyph('a', 0.0, 5.0),
+                    glyph('\t', 5.0, 35.0),
+                    glyph('b', 40.0, 5.0)
+                ],
+                origin: (0.0, 9.0),
+                color: Color {
+                    r: 255,
+                    g: 0,
+                    b: 0,
+                    a: 255
+                },
+                font: Font {
+                    size: 10.0,
+                    ..Default::default()
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let document = Node::from(r#"<ul><li></li><li class="ordered"></li></ul>"#);
+
+        let style = Sheet::from(
+            "
+            ul { display: block; }
+            li { display: list-item; color: #ff0000; }
+            li.ordered { list-style-type: decimal; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // A disc, and a "2" followed by a period, in the color of their list items.
+        let texts: Vec<_> = actual
+            .iter()
+            .map(|command| match command {
+                DisplayCommand::Text { glyphs, color, .. } => {
+                    assert_eq!(color.r, 255);
+                    glyphs.iter().map(|g| g.character).collect::<String>()
+                }
+                _ => panic!("unexpected command {:?}", command),
+            })
+            .collect();
+        assert_eq!(texts, ["\u{2022}", "2."]);
+    }
+
+    #[test]
+    fn test_overflow_clip() {
+        let document = Node::from("<a><b><c></c><d></d></b></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d {
+                display: block;
+            }
+
+            b { overflow: hidden; position: relative; height: 10px; padding: 5px; }
+            c { height: 50px; background: #ff0000; }
+            d { position: absolute; height: 50px; background: #0000ff; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let clip = layout_root.children[0].dimensions.padding_box();
+
+        let actual = build_display_list(&layout_root);
+
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        let c = layout_root.children[0].children[0].dimensions.border_box();
+        let d = layout_root.children[0].children[1].dimensions.border_box();
+
+        let expected = vec![
+            DisplayCommand::PushClip(clip),
+            DisplayCommand::SolidColor(red, c),
+            DisplayCommand::PopClip,
+            DisplayCommand::PushClip(clip),
+            DisplayCommand::SolidColor(blue, d),
+            DisplayCommand::PopClip,
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_transform() {
+        let document = Node::from("<a><b></b></a>");
+
+        let style = Sheet::from(
+            "
+            a, b { display: block; }
+            b {
+                width: 100px;
+                height: 10px;
+                background: #ff0000;
+                transform: translate(10px, 0) scale(2);
+            }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The box is scaled around its center at (50, 5), and then moved to the right.
+        let transform = Transform {
+            a: 2.0,
+            b: 0.0,
+            c: 0.0,
+            d: 2.0,
+            e: -40.0,
+            f: -5.0,
+        };
+        assert_eq!(actual.len(), 3);
+        assert_eq!(actual[0], DisplayCommand::PushTransform(transform));
+        assert_eq!(actual[2], DisplayCommand::PopTransform);
+    }
+
+    #[test]
+    fn test_rounded_overflow_clip() {
+        let document = Node::from("<a><b></b></a>");
+
+        let style = Sheet::from(
+            "
+            a, b { display: block; }
+            a { overflow: hidden; border-width: 2px; border-radius: 10px; }
+            b { height: 40px; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The content is clipped to the padding box, with the radii less the border width.
+        let radii = CornerRadii {
+            top_left: 8.0,
+            top_right: 8.0,
+            bottom_right: 8.0,
+            bottom_left: 8.0,
+        };
+        let padding_box = layout_root.dimensions.padding_box();
+        assert!(actual.contains(&DisplayCommand::PushRoundedClip(padding_box, radii)));
+        assert_eq!(actual.last(), Some(&DisplayCommand::PopClip));
+    }
+
+    #[test]
+    fn test_visible_display_list() {
+        let document = Node::from("<a><b></b><c></c><d></d></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c, d { display: block; }
+            b, c { height: 100px; background: #ff0000; }
+            d { height: 100px; background: #0000ff; transform: translate(0, -150px); }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let visible = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 800.0,
+            height: 100.0,
+        };
+        let actual = build_visible_display_list(&layout_root, Some(visible));
+
+        // c is below the visible part, but d is moved into it by its transformation.
+        let backgrounds: Vec<_> = actual
+            .iter()
+            .filter_map(|command| match command {
+                DisplayCommand::SolidColor(_, rect) => Some(rect.y),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(backgrounds, vec![0.0, 200.0]);
+        assert_eq!(
+            build_visible_display_list(&layout_root, None),
+            build_display_list(&layout_root)
+        );
+    }
+
+    #[test]
+    fn test_opacity() {
+        let document = Node::from("<a><b><c></c></b></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; }
+            b { height: 10px; background: #ff0000; opacity: 0.5; }
+            c { height: 10px; background: #0000ff; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_display_list(&layout_root);
+
+        // The box and its overlapping child are composited together.
+        assert_eq!(actual.len(), 4);
+        assert_eq!(actual[0], DisplayCommand::PushOpacityLayer(0.5));
+        assert!(matches!(actual[1], DisplayCommand::SolidColor(..)));
+        assert!(matches!(actual[2], DisplayCommand::SolidColor(..)));
+        assert_eq!(actual[3], DisplayCommand::PopLayer);
+    }
+
+    #[test]
+    fn test_snap_to_device_pixels() {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        let mut list = vec![
+            DisplayCommand::SolidColor(black.clone(), rect(0.3, 0.0, 10.4, 10.0)),
+            DisplayCommand::SolidColor(black.clone(), rect(10.7, 0.0, 0.2, 10.0)),
+        ];
+        snap_to_device_pixels(&mut list, 2.0);
+
+        // The shared edge at 10.7 snaps to 10.5, and the thin rectangle keeps half a pixel.
+        let expected = vec![
+            DisplayCommand::SolidColor(black.clone(), rect(0.5, 0.0, 10.0, 10.0)),
+            DisplayCommand::SolidColor(black, rect(10.5, 0.0, 0.5, 10.0)),
+        ];
+        assert_eq!(list, expected);
+    }
+
+    #[test]
+    fn test_page_display_lists() {
+        let document = Node::from("<a><b></b><c></c></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; height: 60px; }
+            a { height: auto; }
+            b { background: #ff0000; }
+            c { background: #0000ff; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 100.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let actual = build_page_display_lists(&layout_root, 100.0);
+
+        let page = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 800.0,
+            height: 100.0,
+        };
+        let c = Rect {
+            height: 60.0,
+            ..page
+        };
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+
+        // c doesn't fit on the first page, so it is painted at the top of the second.
+        assert_eq!(actual.len(), 2);
+        assert_eq!(
+            actual[1],
+            vec![
+                DisplayCommand::PushClip(page),
+                DisplayCommand::SolidColor(blue, c),
+                DisplayCommand::PopClip,
+            ]
+        );
     }
 }
@@ -0,0 +1,92 @@
+//! A backend that paints display lists as colored text in a terminal. Each character shows two
+//! pixels, one above the other, as an upper half block in the color of the top pixel on the
+//! background color of the bottom pixel.
+
+use std::fmt::Write;
+
+use crate::css::Color;
+use crate::images::ImageCache;
+use crate::painting::raster::Canvas;
+use crate::painting::DisplayList;
+
+/// The character that fills the top half of its cell with the foreground color.
+const UPPER_HALF_BLOCK: char = '▀';
+
+/// Paint a display list on a white canvas of `width` by `height` pixels, and return it as lines
+/// of text with 24-bit color escape codes.
+pub fn render(
+    list: &DisplayList,
+    width: usize,
+    height: usize,
+    images: Option<&ImageCache>,
+) -> String {
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 255,
+    };
+    let mut canvas = Canvas::new(width, height, white);
+    canvas.paint(list, images);
+    canvas.to_ansi()
+}
+
+impl Canvas {
+    /// The pixels as lines of text with 24-bit color escape codes, for two rows of pixels per
+    /// line. Transparency is ignored. The bottom half of the last line keeps the terminal's
+    /// background if the height is odd.
+    pub fn to_ansi(&self) -> String {
+        let mut text = String::new();
+        for y in (0..self.height).step_by(2) {
+            for x in 0..self.width {
+                let top = self.pixel(x, y);
+                write!(text, "\x1b[38;2;{};{};{}m", top.r, top.g, top.b).unwrap();
+                if y + 1 < self.height {
+                    let bottom = self.pixel(x, y + 1);
+                    write!(text, "\x1b[48;2;{};{};{}m", bottom.r, bottom.g, bottom.b).unwrap();
+                } else {
+                    text.push_str("\x1b[49m");
+                }
+                text.push(UPPER_HALF_BLOCK);
+            }
+            text.push_str("\x1b[0m\n");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::layout::*;
+    use crate::painting::ansi::*;
+    use crate::painting::*;
+
+    #[test]
+    fn test_render() {
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let list = vec![DisplayCommand::SolidColor(
+            red,
+            Rect {
+                x: 0.0,
+                y: 0.0,
+                width: 1.0,
+                height: 1.0,
+            },
+        )];
+
+        // Three rows of pixels take two lines, and the last one only has a top half.
+        assert_eq!(
+            render(&list, 2, 3, None),
+            "\x1b[38;2;255;0;0m\x1b[48;2;255;255;255m▀\
+             \x1b[38;2;255;255;255m\x1b[48;2;255;255;255m▀\x1b[0m\n\
+             \x1b[38;2;255;255;255m\x1b[49m▀\
+             \x1b[38;2;255;255;255m\x1b[49m▀\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Rasterized glyphs packed into one texture, so that backends that draw with a GPU can draw text
+//! as textured rectangles without uploading a texture for each glyph.
+
+use std::collections::HashMap;
+
+use crate::fonts::{FaceId, FontContext, Synthesis};
+use crate::layout::{Font, Rect};
+
+/// The empty pixels around each glyph, so that sampling near its edge doesn't pick up its
+/// neighbours.
+const PADDING: u32 = 1;
+
+/// A glyph that was rasterized into the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasGlyph {
+    /// Where the glyph is drawn, in pixels from the pen position on the baseline.
+    pub rect: Rect,
+    /// Where the glyph is in the atlas, as fractions of its width and height.
+    pub uv: Rect,
+}
+
+/// A row of glyphs in the atlas, as tall as the tallest glyph that was first put in it.
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next glyph goes.
+    x: u32,
+}
+
+/// A cache of rasterized glyphs, by their face, glyph index and font. The glyphs are packed
+/// in rows into a texture of one coverage value per pixel.
+#[derive(Debug)]
+pub struct GlyphAtlas {
+    pub width: u32,
+    pub height: u32,
+    /// The coverage of each pixel, row by row.
+    pub pixels: Vec<u8>,
+    glyphs: HashMap<(FaceId, u16, u32, Synthesis), Option<AtlasGlyph>>,
+    shelves: Vec<Shelf>,
+    /// Counts the changes to the pixels, so that a backend knows when to upload them again.
+    generation: u64,
+}
+
+impl GlyphAtlas {
+    pub fn new(width: u32, height: u32) -> GlyphAtlas {
+        GlyphAtlas {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize],
+            glyphs: HashMap::new(),
+            shelves: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// A glyph in a font, which is rasterized the first time it is needed. Glyphs without an
+    /// outline and glyphs that don't fit in the atlas any more have none.
+    pub fn glyph(
+        &mut self,
+        fonts: &FontContext,
+        face: FaceId,
+        id: u16,
+        font: &Font,
+    ) -> Option<AtlasGlyph> {
+        let key = (face, id, font.size.to_bits(), font.synthesis);
+        if let Some(&glyph) = self.glyphs.get(&key) {
+            return glyph;
+        }
+
+        let bitmap = fonts.rasterize(face, id, font.size, font.synthesis);
+        let glyph = bitmap.and_then(|bitmap| {
+            let (x, y) = self.allocate(bitmap.width, bitmap.height)?;
+            for (row, line) in bitmap.coverage.chunks(bitmap.width as usize).enumerate() {
+                let start = ((y + row as u32) * self.width + x) as usize;
+                self.pixels[start..start + line.len()].copy_from_slice(line);
+            }
+            self.generation += 1;
+
+            Some(AtlasGlyph {
+                rect: Rect {
+                    x: bitmap.left as f32,
+                    y: -bitmap.top as f32,
+                    width: bitmap.width as f32,
+                    height: bitmap.height as f32,
+                },
+                uv: Rect {
+                    x: x as f32 / self.width as f32,
+                    y: y as f32 / self.height as f32,
+                    width: bitmap.width as f32 / self.width as f32,
+                    height: bitmap.height as f32 / self.height as f32,
+                },
+            })
+        });
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    /// How many times the pixels changed since the atlas was created.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Remove every glyph, for when the atlas is full.
+    pub fn clear(&mut self) {
+        self.pixels.fill(0);
+        self.glyphs.clear();
+        self.shelves.clear();
+        self.generation += 1;
+    }
+
+    /// Find room for a glyph in the first shelf that it fits in, or start a new shelf below the
+    /// others. Returns the top left corner of the room.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (width, height) = (width + PADDING, height + PADDING);
+        if width > self.width {
+            return None;
+        }
+
+        let fits = |shelf: &&mut Shelf| shelf.height >= height && shelf.x + width <= self.width;
+        if let Some(shelf) = self.shelves.iter_mut().find(fits) {
+            shelf.x += width;
+            return Some((shelf.x - width, shelf.y));
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        if y + height > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            x: width,
+        });
+        Some((0, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::painting::atlas::*;
+
+    #[test]
+    fn test_allocate() {
+        let mut atlas = GlyphAtlas::new(20, 20);
+
+        // Glyphs are put next to each other in a row, and in a new row when they don't fit.
+        assert_eq!(atlas.allocate(8, 5), Some((0, 0)));
+        assert_eq!(atlas.allocate(8, 4), Some((9, 0)));
+        assert_eq!(atlas.allocate(8, 4), Some((0, 6)));
+        // Shorter glyphs fit in the rows that are left.
+        assert_eq!(atlas.allocate(1, 3), Some((18, 0)));
+        // The atlas is full.
+        assert_eq!(atlas.allocate(8, 12), None);
+        assert_eq!(atlas.allocate(20, 1), None);
+
+        atlas.clear();
+        assert_eq!(atlas.allocate(8, 12), Some((0, 0)));
+    }
+}
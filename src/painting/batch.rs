@@ -0,0 +1,353 @@
+//! Display lists turned into batches of quads, for backends that draw with a GPU, such as wgpu,
+//! in as few draw calls as they can. Each batch is drawn with one instanced draw call, with one
+//! texture and one scissor rectangle, and the batches are in painting order. Groups with an
+//! opacity are drawn into layers of their own, which are composited when they end.
+
+use std::slice;
+
+use crate::css::Color;
+use crate::fonts::FontContext;
+use crate::images::ImageHandle;
+use crate::layout::{CornerRadii, Rect};
+use crate::painting::atlas::GlyphAtlas;
+use crate::painting::raster::{glyph_boxes, scale_radii};
+use crate::painting::DisplayCommand;
+use crate::style::Transform;
+
+/// A quad to draw, laid out to be uploaded as the data of one instance.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "wgpu", derive(bytemuck::Pod, bytemuck::Zeroable))]
+pub struct Instance {
+    /// Where the quad is drawn: x, y, width and height in device pixels.
+    pub rect: [f32; 4],
+    /// Where the quad is in the texture of its batch, as fractions of its width and height.
+    pub uv: [f32; 4],
+    /// The color of the quad, with each channel from 0 to 1, in sRGB. Quads with a texture
+    /// multiply it with its color.
+    pub color: [f32; 4],
+    /// The radii of the top left, top right, bottom right and bottom left corners of the quad.
+    pub radii: [f32; 4],
+    /// The innermost clip with rounded corners, which the fragment shader cuts the quad to, and
+    /// its radii. Without one, it is the whole target.
+    pub clip: [f32; 4],
+    pub clip_radii: [f32; 4],
+    /// The innermost rectangle with rounded corners that is clipped out of the quad, and its
+    /// radii. Without one, it is empty.
+    pub clip_out: [f32; 4],
+    pub clip_out_radii: [f32; 4],
+}
+
+/// What the quads of a batch sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BatchTexture {
+    /// Nothing: the quads are filled with their color.
+    None,
+    /// The coverage of the glyph atlas.
+    Atlas,
+    /// The pixels of an image.
+    Image(ImageHandle),
+}
+
+/// Quads that are drawn with one draw call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    pub texture: BatchTexture,
+    /// The rectangle that the quads are clipped to, in device pixels.
+    pub scissor: Rect,
+    pub instances: Vec<Instance>,
+}
+
+/// A step in drawing the batches of a display list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchCommand {
+    /// Draw a batch on the innermost layer.
+    Draw(Batch),
+    /// Draw the following batches on a new transparent layer, until the matching `PopLayer`.
+    PushLayer(f32),
+    /// Composite the innermost layer on the layer below it, with the opacity it was pushed with.
+    PopLayer,
+}
+
+/// The clips that apply to the quads, in device pixels.
+#[derive(Clone, Copy)]
+struct Clip {
+    /// The intersection of the clip rectangles, which is the scissor rectangle.
+    scissor: Rect,
+    rounded: (Rect, CornerRadii),
+    out: (Rect, CornerRadii),
+}
+
+/// Turn a display list into batches of quads for a target of a size in device pixels. The glyphs
+/// of text are rasterized into the atlas, and glyphs without a face are filled as boxes.
+/// Consecutive quads with the same texture and clip rectangle go into the same batch.
+///
+/// Only the innermost clip with rounded corners, and the innermost one that clips out, are
+/// rounded. The rounded clips around them clip to their rectangles.
+pub fn batch_display_list(
+    list: &[DisplayCommand],
+    (width, height): (f32, f32),
+    atlas: &mut GlyphAtlas,
+    fonts: &FontContext,
+) -> Vec<BatchCommand> {
+    let mut batches = Batches(Vec::new());
+
+    // The innermost clip is on top, starting with the whole target.
+    let target = Rect {
+        x: 0.0,
+        y: 0.0,
+        width,
+        height,
+    };
+    let mut clips = vec![Clip {
+        scissor: target,
+        rounded: (target, CornerRadii::default()),
+        out: (Rect::default(), CornerRadii::default()),
+    }];
+
+    // Transformations only translate and scale, so rectangles stay rectangles.
+    let mut transforms = vec![Transform::identity()];
+
+    for command in list {
+        let clip = *clips.last().unwrap();
+        let transform = *transforms.last().unwrap();
+        let mut push = |texture, rect: Rect, uv: [f32; 4], color: &Color, radii| {
+            let rect = rect.transformed(&transform);
+            let radii = scale_radii(radii, &transform);
+            let instance = Instance {
+                rect: to_vec4(rect),
+                uv,
+                color: [color.r, color.g, color.b, color.a].map(|c| c as f32 / 255.0),
+                radii: to_radii(radii),
+                clip: to_vec4(clip.rounded.0),
+                clip_radii: to_radii(clip.rounded.1),
+                clip_out: to_vec4(clip.out.0),
+                clip_out_radii: to_radii(clip.out.1),
+            };
+            batches.push(texture, clip.scissor, instance);
+        };
+        let square = CornerRadii::default();
+
+        match command {
+            DisplayCommand::SolidColor(color, rect) => {
+                push(BatchTexture::None, *rect, [0.0; 4], color, square)
+            }
+            DisplayCommand::RoundedRect(color, rect, radii) => {
+                push(BatchTexture::None, *rect, [0.0; 4], color, *radii)
+            }
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                color,
+                font,
+            } => {
+                for glyph in glyphs.iter().filter(|g| !g.character.is_whitespace()) {
+                    let (face, id) = match (glyph.face, glyph.id) {
+                        (Some(face), Some(id)) => (face, id),
+                        _ => {
+                            for rect in glyph_boxes(slice::from_ref(glyph), *origin, font) {
+                                push(BatchTexture::None, rect, [0.0; 4], color, square);
+                            }
+                            continue;
+                        }
+                    };
+                    if let Some(atlas_glyph) = atlas.glyph(fonts, face, id, font) {
+                        // Bitmaps line up with the device pixels when the pen does.
+                        let rect = Rect {
+                            x: (origin.0 + glyph.x).round() + atlas_glyph.rect.x,
+                            y: origin.1.round() + atlas_glyph.rect.y,
+                            ..atlas_glyph.rect
+                        };
+                        let uv = atlas_glyph.uv;
+                        let uv = [uv.x, uv.y, uv.width, uv.height];
+                        push(BatchTexture::Atlas, rect, uv, color, square);
+                    }
+                }
+            }
+            DisplayCommand::Image { image, rect } => {
+                let white = Color {
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                    a: 255,
+                };
+                push(
+                    BatchTexture::Image(*image),
+                    *rect,
+                    [0.0, 0.0, 1.0, 1.0],
+                    &white,
+                    square,
+                );
+            }
+            DisplayCommand::PushClip(rect) => {
+                let rect = rect.transformed(&transform);
+                clips.push(Clip {
+                    scissor: rect.intersect(clip.scissor),
+                    ..clip
+                })
+            }
+            DisplayCommand::PushRoundedClip(rect, radii) => {
+                let rect = rect.transformed(&transform);
+                clips.push(Clip {
+                    scissor: rect.intersect(clip.scissor),
+                    rounded: (rect, scale_radii(*radii, &transform)),
+                    ..clip
+                })
+            }
+            DisplayCommand::PushRoundedClipOut(rect, radii) => clips.push(Clip {
+                out: (
+                    rect.transformed(&transform),
+                    scale_radii(*radii, &transform),
+                ),
+                ..clip
+            }),
+            DisplayCommand::PopClip => {
+                clips.pop();
+            }
+            DisplayCommand::PushTransform(t) => transforms.push(transform.multiply(*t)),
+            DisplayCommand::PopTransform => {
+                transforms.pop();
+            }
+            DisplayCommand::PushOpacityLayer(opacity) => {
+                batches.0.push(BatchCommand::PushLayer(*opacity))
+            }
+            DisplayCommand::PopLayer => batches.0.push(BatchCommand::PopLayer),
+        }
+    }
+    batches.0
+}
+
+fn to_vec4(rect: Rect) -> [f32; 4] {
+    [rect.x, rect.y, rect.width, rect.height]
+}
+
+fn to_radii(radii: CornerRadii) -> [f32; 4] {
+    [
+        radii.top_left,
+        radii.top_right,
+        radii.bottom_right,
+        radii.bottom_left,
+    ]
+}
+
+struct Batches(Vec<BatchCommand>);
+
+impl Batches {
+    /// Add a quad to the last batch, or start a new batch if it has another texture or clip, or
+    /// if a layer started or ended after it.
+    fn push(&mut self, texture: BatchTexture, scissor: Rect, instance: Instance) {
+        match self.0.last_mut() {
+            Some(BatchCommand::Draw(batch))
+                if batch.texture == texture && batch.scissor == scissor =>
+            {
+                batch.instances.push(instance)
+            }
+            _ => self.0.push(BatchCommand::Draw(Batch {
+                texture,
+                scissor,
+                instances: vec![instance],
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::fonts::FontContext;
+    use crate::layout::{CornerRadii, Rect};
+    use crate::painting::atlas::GlyphAtlas;
+    use crate::painting::batch::*;
+    use crate::painting::DisplayCommand;
+
+    #[test]
+    fn test_batch_display_list() {
+        let rect = |x| Rect {
+            x,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+        };
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let list = vec![
+            DisplayCommand::SolidColor(red.clone(), rect(0.0)),
+            DisplayCommand::SolidColor(red.clone(), rect(10.0)),
+            DisplayCommand::PushClip(rect(5.0)),
+            DisplayCommand::SolidColor(red.clone(), rect(20.0)),
+            DisplayCommand::PopClip,
+            DisplayCommand::SolidColor(red, rect(30.0)),
+        ];
+
+        let mut atlas = GlyphAtlas::new(16, 16);
+        let batches = draws(batch_display_list(
+            &list,
+            (100.0, 50.0),
+            &mut atlas,
+            &FontContext::new(),
+        ));
+        let sizes: Vec<_> = batches.iter().map(|batch| batch.instances.len()).collect();
+        assert_eq!(sizes, [2, 1, 1]);
+        assert_eq!(batches[1].scissor, rect(5.0));
+        assert_eq!(batches[2].scissor.width, 100.0);
+        assert_eq!(batches[0].instances[1].rect, [10.0, 0.0, 10.0, 10.0]);
+        assert_eq!(batches[0].instances[1].color, [1.0, 0.0, 0.0, 1.0]);
+        assert!(batches
+            .iter()
+            .all(|batch| batch.texture == BatchTexture::None));
+    }
+
+    #[test]
+    fn test_rounded_corners_and_layers() {
+        let rect = |x, width| Rect {
+            x,
+            y: 0.0,
+            width,
+            height: 10.0,
+        };
+        let radii = |r| CornerRadii {
+            top_left: r,
+            top_right: r,
+            bottom_right: r,
+            bottom_left: r,
+        };
+        let list = vec![
+            DisplayCommand::PushRoundedClip(rect(0.0, 40.0), radii(5.0)),
+            DisplayCommand::PushRoundedClipOut(rect(2.0, 36.0), radii(3.0)),
+            DisplayCommand::PushOpacityLayer(0.5),
+            DisplayCommand::RoundedRect(Color::default(), rect(0.0, 10.0), radii(4.0)),
+            DisplayCommand::PopLayer,
+            DisplayCommand::PopClip,
+            DisplayCommand::PopClip,
+        ];
+
+        let mut atlas = GlyphAtlas::new(16, 16);
+        let commands = batch_display_list(&list, (100.0, 50.0), &mut atlas, &FontContext::new());
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0], BatchCommand::PushLayer(0.5));
+        assert_eq!(commands[2], BatchCommand::PopLayer);
+
+        // The shader rounds the quad, and cuts it to the rounded clips.
+        let instance = draws(commands)[0].instances[0];
+        assert_eq!(instance.radii, [4.0; 4]);
+        assert_eq!(instance.clip, [0.0, 0.0, 40.0, 10.0]);
+        assert_eq!(instance.clip_radii, [5.0; 4]);
+        assert_eq!(instance.clip_out, [2.0, 0.0, 36.0, 10.0]);
+        assert_eq!(instance.clip_out_radii, [3.0; 4]);
+    }
+
+    /// The batches that are drawn, without the layers.
+    fn draws(commands: Vec<BatchCommand>) -> Vec<Batch> {
+        commands
+            .into_iter()
+            .filter_map(|command| match command {
+                BatchCommand::Draw(batch) => Some(batch),
+                _ => None,
+            })
+            .collect()
+    }
+}
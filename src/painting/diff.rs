@@ -0,0 +1,190 @@
+//! Finding the parts of the page that change between two display lists, so that only those need
+//! to be painted again.
+
+use crate::layout::Rect;
+use crate::painting::{DisplayCommand, DisplayList};
+use crate::style::Transform;
+
+/// Rectangles in page coordinates that cover everything that changed. They don't overlap.
+pub type DirtyRegions = Vec<Rect>;
+
+/// A drawing command together with the state it is drawn in: the clips, transformations and
+/// layers that are open around it.
+#[derive(Debug, PartialEq)]
+struct Entry<'a> {
+    command: &'a DisplayCommand,
+    state: Vec<&'a DisplayCommand>,
+    /// The area that the command paints in page coordinates, within its clips.
+    bounds: Rect,
+}
+
+/// The regions that must be painted again to turn a page painted from `old` into one painted
+/// from `new`.
+///
+/// Drawing commands are compared along with the state they are drawn in. The commands that only
+/// appear in one of the lists are damaged, as are commands that are painted in a different order
+/// relative to each other.
+pub fn diff(old: &DisplayList, new: &DisplayList) -> DirtyRegions {
+    let old = entries(old);
+    let new = entries(new);
+
+    // Most changes leave the start and the end of the list as they were.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    // Match each entry in the old list with an equal entry in the new list.
+    let mut matched = vec![false; new.len()];
+    let mut old_matches = Vec::new();
+    let mut damage = Vec::new();
+    for entry in old {
+        let found = (0..new.len()).find(|&i| !matched[i] && new[i] == *entry);
+        match found {
+            Some(i) => {
+                matched[i] = true;
+                old_matches.push(i);
+            }
+            None => damage.push(entry.bounds),
+        }
+    }
+    for (entry, _) in new.iter().zip(&matched).filter(|(_, &matched)| !matched) {
+        damage.push(entry.bounds);
+    }
+
+    // Entries in both lists that are painted in another order relative to each other change too.
+    if old_matches.windows(2).any(|pair| pair[0] > pair[1]) {
+        damage.extend(old_matches.iter().map(|&i| new[i].bounds));
+    }
+
+    merge(damage)
+}
+
+/// The drawing commands of a display list, with their state and bounds.
+fn entries(list: &DisplayList) -> Vec<Entry<'_>> {
+    let mut state = Vec::new();
+    let mut clips: Vec<Option<Rect>> = vec![None];
+    let mut transforms = vec![Transform::identity()];
+
+    let mut entries = Vec::new();
+    for command in list {
+        let clip = *clips.last().unwrap();
+        let transform = *transforms.last().unwrap();
+
+        match command {
+            DisplayCommand::PushClip(rect) | DisplayCommand::PushRoundedClip(rect, _) => {
+                let rect = rect.transformed(&transform);
+                clips.push(Some(clip.map_or(rect, |clip| clip.intersect(rect))));
+                state.push(command);
+            }
+            // Clipping out keeps the bounds of the clip around it.
+            DisplayCommand::PushRoundedClipOut(..) => {
+                clips.push(clip);
+                state.push(command);
+            }
+            DisplayCommand::PushTransform(t) => {
+                transforms.push(transform.multiply(*t));
+                state.push(command);
+            }
+            DisplayCommand::PushOpacityLayer(_) => state.push(command),
+            DisplayCommand::PopClip => {
+                clips.pop();
+                state.pop();
+            }
+            DisplayCommand::PopTransform => {
+                transforms.pop();
+                state.pop();
+            }
+            DisplayCommand::PopLayer => {
+                state.pop();
+            }
+            _ => {}
+        }
+
+        if let Some(bounds) = command.bounds() {
+            let bounds = bounds.transformed(&transform);
+            entries.push(Entry {
+                command,
+                state: state.clone(),
+                bounds: clip.map_or(bounds, |clip| clip.intersect(bounds)),
+            });
+        }
+    }
+    entries
+}
+
+/// Combine overlapping rectangles into the rectangle around them, until none overlap. Empty
+/// rectangles are left out.
+fn merge(rects: Vec<Rect>) -> DirtyRegions {
+    let mut merged: DirtyRegions = Vec::new();
+    for mut rect in rects {
+        if rect.width <= 0.0 || rect.height <= 0.0 {
+            continue;
+        }
+
+        // Growing a rectangle can make it overlap others that were merged before.
+        while let Some(i) = merged.iter().position(|&other| overlaps(rect, other)) {
+            rect = rect.union(merged.swap_remove(i));
+        }
+        merged.push(rect);
+    }
+    merged
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    let area = a.intersect(b);
+    area.width > 0.0 && area.height > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::painting::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_diff() {
+        let document = Node::from("<a><b></b><c></c><d></d></a>");
+        let before = Sheet::from(
+            "
+            a, b, c, d { display: block; height: 10px; }
+            b, c, d { background: #ff0000; }
+        ",
+        );
+        let after = Sheet::from(
+            "
+            a, b, c, d { display: block; height: 10px; }
+            b, c, d { background: #ff0000; }
+            c { background: #0000ff; }
+        ",
+        );
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let before_styles = style_tree(&document, &before);
+        let old = build_display_list(&layout_tree(&before_styles, viewport));
+        let after_styles = style_tree(&document, &after);
+        let new = build_display_list(&layout_tree(&after_styles, viewport));
+
+        // Only the box that changed color needs to be painted again.
+        assert_eq!(diff(&old, &old), vec![]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![Rect {
+                x: 0.0,
+                y: 10.0,
+                width: 800.0,
+                height: 10.0,
+            }]
+        );
+    }
+}
@@ -0,0 +1,470 @@
+//! A backend that paints display lists with OpenGL through glium, for applications that show
+//! documents in a window. Rectangles are drawn as quads, text as quads textured from a glyph
+//! atlas, and images as quads textured from the images they show.
+//!
+//! https://github.com/glium/glium
+
+use std::collections::HashMap;
+use std::fmt;
+use std::slice;
+
+use glium::backend::Facade;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{
+    ClientFormat, MipmapsOption, RawImage2d, SrgbTexture2d, Texture2d, TextureCreationError,
+    UncompressedFloatFormat,
+};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Uniforms};
+use glium::vertex::BufferCreationError;
+use glium::{
+    implement_vertex, uniform, Blend, DrawError, DrawParameters, Program, ProgramCreationError,
+    Surface, VertexBuffer,
+};
+
+use crate::css::Color;
+use crate::fonts::FontContext;
+use crate::images::ImageCache;
+use crate::layout::Rect;
+use crate::painting::atlas::GlyphAtlas;
+use crate::painting::raster::glyph_boxes;
+use crate::painting::{DisplayCommand, DisplayList};
+use crate::style::Transform;
+
+/// The width and the height of the glyph atlas, in pixels.
+const ATLAS_SIZE: u32 = 1024;
+
+/// Places a unit square on a rectangle of the target, in pixels from its top left, and passes
+/// on where the vertex is in the rectangle of a texture.
+const VERTEX_SHADER: &str = r#"
+    #version 140
+
+    in vec2 position;
+    out vec2 texture_position;
+
+    uniform vec2 viewport;
+    uniform vec4 rect;
+    uniform vec4 uv;
+
+    void main() {
+        texture_position = uv.xy + position * uv.zw;
+        vec2 pixel = rect.xy + position * rect.zw;
+        gl_Position = vec4(pixel / viewport * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    }
+"#;
+
+/// Fills with a color, which is converted from sRGB to linear light.
+const COLOR_SHADER: &str = r#"
+    #version 140
+
+    in vec2 texture_position;
+    out vec4 color;
+
+    uniform vec4 in_color;
+
+    void main() {
+        vec4 normalized = in_color / 255.0;
+        color = vec4(pow(normalized.rgb, vec3(2.2)), normalized.a);
+    }
+"#;
+
+/// Fills with a color, covered as much as a glyph in the atlas covers each pixel.
+const GLYPH_SHADER: &str = r#"
+    #version 140
+
+    in vec2 texture_position;
+    out vec4 color;
+
+    uniform vec4 in_color;
+    uniform sampler2D atlas;
+
+    void main() {
+        vec4 normalized = in_color / 255.0;
+        float coverage = texture(atlas, texture_position).r;
+        color = vec4(pow(normalized.rgb, vec3(2.2)), normalized.a * coverage);
+    }
+"#;
+
+/// Fills with an image, whose sRGB texture is sampled in linear light.
+const IMAGE_SHADER: &str = r#"
+    #version 140
+
+    in vec2 texture_position;
+    out vec4 color;
+
+    uniform sampler2D image;
+
+    void main() {
+        color = texture(image, texture_position);
+    }
+"#;
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+}
+
+implement_vertex!(Vertex, position);
+
+/// Why a renderer could not be created, or could not draw a display list.
+#[derive(Debug)]
+pub enum RendererError {
+    Program(ProgramCreationError),
+    Buffer(BufferCreationError),
+    Texture(TextureCreationError),
+    Draw(DrawError),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RendererError::Program(error) => write!(f, "shader error: {error}"),
+            RendererError::Buffer(error) => write!(f, "buffer error: {error}"),
+            RendererError::Texture(error) => write!(f, "texture error: {error}"),
+            RendererError::Draw(error) => write!(f, "draw error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::Program(error) => Some(error),
+            RendererError::Buffer(error) => Some(error),
+            RendererError::Texture(error) => Some(error),
+            RendererError::Draw(error) => Some(error),
+        }
+    }
+}
+
+impl From<ProgramCreationError> for RendererError {
+    fn from(error: ProgramCreationError) -> RendererError {
+        RendererError::Program(error)
+    }
+}
+
+impl From<BufferCreationError> for RendererError {
+    fn from(error: BufferCreationError) -> RendererError {
+        RendererError::Buffer(error)
+    }
+}
+
+impl From<TextureCreationError> for RendererError {
+    fn from(error: TextureCreationError) -> RendererError {
+        RendererError::Texture(error)
+    }
+}
+
+impl From<DrawError> for RendererError {
+    fn from(error: DrawError) -> RendererError {
+        RendererError::Draw(error)
+    }
+}
+
+/// Draws display lists on the surfaces of an OpenGL context. The glyphs of text are rasterized
+/// into an atlas, and images are uploaded as textures, the first time they are drawn, and kept
+/// for the frames after.
+pub struct Renderer {
+    square: VertexBuffer<Vertex>,
+    color_program: Program,
+    glyph_program: Program,
+    image_program: Program,
+    atlas: GlyphAtlas,
+    /// The atlas on the GPU, with the generation of the atlas that it holds.
+    atlas_texture: Option<(u64, Texture2d)>,
+    /// The images on the GPU, by the id of their handles.
+    images: HashMap<usize, SrgbTexture2d>,
+}
+
+impl Renderer {
+    pub fn new(facade: &impl Facade) -> Result<Renderer, RendererError> {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let square: Vec<_> = corners.map(|position| Vertex { position }).into();
+        let program = |shader| Program::from_source(facade, VERTEX_SHADER, shader, None);
+        Ok(Renderer {
+            square: VertexBuffer::new(facade, &square)?,
+            color_program: program(COLOR_SHADER)?,
+            glyph_program: program(GLYPH_SHADER)?,
+            image_program: program(IMAGE_SHADER)?,
+            atlas: GlyphAtlas::new(ATLAS_SIZE, ATLAS_SIZE),
+            atlas_texture: None,
+            images: HashMap::new(),
+        })
+    }
+
+    /// Draw a display list on top of what is on a surface, whose pixels are device pixels.
+    /// Glyphs are rasterized from `fonts`, and glyphs without a face are drawn as boxes. Images
+    /// are looked up in `images`, and are left out without it.
+    ///
+    /// Rounded corners are drawn square, and opacity layers are drawn opaque.
+    pub fn draw(
+        &mut self,
+        facade: &impl Facade,
+        target: &mut impl Surface,
+        list: &DisplayList,
+        fonts: &FontContext,
+        images: Option<&ImageCache>,
+    ) -> Result<(), RendererError> {
+        self.upload(facade, list, fonts, images)?;
+
+        let (width, height) = target.get_dimensions();
+        let viewport = [width as f32, height as f32];
+
+        // The innermost clip rectangle is on top, starting with the whole surface.
+        let mut clips = vec![Rect {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+        }];
+
+        // Transformations only translate and scale, so rectangles stay rectangles.
+        let mut transforms = vec![Transform::identity()];
+
+        // The display list is in painting order, so later commands are drawn on top.
+        for command in list {
+            let clip = *clips.last().unwrap();
+            let transform = *transforms.last().unwrap();
+            let scissor = scissor(height, &clip);
+
+            match command {
+                // TODO: Round the corners in the fragment shader.
+                DisplayCommand::SolidColor(color, rect)
+                | DisplayCommand::RoundedRect(color, rect, _) => {
+                    let rect = rect.transformed(&transform);
+                    self.draw_color(target, viewport, color, rect, scissor)?;
+                }
+                DisplayCommand::Text {
+                    glyphs,
+                    origin,
+                    color,
+                    font,
+                } => {
+                    for glyph in glyphs.iter().filter(|g| !g.character.is_whitespace()) {
+                        let atlas_glyph = match (glyph.face, glyph.id) {
+                            (Some(face), Some(id)) => self.atlas.glyph(fonts, face, id, font),
+                            // Glyphs without a face are drawn as boxes.
+                            _ => {
+                                let boxes = glyph_boxes(slice::from_ref(glyph), *origin, font);
+                                for rect in boxes {
+                                    let rect = rect.transformed(&transform);
+                                    self.draw_color(target, viewport, color, rect, scissor)?;
+                                }
+                                continue;
+                            }
+                        };
+                        if let Some(atlas_glyph) = atlas_glyph {
+                            // Bitmaps line up with the pixels of the surface when the pen does.
+                            let rect = Rect {
+                                x: (origin.0 + glyph.x).round() + atlas_glyph.rect.x,
+                                y: origin.1.round() + atlas_glyph.rect.y,
+                                ..atlas_glyph.rect
+                            };
+                            let rect = rect.transformed(&transform);
+                            self.draw_glyph(
+                                target,
+                                viewport,
+                                color,
+                                rect,
+                                atlas_glyph.uv,
+                                scissor,
+                            )?;
+                        }
+                    }
+                }
+                DisplayCommand::Image { image, rect } => {
+                    if let Some(texture) = self.images.get(&image.id()) {
+                        let uniforms = uniform! {
+                            viewport: viewport,
+                            rect: to_vec4(rect.transformed(&transform)),
+                            uv: [0.0f32, 0.0, 1.0, 1.0],
+                            image: texture
+                                .sampled()
+                                .magnify_filter(MagnifySamplerFilter::Linear)
+                                .minify_filter(MinifySamplerFilter::Linear),
+                        };
+                        self.draw_quad(target, &self.image_program, &uniforms, scissor)?;
+                    }
+                }
+                DisplayCommand::PushClip(rect) | DisplayCommand::PushRoundedClip(rect, _) => {
+                    clips.push(rect.transformed(&transform).intersect(clip))
+                }
+                DisplayCommand::PushRoundedClipOut(..) => {
+                    // Scissor rectangles can't cut a hole, so nothing is clipped out.
+                    clips.push(clip)
+                }
+                DisplayCommand::PopClip => {
+                    clips.pop();
+                }
+                DisplayCommand::PushTransform(t) => transforms.push(transform.multiply(*t)),
+                DisplayCommand::PopTransform => {
+                    transforms.pop();
+                }
+                // TODO: Render groups into a texture, and composite it with its opacity.
+                DisplayCommand::PushOpacityLayer(_) | DisplayCommand::PopLayer => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Rasterize the glyphs of the display list into the atlas, and upload the atlas and the
+    /// images that changed or weren't uploaded yet.
+    fn upload(
+        &mut self,
+        facade: &impl Facade,
+        list: &DisplayList,
+        fonts: &FontContext,
+        images: Option<&ImageCache>,
+    ) -> Result<(), RendererError> {
+        for command in list {
+            match command {
+                DisplayCommand::Text { glyphs, font, .. } => {
+                    for glyph in glyphs {
+                        if let (Some(face), Some(id)) = (glyph.face, glyph.id) {
+                            self.atlas.glyph(fonts, face, id, font);
+                        }
+                    }
+                }
+                DisplayCommand::Image { image, .. } => {
+                    if let (Some(images), false) = (images, self.images.contains_key(&image.id())) {
+                        let decoded = images.get(*image);
+                        let raw = RawImage2d::from_raw_rgba(
+                            decoded.pixels.clone(),
+                            (decoded.width, decoded.height),
+                        );
+                        self.images
+                            .insert(image.id(), SrgbTexture2d::new(facade, raw)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let generation = self.atlas.generation();
+        if self.atlas_texture.as_ref().map(|(g, _)| *g) != Some(generation) {
+            let raw = RawImage2d {
+                data: self.atlas.pixels.as_slice().into(),
+                width: self.atlas.width,
+                height: self.atlas.height,
+                format: ClientFormat::U8,
+            };
+            let texture = Texture2d::with_format(
+                facade,
+                raw,
+                UncompressedFloatFormat::U8,
+                MipmapsOption::NoMipmap,
+            )?;
+            self.atlas_texture = Some((generation, texture));
+        }
+        Ok(())
+    }
+
+    fn draw_color(
+        &self,
+        target: &mut impl Surface,
+        viewport: [f32; 2],
+        color: &Color,
+        rect: Rect,
+        scissor: glium::Rect,
+    ) -> Result<(), RendererError> {
+        let uniforms = uniform! {
+            viewport: viewport,
+            rect: to_vec4(rect),
+            uv: [0.0f32; 4],
+            in_color: to_color(color),
+        };
+        self.draw_quad(target, &self.color_program, &uniforms, scissor)
+    }
+
+    /// Draw a glyph from the atlas, which gives the coverage of each pixel of the rectangle.
+    fn draw_glyph(
+        &self,
+        target: &mut impl Surface,
+        viewport: [f32; 2],
+        color: &Color,
+        rect: Rect,
+        uv: Rect,
+        scissor: glium::Rect,
+    ) -> Result<(), RendererError> {
+        let atlas = match &self.atlas_texture {
+            Some((_, atlas)) => atlas,
+            None => return Ok(()),
+        };
+        let uniforms = uniform! {
+            viewport: viewport,
+            rect: to_vec4(rect),
+            uv: to_vec4(uv),
+            in_color: to_color(color),
+            atlas: atlas
+                .sampled()
+                .magnify_filter(MagnifySamplerFilter::Nearest)
+                .minify_filter(MinifySamplerFilter::Nearest),
+        };
+        self.draw_quad(target, &self.glyph_program, &uniforms, scissor)
+    }
+
+    fn draw_quad(
+        &self,
+        target: &mut impl Surface,
+        program: &Program,
+        uniforms: &impl Uniforms,
+        scissor: glium::Rect,
+    ) -> Result<(), RendererError> {
+        let indices = NoIndices(PrimitiveType::TriangleStrip);
+        let parameters = DrawParameters {
+            scissor: Some(scissor),
+            blend: Blend::alpha_blending(),
+            ..Default::default()
+        };
+        Ok(target.draw(&self.square, indices, program, uniforms, &parameters)?)
+    }
+}
+
+/// The scissor rectangle of a clip on a surface of a height, which has its origin at the bottom
+/// left of the surface.
+fn scissor(height: u32, clip: &Rect) -> glium::Rect {
+    glium::Rect {
+        left: clip.x.max(0.0) as u32,
+        bottom: (height as f32 - (clip.y + clip.height)).max(0.0) as u32,
+        width: clip.width.max(0.0) as u32,
+        height: clip.height.max(0.0) as u32,
+    }
+}
+
+fn to_vec4(rect: Rect) -> [f32; 4] {
+    [rect.x, rect.y, rect.width, rect.height]
+}
+
+fn to_color(color: &Color) -> [f32; 4] {
+    [color.r, color.g, color.b, color.a].map(f32::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::Rect;
+    use crate::painting::gpu::*;
+
+    #[test]
+    fn test_scissor() {
+        let clip = Rect {
+            x: 10.0,
+            y: 20.0,
+            width: 30.0,
+            height: 40.0,
+        };
+        let expected = glium::Rect {
+            left: 10,
+            bottom: 40,
+            width: 30,
+            height: 40,
+        };
+        assert_eq!(scissor(100, &clip), expected);
+
+        let outside = Rect {
+            x: -5.0,
+            y: 90.0,
+            ..clip
+        };
+        assert_eq!(scissor(100, &outside).left, 0);
+        assert_eq!(scissor(100, &outside).bottom, 0);
+    }
+}
@@ -0,0 +1,161 @@
+//! An overlay that shows the box model of every box, like the element inspectors of browsers,
+//! painted with ordinary drawing commands so that it works in any backend.
+
+use crate::css::Color;
+use crate::dom::Node;
+use crate::layout::{BlockNode, Font, Glyph, GridNode, InlineNode, LayoutBox, Rect};
+use crate::painting::{DisplayCommand, DisplayList};
+
+/// The colors of the margin, border, padding and content areas, like those of the box model
+/// views of browser developer tools.
+const AREA_COLORS: [Color; 4] = [
+    translucent(246, 178, 107),
+    translucent(255, 229, 153),
+    translucent(147, 196, 125),
+    translucent(111, 168, 220),
+];
+
+const LABEL_BACKGROUND: Color = translucent(0, 0, 0);
+
+const LABEL_COLOR: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+
+/// The font size of the labels, and the width of each of their characters.
+const LABEL_SIZE: f32 = 10.0;
+const LABEL_ADVANCE: f32 = 6.0;
+
+const fn translucent(r: u8, g: u8, b: u8) -> Color {
+    Color { r, g, b, a: 160 }
+}
+
+/// Paint outlines around the areas of a box and of the boxes in it, and label the boxes of
+/// elements with their tag names. Transformations and scrolling aren't applied to the overlay.
+pub fn render_debug_overlay(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let d = layout_box.dimensions;
+    let areas = [d.margin_box(), d.border_box(), d.padding_box(), d.content];
+    for (i, (area, color)) in areas.iter().zip(&AREA_COLORS).enumerate() {
+        // An area without an edge around it would be outlined twice.
+        if i == 0 || areas[i - 1] != *area {
+            render_outline(list, *area, color);
+        }
+    }
+
+    let tag = match (&layout_box.box_type, layout_box.node()) {
+        (BlockNode(_) | InlineNode(_) | GridNode(_), Some(Node::Element { tag, .. })) => Some(tag),
+        _ => None,
+    };
+    if let Some(tag) = tag {
+        render_label(list, areas[1], tag);
+    }
+
+    for child in &layout_box.children {
+        render_debug_overlay(list, child);
+    }
+}
+
+/// Paint the edges of a rectangle, one pixel wide, inside of it.
+fn render_outline(list: &mut DisplayList, rect: Rect, color: &Color) {
+    if rect.width <= 0.0 || rect.height <= 0.0 {
+        return;
+    }
+    let width = rect.width.min(1.0);
+    let height = rect.height.min(1.0);
+    let edges = [
+        Rect { height, ..rect },
+        Rect {
+            y: rect.y + rect.height - height,
+            height,
+            ..rect
+        },
+        Rect { width, ..rect },
+        Rect {
+            x: rect.x + rect.width - width,
+            width,
+            ..rect
+        },
+    ];
+    for edge in edges {
+        list.push(DisplayCommand::SolidColor(color.clone(), edge));
+    }
+}
+
+/// Paint a tag name on a dark background at the top left of a border box. The glyphs have no
+/// face, so that they can be drawn without fonts.
+fn render_label(list: &mut DisplayList, border_box: Rect, tag: &str) {
+    let glyphs: Vec<_> = tag
+        .chars()
+        .enumerate()
+        .map(|(i, character)| Glyph {
+            character,
+            x: i as f32 * LABEL_ADVANCE,
+            advance: LABEL_ADVANCE,
+            face: None,
+            id: None,
+        })
+        .collect();
+    let background = Rect {
+        x: border_box.x,
+        y: border_box.y,
+        width: glyphs.len() as f32 * LABEL_ADVANCE + 4.0,
+        height: LABEL_SIZE + 2.0,
+    };
+    list.push(DisplayCommand::SolidColor(LABEL_BACKGROUND, background));
+    list.push(DisplayCommand::Text {
+        glyphs,
+        origin: (background.x + 2.0, background.y + LABEL_SIZE),
+        color: LABEL_COLOR,
+        font: Font {
+            size: LABEL_SIZE,
+            ..Default::default()
+        },
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::painting::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_debug_overlay() {
+        let document = Node::from("<a><b></b></a>");
+        let style = Sheet::from("a, b { display: block; height: 10px; } b { margin: 5px; }");
+        let style = style_tree(&document, &style);
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&style, viewport);
+
+        let options = PaintOptions {
+            debug_overlay: true,
+        };
+        let actual = build_display_list_with_options(&layout_root, &options);
+        assert!(build_display_list(&layout_root).is_empty());
+
+        // `a` has one area to outline, and `b` has a margin around the others.
+        let outlines = actual.iter().filter(
+            |command| matches!(command, DisplayCommand::SolidColor(_, rect) if rect.height == 1.0),
+        );
+        assert_eq!(outlines.count(), 2 * (1 + 2));
+        let labels: Vec<_> = actual
+            .iter()
+            .filter_map(|command| match command {
+                DisplayCommand::Text { glyphs, origin, .. } => Some((
+                    glyphs.iter().map(|g| g.character).collect::<String>(),
+                    *origin,
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [("a".to_owned(), (2.0, 10.0)), ("b".to_owned(), (7.0, 15.0))]
+        );
+    }
+}
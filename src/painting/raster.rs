@@ -0,0 +1,723 @@
+//! A software rasterizer, which paints a display list into a canvas of pixels.
+
+use crate::css::Color;
+use crate::images::{Image, ImageCache};
+use crate::layout::{CornerRadii, Font, Glyph, Rect};
+use crate::painting::{DisplayCommand, DisplayList, PaintLayer};
+use crate::style::Transform;
+
+/// A rectangle with rounded corners, in canvas coordinates.
+type Shape = (Rect, CornerRadii);
+
+/// A clip, which leaves either the inside or the outside of a shape visible.
+#[derive(Clone, Copy)]
+enum Clip {
+    In(Shape),
+    Out(Shape),
+}
+
+const TRANSPARENT: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+};
+
+/// A grid of pixels, row by row from the top, with one CSS pixel per pixel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    /// Whether pixels on the edge of a shape are blended by how much of them the shape covers.
+    /// Otherwise a pixel is painted only if its center is inside the shape, which keeps edges
+    /// sharp. Either way, edges that are snapped to whole pixels stay sharp.
+    pub anti_alias: bool,
+}
+
+impl Canvas {
+    /// A canvas filled with a background color.
+    pub fn new(width: usize, height: usize, background: Color) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![background; width * height],
+            anti_alias: true,
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &Color {
+        &self.pixels[y * self.width + x]
+    }
+
+    /// The pixels as 8-bit RGBA values.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b, color.a])
+            .collect()
+    }
+
+    /// Paint a display list on top of what is on the canvas. Images are looked up in `images`,
+    /// and are left out without it.
+    ///
+    /// Colors are composited with source-over, and anything outside the canvas is clipped.
+    /// Transformations may only translate and scale, so rectangles stay rectangles.
+    pub fn paint(&mut self, list: &DisplayList, images: Option<&ImageCache>) {
+        paint(self, list, images);
+    }
+}
+
+/// The order of the channels of the pixels in a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red, green, blue and alpha, with 8 bits each.
+    Rgba8,
+    /// Blue, green, red and alpha, with 8 bits each, as used by many windowing systems.
+    Bgra8,
+}
+
+/// Paint a display list directly into a buffer of `width` by `height` pixels, on top of what is
+/// in it. Rows start `stride` bytes apart. Images are looked up in `images`, and are left out
+/// without it.
+///
+/// Panics if the buffer is too small for the rows.
+pub fn render_into(
+    list: &DisplayList,
+    buf: &mut [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+    images: Option<&ImageCache>,
+) {
+    assert!(stride >= width * 4, "stride is too small for the width");
+    assert!(
+        height == 0 || buf.len() >= (height - 1) * stride + width * 4,
+        "buffer is too small for the rows"
+    );
+
+    let mut buffer = Buffer {
+        buf,
+        width,
+        height,
+        stride,
+        format,
+    };
+    paint(&mut buffer, list, images);
+}
+
+/// Paint layers that have been rasterized, ready to be composited onto a canvas. Scrolling a
+/// layer only moves its pixels, without painting it again.
+pub struct Compositor {
+    layers: Vec<RasterLayer>,
+}
+
+/// A paint layer with its pixels.
+struct RasterLayer {
+    canvas: Canvas,
+    /// Where the top left pixel of the canvas is on the page, before scrolling.
+    origin: (isize, isize),
+    clip: Option<Rect>,
+    scroll_offset: (f32, f32),
+}
+
+impl Compositor {
+    /// Rasterize each layer on a transparent canvas that is just large enough for its content.
+    pub fn new(layers: Vec<PaintLayer>, images: Option<&ImageCache>) -> Compositor {
+        let layers = layers
+            .into_iter()
+            .map(|layer| {
+                let bounds = list_bounds(&layer.list);
+                let x0 = bounds.x.floor();
+                let y0 = bounds.y.floor();
+                let width = ((bounds.x + bounds.width).ceil() - x0).max(0.0) as usize;
+                let height = ((bounds.y + bounds.height).ceil() - y0).max(0.0) as usize;
+
+                // Move the content to the top left of the canvas.
+                let offset = Transform {
+                    e: -x0,
+                    f: -y0,
+                    ..Transform::identity()
+                };
+                let mut list = vec![DisplayCommand::PushTransform(offset)];
+                list.extend(layer.list);
+                list.push(DisplayCommand::PopTransform);
+
+                let mut canvas = Canvas::new(width, height, TRANSPARENT);
+                canvas.paint(&list, images);
+                RasterLayer {
+                    canvas,
+                    origin: (x0 as isize, y0 as isize),
+                    clip: layer.clip,
+                    scroll_offset: layer.scroll_offset,
+                }
+            })
+            .collect();
+        Compositor { layers }
+    }
+
+    /// Scroll the content of a layer to the given offset.
+    ///
+    /// Panics if there is no layer at `index`.
+    pub fn scroll_to(&mut self, index: usize, scroll_offset: (f32, f32)) {
+        self.layers[index].scroll_offset = scroll_offset;
+    }
+
+    /// Composite the layers onto a canvas, in order. Scroll offsets are rounded to whole pixels.
+    pub fn composite(&self, target: &mut Canvas) {
+        for layer in &self.layers {
+            let dx = layer.origin.0 - layer.scroll_offset.0.round() as isize;
+            let dy = layer.origin.1 - layer.scroll_offset.1.round() as isize;
+            for y in 0..layer.canvas.height {
+                for x in 0..layer.canvas.width {
+                    let color = layer.canvas.pixel(x, y);
+                    let (tx, ty) = (x as isize + dx, y as isize + dy);
+                    if color.a == 0 || tx < 0 || ty < 0 {
+                        continue;
+                    }
+                    let (tx, ty) = (tx as usize, ty as usize);
+                    if tx >= target.width || ty >= target.height {
+                        continue;
+                    }
+                    let (px, py) = (tx as f32 + 0.5, ty as f32 + 0.5);
+                    let coverage = layer.clip.map_or(1.0, |clip| {
+                        coverage(&(clip, CornerRadii::default()), px, py, target.anti_alias)
+                    });
+                    if coverage > 0.0 {
+                        blend(target, tx, ty, color, coverage);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The area that the drawing commands of a display list paint, after their transformations.
+fn list_bounds(list: &DisplayList) -> Rect {
+    let mut transforms = vec![Transform::identity()];
+    let mut bounds: Option<Rect> = None;
+    for command in list {
+        let transform = *transforms.last().unwrap();
+        match command {
+            DisplayCommand::PushTransform(t) => transforms.push(transform.multiply(*t)),
+            DisplayCommand::PopTransform => {
+                transforms.pop();
+            }
+            _ => {}
+        }
+
+        if let Some(rect) = command.bounds() {
+            let rect = rect.transformed(&transform);
+            bounds = Some(bounds.map_or(rect, |bounds| bounds.union(rect)));
+        }
+    }
+    bounds.unwrap_or_default()
+}
+
+/// Pixels that display lists can be painted on.
+trait Target {
+    fn size(&self) -> (usize, usize);
+    fn get(&self, x: usize, y: usize) -> Color;
+    fn set(&mut self, x: usize, y: usize, color: Color);
+
+    fn anti_alias(&self) -> bool {
+        true
+    }
+}
+
+impl Target for Canvas {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn get(&self, x: usize, y: usize) -> Color {
+        self.pixel(x, y).clone()
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    fn anti_alias(&self) -> bool {
+        self.anti_alias
+    }
+}
+
+/// A buffer of pixels that belongs to the caller.
+struct Buffer<'a> {
+    buf: &'a mut [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl Target for Buffer<'_> {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn get(&self, x: usize, y: usize) -> Color {
+        let i = y * self.stride + x * 4;
+        let [first, g, third, a] = [
+            self.buf[i],
+            self.buf[i + 1],
+            self.buf[i + 2],
+            self.buf[i + 3],
+        ];
+        match self.format {
+            PixelFormat::Rgba8 => Color {
+                r: first,
+                g,
+                b: third,
+                a,
+            },
+            PixelFormat::Bgra8 => Color {
+                r: third,
+                g,
+                b: first,
+                a,
+            },
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Color) {
+        let i = y * self.stride + x * 4;
+        let pixel = match self.format {
+            PixelFormat::Rgba8 => [color.r, color.g, color.b, color.a],
+            PixelFormat::Bgra8 => [color.b, color.g, color.r, color.a],
+        };
+        self.buf[i..i + 4].copy_from_slice(&pixel);
+    }
+}
+
+/// Paint a display list on a target.
+fn paint(target: &mut dyn Target, list: &DisplayList, images: Option<&ImageCache>) {
+    let (width, height) = target.size();
+
+    // Every clip applies, with the innermost on top, starting with the target boundaries.
+    let bounds = Rect {
+        x: 0.0,
+        y: 0.0,
+        width: width as f32,
+        height: height as f32,
+    };
+    let mut clips = vec![Clip::In((bounds, CornerRadii::default()))];
+    let mut transforms = vec![Transform::identity()];
+
+    // Groups with an opacity are painted into a transparent layer of their own, which is
+    // composited onto the pixels below it when the group ends.
+    let mut layers: Vec<(Canvas, f32)> = Vec::new();
+
+    for command in list {
+        let transform = *transforms.last().unwrap();
+        let surface: &mut dyn Target = match layers.last_mut() {
+            Some((layer, _)) => layer,
+            None => &mut *target,
+        };
+
+        match command {
+            DisplayCommand::SolidColor(color, rect) => {
+                let shape = (rect.transformed(&transform), CornerRadii::default());
+                fill(surface, color, &shape, &clips);
+            }
+            DisplayCommand::RoundedRect(color, rect, radii) => {
+                let shape = (
+                    rect.transformed(&transform),
+                    scale_radii(*radii, &transform),
+                );
+                fill(surface, color, &shape, &clips);
+            }
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                color,
+                font,
+            } => {
+                for rect in glyph_boxes(glyphs, *origin, font) {
+                    let shape = (rect.transformed(&transform), CornerRadii::default());
+                    fill(surface, color, &shape, &clips);
+                }
+            }
+            DisplayCommand::Image { image, rect } => {
+                if let Some(images) = images {
+                    let rect = rect.transformed(&transform);
+                    draw_image(surface, images.get(*image), &rect, &clips);
+                }
+            }
+            DisplayCommand::PushClip(rect) => clips.push(Clip::In((
+                rect.transformed(&transform),
+                CornerRadii::default(),
+            ))),
+            DisplayCommand::PushRoundedClip(rect, radii) => clips.push(Clip::In((
+                rect.transformed(&transform),
+                scale_radii(*radii, &transform),
+            ))),
+            DisplayCommand::PushRoundedClipOut(rect, radii) => clips.push(Clip::Out((
+                rect.transformed(&transform),
+                scale_radii(*radii, &transform),
+            ))),
+            DisplayCommand::PopClip => {
+                clips.pop();
+            }
+            DisplayCommand::PushTransform(t) => transforms.push(transform.multiply(*t)),
+            DisplayCommand::PopTransform => {
+                transforms.pop();
+            }
+            DisplayCommand::PushOpacityLayer(opacity) => {
+                let mut layer = Canvas::new(width, height, TRANSPARENT);
+                layer.anti_alias = target.anti_alias();
+                layers.push((layer, *opacity));
+            }
+            DisplayCommand::PopLayer => {
+                let (layer, opacity) = layers.pop().unwrap();
+                let below: &mut dyn Target = match layers.last_mut() {
+                    Some((below, _)) => below,
+                    None => &mut *target,
+                };
+                for y in 0..height {
+                    for x in 0..width {
+                        let color = layer.pixel(x, y);
+                        if color.a > 0 {
+                            blend(below, x, y, color, opacity);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fill a shape with a color, blending it with the target by how much of each pixel it covers.
+fn fill(target: &mut dyn Target, color: &Color, shape: &Shape, clips: &[Clip]) {
+    for_each_pixel(target, shape.0, clips, |target, x, y, clip_coverage| {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let coverage = clip_coverage * coverage(shape, px, py, target.anti_alias());
+        if coverage > 0.0 {
+            blend(target, x, y, color, coverage);
+        }
+    });
+}
+
+/// Draw an image scaled to a rectangle, sampling the nearest pixel of the image.
+fn draw_image(target: &mut dyn Target, image: &Image, rect: &Rect, clips: &[Clip]) {
+    if image.width == 0 || image.height == 0 || rect.width <= 0.0 || rect.height <= 0.0 {
+        return;
+    }
+
+    for_each_pixel(target, *rect, clips, |target, x, y, coverage| {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let u = ((px - rect.x) / rect.width * image.width as f32) as u32;
+        let v = ((py - rect.y) / rect.height * image.height as f32) as u32;
+        let i = (v.min(image.height - 1) * image.width + u.min(image.width - 1)) as usize * 4;
+        let color = Color {
+            r: image.pixels[i],
+            g: image.pixels[i + 1],
+            b: image.pixels[i + 2],
+            a: image.pixels[i + 3],
+        };
+        blend(target, x, y, &color, coverage);
+    });
+}
+
+/// Call `f` for each pixel that overlaps a rectangle within the clips, with how much of the pixel
+/// the clips leave visible.
+fn for_each_pixel<F>(target: &mut dyn Target, rect: Rect, clips: &[Clip], mut f: F)
+where
+    F: FnMut(&mut dyn Target, usize, usize, f32),
+{
+    // Only the clips that keep the inside of a shape limit the area.
+    let bounds = clips.iter().fold(rect, |bounds, clip| match clip {
+        Clip::In((clip, _)) => bounds.intersect(*clip),
+        Clip::Out(_) => bounds,
+    });
+    if bounds.width <= 0.0 || bounds.height <= 0.0 {
+        return;
+    }
+
+    // The first clip holds the target boundaries, so the bounds are within the target.
+    let (width, height) = target.size();
+    let anti_alias = target.anti_alias();
+    let x0 = bounds.x.floor() as usize;
+    let y0 = bounds.y.floor() as usize;
+    let x1 = ((bounds.x + bounds.width).ceil() as usize).min(width);
+    let y1 = ((bounds.y + bounds.height).ceil() as usize).min(height);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let coverage = clips.iter().fold(1.0, |total, clip| match clip {
+                Clip::In(shape) => total * coverage(shape, px, py, anti_alias),
+                Clip::Out(shape) => total * (1.0 - coverage(shape, px, py, anti_alias)),
+            });
+            if coverage > 0.0 {
+                f(target, x, y, coverage);
+            }
+        }
+    }
+}
+
+/// The fraction of the pixel with its center at (px, py) that is covered by a rectangle with
+/// rounded corners, estimated from the distance between the center and the edge of the shape.
+/// Without anti-aliasing, the pixel is either covered or not.
+fn coverage((rect, radii): &Shape, px: f32, py: f32, anti_alias: bool) -> f32 {
+    let edge = |distance: f32| match anti_alias {
+        true => (distance + 0.5).clamp(0.0, 1.0),
+        false if distance >= 0.0 => 1.0,
+        false => 0.0,
+    };
+    let right = rect.x + rect.width;
+    let bottom = rect.y + rect.height;
+
+    let mut coverage = edge(px - rect.x)
+        .min(edge(right - px))
+        .min(edge(py - rect.y))
+        .min(edge(bottom - py));
+
+    // Near a rounded corner, the edge is the circle around the center of the corner.
+    let corners = [
+        (radii.top_left, -1.0, -1.0),
+        (radii.top_right, 1.0, -1.0),
+        (radii.bottom_right, 1.0, 1.0),
+        (radii.bottom_left, -1.0, 1.0),
+    ];
+    for (radius, sx, sy) in corners {
+        if radius <= 0.0 {
+            continue;
+        }
+        let cx = if sx < 0.0 {
+            rect.x + radius
+        } else {
+            right - radius
+        };
+        let cy = if sy < 0.0 {
+            rect.y + radius
+        } else {
+            bottom - radius
+        };
+        let (dx, dy) = ((px - cx) * sx, (py - cy) * sy);
+        if dx > 0.0 && dy > 0.0 {
+            coverage = coverage.min(edge(radius - (dx * dx + dy * dy).sqrt()));
+        }
+    }
+
+    coverage
+}
+
+/// Composite a color over a pixel with source-over, with the given coverage on top of its own
+/// alpha. The pixel may be transparent itself, when it is part of a layer.
+fn blend(target: &mut dyn Target, x: usize, y: usize, color: &Color, coverage: f32) {
+    let pixel = target.get(x, y);
+    target.set(x, y, color.over_with_coverage(&pixel, coverage));
+}
+
+/// Until fonts can be rasterized, every glyph that isn't white space is drawn as a box, from the
+/// baseline up to the height of lowercase letters.
+pub(super) fn glyph_boxes(glyphs: &[Glyph], (x, y): (f32, f32), font: &Font) -> Vec<Rect> {
+    let x_height = 0.5 * font.size;
+    glyphs
+        .iter()
+        .filter(|glyph| !glyph.character.is_whitespace())
+        .map(|glyph| Rect {
+            x: x + glyph.x + 0.1 * glyph.advance,
+            y: y - x_height,
+            width: 0.8 * glyph.advance,
+            height: x_height,
+        })
+        .collect()
+}
+
+/// Scale corner radii along with a transformation, which only translates and scales.
+pub(crate) fn scale_radii(radii: CornerRadii, transform: &Transform) -> CornerRadii {
+    let scale = transform.a.abs().min(transform.d.abs());
+    CornerRadii {
+        top_left: radii.top_left * scale,
+        top_right: radii.top_right * scale,
+        bottom_right: radii.bottom_right * scale,
+        bottom_left: radii.bottom_left * scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::dom::*;
+    use crate::layout::*;
+    use crate::painting::raster::*;
+    use crate::painting::*;
+    use crate::style::*;
+
+    #[test]
+    fn test_paint() {
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        };
+        let translucent_red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 128,
+        };
+        let rect = |x, width| Rect {
+            x,
+            y: 0.0,
+            width,
+            height: 2.0,
+        };
+
+        // The second rectangle is clipped to the canvas, and half of the third to the clip.
+        let list = vec![
+            DisplayCommand::SolidColor(translucent_red.clone(), rect(0.0, 1.0)),
+            DisplayCommand::SolidColor(white.clone(), rect(3.0, 10.0)),
+            DisplayCommand::PushClip(rect(0.0, 2.5)),
+            DisplayCommand::SolidColor(translucent_red.clone(), rect(2.0, 1.0)),
+            DisplayCommand::PopClip,
+        ];
+
+        let mut canvas = Canvas::new(4, 2, TRANSPARENT);
+        canvas.paint(&list, None);
+
+        assert_eq!(canvas.pixel(0, 1), &translucent_red);
+        assert_eq!(canvas.pixel(1, 1), &TRANSPARENT);
+        assert_eq!(canvas.pixel(2, 0).a, 64);
+        assert_eq!(canvas.pixel(3, 0), &white);
+    }
+
+    #[test]
+    fn test_clip_out() {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let square = |offset, size| Rect {
+            x: offset,
+            y: offset,
+            width: size,
+            height: size,
+        };
+
+        // A ring of 4px around a hole with corners of 6px, which curve away from the square.
+        let list = vec![
+            DisplayCommand::PushRoundedClipOut(
+                square(4.0, 12.0),
+                CornerRadii {
+                    top_left: 6.0,
+                    top_right: 6.0,
+                    bottom_right: 6.0,
+                    bottom_left: 6.0,
+                },
+            ),
+            DisplayCommand::SolidColor(black, square(0.0, 20.0)),
+            DisplayCommand::PopClip,
+        ];
+
+        let mut canvas = Canvas::new(20, 20, TRANSPARENT);
+        canvas.anti_alias = false;
+        canvas.paint(&list, None);
+        assert_eq!(canvas.pixel(2, 10).a, 255);
+        assert_eq!(canvas.pixel(10, 10).a, 0);
+        assert_eq!(canvas.pixel(4, 4).a, 255);
+        assert_eq!(canvas.pixel(8, 8).a, 0);
+    }
+
+    #[test]
+    fn test_anti_alias() {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let list = vec![DisplayCommand::SolidColor(
+            black,
+            Rect {
+                x: 0.25,
+                y: 0.0,
+                width: 1.5,
+                height: 1.0,
+            },
+        )];
+
+        // The edges cover three quarters of the pixels they are in.
+        let mut canvas = Canvas::new(3, 1, TRANSPARENT);
+        canvas.paint(&list, None);
+        let alphas: Vec<_> = canvas.pixels.iter().map(|color| color.a).collect();
+        assert_eq!(alphas, vec![191, 191, 0]);
+
+        let mut canvas = Canvas::new(3, 1, TRANSPARENT);
+        canvas.anti_alias = false;
+        canvas.paint(&list, None);
+        let alphas: Vec<_> = canvas.pixels.iter().map(|color| color.a).collect();
+        assert_eq!(alphas, vec![255, 255, 0]);
+    }
+
+    #[test]
+    fn test_render_into() {
+        let list = vec![DisplayCommand::SolidColor(
+            Color {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 255,
+            },
+            Rect {
+                x: 1.0,
+                y: 0.0,
+                width: 1.0,
+                height: 2.0,
+            },
+        )];
+
+        // Each row has room for three pixels, of which two are painted.
+        let mut buf = vec![0; 24];
+        render_into(&list, &mut buf, 2, 2, 12, PixelFormat::Bgra8, None);
+        assert_eq!(
+            buf,
+            vec![0, 0, 0, 0, 3, 2, 1, 255, 0, 0, 0, 0, 0, 0, 0, 0, 3, 2, 1, 255, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_compositor() {
+        let document = Node::from("<a><b></b><c></c></a>");
+
+        let style = Sheet::from(
+            "
+            a, b, c { display: block; }
+            a { height: 2px; overflow: scroll; }
+            b { height: 2px; background: #ff0000; }
+            c { height: 2px; background: #0000ff; }
+        ",
+        );
+
+        let applied_styles = style_tree(&document, &style);
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 1.0;
+        viewport.content.height = 4.0;
+
+        let layout_root = layout_tree(&applied_styles, viewport);
+        let layers = build_paint_layers(&layout_root);
+        assert_eq!(layers.len(), 2);
+
+        let mut compositor = Compositor::new(layers, None);
+        let mut canvas = Canvas::new(1, 4, TRANSPARENT);
+        compositor.composite(&mut canvas);
+        assert_eq!(canvas.pixel(0, 0).r, 255);
+        assert_eq!(canvas.pixel(0, 2), &TRANSPARENT);
+
+        // The content that is scrolled into view replaces the content above it, and stays
+        // within the scroll container.
+        compositor.scroll_to(1, (0.0, 2.0));
+        let mut canvas = Canvas::new(1, 4, TRANSPARENT);
+        compositor.composite(&mut canvas);
+        assert_eq!(canvas.pixel(0, 0).b, 255);
+        assert_eq!(canvas.pixel(0, 2), &TRANSPARENT);
+    }
+}
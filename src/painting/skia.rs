@@ -0,0 +1,281 @@
+//! A backend that paints display lists with tiny-skia, which anti-aliases every path.
+//!
+//! https://github.com/RazrFalcon/tiny-skia
+
+use tiny_skia::{
+    FillRule, FilterQuality, Mask, Paint, Path, PathBuilder, Pattern, Pixmap, PixmapPaint,
+    SpreadMode,
+};
+
+use crate::css::Color;
+use crate::images::{Image, ImageCache};
+use crate::layout::{CornerRadii, Rect};
+use crate::painting::raster::glyph_boxes;
+use crate::painting::{DisplayCommand, DisplayList};
+use crate::style::Transform;
+
+/// How far the control points of a cubic Bézier curve are from its ends, as a fraction of the
+/// radius, for the curve to approximate a quarter circle.
+const KAPPA: f32 = 0.552_284_8;
+
+/// Paint a display list on top of what is in a pixmap. Images are looked up in `images`, and are
+/// left out without it.
+pub fn paint(pixmap: &mut Pixmap, list: &DisplayList, images: Option<&ImageCache>) {
+    let (width, height) = (pixmap.width(), pixmap.height());
+
+    // The clip is the intersection of the open clips, or `None` when nothing is clipped.
+    let mut clips: Vec<Option<Mask>> = vec![None];
+    let mut transforms = vec![Transform::identity()];
+    let mut layers: Vec<(Pixmap, f32)> = Vec::new();
+
+    for command in list {
+        let transform = *transforms.last().unwrap();
+        let ts = to_skia(&transform);
+        let clip = clips.last().unwrap().as_ref();
+
+        match command {
+            DisplayCommand::SolidColor(color, rect) => {
+                if let Some(path) = rounded_rect(rect, &CornerRadii::default()) {
+                    pixmap.fill_path(&path, &solid(color), FillRule::Winding, ts, clip);
+                }
+            }
+            DisplayCommand::RoundedRect(color, rect, radii) => {
+                if let Some(path) = rounded_rect(rect, radii) {
+                    pixmap.fill_path(&path, &solid(color), FillRule::Winding, ts, clip);
+                }
+            }
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                color,
+                font,
+            } => {
+                let paint = solid(color);
+                for rect in glyph_boxes(glyphs, *origin, font) {
+                    if let Some(path) = rounded_rect(&rect, &CornerRadii::default()) {
+                        pixmap.fill_path(&path, &paint, FillRule::Winding, ts, clip);
+                    }
+                }
+            }
+            DisplayCommand::Image { image, rect } => {
+                let image = images.and_then(|images| to_pixmap(images.get(*image)));
+                let path = rounded_rect(rect, &CornerRadii::default());
+                if let (Some(image), Some(path)) = (image, path) {
+                    let scale = tiny_skia::Transform::from_row(
+                        rect.width / image.width() as f32,
+                        0.0,
+                        0.0,
+                        rect.height / image.height() as f32,
+                        rect.x,
+                        rect.y,
+                    );
+                    let paint = Paint {
+                        shader: Pattern::new(
+                            image.as_ref(),
+                            SpreadMode::Pad,
+                            FilterQuality::Bilinear,
+                            1.0,
+                            scale,
+                        ),
+                        ..Default::default()
+                    };
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, ts, clip);
+                }
+            }
+            DisplayCommand::PushClip(rect) | DisplayCommand::PushRoundedClip(rect, _) => {
+                let radii = match command {
+                    DisplayCommand::PushRoundedClip(_, radii) => *radii,
+                    _ => CornerRadii::default(),
+                };
+                let mut mask = match clip {
+                    Some(mask) => mask.clone(),
+                    None => full_mask(width, height),
+                };
+                match rounded_rect(rect, &radii) {
+                    Some(path) => mask.intersect_path(&path, FillRule::Winding, true, ts),
+                    // An empty clip hides everything.
+                    None => mask.clear(),
+                }
+                clips.push(Some(mask));
+            }
+            DisplayCommand::PushRoundedClipOut(rect, radii) => {
+                let mut mask = match clip {
+                    Some(mask) => mask.clone(),
+                    None => full_mask(width, height),
+                };
+                // An empty rectangle has no inside to clip out.
+                if let Some(path) = rounded_rect(rect, radii) {
+                    let mut hole = Mask::new(width, height).unwrap();
+                    hole.fill_path(&path, FillRule::Winding, true, ts);
+                    for (m, h) in mask.data_mut().iter_mut().zip(hole.data()) {
+                        *m = (*m as u16 * (255 - *h as u16) / 255) as u8;
+                    }
+                }
+                clips.push(Some(mask));
+            }
+            DisplayCommand::PopClip => {
+                clips.pop();
+            }
+            DisplayCommand::PushTransform(t) => transforms.push(transform.multiply(*t)),
+            DisplayCommand::PopTransform => {
+                transforms.pop();
+            }
+            DisplayCommand::PushOpacityLayer(opacity) => {
+                let layer = Pixmap::new(width, height).unwrap();
+                layers.push((std::mem::replace(pixmap, layer), *opacity));
+            }
+            DisplayCommand::PopLayer => {
+                let (below, opacity) = layers.pop().unwrap();
+                let layer = std::mem::replace(pixmap, below);
+                let paint = PixmapPaint {
+                    opacity,
+                    ..Default::default()
+                };
+                pixmap.draw_pixmap(
+                    0,
+                    0,
+                    layer.as_ref(),
+                    &paint,
+                    tiny_skia::Transform::identity(),
+                    None,
+                );
+            }
+        }
+    }
+}
+
+fn to_skia(transform: &Transform) -> tiny_skia::Transform {
+    let Transform { a, b, c, d, e, f } = *transform;
+    tiny_skia::Transform::from_row(a, b, c, d, e, f)
+}
+
+fn solid(color: &Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.r, color.g, color.b, color.a);
+    paint.anti_alias = true;
+    paint
+}
+
+/// A mask that doesn't hide anything.
+fn full_mask(width: u32, height: u32) -> Mask {
+    let mut mask = Mask::new(width, height).unwrap();
+    let rect = tiny_skia::Rect::from_xywh(0.0, 0.0, width as f32, height as f32).unwrap();
+    mask.fill_path(
+        &PathBuilder::from_rect(rect),
+        FillRule::Winding,
+        false,
+        tiny_skia::Transform::identity(),
+    );
+    mask
+}
+
+/// The outline of a rectangle with rounded corners, or `None` if it is empty.
+fn rounded_rect(rect: &Rect, radii: &CornerRadii) -> Option<Path> {
+    if rect.width <= 0.0 || rect.height <= 0.0 {
+        return None;
+    }
+
+    let (left, top) = (rect.x, rect.y);
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+    let CornerRadii {
+        top_left,
+        top_right,
+        bottom_right,
+        bottom_left,
+    } = *radii;
+    let k = 1.0 - KAPPA;
+
+    let mut builder = PathBuilder::new();
+    builder.move_to(left + top_left, top);
+    builder.line_to(right - top_right, top);
+    builder.cubic_to(
+        right - top_right * k,
+        top,
+        right,
+        top + top_right * k,
+        right,
+        top + top_right,
+    );
+    builder.line_to(right, bottom - bottom_right);
+    builder.cubic_to(
+        right,
+        bottom - bottom_right * k,
+        right - bottom_right * k,
+        bottom,
+        right - bottom_right,
+        bottom,
+    );
+    builder.line_to(left + bottom_left, bottom);
+    builder.cubic_to(
+        left + bottom_left * k,
+        bottom,
+        left,
+        bottom - bottom_left * k,
+        left,
+        bottom - bottom_left,
+    );
+    builder.line_to(left, top + top_left);
+    builder.cubic_to(
+        left,
+        top + top_left * k,
+        left + top_left * k,
+        top,
+        left + top_left,
+        top,
+    );
+    builder.close();
+    builder.finish()
+}
+
+/// Copy an image into a pixmap, which holds premultiplied colors.
+fn to_pixmap(image: &Image) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(image.width, image.height)?;
+    for (pixel, rgba) in pixmap.pixels_mut().iter_mut().zip(image.pixels.chunks(4)) {
+        *pixel = tiny_skia::ColorU8::from_rgba(rgba[0], rgba[1], rgba[2], rgba[3]).premultiply();
+    }
+    Some(pixmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::layout::*;
+    use crate::painting::skia::*;
+
+    #[test]
+    fn test_paint() {
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+        };
+        let radii = CornerRadii {
+            top_left: 5.0,
+            top_right: 0.0,
+            bottom_right: 0.0,
+            bottom_left: 0.0,
+        };
+        let list = vec![
+            DisplayCommand::PushClip(Rect { width: 5.5, ..rect }),
+            DisplayCommand::RoundedRect(red, rect, radii),
+            DisplayCommand::PopClip,
+        ];
+
+        let mut pixmap = Pixmap::new(10, 10).unwrap();
+        paint(&mut pixmap, &list, None);
+
+        // The rounded corner is left out, and the pixel on the edge of the clip is blended.
+        let alpha = |x, y| pixmap.pixel(x, y).unwrap().alpha();
+        assert_eq!(alpha(0, 0), 0);
+        assert_eq!(alpha(4, 9), 255);
+        assert!((100..156).contains(&alpha(5, 9)));
+        assert_eq!(alpha(6, 9), 0);
+    }
+}
@@ -0,0 +1,112 @@
+//! A trait for backends that walk display lists, with a method per kind of command. New kinds of
+//! commands come with methods that do nothing by default, so that backends keep compiling.
+
+use crate::css::Color;
+use crate::images::ImageHandle;
+use crate::layout::{CornerRadii, Font, Glyph, Rect};
+use crate::painting::DisplayCommand;
+use crate::style::Transform;
+
+/// Handles the commands of a display list, one method per kind of [`DisplayCommand`]. Every
+/// method does nothing unless it is implemented, so a backend only implements the commands it
+/// supports.
+pub trait DisplayListVisitor {
+    fn solid_color(&mut self, _color: &Color, _rect: Rect) {}
+
+    fn rounded_rect(&mut self, _color: &Color, _rect: Rect, _radii: CornerRadii) {}
+
+    fn push_clip(&mut self, _rect: Rect) {}
+
+    fn push_rounded_clip(&mut self, _rect: Rect, _radii: CornerRadii) {}
+
+    fn push_rounded_clip_out(&mut self, _rect: Rect, _radii: CornerRadii) {}
+
+    fn pop_clip(&mut self) {}
+
+    fn text(&mut self, _glyphs: &[Glyph], _origin: (f32, f32), _color: &Color, _font: &Font) {}
+
+    fn image(&mut self, _image: ImageHandle, _rect: Rect) {}
+
+    fn push_transform(&mut self, _transform: Transform) {}
+
+    fn pop_transform(&mut self) {}
+
+    fn push_opacity_layer(&mut self, _opacity: f32) {}
+
+    fn pop_layer(&mut self) {}
+}
+
+impl DisplayCommand {
+    /// Call the method of the visitor for this kind of command.
+    pub fn accept(&self, visitor: &mut impl DisplayListVisitor) {
+        match self {
+            DisplayCommand::SolidColor(color, rect) => visitor.solid_color(color, *rect),
+            DisplayCommand::RoundedRect(color, rect, radii) => {
+                visitor.rounded_rect(color, *rect, *radii)
+            }
+            DisplayCommand::PushClip(rect) => visitor.push_clip(*rect),
+            DisplayCommand::PushRoundedClip(rect, radii) => {
+                visitor.push_rounded_clip(*rect, *radii)
+            }
+            DisplayCommand::PushRoundedClipOut(rect, radii) => {
+                visitor.push_rounded_clip_out(*rect, *radii)
+            }
+            DisplayCommand::PopClip => visitor.pop_clip(),
+            DisplayCommand::Text {
+                glyphs,
+                origin,
+                color,
+                font,
+            } => visitor.text(glyphs, *origin, color, font),
+            DisplayCommand::Image { image, rect } => visitor.image(*image, *rect),
+            DisplayCommand::PushTransform(transform) => visitor.push_transform(*transform),
+            DisplayCommand::PopTransform => visitor.pop_transform(),
+            DisplayCommand::PushOpacityLayer(opacity) => visitor.push_opacity_layer(*opacity),
+            DisplayCommand::PopLayer => visitor.pop_layer(),
+        }
+    }
+}
+
+/// Pass the commands of a display list to a visitor, in order.
+pub fn visit(list: &[DisplayCommand], visitor: &mut impl DisplayListVisitor) {
+    for command in list {
+        command.accept(visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::*;
+    use crate::layout::*;
+    use crate::painting::*;
+
+    /// Collects the rectangles that are filled, and ignores everything else.
+    struct Fills(Vec<Rect>);
+
+    impl DisplayListVisitor for Fills {
+        fn solid_color(&mut self, _color: &Color, rect: Rect) {
+            self.0.push(rect);
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let rect = |x| Rect {
+            x,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+        };
+        let list = vec![
+            DisplayCommand::PushClip(rect(0.0)),
+            DisplayCommand::SolidColor(Color::default(), rect(1.0)),
+            DisplayCommand::RoundedRect(Color::default(), rect(2.0), CornerRadii::default()),
+            DisplayCommand::PopClip,
+            DisplayCommand::SolidColor(Color::default(), rect(3.0)),
+        ];
+
+        let mut fills = Fills(Vec::new());
+        visit(&list, &mut fills);
+        assert_eq!(fills.0, [rect(1.0), rect(3.0)]);
+    }
+}
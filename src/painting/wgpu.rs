@@ -0,0 +1,708 @@
+//! A backend that paints display lists with wgpu, on Vulkan, Metal, Direct3D 12 or OpenGL. The
+//! display list is drawn in the batches of instanced quads of [`batch_display_list`]: rectangles
+//! whose rounded corners and clips the fragment shader cuts, text from a glyph atlas, and images
+//! from textures of their own. Groups with an opacity are drawn into a texture, and composited
+//! when they end.
+//!
+//! https://wgpu.rs
+
+use std::collections::HashMap;
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBindingType,
+    BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FilterMode,
+    FragmentState, LoadOp, MultisampleState, Operations, Origin3d, PipelineLayoutDescriptor,
+    PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension, VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::fonts::FontContext;
+use crate::images::ImageCache;
+use crate::painting::atlas::GlyphAtlas;
+use crate::painting::batch::{batch_display_list, BatchCommand, BatchTexture, Instance};
+use crate::painting::{DisplayCommand, DisplayList};
+
+/// The width and the height of the glyph atlas, in pixels.
+const ATLAS_SIZE: u32 = 1024;
+
+/// Draws each instance as a quad, and cuts it to its rectangle with rounded corners and to its
+/// clips, anti-aliased by how far each pixel center is from their edges. Colors are
+/// premultiplied with their alpha, so that layers can be composited.
+const SHADER: &str = r#"
+    struct Globals {
+        viewport: vec2<f32>,
+        // Whether the target stores sRGB, so that colors are converted to linear light.
+        linear: f32,
+    }
+
+    struct Instance {
+        @location(0) rect: vec4<f32>,
+        @location(1) uv: vec4<f32>,
+        @location(2) color: vec4<f32>,
+        @location(3) radii: vec4<f32>,
+        @location(4) clip: vec4<f32>,
+        @location(5) clip_radii: vec4<f32>,
+        @location(6) clip_out: vec4<f32>,
+        @location(7) clip_out_radii: vec4<f32>,
+    }
+
+    struct Varyings {
+        @builtin(position) position: vec4<f32>,
+        @location(0) uv: vec2<f32>,
+        @location(1) color: vec4<f32>,
+        @location(2) @interpolate(flat) rect: vec4<f32>,
+        @location(3) @interpolate(flat) radii: vec4<f32>,
+        @location(4) @interpolate(flat) clip: vec4<f32>,
+        @location(5) @interpolate(flat) clip_radii: vec4<f32>,
+        @location(6) @interpolate(flat) clip_out: vec4<f32>,
+        @location(7) @interpolate(flat) clip_out_radii: vec4<f32>,
+    }
+
+    @group(0) @binding(0) var<uniform> globals: Globals;
+    @group(1) @binding(0) var image: texture_2d<f32>;
+    @group(1) @binding(1) var image_sampler: sampler;
+
+    @vertex
+    fn vs_main(@builtin(vertex_index) index: u32, instance: Instance) -> Varyings {
+        // The quad grows by a pixel on each side, for the pixels that its edges cover partly.
+        let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+        let pixel = instance.rect.xy - 1.0 + corner * (instance.rect.zw + 2.0);
+        let along = (pixel - instance.rect.xy) / max(instance.rect.zw, vec2<f32>(1e-6));
+
+        var out: Varyings;
+        out.position = vec4<f32>(pixel / globals.viewport * vec2<f32>(2.0, -2.0)
+            + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+        out.uv = instance.uv.xy + along * instance.uv.zw;
+        out.color = instance.color;
+        if globals.linear > 0.5 {
+            out.color = vec4<f32>(pow(instance.color.rgb, vec3<f32>(2.2)), instance.color.a);
+        }
+        out.rect = instance.rect;
+        out.radii = instance.radii;
+        out.clip = instance.clip;
+        out.clip_radii = instance.clip_radii;
+        out.clip_out = instance.clip_out;
+        out.clip_out_radii = instance.clip_out_radii;
+        return out;
+    }
+
+    // How much of the pixel centered at p a rectangle with rounded corners covers.
+    fn coverage(p: vec2<f32>, rect: vec4<f32>, radii: vec4<f32>) -> f32 {
+        if rect.z <= 0.0 || rect.w <= 0.0 {
+            return 0.0;
+        }
+        let half = rect.zw * 0.5;
+        let q = p - rect.xy - half;
+        // The radius of the corner on the side of the center that p is on.
+        let top = select(radii.x, radii.y, q.x > 0.0);
+        let bottom = select(radii.w, radii.z, q.x > 0.0);
+        let radius = min(select(bottom, top, q.y < 0.0), min(half.x, half.y));
+        let d = abs(q) - half + radius;
+        let distance = length(max(d, vec2<f32>(0.0))) + min(max(d.x, d.y), 0.0) - radius;
+        return clamp(0.5 - distance, 0.0, 1.0);
+    }
+
+    fn clipped(in: Varyings) -> f32 {
+        let p = in.position.xy;
+        return coverage(p, in.rect, in.radii)
+            * coverage(p, in.clip, in.clip_radii)
+            * (1.0 - coverage(p, in.clip_out, in.clip_out_radii));
+    }
+
+    @fragment
+    fn fs_color(in: Varyings) -> @location(0) vec4<f32> {
+        let alpha = in.color.a * clipped(in);
+        return vec4<f32>(in.color.rgb * alpha, alpha);
+    }
+
+    @fragment
+    fn fs_glyph(in: Varyings) -> @location(0) vec4<f32> {
+        let alpha = in.color.a * textureSample(image, image_sampler, in.uv).r * clipped(in);
+        return vec4<f32>(in.color.rgb * alpha, alpha);
+    }
+
+    @fragment
+    fn fs_image(in: Varyings) -> @location(0) vec4<f32> {
+        let color = textureSample(image, image_sampler, in.uv);
+        let alpha = color.a * clipped(in);
+        return vec4<f32>(color.rgb * alpha, alpha);
+    }
+
+    // Layers hold premultiplied colors, which are multiplied with the opacity of the group.
+    @fragment
+    fn fs_layer(in: Varyings) -> @location(0) vec4<f32> {
+        return textureSample(image, image_sampler, in.uv) * in.color.a * clipped(in);
+    }
+"#;
+
+/// A texture that a shader samples, with the bind group that binds it.
+struct Bound {
+    texture: Texture,
+    bind_group: BindGroup,
+}
+
+/// Draws display lists on the textures of a wgpu device, which have the format that the renderer
+/// was created for. The glyphs of text are rasterized into an atlas, and images are uploaded as
+/// textures, the first time they are drawn, and kept for the frames after.
+pub struct Renderer {
+    format: TextureFormat,
+    globals: Buffer,
+    globals_bind_group: BindGroup,
+    texture_layout: BindGroupLayout,
+    linear_sampler: Sampler,
+    nearest_sampler: Sampler,
+    color_pipeline: RenderPipeline,
+    glyph_pipeline: RenderPipeline,
+    image_pipeline: RenderPipeline,
+    layer_pipeline: RenderPipeline,
+    /// What color quads bind, since every pipeline samples a texture.
+    blank: Bound,
+    atlas: GlyphAtlas,
+    /// The atlas on the GPU, with the generation of the atlas that it holds.
+    atlas_texture: Option<(u64, Bound)>,
+    /// The images on the GPU, by the id of their handles.
+    images: HashMap<usize, Bound>,
+}
+
+impl Renderer {
+    /// Create a renderer for targets with a format. Colors are converted to linear light for
+    /// formats that store sRGB, which the hardware converts back.
+    pub fn new(device: &Device, format: TextureFormat) -> Renderer {
+        let globals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("boxrs globals"),
+            size: 16,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("boxrs globals"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let globals_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("boxrs globals"),
+            layout: &globals_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: globals.as_entire_binding(),
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("boxrs texture"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("boxrs"),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("boxrs"),
+            bind_group_layouts: &[&globals_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let attributes = wgpu::vertex_attr_array![
+            0 => Float32x4, 1 => Float32x4, 2 => Float32x4, 3 => Float32x4,
+            4 => Float32x4, 5 => Float32x4, 6 => Float32x4, 7 => Float32x4,
+        ];
+        let pipeline = |entry_point| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[VertexBufferLayout {
+                        array_stride: size_of::<Instance>() as u64,
+                        step_mode: VertexStepMode::Instance,
+                        attributes: &attributes,
+                    }],
+                },
+                primitive: PrimitiveState {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let linear_sampler = device.create_sampler(&SamplerDescriptor {
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let nearest_sampler = device.create_sampler(&SamplerDescriptor::default());
+
+        let blank = texture(device, (1, 1), TextureFormat::R8Unorm);
+        Renderer {
+            format,
+            globals,
+            globals_bind_group,
+            color_pipeline: pipeline("fs_color"),
+            glyph_pipeline: pipeline("fs_glyph"),
+            image_pipeline: pipeline("fs_image"),
+            layer_pipeline: pipeline("fs_layer"),
+            blank: bind(device, &texture_layout, &nearest_sampler, blank),
+            texture_layout,
+            linear_sampler,
+            nearest_sampler,
+            atlas: GlyphAtlas::new(ATLAS_SIZE, ATLAS_SIZE),
+            atlas_texture: None,
+            images: HashMap::new(),
+        }
+    }
+
+    /// Draw a display list on top of what is on a target, whose pixels are device pixels.
+    /// Glyphs are rasterized from `fonts`, and glyphs without a face are drawn as boxes. Images
+    /// are looked up in `images`, and are left out without it.
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        target: &Texture,
+        list: &DisplayList,
+        fonts: &FontContext,
+        images: Option<&ImageCache>,
+    ) {
+        let (width, height) = (target.width(), target.height());
+        let size = (width as f32, height as f32);
+        let commands = batch_display_list(list, size, &mut self.atlas, fonts);
+        self.upload(device, queue, list, images);
+
+        let linear = if self.format.is_srgb() { 1.0f32 } else { 0.0 };
+        let globals = [size.0, size.1, linear, 0.0];
+        queue.write_buffer(&self.globals, 0, bytemuck::cast_slice(&globals));
+
+        // The instances of all batches are in one buffer, with a quad that covers the target
+        // for each layer, which composites it.
+        let mut instances = Vec::new();
+        let mut opacities = Vec::new();
+        for command in &commands {
+            match command {
+                BatchCommand::Draw(batch) => instances.extend_from_slice(&batch.instances),
+                BatchCommand::PushLayer(opacity) => opacities.push(*opacity),
+                BatchCommand::PopLayer => {
+                    let opacity = opacities.pop().unwrap_or(1.0);
+                    instances.push(layer_instance(size, opacity));
+                }
+            }
+        }
+        if instances.is_empty() {
+            return;
+        }
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("boxrs instances"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: BufferUsages::VERTEX,
+        });
+
+        let target = &target.create_view(&TextureViewDescriptor::default());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        // The layers of the groups that are being drawn, with the innermost on top.
+        let mut layers: Vec<(Texture, TextureView)> = Vec::new();
+        let mut next = 0;
+        for command in &commands {
+            match command {
+                BatchCommand::Draw(batch) => {
+                    let view = layers.last().map_or(target, |(_, view)| view);
+                    let count = batch.instances.len() as u32;
+                    let (pipeline, bind_group) = match batch.texture {
+                        BatchTexture::None => (&self.color_pipeline, &self.blank.bind_group),
+                        BatchTexture::Atlas => match &self.atlas_texture {
+                            Some((_, atlas)) => (&self.glyph_pipeline, &atlas.bind_group),
+                            None => {
+                                next += count;
+                                continue;
+                            }
+                        },
+                        BatchTexture::Image(image) => match self.images.get(&image.id()) {
+                            Some(image) => (&self.image_pipeline, &image.bind_group),
+                            None => {
+                                next += count;
+                                continue;
+                            }
+                        },
+                    };
+                    let scissor = scissor((width, height), batch.scissor);
+                    let range = next..next + count;
+                    next += count;
+                    if let Some(scissor) = scissor {
+                        let mut pass = begin_pass(&mut encoder, view, LoadOp::Load);
+                        pass.set_pipeline(pipeline);
+                        pass.set_bind_group(0, &self.globals_bind_group, &[]);
+                        pass.set_bind_group(1, bind_group, &[]);
+                        pass.set_vertex_buffer(0, buffer.slice(..));
+                        pass.set_scissor_rect(scissor.0, scissor.1, scissor.2, scissor.3);
+                        pass.draw(0..4, range);
+                    }
+                }
+                BatchCommand::PushLayer(_) => {
+                    let layer = texture(device, (width, height), self.format);
+                    let view = layer.create_view(&TextureViewDescriptor::default());
+                    begin_pass(&mut encoder, &view, LoadOp::Clear(wgpu::Color::TRANSPARENT));
+                    layers.push((layer, view));
+                }
+                BatchCommand::PopLayer => {
+                    let (layer, _) = match layers.pop() {
+                        Some(layer) => layer,
+                        None => continue,
+                    };
+                    let bound = self.bind(device, layer, true);
+                    let view = layers.last().map_or(target, |(_, view)| view);
+                    let mut pass = begin_pass(&mut encoder, view, LoadOp::Load);
+                    pass.set_pipeline(&self.layer_pipeline);
+                    pass.set_bind_group(0, &self.globals_bind_group, &[]);
+                    pass.set_bind_group(1, &bound.bind_group, &[]);
+                    pass.set_vertex_buffer(0, buffer.slice(..));
+                    pass.draw(0..4, next..next + 1);
+                    next += 1;
+                }
+            }
+        }
+        queue.submit([encoder.finish()]);
+    }
+
+    /// Upload the atlas, if it changed, and the images of the display list that weren't
+    /// uploaded yet.
+    fn upload(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        list: &DisplayList,
+        images: Option<&ImageCache>,
+    ) {
+        if let Some(images) = images {
+            for command in list {
+                if let DisplayCommand::Image { image, .. } = command {
+                    if self.images.contains_key(&image.id()) {
+                        continue;
+                    }
+                    let decoded = images.get(*image);
+                    if decoded.width == 0 || decoded.height == 0 {
+                        continue;
+                    }
+                    // Images are sampled in linear light when the target converts it to sRGB.
+                    let format = match self.format.is_srgb() {
+                        true => TextureFormat::Rgba8UnormSrgb,
+                        false => TextureFormat::Rgba8Unorm,
+                    };
+                    let size = (decoded.width, decoded.height);
+                    let uploaded = texture(device, size, format);
+                    write_pixels(queue, &uploaded, size, 4, &decoded.pixels);
+                    let bound = self.bind(device, uploaded, true);
+                    self.images.insert(image.id(), bound);
+                }
+            }
+        }
+
+        let generation = self.atlas.generation();
+        if self.atlas_texture.as_ref().map(|(g, _)| *g) != Some(generation) {
+            let atlas = match self.atlas_texture.take() {
+                Some((_, atlas)) => atlas,
+                None => {
+                    let size = (self.atlas.width, self.atlas.height);
+                    let atlas = texture(device, size, TextureFormat::R8Unorm);
+                    self.bind(device, atlas, false)
+                }
+            };
+            let size = (self.atlas.width, self.atlas.height);
+            write_pixels(queue, &atlas.texture, size, 1, &self.atlas.pixels);
+            self.atlas_texture = Some((generation, atlas));
+        }
+    }
+
+    /// Bind a texture with a sampler that interpolates, or that picks the nearest pixel.
+    fn bind(&self, device: &Device, texture: Texture, interpolate: bool) -> Bound {
+        let sampler = match interpolate {
+            true => &self.linear_sampler,
+            false => &self.nearest_sampler,
+        };
+        bind(device, &self.texture_layout, sampler, texture)
+    }
+}
+
+fn bind(device: &Device, layout: &BindGroupLayout, sampler: &Sampler, texture: Texture) -> Bound {
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+    Bound {
+        texture,
+        bind_group,
+    }
+}
+
+/// A texture that can be sampled, drawn on and written to.
+fn texture(device: &Device, (width, height): (u32, u32), format: TextureFormat) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING
+            | TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_DST
+            | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Write pixels of some bytes each into a whole texture, row by row.
+fn write_pixels(
+    queue: &Queue,
+    texture: &Texture,
+    (width, height): (u32, u32),
+    bytes: u32,
+    data: &[u8],
+) {
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        data,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(width * bytes),
+            rows_per_image: Some(height),
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+fn begin_pass<'e>(
+    encoder: &'e mut CommandEncoder,
+    view: &TextureView,
+    load: LoadOp<wgpu::Color>,
+) -> wgpu::RenderPass<'e> {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: Operations {
+                load,
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+/// The quad that composites a layer with an opacity onto the whole target below it.
+fn layer_instance((width, height): (f32, f32), opacity: f32) -> Instance {
+    let target = [0.0, 0.0, width, height];
+    Instance {
+        rect: target,
+        uv: [0.0, 0.0, 1.0, 1.0],
+        color: [1.0, 1.0, 1.0, opacity],
+        radii: [0.0; 4],
+        clip: target,
+        clip_radii: [0.0; 4],
+        clip_out: [0.0; 4],
+        clip_out_radii: [0.0; 4],
+    }
+}
+
+/// The scissor rectangle of a clip on a target of a size: x, y, width and height in whole
+/// pixels, or `None` if it is empty.
+fn scissor((width, height): (u32, u32), clip: crate::layout::Rect) -> Option<(u32, u32, u32, u32)> {
+    let left = (clip.x.max(0.0) as u32).min(width);
+    let top = (clip.y.max(0.0) as u32).min(height);
+    let right = ((clip.x + clip.width).ceil().max(0.0) as u32).min(width);
+    let bottom = ((clip.y + clip.height).ceil().max(0.0) as u32).min(height);
+    match right > left && bottom > top {
+        true => Some((left, top, right - left, bottom - top)),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::css::Color;
+    use crate::fonts::FontContext;
+    use crate::layout::{CornerRadii, Rect};
+    use crate::painting::wgpu::*;
+
+    /// The first device of the system, or `None` on systems without a graphics driver.
+    fn device() -> Option<(Device, Queue)> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .into_iter()
+            .next()?;
+        pollster::block_on(adapter.request_device(&Default::default(), None)).ok()
+    }
+
+    /// Draw a display list on a transparent target of 64 by 16 pixels, and read its pixels.
+    fn render(device: &Device, queue: &Queue, list: &DisplayList) -> Vec<[u8; 4]> {
+        let format = TextureFormat::Rgba8Unorm;
+        let target = texture(device, (64, 16), format);
+        let mut renderer = Renderer::new(device, format);
+        renderer.draw(device, queue, &target, list, &FontContext::new(), None);
+
+        // Rows are copied 256 bytes apart, which is 64 pixels.
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 64 * 16 * 4,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(64 * 4),
+                    rows_per_image: Some(16),
+                },
+            },
+            target.size(),
+        );
+        queue.submit([encoder.finish()]);
+        buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let pixels = buffer.slice(..).get_mapped_range();
+        pixels
+            .chunks(4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+            .collect()
+    }
+
+    #[test]
+    fn test_draw() {
+        // Systems without a graphics driver have nothing to draw with.
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let square = |x| Rect {
+            x,
+            y: 0.0,
+            width: 16.0,
+            height: 16.0,
+        };
+        let list = vec![
+            // A circle, whose corner pixels are left out.
+            DisplayCommand::RoundedRect(
+                red.clone(),
+                square(0.0),
+                CornerRadii {
+                    top_left: 8.0,
+                    top_right: 8.0,
+                    bottom_right: 8.0,
+                    bottom_left: 8.0,
+                },
+            ),
+            // Two squares in a group with an opacity, which doesn't add up where they overlap.
+            DisplayCommand::PushOpacityLayer(0.5),
+            DisplayCommand::SolidColor(red.clone(), square(24.0)),
+            DisplayCommand::SolidColor(red.clone(), square(32.0)),
+            DisplayCommand::PopLayer,
+            // A square with a round hole.
+            DisplayCommand::PushRoundedClipOut(
+                Rect {
+                    x: 52.0,
+                    y: 4.0,
+                    width: 8.0,
+                    height: 8.0,
+                },
+                CornerRadii {
+                    top_left: 4.0,
+                    top_right: 4.0,
+                    bottom_right: 4.0,
+                    bottom_left: 4.0,
+                },
+            ),
+            DisplayCommand::SolidColor(red, square(48.0)),
+            DisplayCommand::PopClip,
+        ];
+
+        let pixels = render(&device, &queue, &list);
+        let pixel = |x: usize, y: usize| pixels[y * 64 + x];
+        assert_eq!(pixel(0, 0)[3], 0);
+        assert_eq!(pixel(8, 8), [255, 0, 0, 255]);
+        assert!(pixel(36, 8)[3].abs_diff(128) <= 1);
+        assert!(pixel(28, 8)[3].abs_diff(128) <= 1);
+        assert_eq!(pixel(49, 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(52, 4), [255, 0, 0, 255]);
+        assert_eq!(pixel(56, 8)[3], 0);
+    }
+}
@@ -0,0 +1,118 @@
+//! A window that shows a document, to look at it while working on it. The document is rendered
+//! with the software rasterizer, like `Engine::render`, and the pixels are copied to the window.
+
+use glium::glutin::dpi::LogicalSize;
+use glium::glutin::event::{Event, MouseScrollDelta, WindowEvent};
+use glium::glutin::event_loop::{ControlFlow, EventLoop};
+use glium::glutin::platform::run_return::EventLoopExtRunReturn;
+use glium::glutin::window::WindowBuilder;
+use glium::glutin::ContextBuilder;
+use glium::texture::{RawImage2d, Texture2d};
+use glium::uniforms::MagnifySamplerFilter;
+use glium::{Display, Surface};
+
+use crate::engine::{Engine, ScrollTarget};
+use crate::layout::Viewport;
+use crate::Error;
+
+/// The distance that the document scrolls for each line that a mouse wheel turns, in CSS pixels.
+const LINE_HEIGHT: f32 = 40.0;
+
+/// Open a window that shows a document with a stylesheet, and return when it is closed. The
+/// document is laid out again when the window is resized, and scrolls with the mouse wheel.
+pub fn show(html: &str, css: &str) -> Result<(), Error> {
+    let mut engine = Engine::new(Viewport::new(800.0, 600.0));
+    engine.load_html(html)?;
+    engine.add_stylesheet(css)?;
+    show_engine(&mut engine)
+}
+
+/// Open a window that shows the document of an engine, like [`show`].
+pub fn show_engine(engine: &mut Engine) -> Result<(), Error> {
+    let mut event_loop = EventLoop::new();
+    let viewport = engine.viewport();
+    let window = WindowBuilder::new()
+        .with_title("boxrs")
+        .with_inner_size(LogicalSize::new(viewport.width, viewport.height));
+    let display = Display::new(window, ContextBuilder::new(), &event_loop)?;
+    resize(engine, &display);
+
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                    resize(engine, &display);
+                    display.gl_window().window().request_redraw();
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let (x, y) = engine.scroll_offset(&ScrollTarget::Root);
+                    let (dx, dy) = scroll_delta(delta, engine.viewport().dpr);
+                    let offset = ((x + dx).max(0.0), (y + dy).max(0.0));
+                    engine.set_scroll_offset(ScrollTarget::Root, offset.0, offset.1);
+                    display.gl_window().window().request_redraw();
+                }
+                _ => {}
+            },
+            Event::RedrawRequested(_) => draw(engine, &display),
+            _ => {}
+        }
+    });
+    Ok(())
+}
+
+/// Fit the viewport to the window, in CSS pixels at the scale of the screen.
+fn resize(engine: &mut Engine, display: &Display) {
+    let window = display.gl_window();
+    let size = window.window().inner_size();
+    let dpr = window.window().scale_factor() as f32;
+    engine.set_viewport(Viewport {
+        width: size.width as f32 / dpr,
+        height: size.height as f32 / dpr,
+        dpr,
+        ..engine.viewport()
+    });
+}
+
+/// Render the document, and copy the canvas to the window.
+fn draw(engine: &mut Engine, display: &Display) {
+    let canvas = engine.render();
+    let size = (canvas.width as u32, canvas.height as u32);
+    // Textures have their first row at the bottom.
+    let image = RawImage2d::from_raw_rgba_reversed(&canvas.to_rgba8(), size);
+    let texture = Texture2d::new(display, image).expect("canvases fit in a texture");
+
+    let target = display.draw();
+    texture
+        .as_surface()
+        .fill(&target, MagnifySamplerFilter::Nearest);
+    target.finish().expect("the window can be drawn on");
+}
+
+/// How far a turn of the mouse wheel scrolls the document, in CSS pixels. Wheels turn down to
+/// scroll down, and touchpads give the distance in device pixels.
+fn scroll_delta(delta: MouseScrollDelta, dpr: f32) -> (f32, f32) {
+    match delta {
+        MouseScrollDelta::LineDelta(x, y) => (-x * LINE_HEIGHT, -y * LINE_HEIGHT),
+        MouseScrollDelta::PixelDelta(position) => {
+            (-position.x as f32 / dpr, -position.y as f32 / dpr)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::glutin::dpi::PhysicalPosition;
+    use glium::glutin::event::MouseScrollDelta;
+
+    use crate::preview::*;
+
+    #[test]
+    fn test_scroll_delta() {
+        let lines = MouseScrollDelta::LineDelta(0.0, -2.0);
+        assert_eq!(scroll_delta(lines, 2.0), (0.0, 2.0 * LINE_HEIGHT));
+        let pixels = MouseScrollDelta::PixelDelta(PhysicalPosition::new(10.0, -30.0));
+        assert_eq!(scroll_delta(pixels, 2.0), (-5.0, 15.0));
+    }
+}
@@ -0,0 +1,200 @@
+//! Timings of each phase of rendering a document, and counts of what each phase produced, to find
+//! out which parts of a document are slow to render.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::debug_span;
+
+use crate::css::units::UnitContext;
+use crate::css::{Color, Sheet};
+use crate::dom::Node;
+use crate::layout::{
+    layout_tree_with_context, AnonymousBlock, BlockNode, GridNode, InlineNode, LayoutBox,
+    LayoutContext, MarkerBox, TextRun, Viewport,
+};
+use crate::painting::raster::Canvas;
+use crate::painting::{build_display_list, scale_display_list, DisplayList};
+use crate::style::style_tree;
+
+pub use self::trace::{record_trace, ChromeTrace, TraceEvent};
+
+mod trace;
+
+/// A phase of rendering a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Parse,
+    Style,
+    Layout,
+    DisplayList,
+    Raster,
+}
+
+/// How long each phase of rendering took, and how many boxes and drawing commands it produced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub parse: Duration,
+    pub style: Duration,
+    pub layout: Duration,
+    pub display_list: Duration,
+    pub raster: Duration,
+    /// The number of layout boxes of each element, by tag name. Text nodes are counted as `#text`
+    /// and the lines of text in them as `#text-run`, list markers as `::marker` and anonymous
+    /// boxes as `#anonymous`.
+    pub boxes: HashMap<String, usize>,
+    pub display_commands: usize,
+}
+
+impl RenderStats {
+    pub fn new() -> RenderStats {
+        Default::default()
+    }
+
+    /// Run a phase, and add the time it takes to the timing of the phase. The phase is a `phase`
+    /// span, named after it, in a recorded trace.
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let _span = debug_span!("phase", name = ?phase).entered();
+        let start = Instant::now();
+        let result = f();
+        *self.timing_mut(phase) += start.elapsed();
+        result
+    }
+
+    /// The time spent in a phase.
+    pub fn timing(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Parse => self.parse,
+            Phase::Style => self.style,
+            Phase::Layout => self.layout,
+            Phase::DisplayList => self.display_list,
+            Phase::Raster => self.raster,
+        }
+    }
+
+    fn timing_mut(&mut self, phase: Phase) -> &mut Duration {
+        match phase {
+            Phase::Parse => &mut self.parse,
+            Phase::Style => &mut self.style,
+            Phase::Layout => &mut self.layout,
+            Phase::DisplayList => &mut self.display_list,
+            Phase::Raster => &mut self.raster,
+        }
+    }
+
+    /// The time spent in all phases together.
+    pub fn total(&self) -> Duration {
+        self.parse + self.style + self.layout + self.display_list + self.raster
+    }
+
+    /// Count the boxes in a layout tree.
+    pub fn count_boxes(&mut self, layout_box: &LayoutBox) {
+        let name = match layout_box.box_type {
+            BlockNode(style) | InlineNode(style) | GridNode(style) => match style.node {
+                Node::Element { tag, .. } => tag.as_str(),
+                Node::Text(_) => "#text",
+            },
+            TextRun(..) => "#text-run",
+            MarkerBox(..) => "::marker",
+            AnonymousBlock => "#anonymous",
+        };
+        *self.boxes.entry(name.to_owned()).or_default() += 1;
+
+        for child in &layout_box.children {
+            self.count_boxes(child);
+        }
+    }
+
+    /// Count the drawing commands in a display list.
+    pub fn count_commands(&mut self, list: &DisplayList) {
+        self.display_commands += list.len();
+    }
+}
+
+/// Render a document on a white canvas the size of the viewport, and measure each phase.
+pub fn render(html: &str, css: &str, viewport: Viewport) -> (Canvas, RenderStats) {
+    let mut stats = RenderStats::new();
+    let rect = viewport.rect();
+
+    let (document, sheet) = stats.time(Phase::Parse, || (Node::from(html), Sheet::from(css)));
+    let style_root = stats.time(Phase::Style, || {
+        let mut style_root = style_tree(&document, &sheet);
+        style_root.resolve_units(&UnitContext {
+            viewport: (rect.width, rect.height),
+            ..Default::default()
+        });
+        style_root
+    });
+    let layout_root = stats.time(Phase::Layout, || {
+        layout_tree_with_context(&style_root, &LayoutContext::for_viewport(&viewport))
+    });
+    let list = stats.time(Phase::DisplayList, || {
+        let mut list = build_display_list(&layout_root);
+        scale_display_list(&mut list, viewport.scale());
+        list
+    });
+    stats.count_boxes(&layout_root);
+    stats.count_commands(&list);
+
+    let white = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 255,
+    };
+    let (width, height) = viewport.device_size();
+    let mut canvas = Canvas::new(width, height, white);
+    stats.time(Phase::Raster, || canvas.paint(&list, None));
+
+    (canvas, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::*;
+    use crate::profiling::*;
+
+    #[test]
+    fn test_render() {
+        let (canvas, stats) = render(
+            "<div><p>a</p><p>b</p></div>",
+            "div, p { display: block; } p { background: #ff0000; }",
+            Viewport::new(80.0, 60.0),
+        );
+
+        assert_eq!((canvas.width, canvas.height), (80, 60));
+        assert_eq!(stats.boxes["div"], 1);
+        assert_eq!(stats.boxes["p"], 2);
+        assert_eq!(stats.boxes["#text"], 2);
+        assert_eq!(stats.boxes["#text-run"], 2);
+        assert!(stats.display_commands >= 2);
+        assert!(stats.total() >= stats.timing(Phase::Layout));
+    }
+
+    #[test]
+    fn test_record_trace() {
+        let (_, trace) = record_trace(|| {
+            render(
+                "<div><p>a</p></div>",
+                "div, p { display: block; }",
+                Viewport::new(80.0, 60.0),
+            )
+        });
+
+        let begins = trace.events.iter().filter(|event| event.begin).count();
+        assert_eq!(begins * 2, trace.events.len());
+        let phases = trace.events.iter().filter(|event| event.begin);
+        let phases: Vec<_> = phases
+            .filter(|event| event.category == "phase")
+            .map(|event| event.name.as_str())
+            .collect();
+        assert_eq!(
+            phases,
+            ["Parse", "Style", "Layout", "DisplayList", "Raster"]
+        );
+        assert!(trace.events.iter().any(|event| event.name == "p"));
+
+        let json = trace.to_json();
+        assert!(json.starts_with("{\"traceEvents\":[{\"name\":\"Parse\",\"cat\":\"phase\""));
+    }
+}
@@ -0,0 +1,188 @@
+//! Recording the spans of rendering as a trace in the Chrome trace event format, which
+//! about:tracing and Perfetto open, to see when each phase ran and how long the layout of each
+//! box took.
+//!
+//! https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Run `f` while recording the spans that it enters, such as the phases of rendering and, at the
+/// trace level, the layout of each box.
+pub fn record_trace<T>(f: impl FnOnce() -> T) -> (T, ChromeTrace) {
+    let recorder = Arc::new(Recorder::new());
+    let result = tracing::subscriber::with_default(recorder.clone(), f);
+    let trace = std::mem::take(&mut recorder.state.lock().unwrap().trace);
+    (result, trace)
+}
+
+/// The spans that were entered while recording, in the order that they began and ended.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChromeTrace {
+    pub events: Vec<TraceEvent>,
+}
+
+/// The beginning or the end of a span.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    /// The `name` field of the span, like the tag name of a box, or else the name of the span.
+    pub name: String,
+    /// The name of the span, like `layout` or `layout_box`.
+    pub category: &'static str,
+    /// Whether the span begins or ends.
+    pub begin: bool,
+    /// The microseconds since recording started.
+    pub timestamp: f64,
+    /// The thread that entered the span, numbered from 0 in the order they were first seen.
+    pub thread: usize,
+    /// The other fields of the span, formatted with `Debug`.
+    pub args: Vec<(&'static str, String)>,
+}
+
+impl ChromeTrace {
+    /// The trace in the JSON object format of Chrome trace events.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, event) in self.events.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"name\":");
+            push_string(&mut json, &event.name);
+            json.push_str(",\"cat\":");
+            push_string(&mut json, event.category);
+            let phase = if event.begin { "B" } else { "E" };
+            write!(json, ",\"ph\":\"{}\",\"ts\":{}", phase, event.timestamp).unwrap();
+            write!(json, ",\"pid\":1,\"tid\":{},\"args\":{{", event.thread).unwrap();
+            for (j, (name, value)) in event.args.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                push_string(&mut json, name);
+                json.push(':');
+                push_string(&mut json, value);
+            }
+            json.push_str("}}");
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Append a string as a JSON string.
+fn push_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// A subscriber that turns the spans it sees into trace events.
+struct Recorder {
+    start: Instant,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The spans that were created, by their id minus one.
+    spans: Vec<Span>,
+    threads: HashMap<ThreadId, usize>,
+    trace: ChromeTrace,
+}
+
+struct Span {
+    name: String,
+    category: &'static str,
+    args: Vec<(&'static str, String)>,
+}
+
+impl Recorder {
+    fn new() -> Recorder {
+        Recorder {
+            start: Instant::now(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn push_event(&self, span: &Id, begin: bool) {
+        let timestamp = self.start.elapsed().as_secs_f64() * 1e6;
+        let mut state = self.state.lock().unwrap();
+        let threads = state.threads.len();
+        let thread = *state
+            .threads
+            .entry(thread::current().id())
+            .or_insert(threads);
+        let span = &state.spans[span.into_u64() as usize - 1];
+        let event = TraceEvent {
+            name: span.name.clone(),
+            category: span.category,
+            begin,
+            timestamp,
+            thread,
+            args: span.args.clone(),
+        };
+        state.trace.events.push(event);
+    }
+}
+
+impl Visit for Span {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "name" => self.name = value.to_owned(),
+            name => self.args.push((name, value.to_owned())),
+        }
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes) -> Id {
+        let mut span = Span {
+            name: attributes.metadata().name().to_owned(),
+            category: attributes.metadata().name(),
+            args: Vec::new(),
+        };
+        attributes.record(&mut span);
+        let mut state = self.state.lock().unwrap();
+        state.spans.push(span);
+        Id::from_u64(state.spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record) {
+        let mut state = self.state.lock().unwrap();
+        values.record(&mut state.spans[span.into_u64() as usize - 1]);
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event) {}
+
+    fn enter(&self, span: &Id) {
+        self.push_event(span, true);
+    }
+
+    fn exit(&self, span: &Id) {
+        self.push_event(span, false);
+    }
+}
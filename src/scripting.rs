@@ -0,0 +1,305 @@
+//! Running the scripts of a document with boa. Scripts change the document through a small part
+//! of the DOM: `document.getElementById`, and the attributes, classes and text of elements.
+//!
+//! https://dom.spec.whatwg.org/
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use boa_engine::object::ObjectInitializer;
+use boa_engine::property::Attribute;
+use boa_engine::{
+    js_string, Context, JsError, JsObject, JsResult, JsString, JsValue, NativeFunction, Source,
+};
+
+use crate::dom::Node;
+use crate::engine::Mutator;
+
+/// How many times a loop may run, and how deep functions may call themselves, before the script
+/// is stopped with an error. Without them, a script like `while (true) {}` would never end.
+const LOOP_ITERATION_LIMIT: u64 = 100_000;
+const RECURSION_LIMIT: usize = 512;
+
+/// The document that the functions of a script context share, with the paths of the elements
+/// that they changed.
+struct ScriptDocument {
+    document: Node,
+    changed: Vec<Vec<usize>>,
+}
+
+type SharedDocument = Rc<RefCell<ScriptDocument>>;
+
+/// Run the `script` elements of a document in order, in one context, so that later scripts see
+/// what earlier ones defined. Scripts with a `src` attribute are not loaded. The elements that
+/// they change are changed through the mutator, so that only those are styled and laid out again.
+///
+/// Returns the errors of the scripts that failed, including those that ran into the limits of
+/// loops or recursion. A script that fails doesn't stop the scripts after it.
+pub fn run_scripts(mutator: &mut Mutator) -> Vec<JsError> {
+    let sources: Vec<String> = mutator
+        .document()
+        .get_elements_by_tag_name("script")
+        .into_iter()
+        .filter(|script| script.get_attribute("src").is_none())
+        .map(Node::get_text_content)
+        .collect();
+    if sources.is_empty() {
+        return Vec::new();
+    }
+
+    // The document is moved into the context while the scripts run, and taken back afterwards.
+    let shared = Rc::new(RefCell::new(ScriptDocument {
+        document: std::mem::replace(mutator.document, Node::text("")),
+        changed: Vec::new(),
+    }));
+    let mut context = Context::default();
+    context
+        .runtime_limits_mut()
+        .set_loop_iteration_limit(LOOP_ITERATION_LIMIT);
+    context
+        .runtime_limits_mut()
+        .set_recursion_limit(RECURSION_LIMIT);
+    let mut errors = Vec::new();
+    match register_document(&mut context, &shared) {
+        Ok(()) => {
+            for source in sources {
+                if let Err(error) = context.eval(Source::from_bytes(&source)) {
+                    errors.push(error);
+                }
+            }
+        }
+        Err(error) => errors.push(error),
+    }
+    let ScriptDocument {
+        document,
+        mut changed,
+    } = shared.replace(ScriptDocument {
+        document: Node::text(""),
+        changed: Vec::new(),
+    });
+    *mutator.document = document;
+    mutator.changed.append(&mut changed);
+    errors
+}
+
+/// Add the global `document` object to a context.
+fn register_document(context: &mut Context, document: &SharedDocument) -> JsResult<()> {
+    let shared = document.clone();
+    // SAFETY: The closure only holds the document, which the garbage collector doesn't trace.
+    let get_element_by_id = unsafe {
+        NativeFunction::from_closure(move |_, args, context| {
+            let args = to_strings(args, context)?;
+            let id = arg(&args, 0);
+            if shared.borrow().document.path_to_element_by_id(id).is_none() {
+                return Ok(JsValue::null());
+            }
+            Ok(element(context, &shared, id).into())
+        })
+    };
+
+    let object = ObjectInitializer::new(context)
+        .function(get_element_by_id, js_string!("getElementById"), 1)
+        .build();
+    context.register_global_property(js_string!("document"), object, Attribute::all())
+}
+
+/// The object of the element with an `id`, which changes it through its methods.
+fn element(context: &mut Context, document: &SharedDocument, id: &str) -> JsObject {
+    let class_list = ObjectInitializer::new(context)
+        .function(
+            method(document, id, |mutator, id, args| {
+                has_class(mutator, id, arg(args, 0)).into()
+            }),
+            js_string!("contains"),
+            1,
+        )
+        .function(
+            method(document, id, |mutator, id, args| {
+                if !has_class(mutator, id, arg(args, 0)) {
+                    mutator.toggle_class(id, arg(args, 0));
+                }
+                JsValue::undefined()
+            }),
+            js_string!("add"),
+            1,
+        )
+        .function(
+            method(document, id, |mutator, id, args| {
+                if has_class(mutator, id, arg(args, 0)) {
+                    mutator.toggle_class(id, arg(args, 0));
+                }
+                JsValue::undefined()
+            }),
+            js_string!("remove"),
+            1,
+        )
+        .function(
+            method(document, id, |mutator, id, args| {
+                mutator.toggle_class(id, arg(args, 0));
+                has_class(mutator, id, arg(args, 0)).into()
+            }),
+            js_string!("toggle"),
+            1,
+        )
+        .build();
+
+    let realm = context.realm().clone();
+    let get_text = method(document, id, |mutator, id, _| match mutator.element(id) {
+        Some(element) => JsString::from(element.get_text_content().as_str()).into(),
+        None => JsValue::undefined(),
+    });
+    let set_text = method(document, id, |mutator, id, args| {
+        mutator.set_text_content(id, arg(args, 0));
+        JsValue::undefined()
+    });
+
+    ObjectInitializer::new(context)
+        .property(js_string!("id"), JsString::from(id), Attribute::READONLY)
+        .property(js_string!("classList"), class_list, Attribute::READONLY)
+        .accessor(
+            js_string!("textContent"),
+            Some(get_text.to_js_function(&realm)),
+            Some(set_text.to_js_function(&realm)),
+            Attribute::CONFIGURABLE,
+        )
+        .function(
+            method(document, id, |mutator, id, args| {
+                match mutator
+                    .element(id)
+                    .and_then(|e| e.get_attribute(arg(args, 0)))
+                {
+                    Some(value) => JsString::from(value).into(),
+                    None => JsValue::null(),
+                }
+            }),
+            js_string!("getAttribute"),
+            1,
+        )
+        .function(
+            method(document, id, |mutator, id, args| {
+                mutator.set_attribute(id, arg(args, 0), arg(args, 1));
+                JsValue::undefined()
+            }),
+            js_string!("setAttribute"),
+            2,
+        )
+        .function(
+            method(document, id, |mutator, id, args| {
+                mutator.remove_attribute(id, arg(args, 0));
+                JsValue::undefined()
+            }),
+            js_string!("removeAttribute"),
+            1,
+        )
+        .build()
+}
+
+/// A function that reads or changes the element with an `id` through a mutator, with its
+/// arguments as strings. It does nothing if the element was removed.
+fn method(
+    document: &SharedDocument,
+    id: &str,
+    f: fn(&mut Mutator, &str, &[String]) -> JsValue,
+) -> NativeFunction {
+    let (document, id) = (document.clone(), id.to_owned());
+    // SAFETY: The closure only holds the document and a string, which the garbage collector
+    // doesn't trace.
+    unsafe {
+        NativeFunction::from_closure(move |_, args, context| {
+            // The arguments are converted first, since that may run scripts that use the document.
+            let args = to_strings(args, context)?;
+            let mut shared = document.borrow_mut();
+            let shared = &mut *shared;
+            let mut mutator = Mutator {
+                document: &mut shared.document,
+                changed: Vec::new(),
+            };
+            if mutator.element(&id).is_none() {
+                return Ok(JsValue::undefined());
+            }
+            let result = f(&mut mutator, &id, &args);
+            shared.changed.append(&mut mutator.changed);
+            Ok(result)
+        })
+    }
+}
+
+fn to_strings(args: &[JsValue], context: &mut Context) -> JsResult<Vec<String>> {
+    args.iter()
+        .map(|value| Ok(value.to_string(context)?.to_std_string_escaped()))
+        .collect()
+}
+
+/// An argument of a function, which is `undefined` if it wasn't given, like in JavaScript.
+fn arg(args: &[String], index: usize) -> &str {
+    args.get(index).map_or("undefined", String::as_str)
+}
+
+fn has_class(mutator: &Mutator, id: &str, class: &str) -> bool {
+    mutator
+        .element(id)
+        .is_some_and(|element| element.get_classes().contains(class))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::Node;
+    use crate::scripting::*;
+
+    #[test]
+    fn test_run_scripts() {
+        let mut document = Node::from(
+            "<html>\
+                <p id=\"greeting\" class=\"hidden\">hi</p>\
+                <script>\
+                    var p = document.getElementById('greeting');\
+                    p.classList.toggle('hidden');\
+                    p.textContent = p.textContent + ' there';\
+                </script>\
+                <script>missing();</script>\
+                <script>p.setAttribute('title', document.getElementById('none'));</script>\
+            </html>",
+        );
+        let mut mutator = Mutator {
+            document: &mut document,
+            changed: Vec::new(),
+        };
+        let errors = run_scripts(&mut mutator);
+        assert_eq!(errors.len(), 1);
+        // The paragraph is the only element that changed, so only it is styled again.
+        assert!(!mutator.changed.is_empty());
+        assert!(mutator.changed.iter().all(|path| path == &[0]));
+
+        let p = document.get_element_by_id_mut("greeting").unwrap();
+        assert_eq!(p.get_attribute("class"), Some(""));
+        assert_eq!(p.get_attribute("title"), Some("null"));
+        assert_eq!(p.get_text_content(), "hi there");
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let mut document = Node::from(
+            "<html>\
+                <p id=\"p\">hi</p>\
+                <script>while (true) {}</script>\
+                <script>function f() { return f(); } f();</script>\
+                <script>document.getElementById('p').textContent = 'done';</script>\
+            </html>",
+        );
+        let mut mutator = Mutator {
+            document: &mut document,
+            changed: Vec::new(),
+        };
+
+        // Scripts that would never end are stopped, and the scripts after them still run.
+        let errors = run_scripts(&mut mutator);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            document
+                .get_element_by_id_mut("p")
+                .unwrap()
+                .get_text_content(),
+            "done"
+        );
+    }
+}
@@ -1,116 +1,1135 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::slice;
+use std::sync::Arc;
 
-use crate::css::{Rule, Selector, Sheet, Specificity, Value};
-use crate::dom::Node;
+use crate::css::units::UnitContext;
+use crate::css::{AttrOp, Color, Medium, Rule, Selector, Sheet, Specificity, Unit, Value};
+use crate::dom::{Atom, Attributes, Node};
 
 pub type PropertyMap = HashMap<String, Value>;
 
+/// Properties that take the value of the parent element when they are not specified.
+///
+/// http://www.w3.org/TR/CSS2/cascade.html#inheritance
+const INHERITED_PROPERTIES: [&str; 15] = [
+    "color",
+    "direction",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "letter-spacing",
+    "line-height",
+    "list-style-type",
+    "overflow-wrap",
+    "text-align",
+    "text-transform",
+    "white-space",
+    "word-break",
+    "word-spacing",
+];
+
+/// The properties with lengths whose initial value is `auto`.
+const INITIALLY_AUTO: [&str; 6] = ["width", "height", "top", "right", "bottom", "left"];
+
 #[derive(Debug)]
 pub struct StyledNode<'a> {
     pub node: &'a Node,
-    pub specified_values: PropertyMap,
+    /// The specified values of the node. Siblings with the same style share them, and they are
+    /// copied when they change.
+    pub specified_values: Arc<PropertyMap>,
     pub children: Vec<StyledNode<'a>>,
+    /// The `::before` pseudo-element, if it has content. Its node is the element it belongs to.
+    pub before: Option<Box<StyledNode<'a>>>,
+    /// The `::after` pseudo-element, if it has content. Its node is the element it belongs to.
+    pub after: Option<Box<StyledNode<'a>>>,
 }
 
-impl<'a> From<&'a StyledNode<'a>> for String {
-    fn from(styled_node: &StyledNode) -> String {
-        let mut output = String::new();
-
-        match styled_node.node {
+impl fmt::Display for StyledNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.node {
             Node::Element { tag, attrs, .. } => {
-                output.push('<');
-                output.push_str(tag);
-
-                let attrs_str = attrs.iter().fold("".to_owned(), |acc, x| {
-                    format!("{} {}=\"{}\"", acc, x.0, x.1)
-                });
-
-                output.push_str(&attrs_str);
-
-                output.push_str(" style=\"");
-
-                let mut specified_values: Vec<_> = styled_node.specified_values.iter().collect();
-                specified_values.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                write!(f, "<{}", tag)?;
+                for (name, value) in attrs.iter() {
+                    write!(f, " {}=\"{}\"", name, value)?;
+                }
 
+                write!(f, " style=\"")?;
+                let mut specified_values: Vec<_> = self.specified_values.iter().collect();
+                specified_values.sort_by_key(|&(a, _)| a);
                 for (key, value) in specified_values {
-                    output.push_str(&format!("{}:{};", key, String::from(value)));
+                    write!(f, "{}:{};", key, value)?;
                 }
-                output.push('"');
+                write!(f, "\">")?;
 
-                output.push('>');
-
-                for child in &styled_node.children {
-                    output.push_str(&String::from(child));
+                for child in &self.children {
+                    write!(f, "{}", child)?;
                 }
 
-                output.push_str("</");
-                output.push_str(tag);
-                output.push('>');
-            }
-            Node::Text(t) => {
-                output.push_str(t);
+                write!(f, "</{}>", tag)
             }
+            Node::Text(t) => write!(f, "{}", t),
         }
-
-        output
     }
 }
 
 #[derive(PartialEq)]
 pub enum Display {
     Inline,
+    /// An inline-level box that lays out its content as a block.
+    InlineBlock,
     Block,
+    Flex,
+    Grid,
+    ListItem,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    Visible,
+    Hidden,
+    Scroll,
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListStyleType {
+    Disc,
+    Decimal,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+/// The side of the line box that inline content is aligned to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    /// The side that lines start at in the direction of the block.
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    /// Widen the spaces between words so that each line fills the line box, except the last.
+    Justify,
+}
+
+/// The case that text is shown in, whatever its case in the document.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    /// The first letter of each word in uppercase.
+    Capitalize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+}
+
+impl WhiteSpace {
+    /// Whether sequences of white space collapse into a single space, with newlines treated as
+    /// spaces.
+    pub fn collapses(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// Whether lines may break at white space to fit their container.
+    pub fn wraps(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreWrap)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowWrap {
+    Normal,
+    /// Words that don't fit on a line by themselves may break at any character.
+    BreakWord,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordBreak {
+    Normal,
+    /// Lines may break between any two characters of a word.
+    BreakAll,
+}
+
+/// Whether text is set in an upright, italic or slanted face.
+///
+/// https://www.w3.org/TR/css-fonts-3/#font-style-prop
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// A length in px, a percentage of a size that only layout knows, or `auto`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LengthOrAuto {
+    Length(f32),
+    Percent(f32),
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+}
+
+/// How one side of a border is drawn.
+///
+/// http://www.w3.org/TR/CSS2/box.html#border-style-properties
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BorderStyle {
     None,
+    Solid,
+    Dashed,
+    Dotted,
+    /// Two lines, with a gap between them.
+    Double,
+}
+
+/// A 2D affine transformation, as the matrix
+///
+/// ```text
+/// | a c e |
+/// | b d f |
+/// | 0 0 1 |
+/// ```
+///
+/// https://www.w3.org/TR/css-transforms-1/#mathematical-description
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform::scale(1.0, 1.0)
+    }
+
+    pub fn translate(x: f32, y: f32) -> Transform {
+        Transform {
+            e: x,
+            f: y,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn scale(x: f32, y: f32) -> Transform {
+        Transform {
+            a: x,
+            b: 0.0,
+            c: 0.0,
+            d: y,
+            e: 0.0,
+            f: 0.0,
+        }
+    }
+
+    /// The transformation that applies `other` first, and then this one.
+    pub fn multiply(self, other: Transform) -> Transform {
+        Transform {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    /// Transform the point (`x`, `y`).
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
 }
 
 impl<'a> StyledNode<'a> {
-    pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).cloned()
+    /// The specified value of a property, borrowed from the node so that lookups don't allocate.
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.specified_values.get(name)
     }
 
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
+    /// Turn the lengths in relative and physical units of this node and its descendants into
+    /// px, with this node as the root element. `em` and `rem` are relative to the font sizes of
+    /// the elements, and the rest to the viewport and the DPI of the context.
+    ///
+    /// https://www.w3.org/TR/css-values-3/#lengths
+    pub fn resolve_units(&mut self, context: &UnitContext) {
+        let root_font_size = self.font_size(context);
+        self.resolve_units_in_subtree(&UnitContext {
+            root_font_size,
+            ..*context
+        });
+    }
+
+    fn resolve_units_in_subtree(&mut self, parent: &UnitContext) {
+        let context = UnitContext {
+            font_size: self.font_size(parent),
+            ..*parent
+        };
+        if self.specified_values.values().any(has_relative_units) {
+            for value in Arc::make_mut(&mut self.specified_values).values_mut() {
+                resolve_units(value, &context);
+            }
+        }
+        let pseudo_elements = self.before.iter_mut().chain(self.after.iter_mut());
+        for pseudo_element in pseudo_elements {
+            pseudo_element.resolve_units_in_subtree(&context);
+        }
+        for child in &mut self.children {
+            child.resolve_units_in_subtree(&context);
+        }
+    }
+
+    /// The font size of the node in px, with `font-size` relative to the font size of the
+    /// parent, in `parent`.
+    fn font_size(&self, parent: &UnitContext) -> f32 {
+        match self.value("font-size") {
+            Some(Value::Length(size, unit)) => {
+                unit.in_px(parent).map_or(parent.font_size, |px| size * px)
+            }
+            _ => parent.font_size,
+        }
+    }
+
+    pub fn lookup<'b>(&'b self, name: &str, fallback_name: &str, default: &'b Value) -> &'b Value {
         self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+            .or_else(|| self.value(fallback_name))
+            .unwrap_or(default)
+    }
+
+    /// The value of a property, or of the shorthand it is part of, such as `margin` for
+    /// `margin-left` and `border-color` for `border-top-color`.
+    fn longhand_value(&self, name: &str) -> Option<&Value> {
+        self.value(name).or_else(|| self.value(shorthand(name)?))
+    }
+
+    /// The keyword that a property is set to, if it is set to a keyword.
+    pub fn keyword(&self, name: &str) -> Option<&str> {
+        match self.longhand_value(name) {
+            Some(Value::Keyword(keyword)) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    /// The length in px of a property, or of its shorthand. Lengths default to 0, except those
+    /// of properties whose initial value is `auto`, such as `width` and `top`.
+    pub fn length_or_auto(&self, name: &str) -> LengthOrAuto {
+        match self.longhand_value(name) {
+            Some(Value::Length(percent, Unit::Percent)) => LengthOrAuto::Percent(*percent),
+            Some(length @ Value::Length(..)) => LengthOrAuto::Length(length.to_px()),
+            Some(_) => LengthOrAuto::Auto,
+            None if INITIALLY_AUTO.contains(&name) => LengthOrAuto::Auto,
+            None => LengthOrAuto::Length(0.0),
+        }
+    }
+
+    /// The color of a property, or of its shorthand, with `currentColor` resolved to the
+    /// `color` of the node. `color` defaults to black, and border colors to `currentColor`.
+    ///
+    /// https://www.w3.org/TR/css-color-3/#currentcolor
+    pub fn color(&self, name: &str) -> Option<Color> {
+        let is_color = name == "color";
+        match self.longhand_value(name) {
+            Some(Value::ColorValue(color)) => Some(color.clone()),
+            Some(value) if value.is_current_color() && !is_color => self.color("color"),
+            _ if is_color => Some(Color {
+                a: 255,
+                ..Color::default()
+            }),
+            None if name.starts_with("border") => self.color("color"),
+            _ => None,
+        }
     }
 
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
+        match self.keyword("display") {
+            Some("block") => Display::Block,
+            Some("inline-block") => Display::InlineBlock,
+            Some("flex") => Display::Flex,
+            Some("grid") => Display::Grid,
+            Some("list-item") => Display::ListItem,
+            Some("none") => Display::None,
             _ => Display::Inline,
         }
     }
+
+    pub fn position(&self) -> Position {
+        match self.keyword("position") {
+            Some("relative") => Position::Relative,
+            Some("absolute") => Position::Absolute,
+            _ => Position::Static,
+        }
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        match self.keyword("overflow") {
+            Some("hidden") => Overflow::Hidden,
+            Some("scroll") => Overflow::Scroll,
+            Some("auto") => Overflow::Auto,
+            _ => Overflow::Visible,
+        }
+    }
+
+    /// The style of the border on the given side: "top", "right", "bottom" or "left". Borders
+    /// are solid unless `border-style` or `border-*-style` says otherwise.
+    pub fn border_style(&self, side: &str) -> BorderStyle {
+        match self.keyword(&format!("border-{}-style", side)) {
+            Some("none" | "hidden") => BorderStyle::None,
+            Some("dashed") => BorderStyle::Dashed,
+            Some("dotted") => BorderStyle::Dotted,
+            Some("double") => BorderStyle::Double,
+            _ => BorderStyle::Solid,
+        }
+    }
+
+    /// The base direction of text, and the side that lines and blocks start from.
+    ///
+    /// http://www.w3.org/TR/CSS2/visuren.html#direction
+    pub fn direction(&self) -> Direction {
+        match self.keyword("direction") {
+            Some("rtl") => Direction::Rtl,
+            _ => Direction::Ltr,
+        }
+    }
+
+    /// The font families that text is set in, in order of preference. Generic families are
+    /// given by their keyword, such as `serif`.
+    ///
+    /// https://www.w3.org/TR/css-fonts-3/#font-family-prop
+    pub fn font_family(&self) -> Vec<String> {
+        font_family(self.value("font-family"))
+    }
+
+    /// The weight of the face that text is set in, from 1 to 1000, where 400 is normal.
+    ///
+    /// https://www.w3.org/TR/css-fonts-3/#font-weight-prop
+    pub fn font_weight(&self) -> u16 {
+        font_weight(self.value("font-weight"))
+    }
+
+    pub fn font_style(&self) -> FontStyle {
+        font_style(self.value("font-style"))
+    }
+
+    /// How white space in the text of the node is handled.
+    ///
+    /// http://www.w3.org/TR/CSS2/text.html#white-space-prop
+    pub fn white_space(&self) -> WhiteSpace {
+        match self.keyword("white-space") {
+            Some("pre") => WhiteSpace::Pre,
+            Some("nowrap") => WhiteSpace::Nowrap,
+            Some("pre-wrap") => WhiteSpace::PreWrap,
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    /// How the inline content of a block container is aligned on its lines.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#text-align-property
+    pub fn text_align(&self) -> TextAlign {
+        match self.keyword("text-align") {
+            Some("end") => TextAlign::End,
+            Some("left") => TextAlign::Left,
+            Some("right") => TextAlign::Right,
+            Some("center") => TextAlign::Center,
+            Some("justify") => TextAlign::Justify,
+            _ => TextAlign::Start,
+        }
+    }
+
+    /// The case that text is shown in.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#text-transform-property
+    pub fn text_transform(&self) -> TextTransform {
+        match self.keyword("text-transform") {
+            Some("uppercase") => TextTransform::Uppercase,
+            Some("lowercase") => TextTransform::Lowercase,
+            Some("capitalize") => TextTransform::Capitalize,
+            _ => TextTransform::None,
+        }
+    }
+
+    /// The extra space after each character of text, in px.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#letter-spacing-property
+    pub fn letter_spacing(&self) -> f32 {
+        self.value("letter-spacing")
+            .map_or(0.0, |value| value.to_px())
+    }
+
+    /// The extra space after each space between words, in px.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#word-spacing-property
+    pub fn word_spacing(&self) -> f32 {
+        self.value("word-spacing")
+            .map_or(0.0, |value| value.to_px())
+    }
+
+    /// Whether words that are too long for a line may be broken.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#overflow-wrap-property
+    pub fn overflow_wrap(&self) -> OverflowWrap {
+        // `word-wrap` is the legacy name of `overflow-wrap`.
+        match self.keyword("overflow-wrap").or(self.keyword("word-wrap")) {
+            Some("break-word" | "anywhere") => OverflowWrap::BreakWord,
+            _ => OverflowWrap::Normal,
+        }
+    }
+
+    /// Where lines may break within words.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#word-break-property
+    pub fn word_break(&self) -> WordBreak {
+        match self.keyword("word-break") {
+            Some("break-all") => WordBreak::BreakAll,
+            _ => WordBreak::Normal,
+        }
+    }
+
+    /// The counters that this element resets, with the values they start at.
+    ///
+    /// http://www.w3.org/TR/CSS2/generate.html#propdef-counter-reset
+    pub fn counter_reset(&self) -> Vec<(String, i32)> {
+        self.counters("counter-reset", 0)
+    }
+
+    /// The counters that this element increments, with the amounts they are incremented by.
+    ///
+    /// http://www.w3.org/TR/CSS2/generate.html#propdef-counter-increment
+    pub fn counter_increment(&self) -> Vec<(String, i32)> {
+        self.counters("counter-increment", 1)
+    }
+
+    /// A list of counter names, each optionally followed by an integer.
+    fn counters(&self, name: &str, default: i32) -> Vec<(String, i32)> {
+        let values = match self.value(name) {
+            Some(Value::List(values)) => values.as_slice(),
+            Some(value) => slice::from_ref(value),
+            None => &[],
+        };
+
+        let mut counters: Vec<(String, i32)> = Vec::new();
+        for value in values {
+            match value {
+                Value::Keyword(counter) if counter != "none" => {
+                    counters.push((counter.clone(), default))
+                }
+                &Value::Number(n) => {
+                    if let Some(counter) = counters.last_mut() {
+                        counter.1 = n as i32;
+                    }
+                }
+                Value::Length(0.0, _) => {
+                    if let Some(counter) = counters.last_mut() {
+                        counter.1 = 0;
+                    }
+                }
+                _ => {}
+            }
+        }
+        counters
+    }
+
+    /// The kind of marker generated for a list item.
+    pub fn list_style_type(&self) -> ListStyleType {
+        match self.keyword("list-style-type") {
+            Some("decimal") => ListStyleType::Decimal,
+            Some("none") => ListStyleType::None,
+            _ => ListStyleType::Disc,
+        }
+    }
+
+    /// The preferred ratio of the width to the height of the box, as a number or as `width /
+    /// height`, or `None` for `aspect-ratio: auto`.
+    ///
+    /// https://www.w3.org/TR/css-sizing-4/#aspect-ratio
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        let ratio = match self.value("aspect-ratio") {
+            Some(&Value::Number(ratio)) => ratio,
+            Some(Value::List(values)) => match values.as_slice() {
+                [Value::Number(width), Value::Keyword(slash), Value::Number(height)]
+                    if slash == "/" =>
+                {
+                    width / height
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        (ratio.is_finite() && ratio > 0.0).then_some(ratio)
+    }
+
+    /// The transformation of the box, from its list of transform functions, or `None` for
+    /// `transform: none`. Functions that are not supported make the whole value invalid.
+    ///
+    /// https://www.w3.org/TR/css-transforms-1/#transform-property
+    pub fn transform(&self) -> Option<Transform> {
+        let functions = match self.value("transform")? {
+            Value::List(values) => values.as_slice(),
+            value @ Value::Function(..) => slice::from_ref(value),
+            _ => return None,
+        };
+
+        functions
+            .iter()
+            .try_fold(Transform::identity(), |transform, function| {
+                let (name, arguments) = match function {
+                    Value::Function(name, arguments) => (name.as_str(), arguments.as_slice()),
+                    _ => return None,
+                };
+                // A plain `0` parses as a length.
+                let number = |value: &Value| match *value {
+                    Value::Number(n) => Some(n),
+                    Value::Length(0.0, _) => Some(0.0),
+                    _ => None,
+                };
+
+                let next = match (name, arguments) {
+                    ("translate", [x]) => Transform::translate(x.to_px(), 0.0),
+                    ("translate", [x, y]) => Transform::translate(x.to_px(), y.to_px()),
+                    ("translateX", [x]) => Transform::translate(x.to_px(), 0.0),
+                    ("translateY", [y]) => Transform::translate(0.0, y.to_px()),
+                    ("scale", [s]) => Transform::scale(number(s)?, number(s)?),
+                    ("scale", [x, y]) => Transform::scale(number(x)?, number(y)?),
+                    ("scaleX", [x]) => Transform::scale(number(x)?, 1.0),
+                    ("scaleY", [y]) => Transform::scale(1.0, number(y)?),
+                    ("matrix", [a, b, c, d, e, f]) => Transform {
+                        a: number(a)?,
+                        b: number(b)?,
+                        c: number(c)?,
+                        d: number(d)?,
+                        e: number(e)?,
+                        f: number(f)?,
+                    },
+                    _ => return None,
+                };
+                Some(transform.multiply(next))
+            })
+    }
+
+    /// The opacity of the box and its content as a group, between 0 and 1.
+    ///
+    /// https://www.w3.org/TR/css-color-3/#transparency
+    pub fn opacity(&self) -> f32 {
+        let opacity = match self.value("opacity") {
+            Some(&Value::Number(n)) => n,
+            // A plain `0` parses as a length.
+            Some(Value::Length(0.0, _)) => 0.0,
+            Some(Value::Length(percentage, Unit::Percent)) => percentage / 100.0,
+            _ => 1.0,
+        };
+        opacity.clamp(0.0, 1.0)
+    }
+
+    /// The stack level of a positioned box, or `None` for `z-index: auto`.
+    pub fn z_index(&self) -> Option<i32> {
+        match self.value("z-index") {
+            Some(&Value::Number(n)) => Some(n as i32),
+            Some(Value::Length(0.0, _)) => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// The family names in a value of `font-family`, which `@font-face` rules use as well.
+pub(crate) fn font_family(value: Option<&Value>) -> Vec<String> {
+    let family = |value: &Value| match value {
+        Value::Keyword(name) | Value::Str(name) => Some(name.clone()),
+        // A family name that isn't quoted may be several identifiers.
+        Value::List(words) => words
+            .iter()
+            .map(|word| match word {
+                Value::Keyword(word) => Some(word.as_str()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|words| words.join(" ")),
+        _ => None,
+    };
+    match value {
+        Some(Value::CommaList(families)) => families.iter().filter_map(family).collect(),
+        Some(value) => family(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+pub(crate) fn font_weight(value: Option<&Value>) -> u16 {
+    match value {
+        Some(Value::Keyword(s)) if s == "bold" => 700,
+        Some(&Value::Number(n)) if (1.0..=1000.0).contains(&n) => n as u16,
+        _ => 400,
+    }
+}
+
+fn has_relative_units(value: &Value) -> bool {
+    match value {
+        Value::Length(_, unit) => !matches!(unit, Unit::Px | Unit::Percent | Unit::Fr),
+        Value::List(values) | Value::CommaList(values) | Value::Function(_, values) => {
+            values.iter().any(has_relative_units)
+        }
+        _ => false,
+    }
+}
+
+fn resolve_units(value: &mut Value, context: &UnitContext) {
+    match value {
+        Value::Length(length, unit) => {
+            if let Some(px) = unit.in_px(context).filter(|_| *unit != Unit::Px) {
+                *value = Value::Length(*length * px, Unit::Px);
+            }
+        }
+        Value::List(values) | Value::CommaList(values) | Value::Function(_, values) => {
+            for value in values {
+                resolve_units(value, context);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The shorthand that sets a property, if there is one.
+fn shorthand(name: &str) -> Option<&'static str> {
+    let side = |prefix: &str, suffix: &str| {
+        let side = name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix));
+        matches!(side, Some("top" | "right" | "bottom" | "left"))
+    };
+    if side("margin-", "") {
+        Some("margin")
+    } else if side("padding-", "") {
+        Some("padding")
+    } else if side("border-", "-width") {
+        Some("border-width")
+    } else if side("border-", "-style") {
+        Some("border-style")
+    } else if side("border-", "-color") {
+        Some("border-color")
+    } else if name == "background-color" {
+        Some("background")
+    } else {
+        None
+    }
+}
+
+/// The weight of `font-weight: bolder` inside an element of a weight.
+///
+/// https://www.w3.org/TR/css-fonts-4/#relative-weights
+fn bolder(weight: u16) -> u16 {
+    match weight {
+        0..=349 => 400,
+        350..=549 => 700,
+        550..=899 => 900,
+        _ => weight,
+    }
+}
+
+/// The weight of `font-weight: lighter` inside an element of a weight.
+fn lighter(weight: u16) -> u16 {
+    match weight {
+        0..=99 => weight,
+        100..=549 => 100,
+        550..=749 => 400,
+        _ => 700,
+    }
+}
+
+pub(crate) fn font_style(value: Option<&Value>) -> FontStyle {
+    match value {
+        Some(Value::Keyword(s)) => match s.as_str() {
+            "italic" => FontStyle::Italic,
+            "oblique" => FontStyle::Oblique,
+            _ => FontStyle::Normal,
+        },
+        _ => FontStyle::Normal,
+    }
+}
+
+/// The rules that match elements, so that styling an element again, or another element with the
+/// same tag and attributes, skips matching selectors. An element whose tag or attributes change
+/// is matched again. The rules are kept by their index in the stylesheet, so the cache must be
+/// cleared when the stylesheet changes. It holds at most `MATCH_CACHE_CAPACITY` elements, and
+/// starts over when it is full.
+#[derive(Debug, Default)]
+pub struct MatchCache {
+    rules: HashMap<MatchKey, Vec<usize>>,
+}
+
+/// How many elements a `MatchCache` holds the rules of, so that it stays small for documents
+/// whose attributes keep changing.
+const MATCH_CACHE_CAPACITY: usize = 4096;
+
+/// An element, and the medium and the pseudo-element that its rules were matched for.
+type MatchKey = (Medium, Option<&'static str>, Atom, Attributes);
+
+impl MatchCache {
+    pub fn new() -> MatchCache {
+        Default::default()
+    }
+
+    /// The number of elements whose matching rules are cached.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+}
+
+/// The specified values of a style tree without the nodes they belong to, which are kept while
+/// the document changes, so that only the elements that changed are styled again.
+#[derive(Debug)]
+pub struct RetainedStyle {
+    specified_values: Arc<PropertyMap>,
+    before: Option<Arc<PropertyMap>>,
+    after: Option<Arc<PropertyMap>>,
+    children: Vec<RetainedStyle>,
+}
+
+impl StyledNode<'_> {
+    /// The specified values of this node and its descendants, for `restyle_tree`. They are
+    /// shared with the style tree rather than copied.
+    pub fn retain(&self) -> RetainedStyle {
+        let values = |node: &StyledNode| node.specified_values.clone();
+        RetainedStyle {
+            specified_values: self.specified_values.clone(),
+            before: self.before.as_deref().map(values),
+            after: self.after.as_deref().map(values),
+            children: self.children.iter().map(StyledNode::retain).collect(),
+        }
+    }
 }
 
 pub fn style_tree<'a>(root: &'a Node, sheet: &'a Sheet) -> StyledNode<'a> {
-    match root {
-        Node::Element { children, .. } => StyledNode {
-            node: root,
-            specified_values: get_specified_values(root, sheet),
-            children: children
+    style_tree_for_medium(root, sheet, Medium::Screen)
+}
+
+/// Style a document for a medium, with the rules of the `@media` rules that match it.
+pub fn style_tree_for_medium<'a>(
+    root: &'a Node,
+    sheet: &'a Sheet,
+    medium: Medium,
+) -> StyledNode<'a> {
+    style_tree_with_cache(root, sheet, medium, &mut MatchCache::new())
+}
+
+/// Style a document for a medium, with the rules that match its elements kept in `cache`, to
+/// restyle it faster after it changes.
+pub fn style_tree_with_cache<'a>(
+    root: &'a Node,
+    sheet: &'a Sheet,
+    medium: Medium,
+    cache: &mut MatchCache,
+) -> StyledNode<'a> {
+    style_subtree(root, sheet, medium, cache, &HashMap::new(), None)
+}
+
+/// Style a document again after the elements at the end of the `dirty` paths changed, with the
+/// values that were retained from its style tree before. Only those elements and their
+/// descendants are matched against the stylesheet again. A path holds the index of each node
+/// among the document children of its parent, as given by `Node::path_to_element_by_id`.
+pub fn restyle_tree<'a>(
+    root: &'a Node,
+    sheet: &'a Sheet,
+    medium: Medium,
+    cache: &mut MatchCache,
+    retained: RetainedStyle,
+    dirty: &[Vec<usize>],
+) -> StyledNode<'a> {
+    let dirty: Vec<&[usize]> = dirty.iter().map(Vec::as_slice).collect();
+    restyle_subtree(
+        root,
+        sheet,
+        medium,
+        cache,
+        &HashMap::new(),
+        retained,
+        &dirty,
+    )
+}
+
+/// Style a node and its descendants again, given the values of its parent, or take the values
+/// that were retained for those that didn't change.
+fn restyle_subtree<'a>(
+    root: &'a Node,
+    sheet: &'a Sheet,
+    medium: Medium,
+    cache: &mut MatchCache,
+    parent_values: &PropertyMap,
+    retained: RetainedStyle,
+    dirty: &[&[usize]],
+) -> StyledNode<'a> {
+    // Only the descendants of an element that changed can have been added or removed.
+    let nodes = root.document_children();
+    if dirty.contains(&&[][..]) || retained.children.len() != nodes.len() {
+        return style_subtree(root, sheet, medium, cache, parent_values, None);
+    }
+
+    let specified_values = retained.specified_values;
+    let children = nodes.iter().zip(retained.children).enumerate();
+    let children = children
+        .map(|(index, (child, retained))| {
+            let dirty: Vec<&[usize]> = dirty
                 .iter()
-                .map(|child| style_tree(child, sheet))
-                .collect(),
-        },
-        Node::Text(_) => StyledNode {
+                .filter_map(|path| path.strip_prefix(&[index]))
+                .collect();
+            restyle_subtree(
+                child,
+                sheet,
+                medium,
+                cache,
+                &specified_values,
+                retained,
+                &dirty,
+            )
+        })
+        .collect();
+
+    let pseudo_element = |specified_values| {
+        Box::new(StyledNode {
             node: root,
-            specified_values: HashMap::new(),
+            specified_values,
             children: vec![],
-        },
+            before: None,
+            after: None,
+        })
+    };
+    StyledNode {
+        node: root,
+        before: retained.before.map(pseudo_element),
+        after: retained.after.map(pseudo_element),
+        specified_values,
+        children,
+    }
+}
+
+/// Style a node and its descendants, given the values of its parent. If a previous sibling has
+/// the same style, its values and those of its pseudo-elements are shared instead.
+fn style_subtree<'a>(
+    root: &'a Node,
+    sheet: &'a Sheet,
+    medium: Medium,
+    cache: &mut MatchCache,
+    parent_values: &PropertyMap,
+    sibling: Option<&StyledNode<'a>>,
+) -> StyledNode<'a> {
+    let sibling = sibling.filter(|sibling| shares_style(sibling.node, root));
+    let specified_values = match sibling {
+        Some(sibling) => sibling.specified_values.clone(),
+        None => {
+            let mut specified_values = match root {
+                Node::Element { .. } => get_specified_values(root, sheet, medium, cache, None),
+                Node::Text(_) => HashMap::new(),
+            };
+            inherit(&mut specified_values, parent_values);
+            Arc::new(specified_values)
+        }
+    };
+
+    // Text takes the style of the parent, so all text children share it. Elements share the
+    // style of the previous element.
+    let nodes = root.document_children();
+    let mut children: Vec<StyledNode> = Vec::with_capacity(nodes.len());
+    let mut last_text = None;
+    for child in nodes {
+        let sibling = match child {
+            Node::Element { .. } => children.last(),
+            Node::Text(_) => last_text.map(|index| &children[index]),
+        };
+        let styled = style_subtree(child, sheet, medium, cache, &specified_values, sibling);
+        if let Node::Text(_) = child {
+            last_text = Some(children.len());
+        }
+        children.push(styled);
+    }
+
+    let mut pseudo_element = |name| match root {
+        Node::Element { .. } => {
+            style_pseudo_element(root, sheet, medium, cache, name, &specified_values)
+        }
+        Node::Text(_) => None,
+    };
+    let share = |pseudo_element: &Option<Box<StyledNode>>| {
+        let specified_values = pseudo_element.as_ref()?.specified_values.clone();
+        Some(Box::new(StyledNode {
+            node: root,
+            specified_values,
+            children: vec![],
+            before: None,
+            after: None,
+        }))
+    };
+    let (before, after) = match sibling {
+        Some(sibling) => (share(&sibling.before), share(&sibling.after)),
+        None => (pseudo_element("before"), pseudo_element("after")),
+    };
+
+    StyledNode {
+        node: root,
+        before,
+        after,
+        specified_values,
+        children,
+    }
+}
+
+/// Whether two siblings have the same style: when both are text, or both are elements with the
+/// same tag and attributes. Selectors only look at the element itself, and siblings inherit
+/// from the same parent.
+fn shares_style(node: &Node, other: &Node) -> bool {
+    match (node, other) {
+        (Node::Text(_), Node::Text(_)) => true,
+        (
+            Node::Element { tag, attrs, .. },
+            Node::Element {
+                tag: other_tag,
+                attrs: other_attrs,
+                ..
+            },
+        ) => tag == other_tag && attrs == other_attrs,
+        _ => false,
+    }
+}
+
+/// Style a pseudo-element of an element, if it generates any content.
+///
+/// http://www.w3.org/TR/CSS2/generate.html#before-after-content
+fn style_pseudo_element<'a>(
+    element: &'a Node,
+    sheet: &'a Sheet,
+    medium: Medium,
+    cache: &mut MatchCache,
+    name: &'static str,
+    element_values: &PropertyMap,
+) -> Option<Box<StyledNode<'a>>> {
+    let mut specified_values = get_specified_values(element, sheet, medium, cache, Some(name));
+    match specified_values.get("content") {
+        None => return None,
+        Some(Value::Keyword(keyword)) if keyword == "none" || keyword == "normal" => return None,
+        Some(_) => {}
+    }
+    inherit(&mut specified_values, element_values);
+
+    Some(Box::new(StyledNode {
+        node: element,
+        specified_values: Arc::new(specified_values),
+        children: vec![],
+        before: None,
+        after: None,
+    }))
+}
+
+/// Take the values of inherited properties from the parent, where they are not specified.
+fn inherit(specified_values: &mut PropertyMap, parent_values: &PropertyMap) {
+    // `color: currentColor` takes the color of the parent.
+    if specified_values
+        .get("color")
+        .is_some_and(Value::is_current_color)
+    {
+        specified_values.remove("color");
+    }
+
+    // `bolder` and `lighter` are relative to the weight of the parent.
+    if let Some(Value::Keyword(keyword)) = specified_values.get("font-weight") {
+        let parent = font_weight(parent_values.get("font-weight"));
+        let weight = match keyword.as_str() {
+            "bolder" => Some(bolder(parent)),
+            "lighter" => Some(lighter(parent)),
+            _ => None,
+        };
+        if let Some(weight) = weight {
+            specified_values.insert("font-weight".to_owned(), Value::Number(weight as f32));
+        }
+    }
+
+    // A font size in `em` or `%` is relative to the font size of the parent. It is resolved
+    // before it is inherited, so that it doesn't grow again in each descendant.
+    if let Some(Value::Length(size, unit @ (Unit::Em | Unit::Percent))) =
+        specified_values.get("font-size")
+    {
+        let parent = match parent_values.get("font-size") {
+            Some(&Value::Length(parent, Unit::Px)) => parent,
+            _ => UnitContext::default().font_size,
+        };
+        let factor = match unit {
+            Unit::Percent => 0.01,
+            _ => 1.0,
+        };
+        let font_size = Value::Length(size * factor * parent, Unit::Px);
+        specified_values.insert("font-size".to_owned(), font_size);
+    }
+
+    for name in INHERITED_PROPERTIES {
+        if let Some(value) = parent_values.get(name) {
+            specified_values
+                .entry(name.to_owned())
+                .or_insert_with(|| value.clone());
+        }
     }
 }
 
-fn get_specified_values(node: &Node, sheet: &Sheet) -> PropertyMap {
+fn get_specified_values(
+    node: &Node,
+    sheet: &Sheet,
+    medium: Medium,
+    cache: &mut MatchCache,
+    pseudo_element: Option<&'static str>,
+) -> PropertyMap {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(node, sheet);
+    let (tag, attrs) = match node {
+        Node::Element { tag, attrs, .. } => (tag, attrs),
+        Node::Text(_) => return values,
+    };
 
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+    // The `dir` attribute sets the direction, unless a style rule overrides it.
+    if let Some((_, dir)) = attrs.iter().find(|(name, _)| name == "dir") {
+        if dir == "ltr" || dir == "rtl" {
+            values.insert("direction".to_owned(), Value::Keyword(dir.to_owned()));
+        }
+    }
+
+    let key = (medium, pseudo_element, tag.clone(), attrs.clone());
+    if cache.rules.len() >= MATCH_CACHE_CAPACITY && !cache.rules.contains_key(&key) {
+        cache.rules.clear();
+    }
+    let rules = cache
+        .rules
+        .entry(key)
+        .or_insert_with(|| matching_rules(node, sheet, medium, pseudo_element));
+    for &index in rules.iter() {
+        for declaration in &sheet.rules[index].declarations {
+            values.insert(declaration.name.to_string(), declaration.value.clone());
         }
     }
     values
@@ -118,18 +1137,34 @@ fn get_specified_values(node: &Node, sheet: &Sheet) -> PropertyMap {
 
 type MatchedRule<'a> = (Specificity, &'a Rule);
 
-fn matching_rules<'a>(node: &Node, sheet: &'a Sheet) -> Vec<MatchedRule<'a>> {
-    sheet
-        .0
+/// The indices of the rules that match a node, in the order of the cascade.
+fn matching_rules(
+    node: &Node,
+    sheet: &Sheet,
+    medium: Medium,
+    pseudo_element: Option<&str>,
+) -> Vec<usize> {
+    let mut rules: Vec<_> = sheet
+        .rules
         .iter()
-        .filter_map(|rule| match_rule(node, rule))
-        .collect()
+        .enumerate()
+        .filter(|(_, rule)| rule.media.matches(medium))
+        .filter_map(|(index, rule)| Some((index, match_rule(node, rule, pseudo_element)?)))
+        .collect();
+    rules.sort_by_key(|&(_, (specificity, rule))| (rule.origin, specificity));
+    rules.into_iter().map(|(index, _)| index).collect()
 }
 
-fn match_rule<'a>(node: &Node, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+fn match_rule<'a>(
+    node: &Node,
+    rule: &'a Rule,
+    pseudo_element: Option<&str>,
+) -> Option<MatchedRule<'a>> {
     rule.selectors
         .iter()
-        .find(|selector| matches(node, selector))
+        .find(|selector| {
+            selector.pseudo_element.as_deref() == pseudo_element && matches(node, selector)
+        })
         .map(|selector| (selector.get_specificity(), rule))
 }
 
@@ -161,7 +1196,17 @@ fn matches(node: &Node, selector: &Selector) -> bool {
                 return false;
             }
 
-            // TODO: match attrs
+            if selector
+                .attr
+                .iter()
+                .any(|(name, op, value)| match (node.get_attribute(name), op) {
+                    (None, _) => true,
+                    (Some(actual), AttrOp::Eq) => actual != value,
+                    (Some(_), AttrOp::Exists) => false,
+                })
+            {
+                return false;
+            }
 
             // Only matching selector components
             true
@@ -194,7 +1239,9 @@ mod tests {
                 .add_selector(selector().add_tag("body").add_class("foo"))
                 .add_selector(selector().add_tag("p"))
                 .add_declaration("margin", Value::Keyword("auto".to_owned()))
-                .add_declaration("width", Value::Length(24.0, Unit::Px)),
+                .add_declaration("width", Value::Length(24.0, Unit::Px))
+                .build()
+                .unwrap(),
         );
 
         let actual = style_tree(&document, &style);
@@ -205,10 +1252,254 @@ mod tests {
         ]);
 
         // element p matches selector p
-        assert_eq!(actual.children[1].children[1].specified_values, expected);
+        assert_eq!(*actual.children[1].children[1].specified_values, expected);
 
         // element class bar does not match selector class foo
-        assert_eq!(actual.children[1].specified_values, HashMap::new());
+        assert_eq!(*actual.children[1].specified_values, HashMap::new());
+    }
+
+    #[test]
+    fn test_attribute_selector() {
+        let document = Node::from("<p><a type=\"x\" checked=\"\"></a><a type=\"y\"></a></p>");
+        let sheet = Sheet::from("[type=x] { width: 1px; } a[checked] { height: 2px; }");
+        let p = style_tree(&document, &sheet);
+
+        assert_eq!(p.children[0].specified_values.len(), 2);
+        assert_eq!(p.children[1].specified_values.len(), 0);
+    }
+
+    /// A random selector that matches `<p id="x" class="a b c" lang="nl">`, and its specificity
+    /// as the spec counts it in the text of the selector.
+    fn random_selector(random: &mut impl FnMut(usize) -> usize) -> (String, Specificity) {
+        let candidates = ["#x", ".a", ".b", ".c", "[lang]", "[lang=nl]"];
+        let mut parts: Vec<_> = candidates.iter().filter(|_| random(2) == 0).collect();
+        for i in (1..parts.len()).rev() {
+            parts.swap(i, random(i + 1));
+        }
+        let tag = ["", "*", "p"][random(3)];
+        let mut selector = tag.to_owned() + &parts.into_iter().copied().collect::<String>();
+        if selector.is_empty() {
+            selector.push('*');
+        }
+
+        let count = |c| selector.matches(c).count();
+        let specificity = (count('#'), count('.') + count('['), (tag == "p") as usize);
+        (selector, specificity)
+    }
+
+    #[test]
+    fn test_specificity_properties() {
+        let mut state = 0x2545f491u32;
+        let mut random = |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize % n
+        };
+        let document = Node::from("<p id=\"x\" class=\"a b c\" lang=\"nl\"></p>");
+
+        for _ in 0..200 {
+            let (first, first_specificity) = random_selector(&mut random);
+            let (second, second_specificity) = random_selector(&mut random);
+            let selector: Selector = first.parse().unwrap();
+            assert_eq!(selector.get_specificity(), first_specificity, "{}", first);
+
+            // The more specific rule wins, and the later one if they are equally specific.
+            let css = format!("{} {{ width: 1px; }} {} {{ width: 2px; }}", first, second);
+            let sheet = Sheet::from(css.as_str());
+            let style = style_tree(&document, &sheet);
+            let width = match second_specificity >= first_specificity {
+                true => 2.0,
+                false => 1.0,
+            };
+            assert_eq!(
+                style.value("width"),
+                Some(&Value::Length(width, Unit::Px)),
+                "{}",
+                css
+            );
+        }
+    }
+
+    #[test]
+    fn test_style_sharing() {
+        let html = "<p>0<a class=\"x\">1</a><a class=\"x\">2</a><a>3</a>4</p>";
+        let css = "a { color: #ff0000; } .x { width: 1vw; } .x::before { content: \"-\"; }";
+        let (document, sheet) = (Node::from(html), Sheet::from(css));
+        let mut p = style_tree(&document, &sheet);
+        let [text, a, b, c, other_text] = &p.children[..] else {
+            panic!("expected five children");
+        };
+
+        assert!(Arc::ptr_eq(&a.specified_values, &b.specified_values));
+        assert!(!Arc::ptr_eq(&b.specified_values, &c.specified_values));
+        assert_eq!(b.before.as_ref().unwrap().node, b.node);
+        assert!(c.before.is_none());
+        let text_values = &text.specified_values;
+        assert!(Arc::ptr_eq(text_values, &other_text.specified_values));
+
+        // Values are copied when they change.
+        let context = UnitContext {
+            viewport: (200.0, 100.0),
+            ..Default::default()
+        };
+        p.children[1].resolve_units(&context);
+        assert_eq!(
+            p.children[1].value("width"),
+            Some(&Value::Length(2.0, Unit::Px))
+        );
+        assert_eq!(
+            p.children[2].value("width"),
+            Some(&Value::Length(1.0, Unit::Vw))
+        );
+    }
+
+    #[test]
+    fn test_resolve_units() {
+        let document = Node::from("<html><p><a></a><b></b></p></html>");
+        let sheet = Sheet::from(
+            "html { font-size: 2em; } p { font-size: 150%; width: 10em; } \
+             a { height: 2rem; } b { width: 1in; height: 50vh; }",
+        );
+        let mut style = style_tree(&document, &sheet);
+        style.resolve_units(&UnitContext {
+            viewport: (800.0, 600.0),
+            ..Default::default()
+        });
+
+        let p = &style.children[0];
+        let length = |px| Some(Value::Length(px, Unit::Px));
+        assert_eq!(style.value("font-size").cloned(), length(32.0));
+        assert_eq!(p.value("font-size").cloned(), length(48.0));
+        assert_eq!(p.value("width").cloned(), length(480.0));
+        assert_eq!(p.children[0].value("font-size").cloned(), length(48.0));
+        assert_eq!(p.children[0].value("height").cloned(), length(64.0));
+        assert_eq!(p.children[1].value("width").cloned(), length(96.0));
+        assert_eq!(p.children[1].value("height").cloned(), length(300.0));
+    }
+
+    #[test]
+    fn test_typed_getters() {
+        let document = Node::from("<p></p>");
+        let sheet = Sheet::from(
+            "p { color: #ff0000; margin: auto; margin-top: 2px; width: 50%; \
+             border-color: currentColor; background: #00ff00; position: absolute; }",
+        );
+        let p = style_tree(&document, &sheet);
+
+        assert_eq!(p.keyword("position"), Some("absolute"));
+        assert_eq!(p.keyword("margin-left"), Some("auto"));
+        assert_eq!(p.keyword("display"), None);
+
+        assert_eq!(p.length_or_auto("margin-top"), LengthOrAuto::Length(2.0));
+        assert_eq!(p.length_or_auto("margin-left"), LengthOrAuto::Auto);
+        assert_eq!(p.length_or_auto("width"), LengthOrAuto::Percent(50.0));
+        assert_eq!(p.length_or_auto("height"), LengthOrAuto::Auto);
+        assert_eq!(p.length_or_auto("padding-left"), LengthOrAuto::Length(0.0));
+
+        let red = "#ff0000".parse().ok();
+        assert_eq!(p.color("border-top-color"), red);
+        assert_eq!(p.color("outline-color"), None);
+        assert_eq!(p.color("background-color"), "#00ff00".parse().ok());
+        let black = Color::from_css_str("black");
+        assert_eq!(
+            style_tree(&document, &Sheet::from("")).color("color"),
+            black
+        );
+    }
+
+    #[test]
+    fn test_match_cache() {
+        let mut document =
+            Node::from("<p><a class=\"x\"></a><a></a><b><a class=\"x\"></a></b></p>");
+        let sheet = Sheet::from("a.x { width: 10px; } b { width: 20px; }");
+        let mut cache = MatchCache::new();
+        let p = style_tree_with_cache(&document, &sheet, Medium::Screen, &mut cache);
+        let width = Some(&Value::Length(10.0, Unit::Px));
+        assert_eq!(p.children[2].children[0].value("width"), width);
+
+        // The elements `p`, `a.x`, `a` and `b`, with their `::before` and `::after`.
+        assert_eq!(cache.len(), 12);
+
+        // An element whose attributes change takes the rules of its new key.
+        if let Node::Element { children, .. } = &mut document {
+            children[1].set_attribute("class", "x");
+        }
+        let p = style_tree_with_cache(&document, &sheet, Medium::Screen, &mut cache);
+        assert_eq!(p.children[1].value("width"), width);
+        assert_eq!(cache.len(), 12);
+
+        style_tree_with_cache(&document, &sheet, Medium::Print, &mut cache);
+        assert_eq!(cache.len(), 21);
+
+        // A cache that is full starts over.
+        let html: String = (0..MATCH_CACHE_CAPACITY)
+            .map(|i| format!("<a id=\"{i}\"></a>"))
+            .collect();
+        let document = Node::from(format!("<p>{html}</p>").as_str());
+        style_tree_with_cache(&document, &sheet, Medium::Screen, &mut cache);
+        assert!(cache.len() <= MATCH_CACHE_CAPACITY);
+        assert!(!cache.is_empty());
+    }
+
+    #[test]
+    fn test_restyle_tree() {
+        let mut document = Node::from("<p><a id=\"a\"></a><b><i></i></b></p>");
+        let sheet = Sheet::from("p { color: #ff0000; } a.x { width: 10px; }");
+        let retained = style_tree(&document, &sheet).retain();
+        let path = document.path_to_element_by_id("a").unwrap();
+        document
+            .get_element_by_id_mut("a")
+            .unwrap()
+            .set_attribute("class", "x");
+
+        // Only `a` is matched again, with its `::before` and `::after`, and it still inherits.
+        let mut cache = MatchCache::new();
+        let p = restyle_tree(
+            &document,
+            &sheet,
+            Medium::Screen,
+            &mut cache,
+            retained,
+            &[path],
+        );
+        assert_eq!(cache.len(), 3);
+        let a = &p.children[0];
+        assert_eq!(a.value("width"), Some(&Value::Length(10.0, Unit::Px)));
+        assert_eq!(a.value("color"), p.value("color"));
+        assert_eq!(p.to_string(), style_tree(&document, &sheet).to_string());
+    }
+
+    #[test]
+    fn test_relative_font_weight() {
+        let document = Node::from("<p><b><i>a</i></b></p>");
+        let sheet = Sheet::from(
+            "p { font-weight: 300; } b { font-weight: bolder; } i { font-weight: lighter; }",
+        );
+        let p = style_tree(&document, &sheet);
+        let b = &p.children[0];
+        let i = &b.children[0];
+
+        assert_eq!(p.font_weight(), 300);
+        assert_eq!(b.font_weight(), 400);
+        assert_eq!(i.font_weight(), 100);
+        assert_eq!(i.children[0].font_weight(), 100);
+    }
+
+    #[test]
+    fn test_origin() {
+        let document = Node::from("<div><head></head></div>");
+        let mut sheet = user_agent_sheet();
+        let author = Sheet::from("* { display: block; }");
+        sheet.rules.extend(author.rules);
+
+        // The rules of the document win over the more specific rules of the user agent.
+        let div = style_tree(&document, &sheet);
+        assert!(div.display() == Display::Block);
+        assert!(div.children[0].display() == Display::Block);
+        let user_agent = user_agent_sheet();
+        let div = style_tree(&document, &user_agent);
+        assert!(div.children[0].display() == Display::None);
     }
 
     #[test]
@@ -226,11 +1517,13 @@ mod tests {
                 .add_selector(selector().add_tag("body").add_class("foo"))
                 .add_selector(selector().add_tag("p"))
                 .add_declaration("margin", Value::Keyword("auto".to_owned()))
-                .add_declaration("width", Value::Length(24.0, Unit::Px)),
+                .add_declaration("width", Value::Length(24.0, Unit::Px))
+                .build()
+                .unwrap(),
         );
 
         let actual = style_tree(&document, &style);
         let expected = r#"<html style=""><body class="bar" style=""><h1 style="">Hi!</h1><p style="margin:auto;width:24px;">Bye!</p></body></html>"#;
-        assert_eq!(String::from(&actual), expected);
+        assert_eq!(actual.to_string(), expected);
     }
 }
@@ -0,0 +1,165 @@
+//! Reference tests, which render a document with the software rasterizer and compare it with an
+//! image of how it should look, to catch changes to layout and painting.
+//!
+//! When a rendering doesn't match its reference, the rendering and an image of the differences
+//! are saved next to the reference, as `<name>.actual.png` and `<name>.diff.png`. Run the tests
+//! with the `BOXRS_UPDATE_REFERENCES` environment variable set to save the renderings as the new
+//! references instead.
+
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+
+use crate::layout::Viewport;
+#[cfg(feature = "fs")]
+use crate::loader::{file_url, FileLoader};
+use crate::{Engine, Error};
+
+/// The environment variable that makes `assert_matches_reference` save new references.
+pub const UPDATE_REFERENCES: &str = "BOXRS_UPDATE_REFERENCES";
+
+/// How an image differs from its reference.
+#[derive(Debug)]
+pub struct Comparison {
+    /// The number of pixels with a channel that differs by more than the tolerance. Images of
+    /// different sizes differ in every pixel of the larger one.
+    pub different_pixels: usize,
+    /// The largest difference of a channel of a pixel.
+    pub max_difference: u8,
+    /// The reference, faded, with the pixels that differ in red.
+    pub diff: RgbaImage,
+}
+
+impl Comparison {
+    pub fn matches(&self) -> bool {
+        self.different_pixels == 0
+    }
+}
+
+/// Render a document with a stylesheet in a viewport.
+pub fn render(html: &str, css: &str, viewport: Viewport) -> Result<RgbaImage, Error> {
+    let mut engine = Engine::new(viewport);
+    engine.load_html(html)?;
+    engine.add_stylesheet(css)?;
+    Ok(to_image(&mut engine))
+}
+
+/// Render an HTML file with the stylesheets that it links to in a viewport.
+#[cfg(feature = "fs")]
+pub fn render_file(path: impl AsRef<Path>, viewport: Viewport) -> Result<RgbaImage, Error> {
+    let mut engine = Engine::new(viewport);
+    engine.load_document(&FileLoader, &file_url(path)?)?;
+    Ok(to_image(&mut engine))
+}
+
+/// Compare an image with its reference, pixel by pixel. Channels that differ by at most
+/// `tolerance` count as equal, which allows for small differences in anti-aliasing.
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> Comparison {
+    let width = actual.width().max(expected.width());
+    let height = actual.height().max(expected.height());
+    let mut diff = RgbaImage::new(width, height);
+    let mut comparison = Comparison {
+        different_pixels: 0,
+        max_difference: 0,
+        diff: RgbaImage::new(0, 0),
+    };
+
+    for (x, y, pixel) in diff.enumerate_pixels_mut() {
+        let difference = match (
+            actual.get_pixel_checked(x, y),
+            expected.get_pixel_checked(x, y),
+        ) {
+            (Some(a), Some(b)) => (0..4).map(|i| a[i].abs_diff(b[i])).max().unwrap(),
+            _ => u8::MAX,
+        };
+        comparison.max_difference = comparison.max_difference.max(difference);
+
+        *pixel = if difference > tolerance {
+            comparison.different_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.get_pixel(x, y).0;
+            let fade = |channel: u8| 191 + channel / 4;
+            Rgba([fade(r), fade(g), fade(b), 255])
+        };
+    }
+
+    comparison.diff = diff;
+    comparison
+}
+
+/// Compare an image with the reference image at a path, and panic if they don't match. The image
+/// and the differences are saved next to the reference first, or the image is saved as the
+/// reference if `BOXRS_UPDATE_REFERENCES` is set.
+pub fn assert_matches_reference(actual: &RgbaImage, reference: impl AsRef<Path>, tolerance: u8) {
+    let reference = reference.as_ref();
+    if std::env::var_os(UPDATE_REFERENCES).is_some() {
+        actual.save(reference).unwrap();
+        return;
+    }
+
+    let expected = match image::open(reference) {
+        Ok(expected) => expected.into_rgba8(),
+        Err(error) => panic!(
+            "can't open reference {}: {error}; set {UPDATE_REFERENCES} to create it",
+            reference.display()
+        ),
+    };
+
+    let comparison = compare(actual, &expected, tolerance);
+    if !comparison.matches() {
+        let actual_path = reference.with_extension("actual.png");
+        let diff_path = reference.with_extension("diff.png");
+        actual.save(&actual_path).unwrap();
+        comparison.diff.save(&diff_path).unwrap();
+        panic!(
+            "{} pixels differ from {} by up to {}; see {} and {}",
+            comparison.different_pixels,
+            reference.display(),
+            comparison.max_difference,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn to_image(engine: &mut Engine) -> RgbaImage {
+    let canvas = engine.render();
+    let (width, height) = (canvas.width as u32, canvas.height as u32);
+    RgbaImage::from_raw(width, height, canvas.to_rgba8()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::Viewport;
+    use crate::testing::*;
+
+    #[test]
+    fn test_compare() {
+        let viewport = Viewport::new(20.0, 20.0);
+        let render_div = |css: &str| render("<div></div>", css, viewport).unwrap();
+        let expected = render_div("div { height: 10px; background: #ff0000; }");
+
+        let similar = render_div("div { height: 10px; background: #fe0000; }");
+        assert!(compare(&similar, &expected, 1).matches());
+
+        let taller = render_div("div { height: 12px; background: #ff0000; }");
+        let comparison = compare(&taller, &expected, 1);
+        assert_eq!(comparison.different_pixels, 40);
+        assert_eq!(comparison.max_difference, 255);
+        assert_eq!(comparison.diff.get_pixel(0, 10).0, [255, 0, 0, 255]);
+        assert_eq!(comparison.diff.get_pixel(0, 0).0, [254, 191, 191, 255]);
+
+        // The extra column of a wider image differs.
+        let wider = render("<div></div>", "", Viewport::new(21.0, 20.0)).unwrap();
+        let blank = render("<div></div>", "", viewport).unwrap();
+        assert_eq!(compare(&wider, &blank, 0).different_pixels, 20);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_reference() {
+        let actual = render_file("testpages/rainbow.html", Viewport::new(400.0, 300.0)).unwrap();
+        assert_matches_reference(&actual, "testpages/reference/rainbow.png", 0);
+    }
+}
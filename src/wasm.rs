@@ -0,0 +1,96 @@
+//! Bindings for JavaScript, to render documents in the canvas of a web page when boxrs is
+//! compiled to WebAssembly.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+use crate::layout::Viewport;
+use crate::Engine;
+
+/// A document that is rendered in a canvas, with the rasterizer of boxrs. In JavaScript:
+///
+/// ```js
+/// const renderer = new Renderer(800, 600, window.devicePixelRatio);
+/// renderer.loadHtml("<p>Hello, world!</p>");
+/// renderer.addFont(new Uint8Array(await (await fetch("font.ttf")).arrayBuffer()));
+/// renderer.render(canvas.getContext("2d"));
+/// ```
+#[wasm_bindgen]
+pub struct Renderer {
+    engine: Engine,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    /// A renderer with an empty document, in a viewport of CSS pixels on a screen with a device
+    /// pixel ratio.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: f32, height: f32, dpr: f32) -> Renderer {
+        Renderer {
+            engine: Engine::new(viewport(width, height, dpr)),
+        }
+    }
+
+    /// Parse a document with the stylesheets of its `style` elements.
+    #[wasm_bindgen(js_name = loadHtml)]
+    pub fn load_html(&mut self, html: &str) -> Result<(), JsError> {
+        Ok(self.engine.load_html(html)?)
+    }
+
+    #[wasm_bindgen(js_name = addStylesheet)]
+    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), JsError> {
+        Ok(self.engine.add_stylesheet(css)?)
+    }
+
+    #[wasm_bindgen(js_name = clearStylesheets)]
+    pub fn clear_stylesheets(&mut self) {
+        self.engine.clear_stylesheets();
+    }
+
+    /// Add a font from the data of a TrueType or OpenType file. A web page can't read the fonts
+    /// of the system, so text is only set in the fonts that are added.
+    #[wasm_bindgen(js_name = addFont)]
+    pub fn add_font(&mut self, data: Vec<u8>) -> Result<(), JsError> {
+        let fonts = self.engine.fonts_mut();
+        fonts
+            .add_font_data(data)
+            .map_err(|error| JsError::new(&error.to_string()))
+    }
+
+    /// Resize the viewport, keeping the zoom.
+    #[wasm_bindgen(js_name = setViewport)]
+    pub fn set_viewport(&mut self, width: f32, height: f32, dpr: f32) {
+        let zoom = self.engine.viewport().zoom;
+        self.engine.set_viewport(Viewport {
+            zoom,
+            ..viewport(width, height, dpr)
+        });
+    }
+
+    /// Magnify the page, which is laid out again in fewer CSS pixels.
+    #[wasm_bindgen(js_name = setZoom)]
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.engine.set_zoom(zoom);
+    }
+
+    /// Render the document, and draw it at the top left of a 2D context. The canvas should be
+    /// the size of the viewport in device pixels.
+    pub fn render(&mut self, context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        let canvas = self.engine.render();
+        let pixels = canvas.to_rgba8();
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&pixels),
+            canvas.width as u32,
+            canvas.height as u32,
+        )?;
+        context.put_image_data(&image, 0.0, 0.0)
+    }
+}
+
+fn viewport(width: f32, height: f32, dpr: f32) -> Viewport {
+    Viewport {
+        dpr,
+        ..Viewport::new(width, height)
+    }
+}